@@ -23,6 +23,8 @@ pub fn build_from_tokens(tokens: &Vec<Token>) -> String {
                 }
             ),
             TokenKind::Bool(bool) => bool.to_string(),
+            TokenKind::Str(str) => format!("\"{str}\""),
+            TokenKind::Stamp(stamp) => format!("@{stamp}"),
             _ => String::from(match token.kind {
                 TokenKind::Add => "+",
                 TokenKind::Sub => "-",
@@ -43,11 +45,41 @@ pub fn build_from_tokens(tokens: &Vec<Token>) -> String {
                 TokenKind::CloseBracket => ")",
                 TokenKind::OpenCurlBracket => "{",
                 TokenKind::CloseCurlBracket => "}",
+                TokenKind::OpenSquareBracket => "[",
+                TokenKind::CloseSquareBracket => "]",
                 TokenKind::Col => ":",
+                TokenKind::ColCol => "::",
+                TokenKind::Comma => ",",
                 TokenKind::Semi => ";",
                 TokenKind::Arrow => "->",
+                TokenKind::FatArrow => "=>",
+                TokenKind::Dot => ".",
+                TokenKind::DotDot => "..",
+                TokenKind::DotDotEq => "..=",
                 TokenKind::Eq => "=",
+                TokenKind::AddEq => "+=",
+                TokenKind::SubEq => "-=",
+                TokenKind::MulEq => "*=",
+                TokenKind::DivEq => "/=",
                 TokenKind::Func => "func ",
+                TokenKind::If => "if ",
+                TokenKind::While => "while ",
+                TokenKind::Return => "return ",
+                TokenKind::Const => "const ",
+                TokenKind::Break => "break ",
+                TokenKind::Continue => "continue ",
+                TokenKind::For => "for ",
+                TokenKind::In => "in ",
+                TokenKind::Else => "else ",
+                TokenKind::Struct => "struct ",
+                TokenKind::Enum => "enum ",
+                TokenKind::Match => "match ",
+                TokenKind::Import => "import ",
+                TokenKind::Spawn => "spawn ",
+                TokenKind::Within => "within ",
+                TokenKind::Every => "every ",
+                TokenKind::Retry => "retry ",
+                TokenKind::OnInterrupt => "on_interrupt ",
                 TokenKind::Eof => "",
                 _ => unreachable!(),
             }),
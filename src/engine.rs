@@ -0,0 +1,70 @@
+use crate::{
+    interpreter::Interpreter,
+    parser::ast::Ast,
+    resolver::Resolver,
+    runtime::{checkpoint::Saved, Runtime},
+    vm::Vm,
+};
+
+/// Which implementation runs a script
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Backend {
+    /// Walks the syntax tree
+    TreeWalker,
+    /// Compiles to bytecode and runs it on a stack based VM
+    Bytecode,
+}
+
+impl Backend {
+    /// The backend for the name used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tree" => Some(Backend::TreeWalker),
+            "vm" => Some(Backend::Bytecode),
+            _ => None,
+        }
+    }
+}
+
+/// Runs scripts with the chosen backend
+pub struct Engine {
+    pub backend: Backend,
+    pub runtime: Runtime,
+    /// Makes every run draw the same random numbers, they differ between runs otherwise
+    pub seed: Option<u64>,
+}
+
+impl Engine {
+    pub fn new(backend: Backend, runtime: Runtime) -> Self {
+        Self {
+            backend,
+            runtime,
+            seed: None,
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn run(&self, mut ast: Ast) -> Result<(), ()> {
+        Resolver::new().resolve(&mut ast)?;
+
+        if let Some(seed) = self.seed {
+            self.runtime.seed(seed);
+        }
+
+        match self.backend {
+            Backend::TreeWalker => Interpreter::new(self.runtime.clone()).run(ast),
+            Backend::Bytecode => Vm::new(self.runtime.clone()).run(&ast),
+        }
+    }
+
+    /// Continues a script from a checkpoint, which only the VM writes
+    pub fn resume(&self, mut ast: Ast, saved: &Saved) -> Result<(), ()> {
+        Resolver::new().resolve(&mut ast)?;
+
+        Vm::new(self.runtime.clone()).resume(&ast, saved)
+    }
+}
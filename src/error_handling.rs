@@ -1,10 +1,54 @@
-use crate::{
-    parser::{ast::Span, lexer::token::Token},
-    CODE,
-};
+use crate::parser::{ast::Span, lexer::token::Token};
 use colored::Colorize;
-use std::process::exit;
+use std::{process::exit, sync::Mutex};
 
+/// The files of the script, spans point into all of them as if they were
+/// written one after the other
+static SOURCES: Mutex<Vec<Source>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Copy)]
+struct Source {
+    path: &'static str,
+    code: &'static str,
+    /// Where the spans of this file start
+    start: usize,
+}
+
+/// Registers the code of a file, returning where its spans start
+pub fn add_source(path: &'static str, code: &'static str) -> usize {
+    let mut sources = SOURCES.lock().unwrap();
+
+    // One past the end, so the end of a file isn't the start of the next one
+    let start = sources
+        .last()
+        .map_or(0, |last| last.start + last.code.len() + 1);
+    sources.push(Source { path, code, start });
+
+    start
+}
+
+/// The file a span points into
+fn source_at(span: Span) -> Option<Source> {
+    let sources = SOURCES.lock().unwrap();
+
+    sources
+        .iter()
+        .find(|source| (source.start..=source.start + source.code.len()).contains(&span.start))
+        .copied()
+}
+
+/// The file, line and code a span points at, like `script.wait:3 wait(2s);`
+pub fn place(span: Span) -> Option<String> {
+    let Source { path, code, start } = source_at(span)?;
+    let (line, _) = get_line_by_char(span.start - start, code)?;
+
+    Some(format!(
+        "{path}:{line} {}",
+        code.split('\n').nth(line - 1)?.trim()
+    ))
+}
+
+#[derive(Clone, Copy)]
 enum ReportKind {
     Error,
     Warning,
@@ -18,7 +62,20 @@ pub fn error(token: &Token, message: &str) {
     fancy_report(token.span, message, ReportKind::Error);
 }
 
-fn fancy_report(span: Span, message: &str, kind: ReportKind) {
+pub fn warn_at(span: Span, message: &str) {
+    fancy_report(span, message, ReportKind::Warning)
+}
+
+pub fn error_at(span: Span, message: &str) {
+    fancy_report(span, message, ReportKind::Error);
+}
+
+/// Reports an error that doesn't belong to a place in the code
+pub fn error_message(message: &str) {
+    report_header(message, ReportKind::Error);
+}
+
+fn report_header(message: &str, kind: ReportKind) {
     eprint!(
         "{}: ",
         match kind {
@@ -28,8 +85,17 @@ fn fancy_report(span: Span, message: &str, kind: ReportKind) {
         .bold()
     );
     eprintln!("{}", message.bold());
+}
 
-    let (line, char_in_line) = match get_line_by_char(span.end, unsafe { CODE }) {
+fn fancy_report(span: Span, message: &str, kind: ReportKind) {
+    report_header(message, kind);
+
+    // Code that wasn't read from a file, like in tests, has no line to show
+    let Some(Source { path, code, start }) = source_at(span) else {
+        return;
+    };
+
+    let (line, char_in_line) = match get_line_by_char(span.start - start, code) {
         Some(line) => line,
         None => {
             eprintln!("Please tell me what you did. Open an issue on the GitHub Repo (https://github.com/TheBlckbird/waitlang) or reach out to me in some other way.");
@@ -37,7 +103,13 @@ fn fancy_report(span: Span, message: &str, kind: ReportKind) {
         }
     };
 
-    let mut error = String::from(unsafe { CODE }.lines().nth(line - 1).unwrap());
+    eprintln!(
+        "  {} {path}:{line}:{}",
+        "-->".bold().blue(),
+        char_in_line + 1
+    );
+
+    let mut error = String::from(code.split('\n').nth(line - 1).unwrap());
 
     error.insert_str(
         0,
@@ -77,16 +149,17 @@ fn fancy_report(span: Span, message: &str, kind: ReportKind) {
 
 /// Option<(line, char_in_line)>
 ///
-/// You have to pass in span.end
+/// You have to pass in span.start
 fn get_line_by_char(char: usize, code: &str) -> Option<(usize, usize)> {
-    let lines = code.lines();
-    let mut total_length = 0;
+    let mut line_start = 0;
 
-    for (i, line) in lines.enumerate() {
-        total_length += line.len();
-        if total_length >= i {
-            return Some((i + 1, total_length - char));
+    for (i, line) in code.split('\n').enumerate() {
+        // The end of the line still belongs to it, so errors at EOF have a line
+        if char <= line_start + line.len() {
+            return Some((i + 1, char - line_start));
         }
+
+        line_start += line.len() + 1;
     }
 
     None
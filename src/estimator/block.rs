@@ -0,0 +1,74 @@
+use super::{interval::Interval, join_intervals, value::Value, Estimator, Flow};
+use crate::parser::ast::block::Block;
+
+impl<'a> Estimator<'a> {
+    pub fn eval_block(&mut self, block: &'a Block, flow: &mut Flow) -> Result<(), ()> {
+        self.env.push_scope();
+
+        for stmt in block.stmts.iter() {
+            self.eval_stmt(stmt, flow)?;
+        }
+
+        if let Some(value) = &block.value
+            && let Some(mut waited) = flow.live
+        {
+            self.eval_expr(value, &mut waited)?;
+            flow.live = Some(waited);
+        }
+
+        self.env.pop_scope();
+
+        Ok(())
+    }
+
+    /// Evaluates the body of a function, whose value is the one it returns or
+    /// otherwise the one it ends with
+    pub fn eval_body(&mut self, block: &'a Block, flow: &mut Flow) -> Result<(), ()> {
+        self.env.push_scope();
+
+        for stmt in block.stmts.iter() {
+            self.eval_stmt(stmt, flow)?;
+        }
+
+        if let Some(value) = &block.value
+            && let Some(mut waited) = flow.live
+        {
+            let value = self.eval_expr(value, &mut waited)?;
+
+            flow.live = None;
+            flow.returned = join_intervals(flow.returned, Some(waited));
+            flow.return_value = Some(flow.return_value.map_or(value, |other| other.join(&value)));
+        }
+
+        self.env.pop_scope();
+
+        Ok(())
+    }
+
+    /// Evaluates a block used as a value, which can't return or leave a loop
+    pub fn eval_block_value(
+        &mut self,
+        block: &'a Block,
+        waited: &mut Interval,
+    ) -> Result<Value, ()> {
+        self.env.push_scope();
+
+        let mut flow = Flow {
+            live: Some(*waited),
+            ..Flow::new()
+        };
+        for stmt in block.stmts.iter() {
+            self.eval_stmt(stmt, &mut flow)?;
+        }
+        *waited = flow.live.unwrap_or(*waited);
+
+        let value = match &block.value {
+            Some(value) => self.eval_expr(value, waited)?,
+            None => Value::Unknown,
+        };
+
+        self.env.pop_scope();
+
+        Ok(value)
+    }
+}
@@ -0,0 +1,137 @@
+use super::{
+    interval::Interval,
+    value::{Elem, Value},
+};
+use crate::parser::ast::{block::Block, expr::Ident, Type};
+use std::collections::HashMap;
+
+/// The variables and functions visible while estimating, innermost scope last
+#[derive(Debug, Clone)]
+pub struct Env<'a> {
+    scopes: Vec<Scope<'a>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Scope<'a> {
+    variables: HashMap<&'static str, Value>,
+    functions: HashMap<&'static str, Function<'a>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Function<'a> {
+    pub args: &'a [(Ident, Type)],
+    pub body: &'a Block,
+}
+
+impl<'a> Env<'a> {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![Scope::default()],
+        }
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    pub fn define_variable(&mut self, ident: &Ident, value: Value) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.variables.insert(ident.name, value);
+        }
+    }
+
+    /// Changes the value of the closest variable with this name
+    pub fn assign(&mut self, ident: &Ident, value: Value) {
+        let variable = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.variables.get_mut(ident.name));
+
+        if let Some(variable) = variable {
+            *variable = value;
+        }
+    }
+
+    /// Lets every list grow by any number of `elem`s, since any of them could
+    /// be the list something was added to
+    pub fn grow_lists(&mut self, elem: Elem) {
+        for scope in self.scopes.iter_mut() {
+            for value in scope.variables.values_mut() {
+                if let Value::List(len, list_elem) = value {
+                    *value =
+                        Value::List(Interval::new(len.lo, f32::INFINITY), list_elem.join(&elem));
+                }
+            }
+        }
+    }
+
+    pub fn define_function(&mut self, ident: &Ident, function: Function<'a>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.functions.insert(ident.name, function);
+        }
+    }
+
+    pub fn get_variable(&self, ident: &Ident) -> Option<Value> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.variables.get(ident.name).copied())
+    }
+
+    pub fn get_function(&self, ident: &Ident) -> Option<Function<'a>> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.functions.get(ident.name).copied())
+    }
+
+    /// Switches to the environment a closure was created in to call it,
+    /// keeping the variables declared at the top level of the script, which
+    /// aren't copied into closures
+    ///
+    /// Returns the environment of the caller, which [`Env::leave`] restores.
+    pub fn enter(&mut self, closure: &Self) -> Self {
+        let mut env = closure.clone();
+        env.scopes[0] = std::mem::take(&mut self.scopes[0]);
+
+        std::mem::replace(self, env)
+    }
+
+    pub fn leave(&mut self, mut caller: Self) {
+        caller.scopes[0] = std::mem::take(&mut self.scopes[0]);
+        *self = caller;
+    }
+
+    /// Merges the environment of another path through the same code into this one
+    pub fn join(&mut self, other: &Self) {
+        for (scope, other_scope) in self.scopes.iter_mut().zip(other.scopes.iter()) {
+            for (name, value) in scope.variables.iter_mut() {
+                *value = match other_scope.variables.get(name) {
+                    Some(other_value) => value.join(other_value),
+                    None => Value::Unknown,
+                };
+            }
+        }
+    }
+
+    /// Forgets every variable that differs from `before`, returns whether there were any
+    pub fn widen(&mut self, before: &Self) -> bool {
+        let mut changed = false;
+
+        for (scope, before_scope) in self.scopes.iter_mut().zip(before.scopes.iter()) {
+            for (name, value) in scope.variables.iter_mut() {
+                if before_scope.variables.get(name) != Some(value) {
+                    *value = Value::Unknown;
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+}
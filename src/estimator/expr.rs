@@ -0,0 +1,613 @@
+use super::{
+    env::{Env, Function},
+    interval::Interval,
+    value::{Elem, Value},
+    Estimator, Flow, MAX_CALL_DEPTH,
+};
+use crate::{
+    error_handling::error_at,
+    parser::ast::{
+        expr::{BinOp, Expr, ExprKind, Ident, UnOp},
+        lit::{Lit, LitKind, Stamp},
+        pattern::{Arm, PatternKind},
+        Span,
+    },
+    runtime::{calendar::DAY_MS, prelude::parse_ms},
+};
+use std::mem;
+
+impl<'a> Estimator<'a> {
+    /// Evaluates an expression, adding the time it waits to `waited`
+    pub fn eval_expr(&mut self, expr: &'a Expr, waited: &mut Interval) -> Result<Value, ()> {
+        match &expr.expr_kind {
+            ExprKind::Binary(left, bin_op_kind, right) => {
+                let left = self.eval_expr(left, waited)?;
+                let right = self.eval_expr(right, waited)?;
+
+                Ok(bin_op(&left, bin_op_kind, &right))
+            }
+            ExprKind::Unary(un_op_kind, target_expr) => {
+                let target = self.eval_expr(target_expr, waited)?;
+
+                Ok(match (un_op_kind, target) {
+                    (UnOp::Neg, Value::Num(num)) => Value::Num(-num),
+                    (UnOp::Neg, Value::Time(time)) => Value::Time(-time),
+                    (UnOp::Not, Value::Bool(bool)) => Value::Bool(bool.map(|bool| !bool)),
+                    _ => Value::Unknown,
+                })
+            }
+            ExprKind::FnCall(ident, args) => self.eval_fn_call(ident, args, expr.span, waited),
+            ExprKind::Call(callee, args) => {
+                let callee = self.eval_expr(callee, waited)?;
+
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    values.push(self.eval_expr(arg, waited)?);
+                }
+
+                self.call_value(&callee, values, expr.span, waited)
+            }
+            ExprKind::Closure(closure) => {
+                let function = Function {
+                    args: &closure.args,
+                    body: &closure.body,
+                };
+
+                Ok(self.function_value(function))
+            }
+            // The task waits on a clock of its own, starting at the current time
+            ExprKind::Spawn(task) => {
+                let function = Function {
+                    args: &task.args,
+                    body: &task.body,
+                };
+
+                let mut end = *waited;
+                self.call("the task", function, Vec::new(), expr.span, &mut end)?;
+
+                Ok(Value::Task(Some(end)))
+            }
+            ExprKind::FnRef(ident) => match self.env.get_function(ident) {
+                Some(function) => Ok(self.function_value(function)),
+                None => {
+                    error_at(ident.span, &format!("Unknown function `{}`", ident.name));
+                    Err(())
+                }
+            },
+            ExprKind::MethodCall {
+                receiver: receiver_expr,
+                ident,
+                args,
+            } => {
+                let receiver = self.eval_expr(receiver_expr, waited)?;
+
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    values.push(self.eval_expr(arg, waited)?);
+                }
+
+                if let (Value::List(len, elem), "push", [value]) =
+                    (receiver, ident.name, values.as_slice())
+                {
+                    let pushed = Elem::from(*value);
+                    self.pushes += 1;
+                    self.env.grow_lists(pushed);
+
+                    // Only the list the value was added to is known to be one longer
+                    let len = len + Interval::point(1.);
+                    if let ExprKind::Ident(ident) = &receiver_expr.expr_kind {
+                        self.env.assign(ident, Value::List(len, elem.join(&pushed)));
+                    }
+
+                    return Ok(Value::Num(len));
+                }
+
+                Ok(method(&receiver, ident.name, &values))
+            }
+            // Fields aren't tracked, so they could be anything
+            ExprKind::FieldAcc(receiver, _) => {
+                self.eval_expr(receiver, waited)?;
+
+                Ok(Value::Unknown)
+            }
+            ExprKind::StructLit(_, fields) => {
+                for (_, value) in fields {
+                    self.eval_expr(value, waited)?;
+                }
+
+                Ok(Value::Unknown)
+            }
+            ExprKind::List(elems) => {
+                let mut values = Vec::with_capacity(elems.len());
+                for elem in elems {
+                    values.push(self.eval_expr(elem, waited)?);
+                }
+
+                Ok(Value::list(&values))
+            }
+            ExprKind::Index(list, index) => {
+                let list = self.eval_expr(list, waited)?;
+                self.eval_expr(index, waited)?;
+
+                Ok(match list {
+                    Value::List(_, elem) => elem.value(),
+                    _ => Value::Unknown,
+                })
+            }
+            ExprKind::Lit(lit) => Ok(Value::from(lit.0)),
+            // Which time it stands for depends on when the script runs
+            ExprKind::Timestamp(_) => Ok(Value::Unknown),
+            // The estimator runs before the resolver, which tells functions used as values apart
+            ExprKind::Ident(ident) => match self.env.get_variable(ident) {
+                Some(value) => Ok(value),
+                None if let Some(function) = self.env.get_function(ident) => {
+                    Ok(self.function_value(function))
+                }
+                None => {
+                    error_at(ident.span, &format!("Unknown variable `{}`", ident.name));
+                    Err(())
+                }
+            },
+            ExprKind::Grouping(group) => self.eval_expr(group, waited),
+            ExprKind::Block(block) => self.eval_block_value(block, waited),
+            ExprKind::If(condition, true_block, else_expr) => {
+                match self.eval_expr(condition, waited)? {
+                    Value::Bool(Some(true)) => self.eval_block_value(true_block, waited),
+                    Value::Bool(Some(false)) => self.eval_expr(else_expr, waited),
+                    _ => {
+                        let mut else_waited = *waited;
+                        let else_env = self.env.clone();
+
+                        let true_value = self.eval_block_value(true_block, waited)?;
+                        let true_env = mem::replace(&mut self.env, else_env);
+                        let else_value = self.eval_expr(else_expr, &mut else_waited)?;
+
+                        *waited = waited.join(&else_waited);
+                        self.env.join(&true_env);
+
+                        Ok(true_value.join(&else_value))
+                    }
+                }
+            }
+            ExprKind::Match(value, arms) => {
+                let value = self.eval_expr(value, waited)?;
+
+                // Every arm that could run starts from where the arms before it left off
+                let mut joined: Option<(Interval, Env<'a>, Value)> = None;
+                for (body, mut arm_waited, env) in self.arms_to_run(&value, arms, *waited)? {
+                    self.env = env;
+                    let value = self.eval_expr(body, &mut arm_waited)?;
+
+                    joined = Some(match joined {
+                        Some((other_waited, mut other_env, other_value)) => {
+                            other_env.join(&self.env);
+                            (
+                                other_waited.join(&arm_waited),
+                                other_env,
+                                other_value.join(&value),
+                            )
+                        }
+                        None => (arm_waited, self.env.clone(), value),
+                    });
+                }
+
+                // Without an arm that could match the script stops there
+                let Some((arm_waited, env, value)) = joined else {
+                    return Ok(Value::Unknown);
+                };
+
+                *waited = arm_waited;
+                self.env = env;
+
+                Ok(value)
+            }
+        }
+    }
+
+    /// The arms of a `match` that could run, with the time waited and the
+    /// environment after the guards before them, up to the first one that surely runs
+    pub fn arms_to_run<B>(
+        &mut self,
+        value: &Value,
+        arms: &'a [Arm<B>],
+        mut waited: Interval,
+    ) -> Result<Vec<(&'a B, Interval, Env<'a>)>, ()> {
+        let mut candidates = Vec::new();
+
+        for arm in arms {
+            let matches = pattern_matches(value, &arm.pattern.pattern_kind);
+            if matches == Some(false) {
+                continue;
+            }
+
+            let guard = match &arm.guard {
+                Some(guard) => self.eval_expr(guard, &mut waited)?,
+                None => Value::Bool(Some(true)),
+            };
+            if guard == Value::Bool(Some(false)) {
+                continue;
+            }
+
+            candidates.push((&arm.body, waited, self.env.clone()));
+
+            if matches == Some(true) && guard == Value::Bool(Some(true)) {
+                break;
+            }
+        }
+
+        Ok(candidates)
+    }
+
+    fn eval_fn_call(
+        &mut self,
+        ident: &Ident,
+        args: &'a [Expr],
+        span: Span,
+        waited: &mut Interval,
+    ) -> Result<Value, ()> {
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.eval_expr(arg, waited)?);
+        }
+
+        if let Some(function) = self.env.get_function(ident) {
+            return self.call(ident.name, function, values, span, waited);
+        }
+
+        if let Some(callee) = self.env.get_variable(ident) {
+            return self.call_value(&callee, values, span, waited);
+        }
+
+        match ident.name {
+            "wait" => {
+                let [time] = values.as_slice() else {
+                    error_at(span, "`wait` takes exactly one argument");
+                    return Err(());
+                };
+
+                *waited = *waited + time.as_wait();
+
+                Ok(Value::Unit)
+            }
+            "wait_until" => {
+                let longest = match args {
+                    // A time of day comes around within a day, and an hour when the clocks go back
+                    [Expr {
+                        expr_kind: ExprKind::Timestamp(Stamp { date: None, .. }),
+                        ..
+                    }] => (DAY_MS + 3_600_000) as f32,
+                    _ => f32::INFINITY,
+                };
+                let until = Interval::new(0., longest);
+                *waited = *waited + until;
+
+                Ok(Value::Time(until))
+            }
+            // Whatever they wait for could happen right away or never before the timeout
+            "wait_for_file"
+            | "wait_for_process_exit"
+            | "wait_for_port"
+            | "wait_for_line_on_stdin" => {
+                let timeout = values.last().map_or(Interval::TOP, Value::as_wait);
+                *waited = *waited + Interval::new(0., timeout.hi);
+
+                Ok(Value::Bool(None))
+            }
+            "detect_user" => Ok(Value::User),
+            // As far as the estimate goes, the clock only moves while waiting
+            "now" => Ok(Value::Time(*waited)),
+            "elapsed" => match values.as_slice() {
+                [Value::Time(since)] => Ok(Value::Time(*waited - *since)),
+                _ => Ok(Value::Time(Interval::TOP)),
+            },
+            "min_time" | "max_time" => match values.as_slice() {
+                [Value::Time(a), Value::Time(b)] if ident.name == "min_time" => {
+                    Ok(Value::Time(a.min(b)))
+                }
+                [Value::Time(a), Value::Time(b)] => Ok(Value::Time(a.max(b))),
+                _ => Ok(Value::Time(Interval::TOP)),
+            },
+            "clamp" => match values.as_slice() {
+                [Value::Num(value), Value::Num(min), Value::Num(max)] => {
+                    Ok(Value::Num(value.max(min).min(max)))
+                }
+                [Value::Time(value), Value::Time(min), Value::Time(max)] => {
+                    Ok(Value::Time(value.max(min).min(max)))
+                }
+                _ => Ok(Value::Unknown),
+            },
+            "to_seconds" => match values.as_slice() {
+                [Value::Time(ms)] => Ok(Value::Num(*ms * Interval::point(0.001))),
+                _ => Ok(Value::Num(Interval::TOP)),
+            },
+            "from_seconds" => match values.as_slice() {
+                [Value::Num(secs)] => Ok(Value::Time(*secs * Interval::point(1000.))),
+                _ => Ok(Value::Time(Interval::TOP)),
+            },
+            "parse_duration" => match args {
+                [Expr {
+                    expr_kind: ExprKind::Lit(Lit(LitKind::Str(text))),
+                    ..
+                }] => Ok(parse_ms(text).map_or(Value::Time(Interval::TOP), |ms| {
+                    Value::Time(Interval::point(ms))
+                })),
+                _ => Ok(Value::Time(Interval::TOP)),
+            },
+            "format_duration" => Ok(Value::Unknown),
+            "random" => match values.as_slice() {
+                [Value::Num(min), Value::Num(max)] => Ok(Value::Num(Interval::new(min.lo, max.hi))),
+                _ => Ok(Value::Num(Interval::TOP)),
+            },
+            "random_time" => match values.as_slice() {
+                [Value::Time(min), Value::Time(max)] => {
+                    Ok(Value::Time(Interval::new(min.lo, max.hi)))
+                }
+                _ => Ok(Value::Time(Interval::TOP)),
+            },
+            "jitter" => match values.as_slice() {
+                [Value::Time(time), Value::Num(share)] => {
+                    let share = share.abs().hi;
+                    let offset = time.abs() * Interval::new(-share, share);
+                    Ok(Value::Time(*time + offset))
+                }
+                _ => Ok(Value::Time(Interval::TOP)),
+            },
+            "join" => {
+                let before = *waited;
+                *waited = match values.as_slice() {
+                    [Value::Task(Some(end))] => waited.max(end),
+                    _ => Interval::new(waited.lo, f32::INFINITY),
+                };
+
+                Ok(Value::Time(*waited - before))
+            }
+            "race" => match values.as_slice() {
+                [Value::Task(Some(a)), Value::Task(Some(b))] => {
+                    let first = a.min(b);
+                    *waited = waited.max(&first);
+
+                    Ok(Value::Task(Some(first)))
+                }
+                _ => {
+                    *waited = Interval::new(waited.lo, f32::INFINITY);
+                    Ok(Value::Task(None))
+                }
+            },
+            _ => {
+                error_at(ident.span, &format!("Unknown function `{}`", ident.name));
+                Err(())
+            }
+        }
+    }
+
+    /// Follows a call of a function value in the environment it was created in
+    fn call_value(
+        &mut self,
+        callee: &Value,
+        values: Vec<Value>,
+        span: Span,
+        waited: &mut Interval,
+    ) -> Result<Value, ()> {
+        // Without knowing which function runs, it could wait any time
+        let Value::Function(Some(index)) = callee else {
+            *waited = Interval::new(waited.lo, f32::INFINITY);
+            return Ok(Value::Unknown);
+        };
+
+        let (function, env) = self.functions[*index].clone();
+        let caller = self.env.enter(&env);
+        let pushes = self.pushes;
+
+        let result = self.call("the function", function, values, span, waited);
+        self.env.leave(caller);
+
+        // Lists are shared with the closure, so any of them could have grown
+        if self.pushes != pushes {
+            self.env.grow_lists(Elem::Unknown);
+        }
+
+        result
+    }
+
+    fn call(
+        &mut self,
+        name: &str,
+        function: Function<'a>,
+        values: Vec<Value>,
+        span: Span,
+        waited: &mut Interval,
+    ) -> Result<Value, ()> {
+        if function.args.len() != values.len() {
+            error_at(
+                span,
+                &format!(
+                    "`{name}` takes {} arguments but {} were given",
+                    function.args.len(),
+                    values.len()
+                ),
+            );
+            return Err(());
+        }
+
+        // Deep recursion could go on forever
+        if self.call_depth >= MAX_CALL_DEPTH {
+            *waited = Interval::new(waited.lo, f32::INFINITY);
+            return Ok(Value::Unknown);
+        }
+
+        self.env.push_scope();
+        for ((arg, _), value) in function.args.iter().zip(values) {
+            self.env.define_variable(arg, value);
+        }
+
+        let mut flow = Flow::new();
+        self.call_depth += 1;
+        let result = self.eval_body(function.body, &mut flow);
+        self.call_depth -= 1;
+        self.env.pop_scope();
+        result?;
+
+        *waited = *waited + flow.total().unwrap_or(Interval::ZERO);
+
+        Ok(flow.return_value.unwrap_or(Value::Unit))
+    }
+
+    /// Remembers a function used as a value with the environment it's created in
+    fn function_value(&mut self, function: Function<'a>) -> Value {
+        self.functions.push((function, self.env.clone()));
+
+        Value::Function(Some(self.functions.len() - 1))
+    }
+}
+
+/// What calling a method of the built-in value types gives
+fn method(receiver: &Value, name: &str, args: &[Value]) -> Value {
+    match (receiver, name, args) {
+        (Value::Time(ms), "as_seconds", []) => Value::Num(*ms * Interval::point(1. / 1000.)),
+        (Value::Time(ms), "as_minutes", []) => Value::Num(*ms * Interval::point(1. / 60_000.)),
+        (Value::Time(ms), "abs", []) => Value::Time(ms.abs()),
+        (Value::Num(num), "abs", []) => Value::Num(num.abs()),
+        // Rounding keeps the order, so the bounds can be rounded on their own
+        (Value::Time(ms), "round_to", [step]) => match step {
+            Value::Time(step) if step.is_point() && step.lo > 0. => {
+                Value::Time(ms.round_to(step.lo))
+            }
+            Value::Num(secs) if secs.is_point() && secs.lo > 0. => {
+                Value::Time(ms.round_to(secs.lo * 1000.))
+            }
+            _ => Value::Unknown,
+        },
+        (Value::Num(num), "round_to", [Value::Num(step)]) if step.is_point() && step.lo > 0. => {
+            Value::Num(num.round_to(step.lo))
+        }
+        (Value::List(len, _), "len", []) => Value::Num(*len),
+        // Every element is within the bounds of the elements, an empty list sums up to zero
+        (Value::List(len, Elem::Num(num)), "sum", []) => Value::Num(*len * *num),
+        (Value::List(len, Elem::Time(ms)), "sum", []) => Value::Time(*len * *ms),
+        (Value::List(_, elem), "min" | "max", []) => elem.value(),
+        _ => Value::Unknown,
+    }
+}
+
+/// Whether a value matches a pattern, `None` if it could go either way
+fn pattern_matches(value: &Value, pattern: &PatternKind) -> Option<bool> {
+    match pattern {
+        PatternKind::Wildcard => Some(true),
+        PatternKind::Lit(lit) => match (value, Value::from(lit.0)) {
+            (Value::Num(value), Value::Num(lit)) | (Value::Time(value), Value::Time(lit)) => {
+                value.eq(&lit)
+            }
+            (Value::Bool(Some(value)), Value::Bool(Some(lit))) => Some(*value == lit),
+            (Value::Enum(Some(value)), Value::Enum(Some(lit))) => Some(*value == lit),
+            _ => None,
+        },
+        PatternKind::Range {
+            start,
+            end,
+            inclusive,
+        } => {
+            let (value, start, end) = match (value, Value::from(start.0), Value::from(end.0)) {
+                (Value::Num(value), Value::Num(start), Value::Num(end))
+                | (Value::Time(value), Value::Time(start), Value::Time(end)) => (value, start, end),
+                _ => return None,
+            };
+
+            let below_end = match inclusive {
+                true => value.le(&end),
+                false => value.lt(&end),
+            };
+
+            match (start.le(value), below_end) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            }
+        }
+    }
+}
+
+pub fn bin_op(left: &Value, bin_op_kind: &BinOp, right: &Value) -> Value {
+    match bin_op_kind {
+        BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => {
+            let (left, right, is_time) = match (left, right) {
+                (Value::Num(left), Value::Num(right)) => (left, right, false),
+                // How many times one time fits into the other
+                (Value::Time(left), Value::Time(right)) if *bin_op_kind == BinOp::Div => {
+                    return Value::Num(left.div(right).unwrap_or(Interval::TOP));
+                }
+                (Value::Time(_), Value::Time(_)) if *bin_op_kind == BinOp::Mul => {
+                    return Value::Unknown;
+                }
+                (Value::Time(left), Value::Num(right) | Value::Time(right))
+                | (Value::Num(left), Value::Time(right)) => (left, right, true),
+                _ => return Value::Unknown,
+            };
+
+            let result = match bin_op_kind {
+                BinOp::Add => *left + *right,
+                BinOp::Sub => *left - *right,
+                BinOp::Mul => *left * *right,
+                BinOp::Div => left.div(right).unwrap_or(Interval::TOP),
+                BinOp::Mod => left.rem(right).unwrap_or(Interval::TOP),
+                _ => unreachable!(),
+            };
+
+            match is_time {
+                true => Value::Time(result),
+                false => Value::Num(result),
+            }
+        }
+        BinOp::EqEq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+            let (left, right) = match (left, right) {
+                (Value::Num(left), Value::Num(right)) | (Value::Time(left), Value::Time(right)) => {
+                    (left, right)
+                }
+                (Value::Bool(Some(left)), Value::Bool(Some(right)))
+                    if *bin_op_kind == BinOp::EqEq =>
+                {
+                    return Value::Bool(Some(left == right));
+                }
+                (Value::Bool(Some(left)), Value::Bool(Some(right)))
+                    if *bin_op_kind == BinOp::Ne =>
+                {
+                    return Value::Bool(Some(left != right));
+                }
+                (Value::Enum(Some(left)), Value::Enum(Some(right)))
+                    if *bin_op_kind == BinOp::EqEq =>
+                {
+                    return Value::Bool(Some(left == right));
+                }
+                (Value::Enum(Some(left)), Value::Enum(Some(right)))
+                    if *bin_op_kind == BinOp::Ne =>
+                {
+                    return Value::Bool(Some(left != right));
+                }
+                _ => return Value::Bool(None),
+            };
+
+            Value::Bool(match bin_op_kind {
+                BinOp::EqEq => left.eq(right),
+                BinOp::Ne => left.eq(right).map(|eq| !eq),
+                BinOp::Lt => left.lt(right),
+                BinOp::Le => left.le(right),
+                BinOp::Gt => right.lt(left),
+                BinOp::Ge => right.le(left),
+                _ => unreachable!(),
+            })
+        }
+        BinOp::And | BinOp::Or | BinOp::Xor => {
+            let (Value::Bool(left), Value::Bool(right)) = (left, right) else {
+                return Value::Bool(None);
+            };
+
+            Value::Bool(match (bin_op_kind, left, right) {
+                (BinOp::And, Some(false), _) | (BinOp::And, _, Some(false)) => Some(false),
+                (BinOp::Or, Some(true), _) | (BinOp::Or, _, Some(true)) => Some(true),
+                (BinOp::And, Some(true), Some(true)) => Some(true),
+                (BinOp::Or, Some(false), Some(false)) => Some(false),
+                (BinOp::Xor, Some(left), Some(right)) => Some(left ^ right),
+                _ => None,
+            })
+        }
+    }
+}
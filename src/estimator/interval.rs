@@ -0,0 +1,203 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// A closed range of numbers, the bounds may be infinite
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Interval {
+    pub lo: f32,
+    pub hi: f32,
+}
+
+impl Interval {
+    pub const ZERO: Self = Self { lo: 0., hi: 0. };
+
+    /// Every possible number
+    pub const TOP: Self = Self {
+        lo: f32::NEG_INFINITY,
+        hi: f32::INFINITY,
+    };
+
+    pub fn new(lo: f32, hi: f32) -> Self {
+        Self { lo, hi }
+    }
+
+    pub fn point(value: f32) -> Self {
+        Self::new(value, value)
+    }
+
+    pub fn is_point(&self) -> bool {
+        self.lo == self.hi
+    }
+
+    /// The smallest interval containing both intervals
+    pub fn join(&self, other: &Self) -> Self {
+        Self::new(self.lo.min(other.lo), self.hi.max(other.hi))
+    }
+
+    /// The smaller of two numbers from these intervals
+    pub fn min(&self, other: &Self) -> Self {
+        Self::new(self.lo.min(other.lo), self.hi.min(other.hi))
+    }
+
+    /// The larger of two numbers from these intervals
+    pub fn max(&self, other: &Self) -> Self {
+        Self::new(self.lo.max(other.lo), self.hi.max(other.hi))
+    }
+
+    /// Raises both bounds to at least `min`
+    pub fn clamp_min(&self, min: f32) -> Self {
+        Self::new(self.lo.max(min), self.hi.max(min))
+    }
+
+    /// The absolute values of every number in the interval
+    pub fn abs(&self) -> Self {
+        if self.lo >= 0. {
+            *self
+        } else if self.hi <= 0. {
+            -*self
+        } else {
+            Self::new(0., self.hi.max(-self.lo))
+        }
+    }
+
+    /// Rounds both bounds to the closest multiple of `step`, which is positive
+    pub fn round_to(&self, step: f32) -> Self {
+        Self::new(
+            (self.lo / step).round() * step,
+            (self.hi / step).round() * step,
+        )
+    }
+
+    /// `None` if the divisor could be zero
+    pub fn div(&self, other: &Self) -> Option<Self> {
+        if other.lo <= 0. && other.hi >= 0. {
+            return None;
+        }
+
+        Some(*self * Self::new(1. / other.hi, 1. / other.lo))
+    }
+
+    /// Only exact for single values, otherwise everything the remainder could be
+    pub fn rem(&self, other: &Self) -> Option<Self> {
+        if other.lo <= 0. && other.hi >= 0. {
+            return None;
+        }
+
+        if self.is_point() && other.is_point() {
+            return Some(Self::point(self.lo % other.lo));
+        }
+
+        let max = other.lo.abs().max(other.hi.abs());
+        Some(Self::new(
+            if self.lo < 0. { -max } else { 0. },
+            if self.hi > 0. { max } else { 0. },
+        ))
+    }
+
+    /// `Some(true)` if every value of `self` is less than every value of `other`
+    pub fn lt(&self, other: &Self) -> Option<bool> {
+        if self.hi < other.lo {
+            Some(true)
+        } else if self.lo >= other.hi {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// `Some(true)` if every value of `self` is less than or equal to every value of `other`
+    pub fn le(&self, other: &Self) -> Option<bool> {
+        if self.hi <= other.lo {
+            Some(true)
+        } else if self.lo > other.hi {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    pub fn eq(&self, other: &Self) -> Option<bool> {
+        if self.is_point() && other.is_point() && self.lo == other.lo {
+            Some(true)
+        } else if self.hi < other.lo || self.lo > other.hi {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+impl Add for Interval {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(self.lo + rhs.lo, self.hi + rhs.hi)
+    }
+}
+
+impl Sub for Interval {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self + -rhs
+    }
+}
+
+impl Mul for Interval {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        // 0 * ∞ is NaN, but a bound of zero times anything stays zero
+        let mul = |a: f32, b: f32| if a == 0. || b == 0. { 0. } else { a * b };
+        let products = [
+            mul(self.lo, rhs.lo),
+            mul(self.lo, rhs.hi),
+            mul(self.hi, rhs.lo),
+            mul(self.hi, rhs.hi),
+        ];
+
+        Self::new(
+            products.into_iter().fold(f32::INFINITY, f32::min),
+            products.into_iter().fold(f32::NEG_INFINITY, f32::max),
+        )
+    }
+}
+
+impl Neg for Interval {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self::new(-self.hi, -self.lo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic_works() {
+        let a = Interval::new(1., 2.);
+        let b = Interval::new(-3., 4.);
+
+        assert_eq!(a + b, Interval::new(-2., 6.));
+        assert_eq!(a - b, Interval::new(-3., 5.));
+        assert_eq!(a * b, Interval::new(-6., 8.));
+        assert_eq!(a.div(&b), None);
+        assert_eq!(b.div(&a), Some(Interval::new(-3., 4.)));
+        assert_eq!(
+            Interval::new(0., f32::INFINITY) * Interval::ZERO,
+            Interval::ZERO
+        );
+    }
+
+    #[test]
+    fn test_comparison_works() {
+        let a = Interval::new(1., 2.);
+
+        assert_eq!(a.lt(&Interval::new(3., 4.)), Some(true));
+        assert_eq!(a.lt(&Interval::point(1.)), Some(false));
+        assert_eq!(a.lt(&Interval::point(1.5)), None);
+        assert_eq!(a.eq(&Interval::point(3.)), Some(false));
+        assert_eq!(Interval::point(3.).eq(&Interval::point(3.)), Some(true));
+    }
+}
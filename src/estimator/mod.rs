@@ -0,0 +1,482 @@
+//! Computes bounds on how long a script waits in total, without running it.
+//!
+//! Values are tracked as intervals, so unknown inputs like the fields of
+//! `detect_user()` widen the bounds instead of stopping the estimation.
+//! Loops are unrolled while their condition is known and otherwise assumed
+//! to run any number of times.
+
+use self::{
+    env::{Env, Function},
+    interval::Interval,
+    value::Value,
+};
+use crate::parser::ast::Ast;
+pub use crate::runtime::prelude::format_ms;
+
+mod block;
+mod env;
+mod expr;
+pub mod interval;
+mod stmt;
+mod value;
+
+/// How many iterations of a loop with a known condition are followed before
+/// it's treated like a loop with an unknown number of iterations
+const MAX_UNROLLED_ITERATIONS: usize = 10_000;
+
+/// How deep function calls are followed before their result is unknown
+const MAX_CALL_DEPTH: usize = 64;
+
+pub struct Estimator<'a> {
+    env: Env<'a>,
+    call_depth: usize,
+    /// How many times something was added to a list, loops over lists that
+    /// grow while looping can't be unrolled
+    pushes: usize,
+    /// The functions and closures used as values with the environment they
+    /// were created in, a [`Value::Function`] refers to them by position
+    functions: Vec<(Function<'a>, Env<'a>)>,
+}
+
+/// The paths through a piece of code, split into those that continue with the
+/// next statement and those that already returned
+#[derive(Debug, Clone)]
+pub struct Flow {
+    /// Time waited on the paths that are still running, `None` if there are none
+    pub live: Option<Interval>,
+    /// Time waited on the paths that returned, `None` if there are none
+    pub returned: Option<Interval>,
+    /// The value returned on the paths that returned
+    pub return_value: Option<Value>,
+    /// Time waited on the paths that left a loop with `break` or `continue`
+    pub jumps: Vec<Jump>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum JumpKind {
+    Break,
+    Continue,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Jump {
+    pub kind: JumpKind,
+    /// The label of the loop it leaves, `None` for the innermost one
+    pub label: Option<&'static str>,
+    pub waited: Interval,
+}
+
+impl Flow {
+    pub fn new() -> Self {
+        Self {
+            live: Some(Interval::ZERO),
+            returned: None,
+            return_value: None,
+            jumps: Vec::new(),
+        }
+    }
+
+    /// Merges another way through the same code into this one
+    pub fn join(&mut self, other: &Self) {
+        self.live = join_intervals(self.live, other.live);
+        self.returned = join_intervals(self.returned, other.returned);
+        self.return_value = match (self.return_value, other.return_value) {
+            (Some(a), Some(b)) => Some(a.join(&b)),
+            (a, b) => a.or(b),
+        };
+
+        for jump in other.jumps.iter() {
+            self.add_jump(*jump);
+        }
+    }
+
+    /// Adds a path leaving a loop, joining it with others leaving the same way
+    pub fn add_jump(&mut self, jump: Jump) {
+        let same = self
+            .jumps
+            .iter_mut()
+            .find(|other| other.kind == jump.kind && other.label == jump.label);
+
+        match same {
+            Some(same) => same.waited = same.waited.join(&jump.waited),
+            None => self.jumps.push(jump),
+        }
+    }
+
+    /// Removes the paths that leave the loop with this label in the given way
+    /// and returns how long they waited
+    pub fn take_jumps(&mut self, kind: JumpKind, loop_label: Option<&str>) -> Option<Interval> {
+        let mut waited = None;
+
+        self.jumps.retain(|jump| {
+            let targets = jump.label.is_none() || jump.label == loop_label;
+            if jump.kind == kind && targets {
+                waited = join_intervals(waited, Some(jump.waited));
+            }
+
+            !(jump.kind == kind && targets)
+        });
+
+        waited
+    }
+
+    /// The time waited on any path
+    pub fn total(&self) -> Option<Interval> {
+        join_intervals(self.live, self.returned)
+    }
+
+    /// The time waited on any path, including the ones leaving a loop
+    pub fn bounds(&self) -> Option<Interval> {
+        self.jumps.iter().fold(self.total(), |bounds, jump| {
+            join_intervals(bounds, Some(jump.waited))
+        })
+    }
+
+    /// Drops the time past `max` from every path, which ends there at the latest
+    pub fn clip(&mut self, max: f32) {
+        let clip = |waited: Interval| Interval::new(waited.lo.min(max), waited.hi.min(max));
+
+        self.live = self.live.map(clip);
+        self.returned = self.returned.map(clip);
+        for jump in self.jumps.iter_mut() {
+            jump.waited = clip(jump.waited);
+        }
+    }
+}
+
+fn join_intervals(a: Option<Interval>, b: Option<Interval>) -> Option<Interval> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.join(&b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl<'a> Estimator<'a> {
+    pub fn new() -> Self {
+        Self {
+            env: Env::new(),
+            call_depth: 0,
+            pushes: 0,
+            functions: Vec::new(),
+        }
+    }
+
+    /// The range of milliseconds the script could wait for in total
+    pub fn estimate(&mut self, ast: &'a Ast) -> Result<Interval, ()> {
+        let mut flow = Flow::new();
+
+        for stmt in ast.program.iter() {
+            self.eval_stmt(stmt, &mut flow)?;
+        }
+
+        Ok(flow.total().unwrap_or(Interval::ZERO))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn estimate(code: &str) -> Interval {
+        let ast = Box::leak(Box::new(parse(code).unwrap()));
+        Estimator::new().estimate(ast).unwrap()
+    }
+
+    #[test]
+    fn test_straight_line_waits_add_up() {
+        assert_eq!(estimate("wait(30s); wait(1min);"), Interval::point(90_000.));
+        assert_eq!(
+            estimate("time t = 2s; wait(t * 3);"),
+            Interval::point(6000.)
+        );
+    }
+
+    #[test]
+    fn test_unknown_values_widen_bounds() {
+        let code = "
+            user a = detect_user();
+            wait(30s);
+            if (a.bpm > 120) { wait(15s); }
+            while (a.bpm > 120) { wait(15s); }
+        ";
+
+        assert_eq!(estimate(code), Interval::new(30_000., f32::INFINITY));
+    }
+
+    #[test]
+    fn test_known_loops_are_unrolled() {
+        assert_eq!(estimate("while (false) { wait(1s); }"), Interval::ZERO);
+        assert_eq!(
+            estimate("while (true) { wait(1ms); }"),
+            Interval::new(MAX_UNROLLED_ITERATIONS as f32, f32::INFINITY)
+        );
+    }
+
+    #[test]
+    fn test_assignments_are_tracked() {
+        assert_eq!(
+            estimate("num i = 0; while (i < 3) { wait(1s); i += 1; }"),
+            Interval::point(3000.)
+        );
+
+        // After a loop of unknown length the counter could be anything
+        let code = "
+            user u = detect_user();
+            num i = 0;
+            while (u == u) { i += 1; }
+            while (i > 0) { wait(1s); i -= 1; }
+        ";
+        assert_eq!(estimate(code).hi, f32::INFINITY);
+    }
+
+    #[test]
+    fn test_loops_can_be_left_early() {
+        assert_eq!(
+            estimate("while (true) { wait(1s); break; }"),
+            Interval::point(1000.)
+        );
+
+        let code = "
+            num tries = 0;
+            outer: while (tries < 5) {
+                tries += 1;
+                while (true) {
+                    wait(1s);
+                    if (tries < 3) { continue outer; }
+                    break outer;
+                }
+            }
+        ";
+        assert_eq!(estimate(code), Interval::point(3000.));
+    }
+
+    #[test]
+    fn test_clock_follows_the_waits() {
+        assert_eq!(
+            estimate("time start = now(); while (elapsed(start) < 10s) { wait(1s); }"),
+            Interval::point(10_000.)
+        );
+        assert_eq!(
+            estimate("wait(clamp(1h, 1s, 1min));"),
+            Interval::point(60_000.)
+        );
+        assert_eq!(
+            estimate("wait(parse_duration(\"1min 30s\"));"),
+            Interval::point(90_000.)
+        );
+    }
+
+    #[test]
+    fn test_randomness_widens_the_bounds() {
+        assert_eq!(
+            estimate("wait(random_time(1s, 5s));"),
+            Interval::new(1000., 5000.)
+        );
+        assert_eq!(
+            estimate("wait(jitter(10s, 10%));"),
+            Interval::new(9000., 11_000.)
+        );
+    }
+
+    #[test]
+    fn test_tasks_overlap() {
+        let code = "
+            task a = spawn { wait(5s); };
+            task b = spawn { wait(random_time(1s, 3s)); };
+            join(a);
+            join(b);
+        ";
+        assert_eq!(estimate(code), Interval::point(5000.));
+        assert_eq!(
+            estimate("task a = spawn { wait(5s); }; race(a, spawn { wait(2s); });"),
+            Interval::point(2000.)
+        );
+    }
+
+    #[test]
+    fn test_deadlines_bound_the_waits() {
+        assert_eq!(
+            estimate("within 10s { wait(1h); } else { wait(1s); }"),
+            Interval::point(11_000.)
+        );
+        assert_eq!(
+            estimate("within 10s { wait(5s); } else { wait(1h); }"),
+            Interval::point(5000.)
+        );
+        assert_eq!(
+            estimate("within 10s { wait(random_time(5s, 20s)); } else { wait(1s); }"),
+            Interval::new(5000., 11_000.)
+        );
+    }
+
+    #[test]
+    fn test_schedules_space_out_the_runs() {
+        assert_eq!(
+            estimate("every 10min times 6 { wait(1s); }"),
+            Interval::point(3_001_000.)
+        );
+        assert_eq!(
+            estimate("every 1min for 5min { wait(10s); }"),
+            Interval::point(250_000.)
+        );
+        assert_eq!(
+            estimate("every 1s times 3 { wait(5s); }"),
+            Interval::point(15_000.)
+        );
+        assert_eq!(
+            estimate("every \"0 9 * * *\" times 2 { wait(1s); }"),
+            Interval::new(2000., f32::INFINITY)
+        );
+    }
+
+    #[test]
+    fn test_retries_add_up_the_pauses() {
+        assert_eq!(estimate("retry { wait(1s); }"), Interval::new(1000., 3000.));
+        assert_eq!(
+            estimate("retry (attempts: 4, backoff: exponential(1s, max: 3s)) { wait(1s); false }"),
+            Interval::new(1000., 10_000.)
+        );
+        assert_eq!(
+            estimate("retry (attempts: 2, backoff: 10s, jitter: 50%) { attempt == 2 }"),
+            Interval::new(0., 15_000.)
+        );
+    }
+
+    #[test]
+    fn test_external_waits_last_up_to_the_timeout() {
+        let code = "
+            if (wait_for_port(\"127.0.0.1\", 8080, 1min)) { wait(10s); }
+            wait_for_file(\"/tmp/done\", 30s);
+        ";
+        assert_eq!(estimate(code), Interval::new(0., 100_000.));
+    }
+
+    #[test]
+    fn test_ranges_are_counted() {
+        assert_eq!(
+            estimate("for t in 0s..5min step 10s { wait(1s); }"),
+            Interval::point(30_000.)
+        );
+        assert_eq!(
+            estimate("for i in 1..=3 { wait(i); }"),
+            Interval::point(6000.)
+        );
+
+        // The end isn't known, but every iteration waits the same
+        let code = "
+            user u = detect_user();
+            num n = 2;
+            if (u == u) { n = 4; }
+            for i in 0..n { wait(1s); }
+        ";
+        assert_eq!(estimate(code), Interval::new(2000., 4000.));
+    }
+
+    #[test]
+    fn test_else_branches_are_followed() {
+        let code = "
+            num n = 2;
+            if (n > 2) { wait(1s); } else if (n == 2) { wait(2s); } else { wait(3s); }
+
+            user u = detect_user();
+            if (u == u) { wait(1s); } else if (n == 2) { wait(5s); }
+        ";
+
+        assert_eq!(estimate(code), Interval::new(3000., 7000.));
+    }
+
+    #[test]
+    fn test_if_values_are_joined() {
+        let code = "
+            user u = detect_user();
+            time t = if (u == u) { wait(1s); 2s } else { 4s };
+            wait(t);
+            wait(if (true) { 1s } else { 1min });
+        ";
+
+        assert_eq!(estimate(code), Interval::new(3000., 6000.));
+    }
+
+    #[test]
+    fn test_function_calls_are_followed() {
+        let code = "
+            func backoff(n: num) -> time {
+                if (n > 0) {
+                    wait(1s);
+                    return backoff(n - 1);
+                }
+
+                return 0s;
+            }
+
+            backoff(3);
+        ";
+
+        assert_eq!(estimate(code), Interval::point(3000.));
+    }
+
+    #[test]
+    fn test_lists_are_followed() {
+        assert_eq!(
+            estimate("for d in [1s, 2s, 4s] { wait(d); }"),
+            Interval::point(7000.)
+        );
+        assert_eq!(
+            estimate("[time] ds = [1s, 2s]; ds.push(3s); for d in ds { wait(d); }"),
+            Interval::new(3000., 9000.)
+        );
+        assert_eq!(
+            estimate("[time] ds = [1s]; for d in ds { ds.push(d); wait(d); }"),
+            Interval::new(0., f32::INFINITY)
+        );
+    }
+
+    #[test]
+    fn test_matches_are_followed() {
+        let code = "
+            enum State { Pending, Done }
+            State s = State::Done;
+            match s { State::Pending => wait(1min), State::Done => wait(1s) }
+        ";
+        assert_eq!(estimate(code), Interval::point(1000.));
+
+        let code = "
+            user u = detect_user();
+            match u.age { 0..18 => wait(1s), _ if u.age > 60 => wait(3s), _ => wait(2s) }
+        ";
+        assert_eq!(estimate(code), Interval::new(1000., 3000.));
+        assert_eq!(
+            estimate("time t = match 90s { 0s..1min => 1s, _ => 5s }; wait(t);"),
+            Interval::point(5000.)
+        );
+    }
+
+    #[test]
+    fn test_closures_are_followed() {
+        let code = "
+            func scaler(factor: num) -> |time| -> time {
+                return |t: time| -> time { t * factor };
+            }
+            |time| -> time double = scaler(2);
+            wait(double(1s));
+            wait(|t: time| { t + 1s }(1s));
+        ";
+        assert_eq!(estimate(code), Interval::point(4000.));
+
+        // Which closure runs isn't known, so neither is how long it waits
+        let code = "
+            user u = detect_user();
+            || -> time pick = if (u.bpm > 120) { || -> time { 1s } } else { || -> time { 2s } };
+            wait(pick());
+        ";
+        assert_eq!(estimate(code), Interval::new(0., f32::INFINITY));
+    }
+
+    #[test]
+    fn test_format_ms_works() {
+        assert_eq!(format_ms(0.), "0s");
+        assert_eq!(format_ms(90_500.), "1min 30s 500ms");
+        assert_eq!(format_ms(f32::INFINITY), "unbounded");
+    }
+}
@@ -0,0 +1,695 @@
+use super::{
+    env::{Env, Function},
+    expr::bin_op,
+    interval::Interval,
+    join_intervals,
+    value::Value,
+    Estimator, Flow, Jump, JumpKind, MAX_UNROLLED_ITERATIONS,
+};
+use crate::parser::ast::{
+    block::Block,
+    expr::{Expr, ExprKind, Ident},
+    stmt::{BackoffKind, Else, Stmt, StmtKind},
+};
+use std::mem;
+
+impl<'a> Estimator<'a> {
+    pub fn eval_stmt(&mut self, stmt: &'a Stmt, flow: &mut Flow) -> Result<(), ()> {
+        // Nothing after a `return` runs
+        let Some(mut waited) = flow.live else {
+            return Ok(());
+        };
+
+        match &stmt.stmt_kind {
+            StmtKind::VarBind {
+                identifier, value, ..
+            } => {
+                let value = self.eval_expr(value, &mut waited)?;
+                self.env.define_variable(identifier, value);
+                flow.live = Some(waited);
+            }
+            StmtKind::Assign { target, op, value } => {
+                let mut value = self.eval_expr(value, &mut waited)?;
+
+                if let Some(op) = op {
+                    let current = self.env.get_variable(target).unwrap_or(Value::Unknown);
+                    value = bin_op(&current, op, &value);
+                }
+
+                self.env.assign(target, value);
+                flow.live = Some(waited);
+            }
+            StmtKind::FieldAssign {
+                receiver, value, ..
+            } => {
+                self.eval_expr(receiver, &mut waited)?;
+                self.eval_expr(value, &mut waited)?;
+                flow.live = Some(waited);
+            }
+            StmtKind::FnDef {
+                ident, args, body, ..
+            } => {
+                self.env.define_function(ident, Function { args, body });
+            }
+            StmtKind::Expr(expr) => {
+                self.eval_expr(expr, &mut waited)?;
+                flow.live = Some(waited);
+            }
+            StmtKind::If(condition, true_block, else_) => {
+                let condition = self.eval_expr(condition, &mut waited)?;
+                flow.live = Some(waited);
+
+                match condition {
+                    Value::Bool(Some(true)) => self.eval_block(true_block, flow)?,
+                    Value::Bool(Some(false)) => self.eval_else(else_, flow)?,
+                    _ => {
+                        let mut else_flow = flow.clone();
+                        let else_env = self.env.clone();
+
+                        self.eval_block(true_block, flow)?;
+                        let true_env = mem::replace(&mut self.env, else_env);
+
+                        self.eval_else(else_, &mut else_flow)?;
+
+                        flow.join(&else_flow);
+                        self.env.join(&true_env);
+                    }
+                }
+            }
+            StmtKind::Match(value, arms) => {
+                let value = self.eval_expr(value, &mut waited)?;
+                flow.live = Some(waited);
+
+                let mut joined: Option<(Flow, Env<'a>)> = None;
+                for (block, arm_waited, env) in self.arms_to_run(&value, arms, waited)? {
+                    let mut arm_flow = flow.clone();
+                    arm_flow.live = Some(arm_waited);
+                    self.env = env;
+                    self.eval_block(block, &mut arm_flow)?;
+
+                    joined = Some(match joined {
+                        Some((mut other_flow, mut other_env)) => {
+                            other_flow.join(&arm_flow);
+                            other_env.join(&self.env);
+                            (other_flow, other_env)
+                        }
+                        None => (arm_flow, self.env.clone()),
+                    });
+                }
+
+                if let Some((arm_flow, env)) = joined {
+                    *flow = arm_flow;
+                    self.env = env;
+                }
+            }
+            // Only runs when the script is interrupted
+            StmtKind::OnInterrupt(_) => {}
+            StmtKind::Within(time, block, fallback) => {
+                let limit = self.eval_expr(time, &mut waited)?.as_wait();
+                let deadline = waited + limit;
+                flow.live = Some(waited);
+
+                let mut cut = flow.clone();
+                let env = self.env.clone();
+                self.eval_block(block, flow)?;
+
+                let Some(bounds) = flow.bounds() else {
+                    return Ok(());
+                };
+                if bounds.hi <= deadline.lo {
+                    return Ok(());
+                }
+
+                // The block could be cut short anywhere, the fallback runs from the deadline on
+                let finished_env = mem::replace(&mut self.env, env);
+                self.env.join(&finished_env);
+                cut.live = Some(deadline);
+                if let Some(fallback) = fallback {
+                    self.eval_block(fallback, &mut cut)?;
+                }
+
+                if bounds.lo > deadline.hi {
+                    *flow = cut;
+                } else {
+                    flow.clip(deadline.hi);
+                    flow.join(&cut);
+                    self.env.join(&finished_env);
+                }
+            }
+            StmtKind::While(condition, block, label) => {
+                let label = label.as_ref().map(|label| label.name);
+                self.eval_while(condition, block, label, flow)?
+            }
+            StmtKind::For {
+                var,
+                range,
+                block,
+                label,
+            } => {
+                let start = self.eval_expr(&range.start, &mut waited)?;
+                let end = self.eval_expr(&range.end, &mut waited)?;
+                let step = match &range.step {
+                    Some(step) => self.eval_expr(step, &mut waited)?,
+                    None => match start {
+                        Value::Time(_) => Value::Time(Interval::point(1000.)),
+                        _ => Value::Num(Interval::point(1.)),
+                    },
+                };
+                flow.live = Some(waited);
+
+                let label = label.as_ref().map(|label| label.name);
+                let outer_jumps = mem::take(&mut flow.jumps);
+                let result =
+                    self.eval_for(var, (start, end, step), range.inclusive, block, label, flow);
+
+                for jump in outer_jumps {
+                    flow.add_jump(jump);
+                }
+                result?;
+            }
+            StmtKind::Retry(options, block) => {
+                let mut option = |expr: Option<&'a Expr>, default: Value| match expr {
+                    Some(expr) => self.eval_expr(expr, &mut waited),
+                    None => Ok(default),
+                };
+                let backoff = options.backoff.as_ref();
+
+                let attempts = option(options.attempts.as_ref(), Value::Num(Interval::point(3.)))?;
+                let delay = option(
+                    backoff.map(|backoff| &backoff.delay),
+                    Value::Time(Interval::ZERO),
+                )?;
+                let max = option(
+                    backoff.and_then(|backoff| backoff.max.as_ref()),
+                    Value::Time(Interval::point(f32::INFINITY)),
+                )?;
+                let jitter = option(options.jitter.as_ref(), Value::Num(Interval::ZERO))?;
+                flow.live = Some(waited);
+
+                let kind = backoff.map_or(BackoffKind::Constant, |backoff| backoff.kind);
+                self.eval_retry((attempts, delay, max, jitter), kind, block, flow)?;
+            }
+            StmtKind::Every {
+                schedule,
+                times,
+                duration,
+                block,
+                label,
+            } => {
+                let every = self.eval_expr(schedule, &mut waited)?;
+                let times = match times {
+                    Some(times) => self.eval_expr(times, &mut waited)?,
+                    None => Value::Num(Interval::point(f32::INFINITY)),
+                };
+                let duration = match duration {
+                    Some(duration) => Some(self.eval_expr(duration, &mut waited)?),
+                    None => None,
+                };
+                flow.live = Some(waited);
+
+                let label = label.as_ref().map(|label| label.name);
+                let outer_jumps = mem::take(&mut flow.jumps);
+                let result = self.eval_every((every, times, duration), block, label, flow);
+
+                for jump in outer_jumps {
+                    flow.add_jump(jump);
+                }
+                result?;
+            }
+            StmtKind::ForEach {
+                var,
+                list,
+                block,
+                label,
+            } => {
+                // The elements of a list written in the loop are known one by one
+                let (list, elems) = match &list.expr_kind {
+                    ExprKind::List(elems) => {
+                        let mut values = Vec::with_capacity(elems.len());
+                        for elem in elems {
+                            values.push(self.eval_expr(elem, &mut waited)?);
+                        }
+
+                        (Value::list(&values), Some(values))
+                    }
+                    _ => (self.eval_expr(list, &mut waited)?, None),
+                };
+                flow.live = Some(waited);
+
+                let label = label.as_ref().map(|label| label.name);
+                let outer_jumps = mem::take(&mut flow.jumps);
+                let result = self.eval_for_each(var, list, elems, block, label, flow);
+
+                for jump in outer_jumps {
+                    flow.add_jump(jump);
+                }
+                result?;
+            }
+            StmtKind::Return(expr) => {
+                let value = self.eval_expr(expr, &mut waited)?;
+
+                flow.join(&Flow {
+                    live: None,
+                    returned: Some(waited),
+                    return_value: Some(value),
+                    jumps: Vec::new(),
+                });
+                flow.live = None;
+            }
+            StmtKind::Break(label) => {
+                flow.add_jump(Jump {
+                    kind: JumpKind::Break,
+                    label: label.as_ref().map(|label| label.name),
+                    waited,
+                });
+                flow.live = None;
+            }
+            StmtKind::Continue(label) => {
+                flow.add_jump(Jump {
+                    kind: JumpKind::Continue,
+                    label: label.as_ref().map(|label| label.name),
+                    waited,
+                });
+                flow.live = None;
+            }
+            StmtKind::Module(module) => {
+                for stmt in module.program.iter() {
+                    self.eval_stmt(stmt, flow)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn eval_else(&mut self, else_: &'a Option<Else>, flow: &mut Flow) -> Result<(), ()> {
+        match else_ {
+            Some(Else::Block(block)) => self.eval_block(block, flow),
+            Some(Else::If(stmt)) => self.eval_stmt(stmt, flow),
+            None => Ok(()),
+        }
+    }
+
+    fn eval_while(
+        &mut self,
+        condition: &'a Expr,
+        block: &'a Block,
+        label: Option<&'static str>,
+        flow: &mut Flow,
+    ) -> Result<(), ()> {
+        // Paths that left an outer loop before this one don't belong to it
+        let outer_jumps = mem::take(&mut flow.jumps);
+        let result = self.eval_loop(condition, block, label, flow);
+
+        for jump in outer_jumps {
+            flow.add_jump(jump);
+        }
+
+        result
+    }
+
+    fn eval_loop(
+        &mut self,
+        condition: &'a Expr,
+        block: &'a Block,
+        label: Option<&'static str>,
+        flow: &mut Flow,
+    ) -> Result<(), ()> {
+        // Time waited on the paths that left the loop with `break`
+        let mut exited = None;
+        let mut endless = true;
+
+        for _ in 0..MAX_UNROLLED_ITERATIONS {
+            let Some(mut waited) = flow.live else {
+                flow.live = exited;
+                return Ok(());
+            };
+
+            let condition = self.eval_expr(condition, &mut waited)?;
+            flow.live = Some(waited);
+
+            match condition {
+                Value::Bool(Some(false)) => {
+                    flow.live = join_intervals(flow.live, exited);
+                    return Ok(());
+                }
+                Value::Bool(Some(true)) => {
+                    self.eval_block(block, flow)?;
+
+                    exited = join_intervals(exited, flow.take_jumps(JumpKind::Break, label));
+                    let continued = flow.take_jumps(JumpKind::Continue, label);
+                    flow.live = join_intervals(flow.live, continued);
+                }
+                _ => {
+                    endless = false;
+                    break;
+                }
+            }
+        }
+
+        // From here on the loop could run any number of times
+        let Some(waited) = flow.live else {
+            flow.live = exited;
+            return Ok(());
+        };
+
+        let mut iteration = self.eval_any_iteration(block, None, waited)?;
+
+        let breaks = iteration.take_jumps(JumpKind::Break, label);
+        let continues = iteration.take_jumps(JumpKind::Continue, label);
+        let next = join_intervals(iteration.live, continues);
+
+        // A loop that waits in its body can wait for any amount of time
+        let can_wait = endless || next.is_some_and(|live| live.hi > waited.hi);
+        let widen = |interval: Interval| match can_wait {
+            true => Interval::new(interval.lo, f32::INFINITY),
+            false => interval,
+        };
+
+        let live = Some(waited.join(&widen(next.unwrap_or(waited))));
+        flow.live = join_intervals(join_intervals(live, exited), breaks.map(widen));
+
+        iteration.live = None;
+        iteration.returned = iteration.returned.map(widen);
+        for jump in iteration.jumps.iter_mut() {
+            jump.waited = widen(jump.waited);
+        }
+        flow.join(&iteration);
+
+        Ok(())
+    }
+
+    fn eval_for(
+        &mut self,
+        var: &'a Ident,
+        (start, end, step): (Value, Value, Value),
+        inclusive: bool,
+        block: &'a Block,
+        label: Option<&'static str>,
+        flow: &mut Flow,
+    ) -> Result<(), ()> {
+        let (start, end, step, make): (_, _, _, fn(Interval) -> Value) = match (start, end, step) {
+            (Value::Num(start), Value::Num(end), Value::Num(step)) => {
+                (start, end, step, Value::Num)
+            }
+            (Value::Time(start), Value::Time(end), Value::Time(step)) => {
+                (start, end, step, Value::Time)
+            }
+            // Not a range that runs, or nothing is known about it
+            _ => (Interval::TOP, Interval::TOP, Interval::TOP, |_| {
+                Value::Unknown
+            }),
+        };
+
+        // How many values the range has
+        let count = |steps: f32| match inclusive {
+            true => (steps.floor() + 1.).max(0.),
+            false => steps.ceil().max(0.),
+        };
+        let iterations = match (end - start).div(&step) {
+            Some(steps) if step.lo > 0. => Interval::new(count(steps.lo), count(steps.hi)),
+            _ => Interval::new(0., f32::INFINITY),
+        };
+
+        let values = (start.is_point() && step.is_point()).then_some(move |index: usize| {
+            make(Interval::point(start.lo + index as f32 * step.lo))
+        });
+        let any = make(Interval::new(start.lo, end.hi));
+
+        self.eval_iterations(var, (iterations, values, any), block, label, flow)
+    }
+
+    /// Runs are due an interval apart, or right after the one before if that
+    /// took longer, a cron expression could be due at any time
+    fn eval_every(
+        &mut self,
+        (every, times, duration): (Value, Value, Option<Value>),
+        block: &'a Block,
+        label: Option<&'static str>,
+        flow: &mut Flow,
+    ) -> Result<(), ()> {
+        let Some(waited) = flow.live else {
+            return Ok(());
+        };
+
+        let (interval, first) = match every {
+            Value::Time(_) | Value::Num(_) => (every.as_wait(), Interval::ZERO),
+            _ => (
+                Interval::new(0., f32::INFINITY),
+                Interval::new(0., f32::INFINITY),
+            ),
+        };
+
+        let mut runs = match times {
+            Value::Num(times) => Interval::new(times.lo.floor(), times.hi.floor()).clamp_min(0.),
+            _ => Interval::new(0., f32::INFINITY),
+        };
+        // Runs start until the time is up
+        if let Some(duration) = duration {
+            let due = match duration.as_wait().div(&interval) {
+                Some(steps) if interval.lo > 0. => Interval::new(steps.lo.ceil(), steps.hi.ceil()),
+                _ => Interval::new(0., f32::INFINITY),
+            };
+            runs = runs.min(&due);
+        }
+
+        let mut iteration = self.eval_any_iteration(block, None, Interval::ZERO)?;
+        let breaks = iteration.take_jumps(JumpKind::Break, label);
+        let continues = iteration.take_jumps(JumpKind::Continue, label);
+        let body = join_intervals(iteration.live, continues);
+        let gap = body.map_or(interval, |body| body.max(&interval));
+
+        // Every run but the last is followed by the gap to the next one
+        let before = Interval::new(0., (runs.hi - 1.).max(0.)) * gap;
+        let live = match body {
+            Some(body) if runs.hi >= 1. => {
+                let all = Interval::new((runs.lo - 1.).max(0.), runs.hi - 1.);
+                let ran = waited + first + all * gap + body;
+
+                Some(match runs.lo {
+                    0. => ran.join(&waited),
+                    _ => ran,
+                })
+            }
+            _ if runs.lo == 0. => Some(waited),
+            _ => None,
+        };
+        let shift = |interval: Interval| waited + first + before + interval;
+
+        flow.live = join_intervals(live, breaks.map(shift));
+
+        iteration.live = None;
+        iteration.returned = iteration.returned.map(shift);
+        for jump in iteration.jumps.iter_mut() {
+            jump.waited = shift(jump.waited);
+        }
+        flow.join(&iteration);
+
+        Ok(())
+    }
+
+    fn eval_retry(
+        &mut self,
+        (attempts, delay, max, jitter): (Value, Value, Value, Value),
+        backoff: BackoffKind,
+        block: &'a Block,
+        flow: &mut Flow,
+    ) -> Result<(), ()> {
+        let Some(waited) = flow.live else {
+            return Ok(());
+        };
+
+        let attempts = match attempts {
+            Value::Num(attempts) => attempts.hi.floor().max(1.),
+            _ => f32::INFINITY,
+        };
+
+        self.env.push_scope();
+        self.env.define_variable(
+            &Ident::new("attempt", block.span),
+            Value::Num(Interval::new(1., attempts)),
+        );
+        let result = self.eval_any_iteration(block, None, Interval::ZERO);
+        self.env.pop_scope();
+
+        // The first attempt could succeed, or every one fails after the longest pauses
+        let Some(body) = result?.live else {
+            flow.live = None;
+            return Ok(());
+        };
+
+        let (delay, max) = (delay.as_wait().hi, max.as_wait().hi);
+        let pauses = match attempts {
+            attempts if attempts > MAX_UNROLLED_ITERATIONS as f32 => f32::INFINITY,
+            attempts => (1..attempts as u32)
+                .map(|failed| {
+                    let failed = failed as f32;
+                    match backoff {
+                        BackoffKind::Constant => delay,
+                        BackoffKind::Linear => delay * failed,
+                        BackoffKind::Exponential => delay * 2f32.powf(failed - 1.),
+                    }
+                    .min(max)
+                })
+                .sum(),
+        };
+        let jitter = match jitter {
+            Value::Num(share) => share.abs().hi,
+            _ => f32::INFINITY,
+        };
+
+        let tries = Interval::new(1., attempts) * body;
+        flow.live = Some(waited + tries + Interval::new(0., pauses) * Interval::point(1. + jitter));
+
+        Ok(())
+    }
+
+    fn eval_for_each(
+        &mut self,
+        var: &'a Ident,
+        list: Value,
+        elems: Option<Vec<Value>>,
+        block: &'a Block,
+        label: Option<&'static str>,
+        flow: &mut Flow,
+    ) -> Result<(), ()> {
+        let (iterations, any) = match list {
+            Value::List(len, elem) => (len, elem.value()),
+            _ => (Interval::new(0., f32::INFINITY), Value::Unknown),
+        };
+
+        let env = self.env.clone();
+        let before = flow.clone();
+        let pushes = self.pushes;
+
+        let values = |index: usize| match &elems {
+            Some(elems) => elems[index],
+            None => any,
+        };
+        self.eval_iterations(var, (iterations, Some(values), any), block, label, flow)?;
+
+        // Elements added while looping are visited too, so it could go on for any number of them
+        if self.pushes != pushes && iterations.hi != f32::INFINITY {
+            self.env = env;
+            *flow = before;
+
+            let iterations = Interval::new(iterations.lo, f32::INFINITY);
+            let values = None::<fn(usize) -> Value>;
+            self.eval_iterations(
+                var,
+                (iterations, values, Value::Unknown),
+                block,
+                label,
+                flow,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs the body of a loop with the loop variable set to `values(index)`
+    /// if the number of iterations is known, otherwise to `any`
+    fn eval_iterations(
+        &mut self,
+        var: &'a Ident,
+        (iterations, values, any): (Interval, Option<impl Fn(usize) -> Value>, Value),
+        block: &'a Block,
+        label: Option<&'static str>,
+        flow: &mut Flow,
+    ) -> Result<(), ()> {
+        let Some(waited) = flow.live else {
+            return Ok(());
+        };
+
+        if let Some(values) = values
+            && iterations.is_point()
+            && iterations.hi <= MAX_UNROLLED_ITERATIONS as f32
+        {
+            // Time waited on the paths that left the loop with `break`
+            let mut exited = None;
+
+            for index in 0..iterations.hi as usize {
+                if flow.live.is_none() {
+                    break;
+                }
+
+                self.env.push_scope();
+                self.env.define_variable(var, values(index));
+                let result = self.eval_block(block, flow);
+                self.env.pop_scope();
+                result?;
+
+                exited = join_intervals(exited, flow.take_jumps(JumpKind::Break, label));
+                let continued = flow.take_jumps(JumpKind::Continue, label);
+                flow.live = join_intervals(flow.live, continued);
+            }
+
+            flow.live = join_intervals(flow.live, exited);
+            return Ok(());
+        }
+
+        // One iteration with the loop variable being any of the values stands for all of them
+        let mut iteration = self.eval_any_iteration(block, Some((var, any)), Interval::ZERO)?;
+        let breaks = iteration.take_jumps(JumpKind::Break, label);
+        let continues = iteration.take_jumps(JumpKind::Continue, label);
+        let each = join_intervals(iteration.live, continues);
+
+        let live = match each {
+            Some(each) => Some(waited + iterations * each),
+            None if iterations.lo == 0. => Some(waited),
+            None => None,
+        };
+
+        // Paths leaving the loop could do so after any of the earlier iterations
+        let before = match each {
+            Some(each) => Interval::new(0., (iterations.hi - 1.).max(0.)) * each,
+            None => Interval::ZERO,
+        };
+        let shift = |interval: Interval| waited + before + interval;
+
+        flow.live = join_intervals(live, breaks.map(shift));
+
+        iteration.live = None;
+        iteration.returned = iteration.returned.map(shift);
+        for jump in iteration.jumps.iter_mut() {
+            jump.waited = shift(jump.waited);
+        }
+        flow.join(&iteration);
+
+        Ok(())
+    }
+
+    /// Runs the body of a loop once starting from `waited`, with every variable
+    /// the body changes unknown, so the iteration stands for any of them
+    fn eval_any_iteration(
+        &mut self,
+        block: &'a Block,
+        var: Option<(&'a Ident, Value)>,
+        waited: Interval,
+    ) -> Result<Flow, ()> {
+        // Iterate until the environment at the start of an iteration stops changing
+        loop {
+            let mut iteration = Flow {
+                live: Some(waited),
+                returned: None,
+                return_value: None,
+                jumps: Vec::new(),
+            };
+            let env = self.env.clone();
+
+            self.env.push_scope();
+            if let Some((var, value)) = var {
+                self.env.define_variable(var, value);
+            }
+            let result = self.eval_block(block, &mut iteration);
+            self.env.pop_scope();
+            result?;
+
+            self.env.join(&env);
+
+            if !self.env.widen(&env) {
+                return Ok(iteration);
+            }
+        }
+    }
+}
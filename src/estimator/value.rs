@@ -0,0 +1,134 @@
+use super::interval::Interval;
+use crate::parser::ast::lit::LitKind;
+
+/// Everything the estimator knows about a value
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Value {
+    /// Number in the given range
+    Num(Interval),
+    /// Time in the given range of milliseconds
+    Time(Interval),
+    /// Boolean, `None` if it could be either
+    Bool(Option<bool>),
+    /// A user returned by `detect_user()`, all of its fields are unknown
+    User,
+    /// Variant of an enum by its position, `None` if it could be any
+    Enum(Option<usize>),
+    /// A list with a length in the given range, whose elements are all described by the [`Elem`]
+    List(Interval, Elem),
+    /// A function or closure by its position in the ones created so far,
+    /// `None` if it could be any
+    Function(Option<usize>),
+    /// A task started with `spawn` by the time it finishes at in milliseconds
+    /// since the start, `None` if that isn't known
+    Task(Option<Interval>),
+    /// Nothing, returned by builtins like `wait`
+    Unit,
+    /// Could be anything
+    Unknown,
+}
+
+impl Value {
+    /// The smallest value describing both values
+    pub fn join(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Value::Num(a), Value::Num(b)) => Value::Num(a.join(b)),
+            (Value::Time(a), Value::Time(b)) => Value::Time(a.join(b)),
+            (Value::Bool(a), Value::Bool(b)) => Value::Bool(if a == b { *a } else { None }),
+            (Value::User, Value::User) => Value::User,
+            (Value::Enum(a), Value::Enum(b)) => Value::Enum(if a == b { *a } else { None }),
+            (Value::List(a, a_elem), Value::List(b, b_elem)) => {
+                Value::List(a.join(b), a_elem.join(b_elem))
+            }
+            (Value::Function(a), Value::Function(b)) => {
+                Value::Function(if a == b { *a } else { None })
+            }
+            (Value::Task(Some(a)), Value::Task(Some(b))) => Value::Task(Some(a.join(b))),
+            (Value::Task(_), Value::Task(_)) => Value::Task(None),
+            (Value::Unit, Value::Unit) => Value::Unit,
+            _ => Value::Unknown,
+        }
+    }
+
+    /// A list with exactly these elements
+    pub fn list(elems: &[Value]) -> Self {
+        let elem = elems
+            .iter()
+            .fold(Elem::Nothing, |elem, value| elem.join(&Elem::from(*value)));
+
+        Value::List(Interval::point(elems.len() as f32), elem)
+    }
+
+    /// How long `wait` waits when it is passed this value, in milliseconds
+    ///
+    /// Plain numbers are seconds and negative times don't wait at all.
+    pub fn as_wait(&self) -> Interval {
+        let ms = match self {
+            Value::Time(ms) => *ms,
+            Value::Num(secs) => *secs * Interval::point(1000.),
+            _ => Interval::TOP,
+        };
+
+        ms.clamp_min(0.)
+    }
+}
+
+impl From<LitKind> for Value {
+    fn from(value: LitKind) -> Self {
+        match value {
+            LitKind::Num(num) => Value::Num(Interval::point(num)),
+            LitKind::Time(time, time_kind) => {
+                Value::Time(Interval::point(time * time_kind.as_ms()))
+            }
+            LitKind::Bool(bool) => Value::Bool(Some(bool)),
+            LitKind::Enum(_, variant) => Value::Enum(Some(variant)),
+            LitKind::Task(_) => Value::Task(None),
+            LitKind::Struct(..)
+            | LitKind::List(..)
+            | LitKind::Function(..)
+            | LitKind::Str(_)
+            | LitKind::Timestamp(_) => Value::Unknown,
+        }
+    }
+}
+
+/// Everything the estimator knows about the elements of a list, which can't
+/// be lists themselves
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Elem {
+    /// The list is empty
+    Nothing,
+    Num(Interval),
+    Time(Interval),
+    Unknown,
+}
+
+impl Elem {
+    pub fn join(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Elem::Nothing, elem) | (elem, Elem::Nothing) => *elem,
+            (Elem::Num(a), Elem::Num(b)) => Elem::Num(a.join(b)),
+            (Elem::Time(a), Elem::Time(b)) => Elem::Time(a.join(b)),
+            _ => Elem::Unknown,
+        }
+    }
+
+    /// What any of the elements could be
+    pub fn value(&self) -> Value {
+        match self {
+            Elem::Num(num) => Value::Num(*num),
+            Elem::Time(ms) => Value::Time(*ms),
+            Elem::Nothing | Elem::Unknown => Value::Unknown,
+        }
+    }
+}
+
+impl From<Value> for Elem {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Num(num) => Elem::Num(num),
+            Value::Time(ms) => Elem::Time(ms),
+            _ => Elem::Unknown,
+        }
+    }
+}
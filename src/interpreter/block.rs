@@ -1,22 +1,69 @@
-use super::Interpreter;
+use super::{Interpreter, Unwind};
 use crate::parser::ast::{block::Block, lit::Lit};
 
 impl Interpreter {
-    pub fn eval_block(&self, block: Box<Block>, in_function: bool) -> Result<Option<Lit>, ()> {
+    pub fn eval_block(&self, block: Box<Block>, in_function: bool) -> Result<Option<Unwind>, ()> {
         let block = *block;
 
+        self.stack.borrow_mut().push_scope();
+        let result = self.eval_stmts(&block, in_function).and_then(|unwind| {
+            // The value of a block that isn't used as one is thrown away
+            if unwind.is_none()
+                && let Some(value) = &block.value
+            {
+                self.eval_expr(value)?;
+            }
+
+            Ok(unwind)
+        });
+        self.stack.borrow_mut().pop_scope();
+
+        result
+    }
+
+    /// Runs a block used as a value, which the resolver made sure runs to its end
+    pub fn eval_block_value(&self, block: &Block) -> Result<Lit, ()> {
+        self.stack.borrow_mut().push_scope();
+        let result = self
+            .eval_stmts(block, false)
+            .and_then(|_| match &block.value {
+                Some(value) => self.eval_expr(value),
+                None => Err(()),
+            });
+        self.stack.borrow_mut().pop_scope();
+
+        result
+    }
+
+    /// Runs the body of a function, whose value is the one it returns or
+    /// otherwise the one it ends with
+    pub fn eval_body(&self, block: &Block) -> Result<Option<Lit>, ()> {
+        self.stack.borrow_mut().push_scope();
+        let result = self
+            .eval_stmts(block, true)
+            .and_then(|unwind| match unwind {
+                Some(Unwind::Return(value)) => Ok(Some(value)),
+                Some(_) => Ok(None),
+                None => block
+                    .value
+                    .as_ref()
+                    .map(|value| self.eval_expr(value))
+                    .transpose(),
+            });
+        self.stack.borrow_mut().pop_scope();
+
+        result
+    }
+
+    fn eval_stmts(&self, block: &Block, in_function: bool) -> Result<Option<Unwind>, ()> {
         for stmt in block.stmts.iter() {
-            let stmt_value = self.eval_stmt(stmt.clone(), in_function)?;
+            let unwind = self.eval_stmt(stmt.clone(), in_function)?;
 
-            if let Some(stmt_value) = stmt_value {
-                return Ok(Some(stmt_value));
+            if unwind.is_some() {
+                return Ok(unwind);
             }
         }
 
-        if in_function {
-            Err(())
-        } else {
-            Ok(None)
-        }
+        Ok(None)
     }
 }
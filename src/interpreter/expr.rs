@@ -1,233 +1,388 @@
 use super::{
-    stack::{StackItem, Variable},
+    stack::{Function, Variable},
     Interpreter,
 };
-use crate::parser::ast::{
-    expr::{BinOp, Expr, ExprKind, UnOp},
-    lit::{Lit, LitKind},
-    Type,
+use crate::{
+    error_handling::error_at,
+    parser::ast::{
+        block::Block,
+        expr::{Expr, ExprKind, Ident},
+        lit::{Lit, LitKind},
+        pattern::Arm,
+        Span, Type,
+    },
+    runtime::{builtins::Builtin, calendar, heap, methods, ops},
 };
-use std::{ops::Deref, process::exit};
 
 impl Interpreter {
     pub fn eval_expr(&self, expr: &Expr) -> Result<Lit, ()> {
         match &expr.expr_kind {
             ExprKind::Binary(left, bin_op_kind, right) => {
-                let exit_condition = match bin_op_kind {
-                    BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => {
-                        (left.type_ != right.type_
-                            && !(left.type_ == Type::Number && right.type_ == Type::Time)
-                            && !(left.type_ == Type::Time && right.type_ == Type::Number))
-                            || (left.type_ == Type::Time && right.type_ == Type::Time)
-                    }
+                let left = self.eval_expr(left)?;
+                let (left, right) = self.holding(left, |_| self.eval_expr(right))?;
 
-                    BinOp::And | BinOp::Or | BinOp::Xor => {
-                        left.type_ != Type::Bool || right.type_ != Type::Bool
-                    }
+                ops::binary(&left, bin_op_kind, &right).map_err(|message| {
+                    error_at(expr.span, &message);
+                })
+            }
+            ExprKind::Unary(un_op_kind, target_expr) => {
+                let target_lit = self.eval_expr(target_expr)?;
 
-                    BinOp::EqEq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
-                        // No bool
-                        (left.type_ != right.type_
-                            && !(left.type_ == Type::Number && right.type_ == Type::Time)
-                            && !(left.type_ == Type::Time && right.type_ == Type::Number))
-                            || (left.type_ == Type::Bool || right.type_ == Type::Bool)
-                    }
+                ops::unary(un_op_kind, &target_lit).map_err(|message| {
+                    error_at(expr.span, &message);
+                })
+            }
+            ExprKind::FnCall(ident, arguments) => {
+                // Builtins are the only functions the resolver leaves unbound
+                let Some(binding) = ident.binding else {
+                    return self.eval_builtin(expr, ident, arguments);
+                };
+
+                let function = (*self.stack).borrow().get_function(&binding).cloned();
+
+                let Some(function) = function else {
+                    error_at(ident.span, &format!("Unknown function `{}`", ident.name));
+                    return Err(());
                 };
 
-                if exit_condition {
+                if function.args.len() != arguments.len() {
+                    error_at(
+                        expr.span,
+                        &format!(
+                            "`{}` takes {} arguments but {} were given",
+                            ident.name,
+                            function.args.len(),
+                            arguments.len()
+                        ),
+                    );
                     return Err(());
                 }
 
-                let left = self.eval_expr(left)?;
-                let right = self.eval_expr(right)?;
-
-                match bin_op_kind {
-                    BinOp::Add
-                    | BinOp::Sub
-                    | BinOp::Mul
-                    | BinOp::Div
-                    | BinOp::Mod
-                    | BinOp::EqEq
-                    | BinOp::Ne
-                    | BinOp::Lt
-                    | BinOp::Le
-                    | BinOp::Gt
-                    | BinOp::Ge => Ok(Lit::new(self.bin_op_num(&left.0, bin_op_kind, &right.0)?)),
-                    BinOp::And | BinOp::Or | BinOp::Xor => {
-                        Ok(Lit::new(self.bin_op_bool(&left.0, bin_op_kind, &right.0)))
+                let mut types = function.args.iter().map(|(_, type_)| type_);
+                let values = self.eval_all(arguments, |argument, value| {
+                    let type_ = types.next().expect("checked against the arguments");
+                    if value.0.type_() != *type_ {
+                        error_at(argument.span, &format!("Expected a {type_}"));
+                        return Err(());
                     }
-                }
+
+                    Ok(())
+                })?;
+
+                let variables = function
+                    .args
+                    .iter()
+                    .zip(values)
+                    .map(|((arg, type_), value)| Variable::new(*arg, value, *type_))
+                    .collect();
+
+                self.call(&function, variables, expr.span)
             }
-            ExprKind::Unary(un_op_kind, target_expr) => {
-                let target_lit = self.eval_expr(target_expr)?;
+            ExprKind::Call(callee, arguments) => {
+                let callee = self.eval_expr(callee)?;
+                let (callee, values) =
+                    self.holding(callee, |_| self.eval_all(arguments, |_, _| Ok(())))?;
 
-                // if (target_lit.type_ == Type::Bool && *un_op_kind != UnOp::Not)
-                //     || ((target_lit.type_ == Type::Number || target_lit.type_ == Type::Time)
-                //         && *un_op_kind != UnOp::Neg)
-                // {
-                //     return Err(());
-                // }
-
-                let lit_kind = match target_lit.0 {
-                    LitKind::Num(num) => LitKind::Num(match un_op_kind {
-                        UnOp::Neg => -num,
-                        _ => unreachable!(),
-                    }),
-                    LitKind::Time(time, time_kind) => LitKind::Time(
-                        match un_op_kind {
-                            UnOp::Neg => -time,
-                            _ => unreachable!(),
-                        },
-                        time_kind,
-                    ),
-                    LitKind::Bool(bool) => LitKind::Bool(match un_op_kind {
-                        UnOp::Not => !bool,
-                        _ => unreachable!(),
-                    }),
+                let (code, captures) = {
+                    let heap = self.runtime.heap.borrow();
+                    let function = heap.function(&callee, &values).map_err(|message| {
+                        error_at(expr.span, &message);
+                    })?;
+
+                    (function.code, function.captures.clone())
                 };
+                let function = self.functions.borrow()[code].clone();
 
-                Ok(Lit::new(lit_kind))
-            }
-            ExprKind::FnCall(ident, arguments) => {
-                let rc = (*self.stack).borrow();
-                let function = rc.get_function(ident).ok_or(())?;
+                let args = function.args.iter().zip(values);
+                let captures = function.captures.iter().zip(captures);
+                let variables = args
+                    .map(|((arg, type_), value)| Variable::new(*arg, value, *type_))
+                    .chain(
+                        captures
+                            .map(|(ident, value)| Variable::new(*ident, value, value.0.type_())),
+                    )
+                    .collect();
 
-                (*self.stack).borrow_mut().push(StackItem::StackMarker);
+                self.call(&function, variables, expr.span)
+            }
+            ExprKind::Closure(closure) => {
+                let mut captures = Vec::with_capacity(closure.captures.len());
+                for ident in closure.captures.iter() {
+                    captures.push(self.get_variable(ident)?);
+                }
 
-                for (arg_index, argument) in arguments.deref().iter().enumerate() {
-                    if argument.type_ != function.args[arg_index].1 {
-                        return Err(());
-                    }
+                let mut function = Function::new(
+                    Ident::new("", expr.span),
+                    closure.args.clone(),
+                    closure.body.clone(),
+                    closure.return_type,
+                );
+                function.captures = closure.captures.clone();
 
-                    (*self.stack)
-                        .borrow_mut()
-                        .push(StackItem::Variable(Variable::new(
-                            function.args[arg_index].0,
-                            self.eval_expr(argument)?,
-                            function.args[arg_index].1,
-                        )));
+                Ok(self.alloc_function(expr.type_, function, captures))
+            }
+            ExprKind::Spawn(task) => {
+                let mut variables = Vec::with_capacity(task.captures.len());
+                for ident in task.captures.iter() {
+                    let value = self.get_variable(ident)?;
+                    variables.push(Variable::new(*ident, value, value.0.type_()));
                 }
 
-                let maybe_lit = self.eval_block(function.body.clone(), true)?;
+                let id = self.runtime.tasks.spawn(&*self.runtime.clock);
+                let interpreter = self.for_task(id);
+                let body = task.body.clone();
 
-                (*self.stack).borrow_mut().pop_scope();
+                self.runtime
+                    .tasks
+                    .start(id, move || interpreter.run_task(&body, variables));
 
-                match maybe_lit {
-                    Some(lit) => Ok(lit),
-                    None => Err(()),
-                }
+                Ok(Lit::new(LitKind::Task(id)))
+            }
+            ExprKind::FnRef(ident) => {
+                let function = ident
+                    .binding
+                    .and_then(|binding| (*self.stack).borrow().get_function(&binding).cloned());
+
+                let Some(function) = function else {
+                    error_at(ident.span, &format!("Unknown function `{}`", ident.name));
+                    return Err(());
+                };
+
+                Ok(self.alloc_function(expr.type_, function, Vec::new()))
             }
             ExprKind::MethodCall {
                 receiver,
                 ident,
                 args,
-            } => todo!(),
-            ExprKind::FieldAcc(_, _) => todo!(),
+            } => {
+                let receiver = self.eval_expr(receiver)?;
+                let (receiver, values) = self.holding(receiver, |_| {
+                    self.eval_all(args.iter().map(|arg| &**arg), |_, _| Ok(()))
+                })?;
+
+                let heap = &mut self.runtime.heap.borrow_mut();
+                methods::call_method(&receiver, ident.name, &values, heap).map_err(|message| {
+                    error_at(expr.span, &message);
+                })
+            }
+            ExprKind::FieldAcc(receiver, ident) => {
+                let receiver = self.eval_expr(receiver)?;
+
+                self.runtime
+                    .heap
+                    .borrow()
+                    .get_field(&receiver, ident.name)
+                    .map_err(|message| {
+                        error_at(expr.span, &message);
+                    })
+            }
+            ExprKind::StructLit(id, fields) => {
+                let values = self.eval_all(fields.iter().map(|(_, value)| value), |_, _| Ok(()))?;
+                let values = fields
+                    .iter()
+                    .map(|(field, _)| field.name)
+                    .zip(values)
+                    .collect();
+
+                Ok(self.runtime.heap.borrow_mut().alloc_struct(*id, values))
+            }
+            ExprKind::List(elems) => {
+                let Type::List(elem) = expr.type_ else {
+                    unreachable!()
+                };
+
+                let values = self.eval_all(elems, |elem_expr, value| {
+                    if value.0.type_() != *elem {
+                        error_at(
+                            elem_expr.span,
+                            &format!(
+                                "Expected a {elem} in this list, found a {}",
+                                value.0.type_name()
+                            ),
+                        );
+                        return Err(());
+                    }
+
+                    Ok(())
+                })?;
+
+                Ok(self.runtime.heap.borrow_mut().alloc_list(elem, values))
+            }
+            ExprKind::Index(list, index) => {
+                let list = self.eval_expr(list)?;
+                let (list, index) = self.holding(list, |_| self.eval_expr(index))?;
+
+                self.runtime
+                    .heap
+                    .borrow()
+                    .index(&list, &index)
+                    .map_err(|message| {
+                        error_at(expr.span, &message);
+                    })
+            }
             ExprKind::Lit(lit) => Ok(*lit),
-            ExprKind::Ident(ident) => match (*self.stack).borrow().get_variable(ident) {
-                Some(var) => Ok(var.value),
-                None => Err(()),
-            },
+            ExprKind::Timestamp(stamp) => Ok(Lit::new(LitKind::Timestamp(calendar::resolve(
+                stamp,
+                &*self.runtime.clock,
+            )))),
+            ExprKind::Ident(ident) => self.get_variable(ident),
             ExprKind::Grouping(group) => self.eval_expr(group),
+            ExprKind::Block(block) => self.eval_block_value(block),
+            ExprKind::If(condition, true_block, else_expr) => match self.eval_expr(condition)?.0 {
+                LitKind::Bool(true) => self.eval_block_value(true_block),
+                LitKind::Bool(false) => self.eval_expr(else_expr),
+                _ => {
+                    error_at(condition.span, "Expected a bool");
+                    Err(())
+                }
+            },
+            ExprKind::Match(value, arms) => {
+                let body = self.select_arm(value, arms, expr.span)?;
+                self.eval_expr(body)
+            }
         }
     }
 
-    fn bin_op_num(
-        &self,
-        left: &LitKind,
-        bin_op_kind: &BinOp,
-        right: &LitKind,
-    ) -> Result<LitKind, ()> {
-        let mut ret_time_kind = None;
-
-        let left = match left {
-            LitKind::Time(num, time_kind) => {
-                ret_time_kind = Some(time_kind);
-                time_kind.as_ms() * num
-            }
-            LitKind::Num(num) => *num,
-            _ => unreachable!(),
-        };
+    /// The value of a variable the resolver bound
+    pub fn get_variable(&self, ident: &Ident) -> Result<Lit, ()> {
+        match ident.binding.and_then(|binding| {
+            (*self.stack)
+                .borrow()
+                .get_variable(&binding)
+                .map(|var| var.value)
+        }) {
+            Some(value) => Ok(value),
+            None => {
+                error_at(ident.span, &format!("Unknown variable `{}`", ident.name));
+                Err(())
+            }
+        }
+    }
+
+    /// Runs a function with its arguments and captured values
+    fn call(&self, function: &Function, variables: Vec<Variable>, span: Span) -> Result<Lit, ()> {
+        (*self.stack).borrow_mut().push_frame(variables);
+        let value = self.eval_body(&function.body);
+        (*self.stack).borrow_mut().pop_frame();
+
+        match value? {
+            Some(value) => Ok(value),
+            None => {
+                let name = match function.ident.name {
+                    "" => String::from("The closure"),
+                    name => format!("`{name}`"),
+                };
 
-        let right = match right {
-            LitKind::Time(num, time_kind) => {
-                ret_time_kind = Some(time_kind);
-                time_kind.as_ms() * num
+                error_at(span, &format!("{name} ended without returning a value"));
+                Err(())
             }
-            LitKind::Num(num) => *num,
-            _ => unreachable!(),
+        }
+    }
+
+    /// Stores a function on the heap to use it as a value, its code is only
+    /// kept once however often it's used since its name is where it's defined
+    fn alloc_function(&self, type_: Type, function: Function, captures: Vec<Lit>) -> Lit {
+        let Type::Function(type_) = type_ else {
+            unreachable!("the resolver gives functions their type")
         };
 
-        let num_result = match bin_op_kind {
-            BinOp::Add => Some(left + right),
-            BinOp::Sub => Some(left - right),
-            BinOp::Mul => Some(left * right),
-            BinOp::Div => {
-                if right == 0. {
-                    return Err(());
-                }
-                Some(left / right)
-            }
-            BinOp::Mod => {
-                if right == 0. {
-                    return Err(());
-                }
-                Some(left % right)
+        let mut functions = self.functions.borrow_mut();
+        let code = match functions
+            .iter()
+            .position(|known| known.ident.span == function.ident.span)
+        {
+            Some(code) => code,
+            None => {
+                functions.push(function);
+                functions.len() - 1
             }
-            _ => None,
         };
+        let function = heap::Function { code, captures };
 
-        let bool_result = match bin_op_kind {
-            BinOp::EqEq => Some(left == right),
-            BinOp::Ne => Some(left != right),
-            BinOp::Lt => Some(left < right),
-            BinOp::Le => Some(left <= right),
-            BinOp::Gt => Some(left > right),
-            BinOp::Ge => Some(left >= right),
-            _ => None,
-        };
+        self.runtime
+            .heap
+            .borrow_mut()
+            .alloc_function(type_, function)
+    }
 
-        match ret_time_kind {
-            Some(time_kind) => {
-                let result = match num_result {
-                    Some(num) => num,
-                    None => unreachable!(),
-                };
+    /// What the first arm of a `match` that matches the value runs
+    pub fn select_arm<'b, B>(
+        &self,
+        value: &Expr,
+        arms: &'b [Arm<B>],
+        span: Span,
+    ) -> Result<&'b B, ()> {
+        let value = self.eval_expr(value)?;
 
-                Ok(LitKind::Time(result, *time_kind))
+        for arm in arms {
+            let matches = ops::matches(&value, &arm.pattern.pattern_kind).map_err(|message| {
+                error_at(arm.pattern.span, &message);
+            })?;
+            if !matches {
+                continue;
             }
-            None => {
-                if let Some(num_result) = num_result {
-                    return Ok(LitKind::Num(num_result));
-                }
 
-                if let Some(bool_result) = bool_result {
-                    return Ok(LitKind::Bool(bool_result));
+            // The guard only runs once the pattern matched
+            if let Some(guard) = &arm.guard {
+                match self.eval_expr(guard)?.0 {
+                    LitKind::Bool(true) => {}
+                    LitKind::Bool(false) => continue,
+                    _ => {
+                        error_at(guard.span, "Expected a bool");
+                        return Err(());
+                    }
                 }
-
-                unreachable!();
             }
+
+            return Ok(&arm.body);
         }
+
+        error_at(
+            span,
+            &format!("No arm of this `match` matches the {}", value.0.type_name()),
+        );
+        Err(())
     }
 
-    fn bin_op_bool(&self, left: &LitKind, bin_op_kind: &BinOp, right: &LitKind) -> LitKind {
-        let left = match left {
-            LitKind::Bool(bool) => bool,
-            _ => unreachable!(),
+    fn eval_builtin(&self, expr: &Expr, ident: &Ident, arguments: &[Expr]) -> Result<Lit, ()> {
+        let Some(builtin) = Builtin::from_name(ident.name) else {
+            error_at(ident.span, &format!("Unknown function `{}`", ident.name));
+            return Err(());
         };
 
-        let right = match right {
-            LitKind::Bool(bool) => bool,
-            _ => unreachable!(),
-        };
+        let mut args = self.eval_all(arguments, |_, _| Ok(()))?;
+        if builtin.waits() {
+            self.collect(&mut args);
+        }
 
-        let result = match bin_op_kind {
-            BinOp::And => *left && *right,
-            BinOp::Or => *left || *right,
-            BinOp::Xor => left ^ right,
-            _ => unreachable!(),
-        };
+        // Other tasks can run during the call, with their own calls on the stack
+        let frames = std::mem::take(&mut (*self.stack).borrow_mut().frames);
+        self.runtime.waiting_at.set(expr.span);
+        let result = builtin.call(&args, &self.runtime);
+        (*self.stack).borrow_mut().frames = frames;
+
+        let value = result.map_err(|message| {
+            error_at(expr.span, &message);
+        })?;
 
-        LitKind::Bool(result)
+        // Leaves everything up to the `within` block whose deadline cut a wait short
+        if self.runtime.expired.get().is_some() {
+            return Err(());
+        }
+
+        Ok(value)
+    }
+
+    /// Runs the body of a task once the scheduler gives it its turn
+    fn run_task(self, body: &Block, variables: Vec<Variable>) -> Result<(), ()> {
+        (*self.stack).borrow_mut().push_frame(variables);
+        let result = self.eval_body(body);
+        (*self.stack).borrow_mut().pop_frame();
+
+        // An interrupted task ends quietly, the script runs its `on_interrupt` block
+        match result {
+            Err(()) if self.runtime.is_interrupted() => Ok(()),
+            result => result.map(drop),
+        }
     }
 }
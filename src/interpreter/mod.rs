@@ -1,5 +1,13 @@
-use self::stack::Stack;
-use crate::parser::ast::Ast;
+use self::stack::{Function, Stack, Variable};
+use crate::{
+    parser::ast::{
+        block::Block,
+        expr::{Expr, Ident},
+        lit::Lit,
+        Ast,
+    },
+    runtime::Runtime,
+};
 use std::{cell::RefCell, rc::Rc};
 
 mod block;
@@ -9,20 +17,152 @@ mod stmt;
 
 pub struct Interpreter {
     pub stack: Rc<RefCell<Stack>>,
+    pub runtime: Runtime,
+    /// The functions and closures used as values, which the values on the
+    /// heap refer to by their position
+    pub functions: Rc<RefCell<Vec<Function>>>,
+    /// The `on_interrupt` block registered last, shared with the tasks the
+    /// script starts
+    pub on_interrupt: Rc<RefCell<Option<Handler>>>,
+    /// Values evaluated but not stored anywhere yet, like the left side of
+    /// a binary expression while the right side runs, which a collection of
+    /// the heap keeps and moves along
+    pins: RefCell<Vec<Lit>>,
+}
+
+/// An `on_interrupt` block with the variables it captured
+type Handler = (Block, Vec<Variable>);
+
+/// Why a block stopped before running all of its statements
+#[derive(Debug)]
+pub enum Unwind {
+    Return(Lit),
+    /// A `break` with its label, if it has one
+    Break(Option<&'static str>),
+    /// A `continue` with its label, if it has one
+    Continue(Option<&'static str>),
+}
+
+impl Unwind {
+    /// Whether a `break` or `continue` with this label refers to the loop
+    pub fn targets(label: Option<&'static str>, loop_label: &Option<Ident>) -> bool {
+        match label {
+            Some(label) => loop_label
+                .as_ref()
+                .is_some_and(|loop_label| loop_label.name == label),
+            None => true,
+        }
+    }
 }
 
 impl Interpreter {
-    pub fn new() -> Self {
+    pub fn new(runtime: Runtime) -> Self {
         Self {
             stack: Rc::new(RefCell::new(Stack::new())),
+            runtime,
+            functions: Rc::new(RefCell::new(Vec::new())),
+            on_interrupt: Rc::new(RefCell::new(None)),
+            pins: RefCell::new(Vec::new()),
         }
     }
 
+    /// An interpreter for a task started with `spawn`, which shares the
+    /// variables and functions of this one
+    pub fn for_task(&self, task: usize) -> Self {
+        Self {
+            stack: Rc::clone(&self.stack),
+            runtime: self.runtime.for_task(task),
+            functions: Rc::clone(&self.functions),
+            on_interrupt: Rc::clone(&self.on_interrupt),
+            pins: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Runs a script the [`Resolver`](crate::resolver::Resolver) bound the names of
     pub fn run(&self, ast: Ast) -> Result<(), ()> {
-        for node in ast.program {
-            self.eval_stmt(node, false)?;
+        let result = ast
+            .program
+            .into_iter()
+            .try_for_each(|node| self.eval_stmt(node, false).map(drop));
+
+        match self.runtime.take_interrupt() {
+            true => self.run_on_interrupt(),
+            false => result,
         }
+    }
+
+    /// Runs the `on_interrupt` block the script registered, if it did
+    fn run_on_interrupt(&self) -> Result<(), ()> {
+        let Some((body, variables)) = self.on_interrupt.borrow_mut().take() else {
+            return Ok(());
+        };
+
+        // Whatever ran when the script was interrupted is left behind
+        (*self.stack).borrow_mut().push_frame(variables);
+        let result = self.eval_body(&body);
+        (*self.stack).borrow_mut().pop_frame();
 
-        Ok(())
+        result.map(drop)
+    }
+
+    /// Runs `f` while keeping a value evaluated before alive, and gives the
+    /// value back where it is now. `f` gets where the value is in `pins`.
+    fn holding<T>(
+        &self,
+        value: Lit,
+        f: impl FnOnce(usize) -> Result<T, ()>,
+    ) -> Result<(Lit, T), ()> {
+        let index = self.pins.borrow().len();
+        self.pins.borrow_mut().push(value);
+        let result = f(index);
+
+        let mut pins = self.pins.borrow_mut();
+        let value = pins[index];
+        pins.truncate(index);
+
+        result.map(|result| (value, result))
+    }
+
+    /// Evaluates the expressions in order, keeping the values alive like
+    /// [`Interpreter::holding`], and checks each before the next one runs
+    fn eval_all<'e>(
+        &self,
+        exprs: impl IntoIterator<Item = &'e Expr>,
+        mut check: impl FnMut(&Expr, &Lit) -> Result<(), ()>,
+    ) -> Result<Vec<Lit>, ()> {
+        let start = self.pins.borrow().len();
+        let result = exprs.into_iter().try_for_each(|expr| {
+            let value = self.eval_expr(expr)?;
+            check(expr, &value)?;
+            self.pins.borrow_mut().push(value);
+
+            Ok(())
+        });
+        let values = self.pins.borrow_mut().split_off(start);
+
+        result.map(|()| values)
+    }
+
+    /// Frees the values on the heap the script can't reach anymore, which
+    /// only the script itself knows while no other task is running. The
+    /// values given are kept and moved along too.
+    fn collect(&self, held: &mut [Lit]) {
+        let mut heap = self.runtime.heap.borrow_mut();
+        if self.runtime.task != 0 || !heap.is_due() || !self.runtime.tasks.alone() {
+            return;
+        }
+
+        let mut stack = self.stack.borrow_mut();
+        let mut on_interrupt = self.on_interrupt.borrow_mut();
+        let captures = on_interrupt
+            .iter_mut()
+            .flat_map(|(_, variables)| variables.iter_mut());
+        let mut variables: Vec<&mut Variable> = stack.variables_mut().chain(captures).collect();
+
+        let mut values: Vec<Lit> = variables.iter().map(|variable| variable.value).collect();
+        heap.collect(&mut [&mut values, &mut self.pins.borrow_mut(), held]);
+        for (variable, value) in variables.iter_mut().zip(values) {
+            variable.value = value;
+        }
     }
 }
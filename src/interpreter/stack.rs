@@ -1,7 +1,25 @@
-use crate::parser::ast::{block::Block, expr::Ident, lit::Lit, Type};
+use crate::parser::ast::{
+    block::Block,
+    expr::{Binding, Ident},
+    lit::Lit,
+    Type,
+};
 
+/// The variables and functions of a running script, looked up by the
+/// bindings the resolver computed
 pub struct Stack {
-    pub stack: Vec<StackItem>,
+    /// Everything declared at the top level of the script
+    pub globals: Scope,
+    /// The scopes of every running call, innermost last
+    ///
+    /// The first frame holds the blocks of the script itself.
+    pub frames: Vec<Vec<Scope>>,
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub struct Scope {
+    pub variables: Vec<Variable>,
+    pub functions: Vec<Function>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -21,12 +39,15 @@ impl Variable {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Function {
+    /// Empty for a closure
     pub ident: Ident,
     pub args: Box<[(Ident, Type)]>,
     pub body: Box<Block>,
     pub return_type: Type,
+    /// The variables a closure captured, whose values follow the arguments
+    pub captures: Vec<Ident>,
 }
 
 impl Function {
@@ -41,61 +62,103 @@ impl Function {
             args,
             body,
             return_type,
+            captures: Vec::new(),
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum StackItem {
-    Variable(Variable),
-    Function(Function),
-    StackMarker,
-}
-
 impl Stack {
     pub fn new() -> Self {
-        Self { stack: Vec::new() }
+        Self {
+            globals: Scope::default(),
+            frames: vec![Vec::new()],
+        }
     }
 
-    pub fn push(&mut self, item: StackItem) {
-        self.stack.push(item);
+    pub fn push_scope(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.push(Scope::default());
+        }
     }
 
-    pub fn pop(&mut self) -> Option<StackItem> {
-        self.stack.pop()
+    pub fn pop_scope(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.pop();
+        }
     }
 
-    pub fn pop_scope(&mut self) {
-        loop {
-            match self.stack.pop() {
-                Some(StackItem::StackMarker) => break,
-                Some(_) => {}
-                None => break,
-            }
+    /// Starts a call, the arguments form its outermost scope
+    pub fn push_frame(&mut self, args: Vec<Variable>) {
+        self.frames.push(vec![Scope {
+            variables: args,
+            functions: Vec::new(),
+        }]);
+    }
+
+    pub fn pop_frame(&mut self) {
+        self.frames.pop();
+    }
+
+    /// The scope new declarations go into
+    fn innermost(&mut self) -> &mut Scope {
+        match self.frames.last_mut().and_then(|frame| frame.last_mut()) {
+            Some(scope) => scope,
+            None => &mut self.globals,
         }
     }
 
-    pub fn get_variable(&self, ident: &Ident) -> Option<&Variable> {
-        for item in self.stack.iter().rev() {
-            if let StackItem::Variable(var) = item {
-                if var.ident == *ident {
-                    return Some(var);
-                }
+    pub fn define_variable(&mut self, variable: Variable) {
+        self.innermost().variables.push(variable);
+    }
+
+    pub fn define_function(&mut self, function: Function) {
+        self.innermost().functions.push(function);
+    }
+
+    fn scope(&self, binding: &Binding) -> Option<(&Scope, usize)> {
+        match *binding {
+            Binding::Global(slot) => Some((&self.globals, slot)),
+            Binding::Local { depth, slot } => {
+                let frame = self.frames.last()?;
+                let scope = frame.get(frame.len().checked_sub(depth + 1)?)?;
+
+                Some((scope, slot))
             }
         }
-
-        None
     }
 
-    pub fn get_function(&self, ident: &Ident) -> Option<&Function> {
-        for item in self.stack.iter().rev() {
-            if let StackItem::Function(func) = item {
-                if func.ident == *ident {
-                    return Some(func);
-                }
+    fn scope_mut(&mut self, binding: &Binding) -> Option<(&mut Scope, usize)> {
+        match *binding {
+            Binding::Global(slot) => Some((&mut self.globals, slot)),
+            Binding::Local { depth, slot } => {
+                let frame = self.frames.last_mut()?;
+                let index = frame.len().checked_sub(depth + 1)?;
+
+                Some((frame.get_mut(index)?, slot))
             }
         }
+    }
+
+    pub fn get_variable(&self, binding: &Binding) -> Option<&Variable> {
+        let (scope, slot) = self.scope(binding)?;
+        scope.variables.get(slot)
+    }
+
+    pub fn get_variable_mut(&mut self, binding: &Binding) -> Option<&mut Variable> {
+        let (scope, slot) = self.scope_mut(binding)?;
+        scope.variables.get_mut(slot)
+    }
+
+    /// Every variable there is, globals first
+    pub fn variables_mut(&mut self) -> impl Iterator<Item = &mut Variable> {
+        let scopes = self.frames.iter_mut().flatten();
+        std::iter::once(&mut self.globals)
+            .chain(scopes)
+            .flat_map(|scope| scope.variables.iter_mut())
+    }
 
-        None
+    pub fn get_function(&self, binding: &Binding) -> Option<&Function> {
+        let (scope, slot) = self.scope(binding)?;
+        scope.functions.get(slot)
     }
 }
@@ -1,26 +1,90 @@
-use crate::parser::ast::{
-    lit::{Lit, LitKind},
-    stmt::{Stmt, StmtKind},
+use crate::{
+    error_handling::{error_at, warn_at},
+    parser::ast::{
+        expr::{BinOp, Expr, Ident},
+        lit::{Lit, LitKind},
+        stmt::{BackoffKind, Else, Stmt, StmtKind},
+        Type,
+    },
+    runtime::{ops, retry, schedule},
 };
 
 use super::{
-    stack::{Function, StackItem, Variable},
-    Interpreter,
+    stack::{Function, Variable},
+    Interpreter, Unwind,
 };
+use std::time::Duration;
+
 impl Interpreter {
-    pub fn eval_stmt(&self, stmt: Stmt, in_function: bool) -> Result<Option<Lit>, ()> {
+    pub fn eval_stmt(&self, stmt: Stmt, in_function: bool) -> Result<Option<Unwind>, ()> {
         match stmt.stmt_kind {
             StmtKind::VarBind {
                 type_,
                 identifier,
-                value,
+                value: value_expr,
+                ..
             } => {
-                let value = self.eval_expr(&*value)?;
-                self.stack.borrow_mut().push(StackItem::Variable(Variable {
+                let value = self.eval_expr(&*value_expr)?;
+                check_type(&value, type_, &value_expr, &identifier)?;
+
+                self.stack.borrow_mut().define_variable(Variable {
                     ident: identifier,
                     value,
                     type_,
-                }));
+                });
+            }
+            StmtKind::Assign {
+                target,
+                op,
+                value: value_expr,
+            } => {
+                let value = self.eval_expr(&value_expr)?;
+                let Some(binding) = target.binding else {
+                    error_at(target.span, &format!("Unknown variable `{}`", target.name));
+                    return Err(());
+                };
+
+                let mut stack = self.stack.borrow_mut();
+                let Some(variable) = stack.get_variable_mut(&binding) else {
+                    error_at(target.span, &format!("Unknown variable `{}`", target.name));
+                    return Err(());
+                };
+
+                let value = match op {
+                    Some(op) => ops::binary(&variable.value, &op, &value).map_err(|message| {
+                        error_at(stmt.span, &message);
+                    })?,
+                    None => value,
+                };
+                check_type(&value, variable.type_, &value_expr, &target)?;
+
+                variable.value = value;
+            }
+            StmtKind::FieldAssign {
+                receiver,
+                field,
+                op,
+                value,
+            } => {
+                let receiver = self.eval_expr(&receiver)?;
+                let (receiver, mut value) = self.holding(receiver, |_| self.eval_expr(&value))?;
+                let fail = |message: String| error_at(stmt.span, &message);
+
+                if let Some(op) = op {
+                    let current = self
+                        .runtime
+                        .heap
+                        .borrow()
+                        .get_field(&receiver, field.name)
+                        .map_err(fail)?;
+                    value = ops::binary(&current, &op, &value).map_err(fail)?;
+                }
+
+                self.runtime
+                    .heap
+                    .borrow_mut()
+                    .set_field(&receiver, field.name, value)
+                    .map_err(fail)?;
             }
             StmtKind::FnDef {
                 ident,
@@ -28,37 +92,47 @@ impl Interpreter {
                 body,
                 return_type,
             } => {
-                self.stack.borrow_mut().push(StackItem::Function(Function {
+                self.stack.borrow_mut().define_function(Function::new(
                     ident,
                     args,
                     body,
                     return_type,
-                }));
+                ));
             }
             StmtKind::Expr(expr) => {
                 self.eval_expr(&*expr)?;
             }
-            StmtKind::If(condition, true_block, else_expr) => {
-                let condition = if let LitKind::Bool(bool) = self.eval_expr(&condition)?.0 {
-                    bool
-                } else {
+            StmtKind::If(condition, true_block, else_) => {
+                let LitKind::Bool(condition_value) = self.eval_expr(&condition)?.0 else {
+                    error_at(condition.span, "Expected a bool");
                     return Err(());
                 };
 
-                if condition {
-                    self.eval_block(true_block, in_function)?;
-                } else if let Some(else_expr) = else_expr {
-                    self.eval_expr(&*else_expr)?;
-                }
+                // Whatever stopped the branch that ran, like a `return`, stops the `if` too
+                return match (condition_value, else_) {
+                    (true, _) => self.eval_block(true_block, in_function),
+                    (false, Some(Else::Block(block))) => self.eval_block(block, in_function),
+                    (false, Some(Else::If(stmt))) => self.eval_stmt(*stmt, in_function),
+                    (false, None) => Ok(None),
+                };
             }
-            StmtKind::While(condition_expr, block) => {
+            StmtKind::Match(value, arms) => {
+                let block = self.select_arm(&value, &arms, stmt.span)?;
+                return self.eval_block(block.clone(), in_function);
+            }
+            StmtKind::While(condition_expr, block, label) => {
                 let mut condition = match self.eval_expr(&condition_expr)?.0 {
                     LitKind::Bool(bool) => bool,
                     _ => return Err(()),
                 };
 
                 while condition {
-                    self.eval_block(block.clone(), in_function)?;
+                    match self.eval_block(block.clone(), in_function)? {
+                        Some(Unwind::Break(target)) if Unwind::targets(target, &label) => break,
+                        Some(Unwind::Continue(target)) if Unwind::targets(target, &label) => {}
+                        Some(unwind) => return Ok(Some(unwind)),
+                        None => {}
+                    }
 
                     condition = match self.eval_expr(&condition_expr)?.0 {
                         LitKind::Bool(bool) => bool,
@@ -66,15 +140,299 @@ impl Interpreter {
                     };
                 }
             }
+            StmtKind::OnInterrupt(handler) => {
+                let mut variables = Vec::with_capacity(handler.captures.len());
+                for ident in handler.captures.iter() {
+                    let value = self.get_variable(ident)?;
+                    variables.push(Variable::new(*ident, value, value.0.type_()));
+                }
+
+                *self.on_interrupt.borrow_mut() = Some((*handler.body, variables));
+            }
+            StmtKind::Within(time, block, fallback) => {
+                let time_value = self.eval_expr(&time)?;
+                let Some(ms) = ops::as_ms(&time_value) else {
+                    error_at(time.span, "Expected a time for the deadline");
+                    return Err(());
+                };
+
+                let Ok(deadline) = Duration::try_from_secs_f64(ms.max(0.) as f64 / 1000.) else {
+                    error_at(time.span, "The deadline is too far away to keep track of");
+                    return Err(());
+                };
+
+                let index = self.runtime.push_deadline(deadline);
+                let result = self.eval_block(block, in_function);
+                self.runtime.pop_deadline(index);
+
+                // A wait cut short stops everything up to the block whose deadline it was
+                return match result {
+                    Err(()) if self.runtime.expired.get() == Some(index) => {
+                        self.runtime.expired.set(None);
+
+                        match fallback {
+                            Some(fallback) => self.eval_block(fallback, in_function),
+                            None => Ok(None),
+                        }
+                    }
+                    result => result,
+                };
+            }
+            StmtKind::Every {
+                schedule,
+                times,
+                duration,
+                block,
+                label,
+            } => {
+                let every = self.eval_expr(&schedule)?;
+                let times = times.map(|times| self.eval_expr(&times)).transpose()?;
+                let duration = duration
+                    .map(|duration| self.eval_expr(&duration))
+                    .transpose()?;
+
+                let fail = |message: String| error_at(stmt.span, &message);
+                let mut state =
+                    schedule::start(every, times, duration, &self.runtime).map_err(fail)?;
+
+                loop {
+                    self.collect(&mut state);
+
+                    // Other tasks can run while waiting, with their own calls on the stack
+                    let frames = std::mem::take(&mut (*self.stack).borrow_mut().frames);
+                    self.runtime.waiting_at.set(stmt.span);
+                    let due = schedule::next(&mut state, &self.runtime);
+                    (*self.stack).borrow_mut().frames = frames;
+
+                    if !due.map_err(fail)? {
+                        break;
+                    }
+
+                    // Leaves everything up to the `within` block whose deadline cut the wait short
+                    if self.runtime.expired.get().is_some() {
+                        return Err(());
+                    }
+
+                    match self.eval_block(block.clone(), in_function)? {
+                        Some(Unwind::Break(target)) if Unwind::targets(target, &label) => break,
+                        Some(Unwind::Continue(target)) if Unwind::targets(target, &label) => {}
+                        Some(unwind) => return Ok(Some(unwind)),
+                        None => {}
+                    }
+                }
+            }
+            StmtKind::Retry(options, block) => {
+                let attempts = match &options.attempts {
+                    Some(attempts) => self.eval_expr(attempts)?,
+                    None => Lit::from(3),
+                };
+                let (backoff, delay, max) = match &options.backoff {
+                    Some(backoff) => {
+                        let delay = self.eval_expr(&backoff.delay)?;
+                        let max = match &backoff.max {
+                            Some(max) => self.eval_expr(max)?,
+                            None => Lit::from(f32::INFINITY),
+                        };
+
+                        (backoff.kind, delay, max)
+                    }
+                    None => (BackoffKind::Constant, Lit::from(0), Lit::from(0)),
+                };
+                let jitter = match &options.jitter {
+                    Some(jitter) => self.eval_expr(jitter)?,
+                    None => Lit::from(0),
+                };
+
+                let fail = |message: String| error_at(stmt.span, &message);
+                let report = |message: &str| warn_at(stmt.span, message);
+                let mut state = retry::start(attempts, delay, max, jitter).map_err(fail)?;
+
+                loop {
+                    self.collect(&mut state);
+
+                    // Other tasks can run while pausing, with their own calls on the stack
+                    let frames = std::mem::take(&mut (*self.stack).borrow_mut().frames);
+                    self.runtime.waiting_at.set(stmt.span);
+                    let started = retry::next(&mut state, backoff, &self.runtime, report);
+                    (*self.stack).borrow_mut().frames = frames;
+
+                    started.map_err(fail)?;
+                    if self.runtime.expired.get().is_some() {
+                        return Err(());
+                    }
+
+                    self.stack.borrow_mut().push_scope();
+                    let attempt = Ident::new("attempt", block.span);
+                    self.stack.borrow_mut().define_variable(Variable::new(
+                        attempt,
+                        state[0],
+                        Type::Number,
+                    ));
+                    let result = match block.value {
+                        Some(_) => self.eval_block_value(&block),
+                        None => self
+                            .eval_block(block.clone(), in_function)
+                            .map(|_| Lit::from(true)),
+                    };
+                    self.stack.borrow_mut().pop_scope();
+
+                    // An error fails the attempt, unless a deadline or Ctrl-C cut a wait
+                    // short or a task failed
+                    match result {
+                        Err(())
+                            if self.runtime.expired.get().is_some()
+                                || self.runtime.is_interrupted()
+                                || self.runtime.tasks.failed() =>
+                        {
+                            return Err(());
+                        }
+                        Ok(Lit(LitKind::Bool(false))) | Err(()) => {}
+                        Ok(_) => {
+                            retry::succeeded(&state, report);
+                            break;
+                        }
+                    }
+                }
+            }
+            StmtKind::For {
+                var,
+                range,
+                block,
+                label,
+            } => {
+                let start = self.eval_expr(&range.start)?;
+                let end = self.eval_expr(&range.end)?;
+                let step = match &range.step {
+                    Some(step) => self.eval_expr(step)?,
+                    None => ops::default_step(&start),
+                };
+
+                ops::check_range(&start, &end, &step).map_err(|message| {
+                    error_at(stmt.span, &message);
+                })?;
+
+                let comparison = match range.inclusive {
+                    true => BinOp::Le,
+                    false => BinOp::Lt,
+                };
+
+                let mut current = start;
+                while ops::binary(&current, &comparison, &end) == Ok(Lit::new(LitKind::Bool(true)))
+                {
+                    // Every iteration gets a fresh loop variable
+                    self.stack.borrow_mut().push_scope();
+                    self.stack.borrow_mut().define_variable(Variable::new(
+                        var,
+                        current,
+                        current.0.type_(),
+                    ));
+                    let unwind = self.eval_block(block.clone(), in_function);
+                    self.stack.borrow_mut().pop_scope();
+
+                    match unwind? {
+                        Some(Unwind::Break(target)) if Unwind::targets(target, &label) => break,
+                        Some(Unwind::Continue(target)) if Unwind::targets(target, &label) => {}
+                        Some(unwind) => return Ok(Some(unwind)),
+                        None => {}
+                    }
+
+                    current = ops::binary(&current, &BinOp::Add, &step).map_err(|message| {
+                        error_at(stmt.span, &message);
+                    })?;
+                }
+            }
+            StmtKind::ForEach {
+                var,
+                list,
+                block,
+                label,
+            } => {
+                let list_value = self.eval_expr(&list)?;
+                if self.runtime.heap.borrow().list(&list_value).is_none() {
+                    error_at(
+                        list.span,
+                        &format!(
+                            "Expected a list or a range, found a {}",
+                            list_value.0.type_name()
+                        ),
+                    );
+                    return Err(());
+                }
+
+                // Elements added while looping are visited too
+                let looped = self.holding(list_value, |pin| {
+                    let mut index = 0;
+                    loop {
+                        let list_value = self.pins.borrow()[pin];
+                        let heap = self.runtime.heap.borrow();
+                        let Some(&elem) = heap.list(&list_value).and_then(|elems| elems.get(index))
+                        else {
+                            return Ok(None);
+                        };
+                        drop(heap);
+                        index += 1;
+
+                        self.stack.borrow_mut().push_scope();
+                        self.stack.borrow_mut().define_variable(Variable::new(
+                            var,
+                            elem,
+                            elem.0.type_(),
+                        ));
+                        let unwind = self.eval_block(block.clone(), in_function);
+                        self.stack.borrow_mut().pop_scope();
+
+                        match unwind? {
+                            Some(Unwind::Break(target)) if Unwind::targets(target, &label) => {
+                                return Ok(None);
+                            }
+                            Some(Unwind::Continue(target)) if Unwind::targets(target, &label) => {}
+                            Some(unwind) => return Ok(Some(unwind)),
+                            None => {}
+                        }
+                    }
+                });
+
+                return looped.map(|(_, unwind)| unwind);
+            }
             StmtKind::Return(expr) => {
                 if !in_function {
                     return Err(());
                 }
 
-                return Ok(Some(self.eval_expr(&expr)?));
+                return Ok(Some(Unwind::Return(self.eval_expr(&expr)?)));
+            }
+            StmtKind::Break(label) => {
+                return Ok(Some(Unwind::Break(label.map(|label| label.name))));
+            }
+            StmtKind::Continue(label) => {
+                return Ok(Some(Unwind::Continue(label.map(|label| label.name))));
+            }
+            StmtKind::Module(module) => {
+                for stmt in module.program {
+                    self.eval_stmt(stmt, in_function)?;
+                }
             }
         }
 
         Ok(None)
     }
 }
+
+/// Reports a value that doesn't match the type of the variable it's stored in
+fn check_type(value: &Lit, type_: Type, value_expr: &Expr, ident: &Ident) -> Result<(), ()> {
+    if value.0.type_() == type_ {
+        return Ok(());
+    }
+
+    error_at(
+        value_expr.span,
+        &format!(
+            "Expected a {} for `{}`, found a {}",
+            type_,
+            ident.name,
+            value.0.type_name()
+        ),
+    );
+
+    Err(())
+}
@@ -1,68 +1,293 @@
 #![feature(let_chains)]
-use std::process::exit;
+#![cfg_attr(test, feature(test))]
+use std::{env, fs, path::PathBuf, process::exit, rc::Rc};
 
-use error_handling::error;
-use interpreter::Interpreter;
-use parser::{ast::stmt::StmtKind, parse};
+use engine::{Backend, Engine};
+use error_handling::{error, error_message};
+use estimator::{format_ms, Estimator};
+use parser::{
+    ast::Ast,
+    lexer::{
+        lexer,
+        token::{Token, TokenKind},
+    },
+    modules,
+};
+use resolver::Resolver;
+use runtime::{
+    checkpoint::{Checkpoint, Saved},
+    clock::SystemClock,
+    interrupt,
+    progress::{Mode, Progress},
+    Runtime,
+};
 
 mod build_code;
+mod engine;
 mod error_handling;
+mod estimator;
 mod interpreter;
 mod parser;
+mod resolver;
+mod runtime;
+mod vm;
 
-static mut CODE: &str = "";
+const USAGE: &str = "Usage:
+    wait run <script> [--backend tree|vm] [--seed <number>] [--progress=auto|always|never] [--checkpoint <file>]
+    wait resume <checkpoint> [--progress=auto|always|never]
+    wait estimate <script> [--max <time>]
+
+--progress=auto shows the waits on a terminal and nothing when stderr isn't one,
+--progress=always logs a line for each wait every 10s there instead";
 
 fn main() {
-    // let ast = parse(
-    //     "
-    //     user a = detect_user();
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("run") => run(&args[1..]),
+        Some("resume") => resume(&args[1..]),
+        Some("estimate") => estimate(&args[1..]),
+        _ => {
+            eprintln!("{USAGE}");
+            exit(2);
+        }
+    }
+}
 
-    //     wait(30s);
+/// `wait run <script> [--backend tree|vm] [--seed <number>] [--progress=auto|always|never] [--checkpoint <file>]`
+///
+/// With `--checkpoint` the script runs on the VM and writes its state to the
+/// file before every wait, for `wait resume` to continue from.
+fn run(args: &[String]) {
+    let mut path = None;
+    let mut backend = None;
+    let mut seed = None;
+    let mut progress = Mode::Never;
+    let mut checkpoint = None;
 
-    //     if (a.bpm > 120) {
-    //         wait(15s);
-    //     }
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--backend" => {
+                backend = match args.next().and_then(|name| Backend::from_name(name)) {
+                    Some(backend) => Some(backend),
+                    None => {
+                        error_message("`--backend` expects `tree` or `vm`");
+                        exit(2);
+                    }
+                }
+            }
+            "--seed" => {
+                seed = match args.next().and_then(|seed| seed.parse().ok()) {
+                    Some(seed) => Some(seed),
+                    None => {
+                        error_message("`--seed` expects a whole number like `42`");
+                        exit(2);
+                    }
+                }
+            }
+            "--checkpoint" => {
+                checkpoint = match args.next() {
+                    Some(file) => Some(PathBuf::from(file)),
+                    None => {
+                        error_message("`--checkpoint` expects the file to write it to");
+                        exit(2);
+                    }
+                }
+            }
+            "--progress" => progress = progress_mode(args.next().map(String::as_str)),
+            _ if arg.starts_with("--progress=") => {
+                progress = progress_mode(arg.strip_prefix("--progress="))
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    let Some(path) = path else {
+        eprintln!("{USAGE}");
+        exit(2);
+    };
 
-    //     wait(a.impatience * 120);",
-    // )?;
+    // Only the state of the VM can be saved, the tree walker keeps it in its own calls
+    let backend = match (backend, &checkpoint) {
+        (Some(Backend::TreeWalker), Some(_)) => {
+            error_message("`--checkpoint` only works with `--backend vm`");
+            exit(2);
+        }
+        (_, Some(_)) => Backend::Bytecode,
+        (backend, None) => backend.unwrap_or(Backend::TreeWalker),
+    };
 
-    // let mut interpreter = Interpreter::new();
-    // interpreter.run(ast)?;
+    let ast = load_script(path);
 
-    //     let tokens = parse(
-    //         "
-    // a: user = detect_user();
+    // Resuming may happen from another directory
+    let script =
+        fs::canonicalize(path).map_or_else(|_| path.clone(), |path| path.display().to_string());
+    let checkpoint = checkpoint.map(|file| Rc::new(Checkpoint::new(file, script)));
 
-    // wait(30s);
+    let runtime = Runtime {
+        progress: Progress::start(progress).map(Rc::new),
+        checkpoint: checkpoint.clone(),
+        ..Runtime::default()
+    };
+    let mut engine = Engine::new(backend, runtime);
+    if let Some(seed) = seed {
+        engine = engine.with_seed(seed);
+    }
 
-    // if (bpm > 120) {
-    //     wait(15s);
-    // }
+    interrupt::install();
+    let result = engine.run(ast);
+    finish(result, checkpoint.as_deref());
+}
 
-    // wait(a * 120);
-    // func wow(name: type) -> type {}
-    // ",
-    //     )
-    //     .unwrap();
+/// `wait resume <checkpoint> [--progress=auto|always|never]`
+///
+/// Continues a script run with `--checkpoint` at the wait it was at, which
+/// only waits for what is left of it now.
+fn resume(args: &[String]) {
+    let mut path = None;
+    let mut progress = Mode::Never;
 
-    let code = "(-3 + 3) * 7"; // (-3) + (3 * 7)
-    unsafe { CODE = code };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--progress" => progress = progress_mode(args.next().map(String::as_str)),
+            _ if arg.starts_with("--progress=") => {
+                progress = progress_mode(arg.strip_prefix("--progress="))
+            }
+            _ => path = Some(arg),
+        }
+    }
 
-    let ast = match parse(code) {
-        Some(ast) => ast,
-        None => return,
+    let Some(path) = path else {
+        eprintln!("{USAGE}");
+        exit(2);
     };
-    println!("{ast:#?}");
 
-    let expr = match ast.program.first().unwrap().stmt_kind.clone() {
-        StmtKind::Expr(expr) => expr.clone(),
-        _ => {
-            println!("Not an expression");
+    let saved = match Saved::load(path) {
+        Ok(saved) => saved,
+        Err(message) => {
+            error_message(&message);
             exit(1);
         }
     };
+    let ast = load_script(&saved.script);
+    let checkpoint = Rc::new(Checkpoint::resumed(PathBuf::from(path), &saved));
+
+    let runtime = Runtime {
+        clock: Rc::new(SystemClock::since(saved.epoch)),
+        progress: Progress::start(progress).map(Rc::new),
+        checkpoint: Some(checkpoint.clone()),
+        ..Runtime::default()
+    };
+    let engine = Engine::new(Backend::Bytecode, runtime);
+
+    interrupt::install();
+    let result = engine.resume(ast, &saved);
+    finish(result, Some(&checkpoint));
+}
+
+/// Exits like the script ended, a checkpoint is only kept if it didn't finish
+fn finish(result: Result<(), ()>, checkpoint: Option<&Checkpoint>) {
+    if interrupt::received() {
+        exit(interrupt::EXIT_CODE);
+    }
+    if result.is_err() {
+        exit(1);
+    }
+
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.remove();
+    }
+}
+
+/// The mode given to `--progress`, exiting if there is none
+fn progress_mode(name: Option<&str>) -> Mode {
+    match name.and_then(Mode::from_name) {
+        Some(mode) => mode,
+        None => {
+            error_message("`--progress` expects `auto`, `always` or `never`");
+            exit(2);
+        }
+    }
+}
+
+/// `wait estimate <script> [--max <time>]`
+///
+/// Prints bounds on the total wait time of a script without running it and
+/// fails if the worst case exceeds `--max`.
+fn estimate(args: &[String]) {
+    let mut path = None;
+    let mut max = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max" => {
+                max = match args.next().and_then(|time| parse_time_arg(time)) {
+                    Some(max) => Some(max),
+                    None => {
+                        error_message("`--max` expects a time like `5min`");
+                        exit(2);
+                    }
+                }
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    let Some(path) = path else {
+        eprintln!("{USAGE}");
+        exit(2);
+    };
 
-    let interpreter = Interpreter::new();
-    let res = interpreter.eval_expr(&expr);
-    println!("{res:#?}");
+    let mut ast = load_script(path);
+    // Names are resolved first, so the estimator sees which declaration
+    // each one refers to across imported files
+    if Resolver::new().resolve(&mut ast).is_err() {
+        exit(1);
+    }
+    let ast = Box::leak(Box::new(ast));
+
+    let estimate = match Estimator::new().estimate(ast) {
+        Ok(estimate) => estimate,
+        Err(()) => exit(1),
+    };
+
+    println!("min: {}", format_ms(estimate.lo));
+    println!("max: {}", format_ms(estimate.hi));
+
+    if let Some(max) = max
+        && estimate.hi > max
+    {
+        error_message(&format!(
+            "The script could wait for {}, which is more than {}",
+            format_ms(estimate.hi),
+            format_ms(max)
+        ));
+        exit(1);
+    }
+}
+
+/// Reads and parses a script with everything it imports, exiting if that fails
+fn load_script(path: &str) -> Ast {
+    match modules::load(path) {
+        Some(ast) => ast,
+        None => exit(1),
+    }
+}
+
+/// Parses a time given on the command line like `90s`, in milliseconds
+fn parse_time_arg(arg: &str) -> Option<f32> {
+    match lexer(arg).ok()?.as_slice() {
+        [Token {
+            kind: TokenKind::Time(num, time_kind),
+            ..
+        }, eof]
+            if eof.kind == TokenKind::Eof =>
+        {
+            Some(num * time_kind.as_ms())
+        }
+        _ => None,
+    }
 }
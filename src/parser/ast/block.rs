@@ -1,11 +1,14 @@
+use super::expr::Expr;
 use super::stmt::Stmt;
 use super::{Span, Type};
-use thin_vec::ThinVec;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Block {
     pub stmts: Box<[Stmt]>,
+    /// The last expression if it has no semicolon, which is the value of the block
+    pub value: Option<Box<Expr>>,
     pub span: Span,
+    /// The type of the value, [`Type::Unit`] without one
     pub type_: Type,
 }
 
@@ -13,6 +16,7 @@ impl Block {
     pub fn new(span: Span, type_: Type) -> Self {
         Self {
             stmts: Box::new([]),
+            value: None,
             span,
             type_,
         }
@@ -1,6 +1,12 @@
+use std::fmt::Display;
 use thin_vec::ThinVec;
 
-use super::{lit::Lit, Span, Type};
+use super::{
+    block::Block,
+    lit::{Lit, Stamp},
+    pattern::Arm,
+    Span, StructId, Type,
+};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Expr {
@@ -45,6 +51,49 @@ pub enum ExprKind {
     /// ```
     FnCall(Ident, Box<[Expr]>),
 
+    /// Call of a function value, the resolver turns a [`ExprKind::FnCall`]
+    /// of a variable into this
+    ///
+    /// ## Example
+    /// ```rust
+    /// make_backoff(2)(1s)
+    /// ```
+    Call(Box<Expr>, Box<[Expr]>),
+
+    /// Timestamp, which depends on the clock when a time of day is given without a date
+    ///
+    /// ## Example
+    /// ```rust
+    /// @2026-10-18T17:00
+    /// ```
+    Timestamp(Stamp),
+
+    /// Closure, which captures the variables of the code around it it uses
+    ///
+    /// ## Example
+    /// ```rust
+    /// |t: time| -> time { t * 2 }
+    /// ```
+    Closure(Box<Closure>),
+
+    /// Starts running a block as a task next to the code around it, which
+    /// the block captures the variables of like a closure
+    ///
+    /// ## Example
+    /// ```rust
+    /// spawn { wait(5s); }
+    /// ```
+    Spawn(Box<Closure>),
+
+    /// A function declared with `func` used as a value, the resolver turns a
+    /// [`ExprKind::Ident`] naming one into this
+    ///
+    /// ## Example
+    /// ```rust
+    /// double
+    /// ```
+    FnRef(Ident),
+
     /// Method Call
     ///
     /// ## Example
@@ -89,17 +138,99 @@ pub enum ExprKind {
     /// (a+b) * c
     /// ```
     Grouping(Box<Expr>),
+
+    /// Block with a value, which is its last expression without a semicolon
+    ///
+    /// ## Example
+    /// ```rust
+    /// {
+    ///     time base = 1s;
+    ///     base * 3
+    /// }
+    /// ```
+    Block(Box<Block>),
+
+    /// Struct literal, the resolver puts the fields in the order of the declaration
+    ///
+    /// ## Example
+    /// ```rust
+    /// Job { timeout: 30s, retries: 3 }
+    /// ```
+    StructLit(StructId, Vec<(Ident, Expr)>),
+
+    /// List literal
+    ///
+    /// ## Example
+    /// ```rust
+    /// [1s, 2s, 4s]
+    /// ```
+    List(Vec<Expr>),
+
+    /// Indexing into a list, starting at 0
+    ///
+    /// ## Example
+    /// ```rust
+    /// delays[2]
+    /// ```
+    Index(Box<Expr>, Box<Expr>),
+
+    /// If with a value, the else branch is another block or if
+    ///
+    /// ## Example
+    /// ```rust
+    /// if (fast) { 1s } else { 1min }
+    /// ```
+    If(Box<Expr>, Box<Block>, Box<Expr>),
+
+    /// Match with a value, every arm gives one
+    ///
+    /// ## Example
+    /// ```rust
+    /// match state {
+    ///     State::Pending => 1s,
+    ///     _ => 0s,
+    /// }
+    /// ```
+    Match(Box<Expr>, Vec<Arm<Expr>>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Closure {
+    pub args: Box<[(Ident, Type)]>,
+    pub body: Box<Block>,
+    /// [`Type::Unit`] until the resolver infers it, unless it's given
+    pub return_type: Type,
+    /// The variables of the code around it that it uses, filled in by the
+    /// resolver and bound where the closure is created
+    ///
+    /// Their values are copied into the closure and follow its arguments.
+    pub captures: Vec<Ident>,
+}
+
+impl Closure {
+    pub fn type_(&self) -> Type {
+        Type::function(
+            self.args.iter().map(|(_, type_)| *type_).collect(),
+            self.return_type,
+        )
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Ident {
     pub name: &'static str,
     pub span: Span,
+    /// The declaration a use refers to, filled in by the resolver
+    pub binding: Option<Binding>,
 }
 
 impl Ident {
     pub fn new(name: &'static str, span: Span) -> Self {
-        Self { name, span }
+        Self {
+            name,
+            span,
+            binding: None,
+        }
     }
 
     pub fn as_str(&self) -> &str {
@@ -107,6 +238,16 @@ impl Ident {
     }
 }
 
+/// Where the declaration of a variable or function can be found at runtime
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Binding {
+    /// Declared at the top level of the script, `slot` in declaration order
+    Global(usize),
+    /// Declared in the function or block that is running, `depth` scopes
+    /// outwards from the use and `slot` in declaration order
+    Local { depth: usize, slot: usize },
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum BinOp {
     /// Addition
@@ -139,10 +280,63 @@ pub enum BinOp {
     Ge,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+impl BinOp {
+    /// The type of the result, [`Type::Unit`] if it isn't known before running
+    pub fn result_type(&self, left: Type, right: Type) -> Type {
+        match self {
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => match (left, right) {
+                (Type::Timestamp, Type::Time) if matches!(self, BinOp::Add | BinOp::Sub) => {
+                    Type::Timestamp
+                }
+                (Type::Time, Type::Timestamp) if *self == BinOp::Add => Type::Timestamp,
+                (Type::Timestamp, Type::Timestamp) if *self == BinOp::Sub => Type::Time,
+                // How many times one time fits into the other
+                (Type::Time, Type::Time) if *self == BinOp::Div => Type::Number,
+                (Type::Time, Type::Time | Type::Number) | (Type::Number, Type::Time) => Type::Time,
+                (Type::Number, Type::Number) => Type::Number,
+                _ => Type::Unit,
+            },
+            _ => Type::Bool,
+        }
+    }
+}
+
+impl Display for BinOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let out = match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+            BinOp::Xor => "^^",
+            BinOp::EqEq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+        };
+
+        write!(f, "{out}")
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum UnOp {
     /// Boolean not
     Not,
     /// Negation
     Neg,
 }
+
+impl UnOp {
+    pub fn result_type(&self, target: Type) -> Type {
+        match self {
+            UnOp::Not => Type::Bool,
+            UnOp::Neg => target,
+        }
+    }
+}
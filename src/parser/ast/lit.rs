@@ -1,4 +1,5 @@
-use super::{Span, Type};
+use super::{EnumId, FnType, StructId, Type};
+use crate::runtime::calendar::days_in_month;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Lit(pub LitKind);
@@ -54,6 +55,136 @@ pub enum LitKind {
     Time(f32, TimeKind),
     // Boolean
     Bool(bool),
+    /// Struct, stored on the heap of the runtime at the given index
+    Struct(StructId, usize),
+    /// List with elements of the given type, stored on the heap of the runtime
+    /// at the given index
+    List(&'static Type, usize),
+    /// Variant of an enum, given by its position in the declaration
+    Enum(EnumId, usize),
+    /// Function or closure of the given type, stored on the heap of the
+    /// runtime at the given index
+    Function(&'static FnType, usize),
+    /// String, never freed like the values on the heap
+    Str(&'static str),
+    /// Task started with `spawn`, by its number in the scheduler of the runtime
+    Task(usize),
+    /// Point in time, in milliseconds since the Unix epoch in UTC
+    Timestamp(i64),
+}
+
+impl LitKind {
+    pub fn type_(&self) -> Type {
+        match self {
+            LitKind::Num(_) => Type::Number,
+            LitKind::Time(..) => Type::Time,
+            LitKind::Bool(_) => Type::Bool,
+            LitKind::Struct(id, _) => Type::Struct(*id),
+            LitKind::List(elem, _) => Type::List(elem),
+            LitKind::Enum(id, _) => Type::Enum(*id),
+            LitKind::Function(function, _) => Type::Function(function),
+            LitKind::Str(_) => Type::Str,
+            LitKind::Task(_) => Type::Task,
+            LitKind::Timestamp(_) => Type::Timestamp,
+        }
+    }
+
+    /// How the type is called in the source code
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            LitKind::Num(_) => "num",
+            LitKind::Time(..) => "time",
+            LitKind::Bool(_) => "bool",
+            LitKind::Struct(id, _) => id.name,
+            LitKind::List(..) => "list",
+            LitKind::Enum(id, _) => id.name,
+            LitKind::Function(..) => "function",
+            LitKind::Str(_) => "string",
+            LitKind::Task(_) => "task",
+            LitKind::Timestamp(_) => "timestamp",
+        }
+    }
+}
+
+/// A timestamp as written after `@`, which only becomes a
+/// [`LitKind::Timestamp`] while running since it depends on the clock
+///
+/// ## Example
+/// ```rust
+/// @2026-10-18T17:00
+/// @17:00
+/// @17:00Z
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Stamp {
+    /// Year, month and day, `None` for the next time the clock shows the time of day
+    pub date: Option<(i64, u32, u32)>,
+    /// Seconds since midnight
+    pub time: u32,
+    /// Whether it's in UTC rather than local time
+    pub utc: bool,
+}
+
+impl Stamp {
+    /// Reads a date like `2026-10-18`, a time of day like `17:00` or `17:00:30`,
+    /// or a date and time joined by `T`, each in UTC if it ends with `Z`
+    pub fn parse(text: &str) -> Option<Self> {
+        let (text, utc) = match text.strip_suffix('Z') {
+            Some(text) => (text, true),
+            None => (text, false),
+        };
+
+        let (date, time) = match text.split_once('T') {
+            Some((date, time)) => (Some(date), Some(time)),
+            None if text.contains('-') => (Some(text), None),
+            None => (None, Some(text)),
+        };
+
+        let date = match date {
+            Some(date) => {
+                let [year, month, day] = numbers(date, '-')?[..] else {
+                    return None;
+                };
+                let (month, day) = (month as u32, day as u32);
+
+                if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+                    return None;
+                }
+
+                Some((year, month, day))
+            }
+            None => None,
+        };
+
+        let time = match time {
+            Some(time) => {
+                let (hours, minutes, seconds) = match numbers(time, ':')?[..] {
+                    [hours, minutes] => (hours, minutes, 0),
+                    [hours, minutes, seconds] => (hours, minutes, seconds),
+                    _ => return None,
+                };
+
+                if hours > 23 || minutes > 59 || seconds > 59 {
+                    return None;
+                }
+
+                (hours * 3600 + minutes * 60 + seconds) as u32
+            }
+            None => 0,
+        };
+
+        Some(Self { date, time, utc })
+    }
+}
+
+/// The numbers between the separators, which have to be there
+fn numbers(text: &str, separator: char) -> Option<Vec<i64>> {
+    text.split(separator)
+        .map(|part| match part.is_empty() || part.len() > 4 {
+            true => None,
+            false => part.parse().ok(),
+        })
+        .collect()
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -79,7 +210,7 @@ impl TimeKind {
         match self {
             TimeKind::Ms => 1.0,
             TimeKind::Sec => 1000.0,
-            TimeKind::Min => 3600.0,
+            TimeKind::Min => 60_000.0,
             TimeKind::Hour => 3_600_000.0,
             TimeKind::Day => 86_400_000.0,
             TimeKind::Week => 604_800_000.0,
@@ -1,25 +1,138 @@
 use std::fmt::Display;
 
-use self::stmt::Stmt;
+use self::{expr::Ident, stmt::Stmt};
 
 pub mod block;
 pub mod expr;
 pub mod lit;
+pub mod pattern;
 pub mod stmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Ast {
     pub program: Vec<Stmt>,
+    /// The structs declared in the script, in the order of their [`StructId`]s
+    pub structs: Vec<StructDef>,
+    /// The enums declared in the script, in the order of their [`EnumId`]s
+    pub enums: Vec<EnumDef>,
+    /// The names the script itself imported
+    pub namespace: Namespace,
 }
 
 impl Ast {
     pub fn new() -> Self {
         Self {
             program: Vec::new(),
+            structs: Vec::new(),
+            enums: Vec::new(),
+            namespace: Namespace::default(),
         }
     }
 }
 
+/// The names a file can use besides its own and the built-in ones
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Namespace {
+    /// What the declarations at the top level of the file are prefixed with,
+    /// `None` for the script itself
+    pub prefix: Option<&'static str>,
+    /// Names brought in with `import module::{name}` and the full names they
+    /// stand for
+    pub imported: Vec<(&'static str, &'static str)>,
+}
+
+impl Namespace {
+    /// The full name of a declaration at the top level of the file
+    pub fn qualify(&self, name: &'static str) -> &'static str {
+        match self.prefix {
+            Some(prefix) => Box::leak(format!("{prefix}::{name}").into_boxed_str()),
+            None => name,
+        }
+    }
+
+    /// The full names a name used in the file could stand for, its own
+    /// declarations first
+    pub fn candidates(&self, name: &str) -> Vec<String> {
+        // Already qualified with the namespace of another file
+        if name.contains("::") {
+            return vec![name.to_string()];
+        }
+
+        let mut candidates = vec![match self.prefix {
+            Some(prefix) => format!("{prefix}::{name}"),
+            None => name.to_string(),
+        }];
+        candidates.extend(
+            self.imported
+                .iter()
+                .filter(|(imported, _)| *imported == name)
+                .map(|(_, full)| full.to_string()),
+        );
+
+        candidates
+    }
+}
+
+/// Struct declaration, only allowed at the top level of a script
+///
+/// ## Example
+/// ```rust
+/// struct Job {
+///     timeout: time,
+///     retries: num,
+/// }
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct StructDef {
+    pub id: StructId,
+    pub fields: Vec<(Ident, Type)>,
+}
+
+impl StructDef {
+    /// The position and type of a field
+    pub fn field(&self, name: &str) -> Option<(usize, Type)> {
+        self.fields
+            .iter()
+            .position(|(field, _)| field.name == name)
+            .map(|index| (index, self.fields[index].1))
+    }
+}
+
+/// Which of the structs declared in the script a struct type is
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct StructId {
+    pub index: usize,
+    pub name: &'static str,
+}
+
+/// Enum declaration, only allowed at the top level of a script
+///
+/// ## Example
+/// ```rust
+/// enum State { Pending, Running, Done }
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct EnumDef {
+    pub id: EnumId,
+    pub variants: Vec<Ident>,
+}
+
+impl EnumDef {
+    /// The position of a variant, which is how values of the enum are stored
+    pub fn variant(&self, name: &str) -> Option<usize> {
+        self.variants
+            .iter()
+            .position(|variant| variant.name == name)
+    }
+}
+
+/// Which of the enums declared in the script an enum type is
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct EnumId {
+    pub index: usize,
+    pub name: &'static str,
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Span {
     pub start: usize,
@@ -75,4 +188,96 @@ pub enum Type {
     Bool,
     User,
     Unit,
+    /// A struct declared in the script
+    Struct(StructId),
+    /// An enum declared in the script
+    Enum(EnumId),
+    /// A list with elements of the given type, [`Type::Unit`] while that isn't known
+    List(&'static Type),
+    /// A function or closure used as a value
+    Function(&'static FnType),
+    /// Text, like `"1min 30s"`
+    Str,
+    /// A task started with `spawn`
+    Task,
+    /// A point in time, like `@2026-10-18T17:00`
+    Timestamp,
+}
+
+/// The arguments and return type of a function value
+#[derive(Debug, PartialEq, Clone)]
+pub struct FnType {
+    pub args: Vec<Type>,
+    /// [`Type::Unit`] if it isn't known
+    pub return_type: Type,
+}
+
+impl Type {
+    /// The type both of two values fit, where [`Type::Unit`] stands for one
+    /// that isn't known before running
+    pub fn unify(self, other: Self) -> Option<Self> {
+        match (self, other) {
+            (a, b) if a == b => Some(a),
+            (Type::Unit, type_) | (type_, Type::Unit) => Some(type_),
+            // The elements of an empty list fit any type
+            (Type::List(a), Type::List(b)) => match a.unify(*b)? == *a {
+                true => Some(self),
+                false => Some(other),
+            },
+            _ => None,
+        }
+    }
+
+    /// The type of lists with elements of this type
+    pub fn list_of(self) -> Self {
+        Type::List(Box::leak(Box::new(self)))
+    }
+
+    /// The type of functions taking and returning these types
+    pub fn function(args: Vec<Type>, return_type: Type) -> Self {
+        Type::Function(Box::leak(Box::new(FnType { args, return_type })))
+    }
+
+    /// Looks up the built-in type a type name in the source code stands for
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "time" => Some(Type::Time),
+            "num" => Some(Type::Number),
+            "bool" => Some(Type::Bool),
+            "user" => Some(Type::User),
+            "string" => Some(Type::Str),
+            "task" => Some(Type::Task),
+            "timestamp" => Some(Type::Timestamp),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Type::List(elem) = self {
+            return write!(f, "[{elem}]");
+        }
+
+        if let Type::Function(function) = self {
+            let args: Vec<String> = function.args.iter().map(Type::to_string).collect();
+            return write!(f, "|{}| -> {}", args.join(", "), function.return_type);
+        }
+
+        let out = match self {
+            Type::Time => "time",
+            Type::Number => "num",
+            Type::Bool => "bool",
+            Type::User => "user",
+            Type::Str => "string",
+            Type::Task => "task",
+            Type::Timestamp => "timestamp",
+            Type::Unit => "unit",
+            Type::Struct(id) => id.name,
+            Type::Enum(id) => id.name,
+            Type::List(_) | Type::Function(_) => unreachable!(),
+        };
+
+        write!(f, "{out}")
+    }
 }
@@ -0,0 +1,47 @@
+use super::{expr::Expr, lit::Lit, Span, Type};
+
+/// One arm of a `match`, `body` is an expression or a block
+#[derive(Debug, PartialEq, Clone)]
+pub struct Arm<B> {
+    pub pattern: Pattern,
+    /// Extra condition written after `if`, the arm is skipped when it's false
+    pub guard: Option<Expr>,
+    pub body: B,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Pattern {
+    pub pattern_kind: PatternKind,
+    pub span: Span,
+}
+
+/// What the value of a `match` is compared with
+#[derive(Debug, PartialEq, Clone)]
+pub enum PatternKind {
+    /// `_`, matches every value
+    Wildcard,
+    /// A number, time, boolean or enum variant the value has to be equal to
+    Lit(Lit),
+    /// Numbers or times from `start` up to `end`, which is only part of it with `..=`
+    ///
+    /// ## Example
+    /// ```rust
+    /// 0s..1min
+    /// ```
+    Range {
+        start: Lit,
+        end: Lit,
+        inclusive: bool,
+    },
+}
+
+impl PatternKind {
+    /// The type of the values the pattern can match, `None` for all of them
+    pub fn type_(&self) -> Option<Type> {
+        match self {
+            PatternKind::Wildcard => None,
+            PatternKind::Lit(lit) => Some(lit.0.type_()),
+            PatternKind::Range { start, .. } => Some(start.0.type_()),
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use super::block::Block;
-use super::expr::{Expr, Ident};
-use super::{Span, Type};
+use super::expr::{BinOp, Closure, Expr, Ident};
+use super::pattern::Arm;
+use super::{Namespace, Span, Type};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Stmt {
@@ -16,16 +17,45 @@ impl Stmt {
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum StmtKind {
-    /// Variable binding or declaration
+    /// Variable binding or declaration, constants can't be assigned to later
     ///
     /// ## Example
     /// ```c
     /// num myvar = 3;
+    /// const time timeout = 30s;
     /// ```
     VarBind {
         type_: Type,
         identifier: Ident,
         value: Box<Expr>,
+        is_const: bool,
+    },
+
+    /// Assignment to an existing variable, optionally combined with an operation
+    ///
+    /// ## Example
+    /// ```rust
+    /// a = a + 1s;
+    /// a += 1s;
+    /// ```
+    Assign {
+        target: Ident,
+        op: Option<BinOp>,
+        value: Box<Expr>,
+    },
+
+    /// Assignment to a field of a struct, optionally combined with an operation
+    ///
+    /// ## Example
+    /// ```rust
+    /// job.timeout = 1min;
+    /// job.retries += 1;
+    /// ```
+    FieldAssign {
+        receiver: Box<Expr>,
+        field: Ident,
+        op: Option<BinOp>,
+        value: Box<Expr>,
     },
 
     /// Any expression followed by a semicolon
@@ -36,28 +66,134 @@ pub enum StmtKind {
     /// ```
     Expr(Box<Expr>),
 
-    // An if statement
-    //
-    // ## Example
-    // ```rust
-    // if (a > 3) {
-    //    // true block
-    // } else if (a < 3) {
-    //   // else if true block
-    // } else {
-    //  // false block
-    // }
-    If(Box<Expr>, Box<Block>, Option<Box<Expr>>),
+    /// An if statement
+    ///
+    /// ## Example
+    /// ```rust
+    /// if (a > 3) {
+    ///    // true block
+    /// } else if (a < 3) {
+    ///   // else if true block
+    /// } else {
+    ///  // false block
+    /// }
+    /// ```
+    If(Box<Expr>, Box<Block>, Option<Else>),
 
-    /// A while loop
+    /// A match statement, runs the block of the first arm that matches the value
+    ///
+    /// ## Example
+    /// ```rust
+    /// match elapsed {
+    ///     0s..1min => wait(1s),
+    ///     _ if retries > 3 => {}
+    ///     _ => wait(10s),
+    /// }
+    /// ```
+    Match(Box<Expr>, Vec<Arm<Box<Block>>>),
+
+    /// A while loop with an optional label
     ///
     /// ## Example
     /// ```rust
     /// while (a > 3) {
     ///    // block
     /// }
+    ///
+    /// outer: while (a > 3) {
+    ///    // block
+    /// }
+    /// ```
+    While(Box<Expr>, Box<Block>, Option<Ident>),
+
+    /// A loop over a range of numbers or times with an optional label
+    ///
+    /// ## Example
+    /// ```rust
+    /// for i in 0..10 {
+    ///    // block
+    /// }
+    ///
+    /// for t in 0s..=5min step 10s {
+    ///    // block
+    /// }
     /// ```
-    While(Box<Expr>, Box<Block>),
+    For {
+        var: Ident,
+        range: Range,
+        block: Box<Block>,
+        label: Option<Ident>,
+    },
+
+    /// A loop over the elements of a list with an optional label
+    ///
+    /// ## Example
+    /// ```rust
+    /// for delay in [1s, 2s, 4s] {
+    ///    // block
+    /// }
+    /// ```
+    ForEach {
+        var: Ident,
+        list: Box<Expr>,
+        block: Box<Block>,
+        label: Option<Ident>,
+    },
+
+    /// A loop run on a schedule with an optional label, every interval or
+    /// whenever a cron expression matches, at most the given number of times
+    /// and only starting runs for the given time
+    ///
+    /// ## Example
+    /// ```rust
+    /// every 10min times 6 {
+    ///    // block
+    /// }
+    ///
+    /// every "0 9 * * 1-5" for 4w {
+    ///    // block
+    /// }
+    /// ```
+    Every {
+        schedule: Box<Expr>,
+        times: Option<Box<Expr>>,
+        duration: Option<Box<Expr>>,
+        block: Box<Block>,
+        label: Option<Ident>,
+    },
+
+    /// A block run again after a pause when it fails, by ending with `false`
+    /// or with an error, with the number of the attempt in `attempt`
+    ///
+    /// ## Example
+    /// ```rust
+    /// retry (attempts: 5, backoff: exponential(1s, max: 1min), jitter: 20%) {
+    ///     deploy(attempt)
+    /// }
+    /// ```
+    Retry(Box<RetryOptions>, Box<Block>),
+
+    /// A block that runs instead of the rest of the script when it's
+    /// interrupted with Ctrl-C, the one registered last does
+    ///
+    /// ## Example
+    /// ```rust
+    /// on_interrupt { wait(1s); }
+    /// ```
+    OnInterrupt(Box<Closure>),
+
+    /// A block whose waits are cut short once the time is up, running the
+    /// fallback block instead of the rest of it then
+    ///
+    /// ## Example
+    /// ```rust
+    /// within 30s {
+    ///     wait_for_build();
+    /// } else {
+    ///     wait(1min);
+    /// }
+    /// ```
+    Within(Box<Expr>, Box<Block>, Option<Box<Block>>),
 
     /// A function definition
     ///
@@ -74,6 +210,24 @@ pub enum StmtKind {
         return_type: Type,
     },
 
+    /// Leaves the innermost loop or the loop with the given label
+    ///
+    /// ## Example
+    /// ```rust
+    /// break;
+    /// break outer;
+    /// ```
+    Break(Option<Ident>),
+
+    /// Skips to the next iteration of the innermost loop or the loop with the given label
+    ///
+    /// ## Example
+    /// ```rust
+    /// continue;
+    /// continue outer;
+    /// ```
+    Continue(Option<Ident>),
+
     /// A return statement
     ///
     /// ## Example
@@ -81,4 +235,73 @@ pub enum StmtKind {
     /// return 3;
     /// ```
     Return(Box<Expr>),
+
+    /// The code of an imported file, which runs where it's first imported
+    ///
+    /// ## Example
+    /// ```rust
+    /// import "lib/backoff.wait";
+    /// ```
+    Module(Box<Module>),
+}
+
+/// A file imported by the script, its declarations are named `prefix::name`
+/// everywhere else
+#[derive(Debug, PartialEq, Clone)]
+pub struct Module {
+    pub path: &'static str,
+    pub namespace: Namespace,
+    pub program: Vec<Stmt>,
+}
+
+/// What an `if` runs when its condition is false
+#[derive(Debug, PartialEq, Clone)]
+pub enum Else {
+    Block(Box<Block>),
+    /// An `else if`, the `if` is a statement of its own
+    If(Box<Stmt>),
+}
+
+/// How often a `retry` block tries and how long it pauses in between
+#[derive(Debug, PartialEq, Clone)]
+pub struct RetryOptions {
+    /// Three if it's left out
+    pub attempts: Option<Expr>,
+    /// No pause if it's left out
+    pub backoff: Option<Backoff>,
+    /// The share the pauses are moved up or down by at random
+    pub jitter: Option<Expr>,
+}
+
+/// The pauses between the attempts of a `retry` block
+#[derive(Debug, PartialEq, Clone)]
+pub struct Backoff {
+    pub kind: BackoffKind,
+    /// The pause after the first attempt
+    pub delay: Expr,
+    /// The longest pause
+    pub max: Option<Expr>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BackoffKind {
+    /// The same pause every time, written as just the time
+    Constant,
+    /// Grows by the first pause every time, like `linear(1s)`
+    Linear,
+    /// Doubles every time, like `exponential(1s)`
+    Exponential,
+}
+
+/// The values a `for` loop goes through
+#[derive(Debug, PartialEq, Clone)]
+pub struct Range {
+    pub start: Box<Expr>,
+    pub end: Box<Expr>,
+    /// Whether `end` is part of the range, written `..=`
+    pub inclusive: bool,
+    /// How far apart the values are, `1` or `1s` if it's left out
+    pub step: Option<Box<Expr>>,
+    /// The type of the values, which is also the type of the loop variable
+    pub type_: Type,
 }
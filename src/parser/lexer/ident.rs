@@ -23,6 +23,24 @@ pub fn parse_ident(remaining: &mut String, code_index: &mut usize) -> Token {
         "true" => TokenKind::Bool(true),
         "false" => TokenKind::Bool(false),
         "func" => TokenKind::Func,
+        "if" => TokenKind::If,
+        "while" => TokenKind::While,
+        "return" => TokenKind::Return,
+        "const" => TokenKind::Const,
+        "break" => TokenKind::Break,
+        "continue" => TokenKind::Continue,
+        "for" => TokenKind::For,
+        "in" => TokenKind::In,
+        "else" => TokenKind::Else,
+        "struct" => TokenKind::Struct,
+        "enum" => TokenKind::Enum,
+        "match" => TokenKind::Match,
+        "import" => TokenKind::Import,
+        "spawn" => TokenKind::Spawn,
+        "within" => TokenKind::Within,
+        "every" => TokenKind::Every,
+        "retry" => TokenKind::Retry,
+        "on_interrupt" => TokenKind::OnInterrupt,
         _ => TokenKind::Ident(ident),
     };
 
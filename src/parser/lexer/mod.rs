@@ -9,7 +9,23 @@ mod ident;
 mod num;
 pub mod token;
 
-pub fn lexer(code: &str) -> Result<Vec<Token>, ()> {
+/// Code that isn't made of tokens, with where in the file it is
+#[derive(Debug, PartialEq)]
+pub struct LexError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl LexError {
+    fn new(span: Span, message: &str) -> Self {
+        Self {
+            span,
+            message: String::from(message),
+        }
+    }
+}
+
+pub fn lexer(code: &str) -> Result<Vec<Token>, LexError> {
     let mut tokens = vec![];
     let mut remaining = String::from(code);
     let mut code_index = 0;
@@ -20,27 +36,102 @@ pub fn lexer(code: &str) -> Result<Vec<Token>, ()> {
             ')' => tokens.push(make_simple_token(TokenKind::CloseBracket, code_index)),
             '{' => tokens.push(make_simple_token(TokenKind::OpenCurlBracket, code_index)),
             '}' => tokens.push(make_simple_token(TokenKind::CloseCurlBracket, code_index)),
-            '+' => tokens.push(make_simple_token(TokenKind::Add, code_index)),
-            '*' => tokens.push(make_simple_token(TokenKind::Mul, code_index)),
-            '/' => tokens.push(make_simple_token(TokenKind::Div, code_index)),
+            '[' => tokens.push(make_simple_token(TokenKind::OpenSquareBracket, code_index)),
+            ']' => tokens.push(make_simple_token(TokenKind::CloseSquareBracket, code_index)),
+            '+' => tokens.push(make_maybe_eq_token(
+                &mut remaining,
+                &mut code_index,
+                TokenKind::Add,
+                TokenKind::AddEq,
+            )),
+            '*' => tokens.push(make_maybe_eq_token(
+                &mut remaining,
+                &mut code_index,
+                TokenKind::Mul,
+                TokenKind::MulEq,
+            )),
+            '/' => {
+                let is_comment = match remaining.chars().nth(1) {
+                    Some(next_char) => next_char == '/',
+                    None => false,
+                };
+
+                if is_comment {
+                    // Skip everything up to the newline, which is removed below
+                    while let Some(next_char) = remaining.chars().nth(1) {
+                        if next_char == '\n' {
+                            break;
+                        }
+
+                        code_index += 1;
+                        remaining.remove(0);
+                    }
+                } else {
+                    tokens.push(make_maybe_eq_token(
+                        &mut remaining,
+                        &mut code_index,
+                        TokenKind::Div,
+                        TokenKind::DivEq,
+                    ));
+                }
+            }
             '%' => tokens.push(make_simple_token(TokenKind::Mod, code_index)),
             '-' => {
-                remaining.chars().next();
-                let is_minus = match remaining.chars().next() {
+                let is_minus = match remaining.chars().nth(1) {
                     Some(next_char) => next_char != '>',
                     None => true,
                 };
 
                 if is_minus {
-                    tokens.push(make_simple_token(TokenKind::Sub, code_index));
+                    tokens.push(make_maybe_eq_token(
+                        &mut remaining,
+                        &mut code_index,
+                        TokenKind::Sub,
+                        TokenKind::SubEq,
+                    ));
                 } else {
                     tokens.push(Token::new(
                         TokenKind::Arrow,
                         Span::new(code_index, code_index + 1),
                     ));
+
+                    code_index += 1;
+                    remaining.remove(0);
+                }
+            }
+            '.' => {
+                if remaining.chars().nth(1) != Some('.') {
+                    tokens.push(make_simple_token(TokenKind::Dot, code_index));
+                } else {
+                    let start = code_index;
+                    remaining.remove(0);
+                    code_index += 1;
+
+                    let kind = if remaining.chars().nth(1) == Some('=') {
+                        remaining.remove(0);
+                        code_index += 1;
+                        TokenKind::DotDotEq
+                    } else {
+                        TokenKind::DotDot
+                    };
+
+                    tokens.push(Token::new(kind, Span::new(start, code_index)));
+                }
+            }
+            ':' => {
+                if remaining.chars().nth(1) == Some(':') {
+                    tokens.push(Token::new(
+                        TokenKind::ColCol,
+                        Span::new(code_index, code_index + 1),
+                    ));
+
+                    code_index += 1;
+                    remaining.remove(0);
+                } else {
+                    tokens.push(make_simple_token(TokenKind::Col, code_index));
                 }
             }
-            ':' => tokens.push(make_simple_token(TokenKind::Col, code_index)),
+            ',' => tokens.push(make_simple_token(TokenKind::Comma, code_index)),
 
             ';' => tokens.push(make_simple_token(TokenKind::Semi, code_index)),
             'a'..='z' | 'A'..='Z' | '_' => {
@@ -59,35 +150,35 @@ pub fn lexer(code: &str) -> Result<Vec<Token>, ()> {
                         Span::new(code_index - 1, code_index),
                     ))
                 } else {
-                    return Err(());
+                    return Err(LexError::new(
+                        Span::from(code_index - 1),
+                        "Expected `&&`, a single `&` isn't an operator",
+                    ));
                 }
             }
             '|' => {
-                remaining.remove(0);
-                code_index += 1;
-
-                if let Some(next_char) = remaining.chars().next()
-                    && next_char == '|'
-                {
+                if remaining.chars().nth(1) == Some('|') {
                     tokens.push(Token::new(
                         TokenKind::Or,
-                        Span::new(code_index - 1, code_index),
-                    ))
+                        Span::new(code_index, code_index + 1),
+                    ));
+
+                    code_index += 1;
+                    remaining.remove(0);
                 } else {
-                    return Err(());
+                    // A single `|` goes around the arguments of a closure
+                    tokens.push(make_simple_token(TokenKind::Pipe, code_index));
                 }
             }
             '=' => {
-                let is_eq = match remaining.chars().nth(1) {
-                    Some(next_char) => next_char == '=',
-                    None => false,
+                let kind = match remaining.chars().nth(1) {
+                    Some('=') => Some(TokenKind::EqEq),
+                    Some('>') => Some(TokenKind::FatArrow),
+                    _ => None,
                 };
 
-                if is_eq {
-                    tokens.push(Token::new(
-                        TokenKind::Eq,
-                        Span::new(code_index, code_index + 1),
-                    ));
+                if let Some(kind) = kind {
+                    tokens.push(Token::new(kind, Span::new(code_index, code_index + 1)));
 
                     code_index += 1;
                     remaining.remove(0);
@@ -107,12 +198,13 @@ pub fn lexer(code: &str) -> Result<Vec<Token>, ()> {
                         Span::new(code_index - 1, code_index),
                     ));
                 } else {
-                    return Err(());
+                    return Err(LexError::new(
+                        Span::from(code_index - 1),
+                        "Expected `^^`, a single `^` isn't an operator",
+                    ));
                 }
             }
             '!' => {
-                tokens.push(make_simple_token(TokenKind::Not, code_index));
-
                 let is_ne = match remaining.chars().nth(1) {
                     Some(next_char) => next_char == '=',
                     None => false,
@@ -131,8 +223,6 @@ pub fn lexer(code: &str) -> Result<Vec<Token>, ()> {
                 }
             }
             '<' => {
-                tokens.push(make_simple_token(TokenKind::Not, code_index));
-
                 let is_le = match remaining.chars().nth(1) {
                     Some(next_char) => next_char == '=',
                     None => false,
@@ -151,14 +241,12 @@ pub fn lexer(code: &str) -> Result<Vec<Token>, ()> {
                 }
             }
             '>' => {
-                tokens.push(make_simple_token(TokenKind::Not, code_index));
-
-                let is_le = match remaining.chars().nth(1) {
+                let is_ge = match remaining.chars().nth(1) {
                     Some(next_char) => next_char == '=',
                     None => false,
                 };
 
-                if is_le {
+                if is_ge {
                     tokens.push(Token::new(
                         TokenKind::Ge,
                         Span::new(code_index, code_index + 1),
@@ -171,8 +259,55 @@ pub fn lexer(code: &str) -> Result<Vec<Token>, ()> {
                 }
             }
             '0'..='9' => tokens.push(parse_num(&mut remaining, &mut code_index)),
-            ' ' | '\n' | '\r' => {}
-            _ => return Err(()),
+            '"' => {
+                let start = code_index;
+                let mut str = String::new();
+
+                loop {
+                    remaining.remove(0);
+                    code_index += 1;
+
+                    match remaining.chars().next() {
+                        Some('"') => break,
+                        Some('\n') | None => {
+                            return Err(LexError::new(
+                                Span::new(start, code_index),
+                                "Unterminated string",
+                            ));
+                        }
+                        Some(next_char) => str.push(next_char),
+                    }
+                }
+
+                tokens.push(Token::new(
+                    TokenKind::Str(str),
+                    Span::new(start, code_index),
+                ));
+            }
+            '@' => {
+                let start = code_index;
+                let mut stamp = String::new();
+
+                while let Some(next_char) = remaining.chars().nth(1)
+                    && (next_char.is_ascii_digit() || matches!(next_char, '-' | ':' | 'T' | 'Z'))
+                {
+                    stamp.push(next_char);
+                    remaining.remove(0);
+                    code_index += 1;
+                }
+
+                tokens.push(Token::new(
+                    TokenKind::Stamp(stamp),
+                    Span::new(start, code_index),
+                ));
+            }
+            ' ' | '\n' | '\r' | '\t' => {}
+            unexpected => {
+                return Err(LexError {
+                    span: Span::from(code_index),
+                    message: format!("Unexpected character `{unexpected}`"),
+                });
+            }
         }
         code_index += 1;
         remaining.remove(0);
@@ -186,3 +321,43 @@ pub fn lexer(code: &str) -> Result<Vec<Token>, ()> {
 fn make_simple_token(token_kind: TokenKind, code_index: usize) -> Token {
     Token::new(token_kind, Span::from(code_index))
 }
+
+/// Makes `with_eq` if the next char is `=`, like `+=`, and `single` otherwise
+fn make_maybe_eq_token(
+    remaining: &mut String,
+    code_index: &mut usize,
+    single: TokenKind,
+    with_eq: TokenKind,
+) -> Token {
+    if remaining.chars().nth(1) == Some('=') {
+        remaining.remove(0);
+        *code_index += 1;
+
+        return Token::new(with_eq, Span::new(*code_index - 1, *code_index));
+    }
+
+    make_simple_token(single, *code_index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_that_isnt_tokens_is_an_error() {
+        assert_eq!(
+            lexer("wait(\"1s);"),
+            Err(LexError::new(Span::new(5, 10), "Unterminated string"))
+        );
+        assert_eq!(
+            lexer("\"a\nb\""),
+            Err(LexError::new(Span::new(0, 2), "Unterminated string"))
+        );
+        assert_eq!(
+            lexer("wait(1s) $"),
+            Err(LexError::new(Span::from(9), "Unexpected character `$`"))
+        );
+        assert!(lexer("true & false").is_err());
+        assert!(lexer("\"done\" ^^ x").is_ok());
+    }
+}
@@ -9,6 +9,12 @@ pub fn parse_num(remaining: &mut String, code_index: &mut usize) -> Token {
     while let Some(next_char) = remaining.chars().next() {
         match next_char {
             '0'..='9' => num.push(next_char),
+            // Only a decimal point if a digit follows, so `3.abs()` stays a method call
+            '.' if !num.contains('.')
+                && remaining.chars().nth(1).is_some_and(|c| c.is_ascii_digit()) =>
+            {
+                num.push(next_char)
+            }
             _ => break,
         }
 
@@ -16,31 +22,36 @@ pub fn parse_num(remaining: &mut String, code_index: &mut usize) -> Token {
         *code_index += 1;
     }
 
-    remaining.insert(0, last_char);
-    *code_index -= 1;
-
     let num = num.parse().unwrap();
 
-    let time_kind = if remaining.starts_with("ms") {
-        Some(TimeKind::Ms)
-    } else if remaining.starts_with('s') {
-        Some(TimeKind::Sec)
-    } else if remaining.starts_with("min") {
-        Some(TimeKind::Min)
-    } else if remaining.starts_with('h') {
-        Some(TimeKind::Hour)
-    } else if remaining.starts_with('d') {
-        Some(TimeKind::Day)
-    } else if remaining.starts_with('w') {
-        Some(TimeKind::Week)
-    } else if remaining.starts_with('y') {
-        Some(TimeKind::Year)
-    } else {
-        None
-    };
+    let time_kind = [
+        ("ms", TimeKind::Ms),
+        ("min", TimeKind::Min),
+        ("s", TimeKind::Sec),
+        ("h", TimeKind::Hour),
+        ("d", TimeKind::Day),
+        ("w", TimeKind::Week),
+        ("y", TimeKind::Year),
+    ]
+    .into_iter()
+    .find(|(suffix, _)| {
+        // `3sec` is a number followed by an identifier, not seconds
+        remaining.starts_with(suffix)
+            && !remaining[suffix.len()..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+    });
+
+    if let Some((suffix, _)) = time_kind {
+        for _ in 0..suffix.len() {
+            last_char = remaining.remove(0);
+            *code_index += 1;
+        }
+    }
+
+    remaining.insert(0, last_char);
+    *code_index -= 1;
 
     let token_kind = match time_kind {
-        Some(time_kind) => TokenKind::Time(num, time_kind),
+        Some((_, time_kind)) => TokenKind::Time(num, time_kind),
         None => TokenKind::Num(num),
     };
 
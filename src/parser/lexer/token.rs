@@ -58,22 +58,88 @@ pub enum TokenKind {
     OpenCurlBracket,
     /// Closing Curly Bracket
     CloseCurlBracket,
+    /// Opening square Bracket
+    OpenSquareBracket,
+    /// Closing square Bracket
+    CloseSquareBracket,
     /// Colon
     Col,
+    /// Double colon, between an enum and its variant
+    ColCol,
+    /// Comma
+    Comma,
+    /// Pipe, around the arguments of a closure
+    Pipe,
     /// Semicolon
     Semi,
     /// Arrow
     Arrow,
+    /// Fat arrow, between a pattern and its arm
+    FatArrow,
+    /// Dot, before a field or method
+    Dot,
+    /// Range
+    DotDot,
+    /// Inclusive range
+    DotDotEq,
     /// Initialization equal
     Eq,
+    /// Addition assignment
+    AddEq,
+    /// Subtraction assignment
+    SubEq,
+    /// Multiplication assignment
+    MulEq,
+    /// Division assignment
+    DivEq,
     /// Number
     Num(f32),
     /// Time in different units
     Time(f32, TimeKind),
     /// Boolean
     Bool(bool),
+    /// String, like the paths of imports
+    Str(String),
+    /// Timestamp, the text after the `@`
+    Stamp(String),
     /// function keyword
     Func,
+    /// if keyword
+    If,
+    /// while keyword
+    While,
+    /// return keyword
+    Return,
+    /// const keyword
+    Const,
+    /// break keyword
+    Break,
+    /// continue keyword
+    Continue,
+    /// for keyword
+    For,
+    /// in keyword
+    In,
+    /// else keyword
+    Else,
+    /// struct keyword
+    Struct,
+    /// enum keyword
+    Enum,
+    /// match keyword
+    Match,
+    /// import keyword
+    Import,
+    /// Starts a task
+    Spawn,
+    /// Runs a block with a deadline
+    Within,
+    /// Runs a block on a schedule
+    Every,
+    /// Runs a block again when it fails
+    Retry,
+    /// Runs a block when the script is interrupted
+    OnInterrupt,
     /// End of File
     Eof,
 }
@@ -96,6 +162,8 @@ impl Display for TokenKind {
                 }
             ),
             TokenKind::Bool(bool) => bool.to_string(),
+            TokenKind::Str(str) => format!("\"{str}\""),
+            TokenKind::Stamp(stamp) => format!("@{stamp}"),
             _ => String::from(match self {
                 TokenKind::Add => "+",
                 TokenKind::Sub => "-",
@@ -116,11 +184,42 @@ impl Display for TokenKind {
                 TokenKind::CloseBracket => ")",
                 TokenKind::OpenCurlBracket => "{",
                 TokenKind::CloseCurlBracket => "}",
+                TokenKind::OpenSquareBracket => "[",
+                TokenKind::CloseSquareBracket => "]",
                 TokenKind::Col => ":",
+                TokenKind::ColCol => "::",
+                TokenKind::Comma => ",",
+                TokenKind::Pipe => "|",
                 TokenKind::Semi => ";",
                 TokenKind::Arrow => "->",
+                TokenKind::FatArrow => "=>",
+                TokenKind::Dot => ".",
+                TokenKind::DotDot => "..",
+                TokenKind::DotDotEq => "..=",
                 TokenKind::Eq => "=",
+                TokenKind::AddEq => "+=",
+                TokenKind::SubEq => "-=",
+                TokenKind::MulEq => "*=",
+                TokenKind::DivEq => "/=",
                 TokenKind::Func => "func",
+                TokenKind::If => "if",
+                TokenKind::While => "while",
+                TokenKind::Return => "return",
+                TokenKind::Const => "const",
+                TokenKind::Break => "break",
+                TokenKind::Continue => "continue",
+                TokenKind::For => "for",
+                TokenKind::In => "in",
+                TokenKind::Else => "else",
+                TokenKind::Struct => "struct",
+                TokenKind::Enum => "enum",
+                TokenKind::Match => "match",
+                TokenKind::Import => "import",
+                TokenKind::Spawn => "spawn",
+                TokenKind::Within => "within",
+                TokenKind::Every => "every",
+                TokenKind::Retry => "retry",
+                TokenKind::OnInterrupt => "on_interrupt",
                 TokenKind::Eof => "",
                 _ => unreachable!(),
             }),
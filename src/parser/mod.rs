@@ -1,22 +1,25 @@
 use self::ast::Ast;
 use ast::{
-    expr::{BinOp, Expr, ExprKind, UnOp},
-    lit::{Lit, LitKind},
-    stmt::{Stmt, StmtKind},
-    Span, Type,
-};
-use lexer::{
-    lexer,
-    token::{Token, TokenKind},
+    block::Block,
+    expr::{BinOp, Closure, Expr, ExprKind, Ident, UnOp},
+    lit::{Lit, LitKind, Stamp},
+    pattern::{Arm, Pattern, PatternKind},
+    stmt::{Backoff, BackoffKind, Else, Range, RetryOptions, Stmt, StmtKind},
+    EnumDef, EnumId, Namespace, Span, StructDef, StructId, Type,
 };
+use lexer::token::{Token, TokenKind};
 use parse_error::ParseError;
 
 pub mod ast;
 pub mod lexer;
+pub mod modules;
 mod parse_error;
 
+/// Parses a script that doesn't import anything, scripts are loaded from
+/// their file with [`modules::load`] otherwise
+#[cfg(test)]
 pub fn parse(code: &str) -> Option<Ast> {
-    let tokens = lexer(code).unwrap();
+    let tokens = lexer::lexer(code).ok()?;
 
     let mut parser = Parser::new(tokens);
     parser.parse().ok()
@@ -25,24 +28,1065 @@ pub fn parse(code: &str) -> Option<Ast> {
 struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    /// The structs declared so far, their names are types from then on
+    structs: Vec<StructDef>,
+    /// The enums declared so far, like the structs
+    enums: Vec<EnumDef>,
+    /// The names of the file being parsed
+    namespace: Namespace,
+}
+
+/// An `import` at the start of a file
+struct Import {
+    /// The file, relative to the one importing it
+    path: String,
+    /// What the module is called in the importing file, its file name without `.wait`
+    name: &'static str,
+    /// Whether the module was written as a path in quotes instead of its name
+    is_file: bool,
+    /// The names imported with `::{...}`, `None` if the module is imported as a whole
+    names: Option<Vec<Ident>>,
+    /// Where the module is named in the import
+    span: Span,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self {
+            tokens,
+            current: 0,
+            structs: Vec::new(),
+            enums: Vec::new(),
+            namespace: Namespace::default(),
+        }
+    }
+
+    /// Parses the imports at the start of a file, which have to be loaded
+    /// before the rest of it can be parsed
+    fn imports(&mut self) -> Result<Vec<Import>, ParseError> {
+        let mut imports = Vec::new();
+
+        while self.r#match(vec![TokenKind::Import]) {
+            let start = self.peek().span.start;
+
+            let (path, name, is_file) = if let TokenKind::Str(path) = &self.peek().kind {
+                let path = path.clone();
+                let name = std::path::Path::new(&path)
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .unwrap_or_default();
+                let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+                self.advance();
+
+                (path, name, true)
+            } else {
+                let mut segments =
+                    vec![self.ident("Expected a module name or a path in quotes after `import`")?];
+                while self.check(TokenKind::ColCol)
+                    && matches!(
+                        self.tokens.get(self.current + 1).map(|next| &next.kind),
+                        Some(TokenKind::Ident(_))
+                    )
+                {
+                    self.advance();
+                    segments.push(self.ident("Expected a module name after `::`")?);
+                }
+
+                let path = segments
+                    .iter()
+                    .map(|segment| segment.name)
+                    .collect::<Vec<_>>()
+                    .join("/");
+                (path + ".wait", segments[segments.len() - 1].name, false)
+            };
+            let span = Span::new(start, self.previous().span.end);
+
+            let mut names = None;
+            if self.r#match(vec![TokenKind::ColCol]) {
+                self.consume(
+                    TokenKind::OpenCurlBracket,
+                    "Expected `{` and the names to import after `::`",
+                )?;
+
+                let mut idents: Vec<Ident> = Vec::new();
+                while !self.check(TokenKind::CloseCurlBracket) {
+                    idents.push(self.ident("Expected a name to import")?);
+
+                    if !self.r#match(vec![TokenKind::Comma]) {
+                        break;
+                    }
+                }
+
+                self.consume(
+                    TokenKind::CloseCurlBracket,
+                    "Expected `}` after the imported names",
+                )?;
+                names = Some(idents);
+            }
+
+            self.consume(TokenKind::Semi, "Expected `;` after the import")?;
+
+            imports.push(Import {
+                path,
+                name,
+                is_file,
+                names,
+                span,
+            });
+        }
+
+        Ok(imports)
+    }
+
+    /// Turns `module::name` into a single name from here on, for the modules
+    /// imported under these names with their prefixes
+    fn qualify_names(&mut self, modules: &[(&'static str, &'static str)]) {
+        let mut i = self.current;
+
+        while i + 2 < self.tokens.len() {
+            let qualified = match (
+                &self.tokens[i].kind,
+                &self.tokens[i + 1].kind,
+                &self.tokens[i + 2].kind,
+            ) {
+                (TokenKind::Ident(module), TokenKind::ColCol, TokenKind::Ident(name)) => modules
+                    .iter()
+                    .find(|(imported, _)| imported == module)
+                    .map(|(_, prefix)| format!("{prefix}::{name}")),
+                _ => None,
+            };
+
+            if let Some(qualified) = qualified {
+                let span = Span::new(self.tokens[i].span.start, self.tokens[i + 2].span.end);
+                self.tokens
+                    .splice(i..i + 3, [Token::new(TokenKind::Ident(qualified), span)]);
+            }
+
+            i += 1;
+        }
     }
 
     pub fn parse(&mut self) -> Result<Ast, ParseError> {
         let mut ast = Ast::new();
 
-        ast.program.push(Stmt::new(
-            StmtKind::Expr(Box::new(self.expression()?)),
-            Span::new(0, 0),
-        ));
+        while !self.is_at_end() {
+            if self.r#match(vec![TokenKind::Struct]) {
+                self.struct_def()?;
+            } else if self.r#match(vec![TokenKind::Enum]) {
+                self.enum_def()?;
+            } else {
+                ast.program.push(self.statement()?);
+            }
+        }
+
+        ast.structs = std::mem::take(&mut self.structs);
+        ast.enums = std::mem::take(&mut self.enums);
 
         Ok(ast)
     }
 
+    fn struct_def(&mut self) -> Result<(), ParseError> {
+        let ident = self.ident("Expected a struct name")?;
+        if self.type_named(ident.name).is_some() {
+            return Err(self.error(self.previous(), "There already is a type with this name"));
+        }
+
+        self.consume(
+            TokenKind::OpenCurlBracket,
+            "Expected `{` after the struct name",
+        )?;
+
+        let mut fields: Vec<(Ident, Type)> = Vec::new();
+        while !self.check(TokenKind::CloseCurlBracket) {
+            let field = self.ident("Expected a field name")?;
+            if fields.iter().any(|(other, _)| other.name == field.name) {
+                return Err(self.error(self.previous(), "There already is a field with this name"));
+            }
+
+            self.consume(TokenKind::Col, "Expected `:` after the field name")?;
+            fields.push((field, self.type_()?));
+
+            if !self.r#match(vec![TokenKind::Comma]) {
+                break;
+            }
+        }
+
+        self.consume(TokenKind::CloseCurlBracket, "Expected `}` after the fields")?;
+
+        let id = StructId {
+            index: self.structs.len(),
+            name: self.namespace.qualify(ident.name),
+        };
+        self.structs.push(StructDef { id, fields });
+
+        Ok(())
+    }
+
+    fn enum_def(&mut self) -> Result<(), ParseError> {
+        let ident = self.ident("Expected an enum name")?;
+        if self.type_named(ident.name).is_some() {
+            return Err(self.error(self.previous(), "There already is a type with this name"));
+        }
+
+        self.consume(
+            TokenKind::OpenCurlBracket,
+            "Expected `{` after the enum name",
+        )?;
+
+        let mut variants: Vec<Ident> = Vec::new();
+        while !self.check(TokenKind::CloseCurlBracket) {
+            let variant = self.ident("Expected a variant name")?;
+            if variants.iter().any(|other| other.name == variant.name) {
+                return Err(
+                    self.error(self.previous(), "There already is a variant with this name")
+                );
+            }
+
+            variants.push(variant);
+
+            if !self.r#match(vec![TokenKind::Comma]) {
+                break;
+            }
+        }
+
+        self.consume(
+            TokenKind::CloseCurlBracket,
+            "Expected `}` after the variants",
+        )?;
+
+        let id = EnumId {
+            index: self.enums.len(),
+            name: self.namespace.qualify(ident.name),
+        };
+        self.enums.push(EnumDef { id, variants });
+
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.peek().span.start;
+
+        let stmt_kind = if self.r#match(vec![TokenKind::If]) {
+            self.if_()?
+        } else if self.r#match(vec![TokenKind::Match]) {
+            let (value, arms) = self.match_arms(Self::arm_block)?;
+
+            StmtKind::Match(Box::new(value), arms)
+        } else if self.is_label() {
+            let label = self.ident("Expected a label")?;
+            self.consume(TokenKind::Col, "Expected `:` after the label")?;
+
+            if self.r#match(vec![TokenKind::While]) {
+                self.while_(Some(label))?
+            } else if self.r#match(vec![TokenKind::For]) {
+                self.for_(Some(label))?
+            } else if self.r#match(vec![TokenKind::Every]) {
+                self.every(Some(label))?
+            } else {
+                return Err(self.error(self.peek(), "Expected a loop after the label"));
+            }
+        } else if self.r#match(vec![TokenKind::While]) {
+            self.while_(None)?
+        } else if self.r#match(vec![TokenKind::For]) {
+            self.for_(None)?
+        } else if self.r#match(vec![TokenKind::Every]) {
+            self.every(None)?
+        } else if self.r#match(vec![TokenKind::Within]) {
+            self.within()?
+        } else if self.r#match(vec![TokenKind::Retry]) {
+            self.retry()?
+        } else if self.r#match(vec![TokenKind::OnInterrupt]) {
+            self.on_interrupt()?
+        } else if self.r#match(vec![TokenKind::Break]) {
+            let label = self.label()?;
+            self.consume(TokenKind::Semi, "Expected `;` after `break`")?;
+
+            StmtKind::Break(label)
+        } else if self.r#match(vec![TokenKind::Continue]) {
+            let label = self.label()?;
+            self.consume(TokenKind::Semi, "Expected `;` after `continue`")?;
+
+            StmtKind::Continue(label)
+        } else if self.r#match(vec![TokenKind::Func]) {
+            self.fn_def()?
+        } else if self.r#match(vec![TokenKind::Return]) {
+            let expr = self.expression()?;
+            self.consume(TokenKind::Semi, "Expected `;` after return value")?;
+
+            StmtKind::Return(Box::new(expr))
+        } else if self.r#match(vec![TokenKind::Const]) {
+            if !self.is_var_bind() {
+                return Err(
+                    self.error(self.peek(), "Expected a variable declaration after `const`")
+                );
+            }
+
+            self.var_bind(true)?
+        } else if self.is_var_bind() {
+            self.var_bind(false)?
+        } else if self.is_assign() {
+            let assign = self.assign()?;
+            self.consume(TokenKind::Semi, "Expected `;` after assignment")?;
+
+            assign
+        } else if self.check(TokenKind::Import) {
+            return Err(self.error(
+                self.peek(),
+                "Imports have to come before everything else in a file",
+            ));
+        } else if self.check(TokenKind::Struct) {
+            return Err(self.error(self.peek(), "Structs can only be declared at the top level"));
+        } else if self.check(TokenKind::Enum) {
+            return Err(self.error(self.peek(), "Enums can only be declared at the top level"));
+        } else {
+            let expr = self.expression()?;
+
+            if let ExprKind::FieldAcc(receiver, field) = &expr.expr_kind
+                && self.is_assign_op(&self.peek().kind)
+            {
+                let op = self.assign_op();
+                let value = self.expression()?;
+                self.consume(TokenKind::Semi, "Expected `;` after assignment")?;
+
+                return Ok(Stmt::new(
+                    StmtKind::FieldAssign {
+                        receiver: receiver.clone(),
+                        field: *field,
+                        op,
+                        value: Box::new(value),
+                    },
+                    Span::new(start, self.previous().span.end),
+                ));
+            }
+
+            // Without a semicolon at the end of a block it's the value of the block
+            if !self.check(TokenKind::CloseCurlBracket) {
+                self.consume(TokenKind::Semi, "Expected `;` after expression")?;
+            }
+
+            StmtKind::Expr(Box::new(expr))
+        };
+
+        Ok(Stmt::new(
+            stmt_kind,
+            Span::new(start, self.previous().span.end),
+        ))
+    }
+
+    fn if_(&mut self) -> Result<StmtKind, ParseError> {
+        let condition = self.expression()?;
+        let block = self.block()?;
+
+        let else_ = if self.r#match(vec![TokenKind::Else]) {
+            let start = self.previous().span.start;
+
+            if self.r#match(vec![TokenKind::If]) {
+                let stmt_kind = self.if_()?;
+                let span = Span::new(start, self.previous().span.end);
+
+                Some(Else::If(Box::new(Stmt::new(stmt_kind, span))))
+            } else {
+                Some(Else::Block(Box::new(self.block()?)))
+            }
+        } else {
+            None
+        };
+
+        Ok(StmtKind::If(Box::new(condition), Box::new(block), else_))
+    }
+
+    fn within(&mut self) -> Result<StmtKind, ParseError> {
+        let time = self.expression()?;
+        let block = self.block()?;
+
+        let fallback = match self.r#match(vec![TokenKind::Else]) {
+            true => Some(Box::new(self.block()?)),
+            false => None,
+        };
+
+        Ok(StmtKind::Within(Box::new(time), Box::new(block), fallback))
+    }
+
+    /// The value and the arms of a `match` after the `match`, `body` parses what
+    /// comes after each `=>`
+    fn match_arms<B>(
+        &mut self,
+        body: fn(&mut Self) -> Result<B, ParseError>,
+    ) -> Result<(Expr, Vec<Arm<B>>), ParseError> {
+        let value = self.expression()?;
+        self.consume(
+            TokenKind::OpenCurlBracket,
+            "Expected `{` after the value to match",
+        )?;
+
+        let mut arms = Vec::new();
+        while !self.check(TokenKind::CloseCurlBracket) && !self.is_at_end() {
+            let pattern = self.pattern()?;
+            let guard = match self.r#match(vec![TokenKind::If]) {
+                true => Some(self.expression()?),
+                false => None,
+            };
+            self.consume(TokenKind::FatArrow, "Expected `=>` after the pattern")?;
+
+            arms.push(Arm {
+                pattern,
+                guard,
+                body: body(self)?,
+            });
+
+            // Arms ending with a block don't need a comma
+            if !self.r#match(vec![TokenKind::Comma])
+                && self.previous().kind != TokenKind::CloseCurlBracket
+            {
+                break;
+            }
+        }
+
+        self.consume(
+            TokenKind::CloseCurlBracket,
+            "Expected `,` or `}` after the arm",
+        )?;
+
+        Ok((value, arms))
+    }
+
+    /// The block of an arm of a `match` statement, a single expression or
+    /// assignment is one too
+    fn arm_block(&mut self) -> Result<Box<Block>, ParseError> {
+        if self.check(TokenKind::OpenCurlBracket) {
+            return Ok(Box::new(self.block()?));
+        }
+
+        let start = self.peek().span.start;
+        let stmt_kind = match self.is_assign() {
+            true => self.assign()?,
+            false => StmtKind::Expr(Box::new(self.expression()?)),
+        };
+        let span = Span::new(start, self.previous().span.end);
+
+        let mut block = Block::new(span, Type::Unit);
+        block.stmts = Box::new([Stmt::new(stmt_kind, span)]);
+
+        Ok(Box::new(block))
+    }
+
+    /// An assignment without the `;`
+    fn assign(&mut self) -> Result<StmtKind, ParseError> {
+        let target = self.ident("Expected a variable name")?;
+        let op = self.assign_op();
+        let value = self.expression()?;
+
+        Ok(StmtKind::Assign {
+            target,
+            op,
+            value: Box::new(value),
+        })
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, ParseError> {
+        let start = self.peek().span.start;
+
+        let pattern_kind = if matches!(&self.peek().kind, TokenKind::Ident(name) if name == "_") {
+            self.advance();
+            PatternKind::Wildcard
+        } else {
+            let lit = self.pattern_lit()?;
+
+            if self.r#match(vec![TokenKind::DotDot, TokenKind::DotDotEq]) {
+                let inclusive = self.previous().kind == TokenKind::DotDotEq;
+
+                PatternKind::Range {
+                    start: lit,
+                    end: self.pattern_lit()?,
+                    inclusive,
+                }
+            } else {
+                PatternKind::Lit(lit)
+            }
+        };
+
+        Ok(Pattern {
+            pattern_kind,
+            span: Span::new(start, self.previous().span.end),
+        })
+    }
+
+    /// A value in a pattern, numbers and times can be negative
+    fn pattern_lit(&mut self) -> Result<Lit, ParseError> {
+        let negative = self.r#match(vec![TokenKind::Sub]);
+
+        let lit_kind = match &self.peek().kind {
+            TokenKind::Num(num) if negative => LitKind::Num(-num),
+            TokenKind::Num(num) => LitKind::Num(*num),
+            TokenKind::Time(time, time_kind) if negative => LitKind::Time(-time, *time_kind),
+            TokenKind::Time(time, time_kind) => LitKind::Time(*time, *time_kind),
+            TokenKind::Bool(bool) if !negative => LitKind::Bool(*bool),
+            TokenKind::Ident(name) if !negative && self.enum_named(name).is_some() => {
+                let ident = self.ident("Expected an enum name")?;
+                self.consume(TokenKind::ColCol, "Expected `::` after the enum name")?;
+
+                return self.variant(ident);
+            }
+            _ => return Err(self.error(self.peek(), "Expected a pattern")),
+        };
+        self.advance();
+
+        Ok(Lit::new(lit_kind))
+    }
+
+    /// The variant after the `::` following the name of an enum
+    fn variant(&mut self, enum_name: Ident) -> Result<Lit, ParseError> {
+        let Some(id) = self.enum_named(enum_name.name).map(|def| def.id) else {
+            let message = format!("There is no enum named `{}`", enum_name.name);
+            return Err(self.error(self.previous(), &message));
+        };
+
+        let variant = self.ident("Expected a variant name after `::`")?;
+
+        match self.enums[id.index].variant(variant.name) {
+            Some(index) => Ok(Lit::new(LitKind::Enum(id, index))),
+            None => Err(self.error(
+                self.previous(),
+                &format!("`{}` has no variant `{}`", id.name, variant.name),
+            )),
+        }
+    }
+
+    fn while_(&mut self, label: Option<Ident>) -> Result<StmtKind, ParseError> {
+        let condition = self.expression()?;
+        let block = self.block()?;
+
+        Ok(StmtKind::While(Box::new(condition), Box::new(block), label))
+    }
+
+    fn for_(&mut self, label: Option<Ident>) -> Result<StmtKind, ParseError> {
+        let var = self.ident("Expected a variable name after `for`")?;
+        self.consume(TokenKind::In, "Expected `in` after the loop variable")?;
+
+        let start = self.expression()?;
+        let inclusive = match self.peek().kind {
+            TokenKind::DotDot => false,
+            TokenKind::DotDotEq => true,
+            // Without `..` it goes over the elements of a list
+            _ => {
+                let block = self.block()?;
+
+                return Ok(StmtKind::ForEach {
+                    var,
+                    list: Box::new(start),
+                    block: Box::new(block),
+                    label,
+                });
+            }
+        };
+        self.advance();
+        let end = self.expression()?;
+
+        // `step` is only special here, so it can still name variables
+        let step = match &self.peek().kind {
+            TokenKind::Ident(name) if name == "step" => {
+                self.advance();
+                Some(Box::new(self.expression()?))
+            }
+            _ => None,
+        };
+
+        let block = self.block()?;
+        let type_ = start.type_;
+
+        Ok(StmtKind::For {
+            var,
+            range: Range {
+                start: Box::new(start),
+                end: Box::new(end),
+                inclusive,
+                step,
+                type_,
+            },
+            block: Box::new(block),
+            label,
+        })
+    }
+
+    fn retry(&mut self) -> Result<StmtKind, ParseError> {
+        let mut options = RetryOptions {
+            attempts: None,
+            backoff: None,
+            jitter: None,
+        };
+
+        if self.r#match(vec![TokenKind::OpenBracket]) {
+            while !self.check(TokenKind::CloseBracket) {
+                let name = self.ident("Expected an option of `retry`")?;
+                self.consume(TokenKind::Col, "Expected `:` after the option")?;
+
+                match name.name {
+                    "attempts" => options.attempts = Some(self.expression()?),
+                    "backoff" => options.backoff = Some(self.backoff()?),
+                    "jitter" => options.jitter = Some(self.expression()?),
+                    _ => {
+                        let message = format!(
+                            "`{}` isn't an option of `retry`, expected `attempts`, `backoff` or `jitter`",
+                            name.name
+                        );
+                        return Err(self.error(self.previous(), &message));
+                    }
+                }
+
+                if !self.r#match(vec![TokenKind::Comma]) {
+                    break;
+                }
+            }
+
+            self.consume(
+                TokenKind::CloseBracket,
+                "Expected `)` after the options of `retry`",
+            )?;
+        }
+
+        let block = self.block()?;
+
+        Ok(StmtKind::Retry(Box::new(options), Box::new(block)))
+    }
+
+    fn on_interrupt(&mut self) -> Result<StmtKind, ParseError> {
+        let handler = Closure {
+            args: Box::new([]),
+            body: Box::new(self.block()?),
+            return_type: Type::Unit,
+            captures: Vec::new(),
+        };
+
+        Ok(StmtKind::OnInterrupt(Box::new(handler)))
+    }
+
+    /// A time, or `linear` or `exponential` with the first pause and
+    /// optionally the longest one like `exponential(1s, max: 1min)`
+    fn backoff(&mut self) -> Result<Backoff, ParseError> {
+        let is_call = self
+            .tokens
+            .get(self.current + 1)
+            .is_some_and(|next| next.kind == TokenKind::OpenBracket);
+
+        let kind = match &self.peek().kind {
+            TokenKind::Ident(name) if is_call && name == "linear" => BackoffKind::Linear,
+            TokenKind::Ident(name) if is_call && name == "exponential" => BackoffKind::Exponential,
+            // Just a time, which is the pause every time
+            _ => {
+                return Ok(Backoff {
+                    kind: BackoffKind::Constant,
+                    delay: self.expression()?,
+                    max: None,
+                })
+            }
+        };
+        self.advance();
+        self.advance();
+
+        let delay = self.expression()?;
+        let max = match self.r#match(vec![TokenKind::Comma]) {
+            true => {
+                let name = self.ident("Expected `max` after the first pause")?;
+                if name.name != "max" {
+                    return Err(self.error(self.previous(), "Expected `max` after the first pause"));
+                }
+                self.consume(TokenKind::Col, "Expected `:` after `max`")?;
+
+                Some(self.expression()?)
+            }
+            false => None,
+        };
+        self.consume(TokenKind::CloseBracket, "Expected `)` after the backoff")?;
+
+        Ok(Backoff { kind, delay, max })
+    }
+
+    fn every(&mut self, label: Option<Ident>) -> Result<StmtKind, ParseError> {
+        let schedule = self.expression()?;
+
+        // `times` is only special here, so it can still name variables
+        let times = match &self.peek().kind {
+            TokenKind::Ident(name) if name == "times" => {
+                self.advance();
+                Some(Box::new(self.expression()?))
+            }
+            _ => None,
+        };
+
+        let duration = match self.r#match(vec![TokenKind::For]) {
+            true => Some(Box::new(self.expression()?)),
+            false => None,
+        };
+
+        let block = self.block()?;
+
+        Ok(StmtKind::Every {
+            schedule: Box::new(schedule),
+            times,
+            duration,
+            block: Box::new(block),
+            label,
+        })
+    }
+
+    /// The optional label after `break` or `continue`
+    fn label(&mut self) -> Result<Option<Ident>, ParseError> {
+        match self.peek().kind {
+            TokenKind::Ident(_) => Ok(Some(self.ident("Expected a label")?)),
+            _ => Ok(None),
+        }
+    }
+
+    fn var_bind(&mut self, is_const: bool) -> Result<StmtKind, ParseError> {
+        let type_ = self.type_()?;
+        let identifier = self.ident("Expected a variable name")?;
+        self.consume(TokenKind::Eq, "Expected `=` after the variable name")?;
+        let value = self.expression()?;
+        self.consume(TokenKind::Semi, "Expected `;` after variable declaration")?;
+
+        Ok(StmtKind::VarBind {
+            type_,
+            identifier,
+            value: Box::new(value),
+            is_const,
+        })
+    }
+
+    fn fn_def(&mut self) -> Result<StmtKind, ParseError> {
+        let ident = self.ident("Expected a function name")?;
+        self.consume(
+            TokenKind::OpenBracket,
+            "Expected `(` after the function name",
+        )?;
+        let args = self.typed_args(TokenKind::CloseBracket)?;
+        self.consume(TokenKind::CloseBracket, "Expected `)` after the arguments")?;
+
+        let return_type = self.return_type()?;
+        let body = self.block()?;
+
+        Ok(StmtKind::FnDef {
+            ident,
+            args: args.into_boxed_slice(),
+            body: Box::new(body),
+            return_type,
+        })
+    }
+
+    /// A closure after the `|` or `||` it starts with
+    fn closure(&mut self) -> Result<Expr, ParseError> {
+        let start = self.previous().span.start;
+
+        let mut args = Vec::new();
+        if self.previous().kind == TokenKind::Pipe {
+            args = self.typed_args(TokenKind::Pipe)?;
+            self.consume(TokenKind::Pipe, "Expected `|` after the arguments")?;
+        }
+
+        let return_type = self.return_type()?;
+        let body = self.block()?;
+
+        let closure = Closure {
+            args: args.into_boxed_slice(),
+            body: Box::new(body),
+            return_type,
+            captures: Vec::new(),
+        };
+        let type_ = closure.type_();
+
+        Ok(Expr::new(
+            ExprKind::Closure(Box::new(closure)),
+            Span::new(start, self.previous().span.end),
+            type_,
+        ))
+    }
+
+    /// Arguments with their types like `a: time, b: num`, up to the `end` token
+    fn typed_args(&mut self, end: TokenKind) -> Result<Vec<(Ident, Type)>, ParseError> {
+        let mut args = Vec::new();
+        if self.check(end) {
+            return Ok(args);
+        }
+
+        loop {
+            let arg = self.ident("Expected an argument name")?;
+            self.consume(TokenKind::Col, "Expected `:` after the argument name")?;
+            args.push((arg, self.type_()?));
+
+            if !self.r#match(vec![TokenKind::Comma]) {
+                return Ok(args);
+            }
+        }
+    }
+
+    /// The `-> type` of a function, [`Type::Unit`] without one
+    fn return_type(&mut self) -> Result<Type, ParseError> {
+        match self.r#match(vec![TokenKind::Arrow]) {
+            true => self.type_(),
+            false => Ok(Type::Unit),
+        }
+    }
+
+    fn block(&mut self) -> Result<Block, ParseError> {
+        let start = self
+            .consume(TokenKind::OpenCurlBracket, "Expected `{`")?
+            .span
+            .start;
+
+        let mut stmts = Vec::new();
+        let mut value = None;
+        while !self.check(TokenKind::CloseCurlBracket) && !self.is_at_end() {
+            let stmt = self.statement()?;
+
+            match stmt.stmt_kind {
+                StmtKind::Expr(expr) if self.previous().kind != TokenKind::Semi => {
+                    value = Some(expr);
+                }
+                _ => stmts.push(stmt),
+            }
+        }
+
+        let end = self
+            .consume(TokenKind::CloseCurlBracket, "Expected `}` after block")?
+            .span
+            .end;
+
+        let type_ = value.as_ref().map_or(Type::Unit, |value| value.type_);
+        let mut block = Block::new(Span::new(start, end), type_);
+        block.stmts = stmts.into_boxed_slice();
+        block.value = value;
+
+        Ok(block)
+    }
+
+    /// A block used as a value, which has to end with one
+    fn value_block(&mut self) -> Result<Block, ParseError> {
+        let block = self.block()?;
+
+        if block.value.is_none() {
+            return Err(self.error(
+                self.previous(),
+                "Expected a value without `;` at the end of the block",
+            ));
+        }
+
+        Ok(block)
+    }
+
+    /// An `if` used as a value, after the `if`
+    fn if_expr(&mut self) -> Result<Expr, ParseError> {
+        let start = self.previous().span.start;
+
+        let condition = self.expression()?;
+        let true_block = self.value_block()?;
+        self.consume(TokenKind::Else, "An `if` used as a value needs an `else`")?;
+
+        let else_expr = if self.r#match(vec![TokenKind::If]) {
+            self.if_expr()?
+        } else {
+            let block = self.value_block()?;
+            let (span, type_) = (block.span, block.type_);
+
+            Expr::new(ExprKind::Block(Box::new(block)), span, type_)
+        };
+
+        let type_ = true_block
+            .type_
+            .unify(else_expr.type_)
+            .unwrap_or(Type::Unit);
+
+        Ok(Expr::new(
+            ExprKind::If(
+                Box::new(condition),
+                Box::new(true_block),
+                Box::new(else_expr),
+            ),
+            Span::new(start, self.previous().span.end),
+            type_,
+        ))
+    }
+
+    /// A variable binding starts with a type followed by the variable name
+    fn is_var_bind(&self) -> bool {
+        self.type_end(self.current).is_some_and(|next| {
+            self.tokens
+                .get(next)
+                .is_some_and(|next| matches!(next.kind, TokenKind::Ident(_)))
+        })
+    }
+
+    /// Where a type starting at the given token ends, if there is one
+    fn type_end(&self, start: usize) -> Option<usize> {
+        match &self.tokens.get(start)?.kind {
+            TokenKind::Ident(name) => self.type_named(name).map(|_| start + 1),
+            TokenKind::OpenSquareBracket => {
+                let end = self.type_end(start + 1)?;
+                (self.tokens.get(end)?.kind == TokenKind::CloseSquareBracket).then_some(end + 1)
+            }
+            TokenKind::Or => self.return_type_end(start + 1),
+            TokenKind::Pipe => {
+                let mut end = start + 1;
+                if self.tokens.get(end)?.kind != TokenKind::Pipe {
+                    end = self.type_end(end)?;
+                    while self.tokens.get(end)?.kind == TokenKind::Comma {
+                        end = self.type_end(end + 1)?;
+                    }
+                }
+
+                match self.tokens.get(end)?.kind {
+                    TokenKind::Pipe => self.return_type_end(end + 1),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Where the `-> type` of a function type starting at the given token ends
+    fn return_type_end(&self, start: usize) -> Option<usize> {
+        match self.tokens.get(start)?.kind {
+            TokenKind::Arrow => self.type_end(start + 1),
+            _ => None,
+        }
+    }
+
+    /// A `%` right after a number makes it a percentage, like `10%`, unless
+    /// something follows that it could be the remainder of
+    fn is_percent(&self) -> bool {
+        self.check(TokenKind::Mod)
+            && self.tokens.get(self.current + 1).is_some_and(|next| {
+                matches!(
+                    next.kind,
+                    TokenKind::CloseBracket
+                        | TokenKind::CloseSquareBracket
+                        | TokenKind::CloseCurlBracket
+                        | TokenKind::Comma
+                        | TokenKind::Semi
+                )
+            })
+    }
+
+    /// A labeled loop starts with the label followed by `:`
+    fn is_label(&self) -> bool {
+        matches!(self.peek().kind, TokenKind::Ident(_))
+            && self
+                .tokens
+                .get(self.current + 1)
+                .is_some_and(|next| next.kind == TokenKind::Col)
+    }
+
+    /// An assignment starts with the variable name followed by `=` or `+=` and the like
+    fn is_assign(&self) -> bool {
+        matches!(self.peek().kind, TokenKind::Ident(_))
+            && self
+                .tokens
+                .get(self.current + 1)
+                .is_some_and(|next| self.is_assign_op(&next.kind))
+    }
+
+    fn is_assign_op(&self, token_kind: &TokenKind) -> bool {
+        matches!(
+            token_kind,
+            TokenKind::Eq
+                | TokenKind::AddEq
+                | TokenKind::SubEq
+                | TokenKind::MulEq
+                | TokenKind::DivEq
+        )
+    }
+
+    /// Consumes `=` or `+=` and the like, returning the operation it combines with
+    fn assign_op(&mut self) -> Option<BinOp> {
+        match self.advance().kind {
+            TokenKind::AddEq => Some(BinOp::Add),
+            TokenKind::SubEq => Some(BinOp::Sub),
+            TokenKind::MulEq => Some(BinOp::Mul),
+            TokenKind::DivEq => Some(BinOp::Div),
+            _ => None,
+        }
+    }
+
+    /// The built-in type or declared struct or enum with this name
+    fn type_named(&self, name: &str) -> Option<Type> {
+        Type::from_name(name)
+            .or_else(|| self.struct_named(name).map(|def| Type::Struct(def.id)))
+            .or_else(|| self.enum_named(name).map(|def| Type::Enum(def.id)))
+    }
+
+    fn struct_named(&self, name: &str) -> Option<&StructDef> {
+        self.namespace
+            .candidates(name)
+            .iter()
+            .find_map(|name| self.structs.iter().find(|def| def.id.name == *name))
+    }
+
+    fn enum_named(&self, name: &str) -> Option<&EnumDef> {
+        self.namespace
+            .candidates(name)
+            .iter()
+            .find_map(|name| self.enums.iter().find(|def| def.id.name == *name))
+    }
+
+    fn type_(&mut self) -> Result<Type, ParseError> {
+        if self.r#match(vec![TokenKind::OpenSquareBracket]) {
+            let elem = self.type_()?;
+            self.consume(
+                TokenKind::CloseSquareBracket,
+                "Expected `]` after the type of the elements",
+            )?;
+
+            return Ok(elem.list_of());
+        }
+
+        if self.r#match(vec![TokenKind::Pipe, TokenKind::Or]) {
+            let mut args = Vec::new();
+            if self.previous().kind == TokenKind::Pipe && !self.r#match(vec![TokenKind::Pipe]) {
+                loop {
+                    args.push(self.type_()?);
+
+                    if !self.r#match(vec![TokenKind::Comma]) {
+                        break;
+                    }
+                }
+
+                self.consume(
+                    TokenKind::Pipe,
+                    "Expected `|` after the types of the arguments",
+                )?;
+            }
+
+            self.consume(
+                TokenKind::Arrow,
+                "Expected `->` and the return type of the function",
+            )?;
+
+            return Ok(Type::function(args, self.type_()?));
+        }
+
+        let type_ = match &self.peek().kind {
+            TokenKind::Ident(name) => self.type_named(name),
+            _ => None,
+        };
+
+        match type_ {
+            Some(type_) => {
+                self.advance();
+                Ok(type_)
+            }
+            None => Err(self.error(self.peek(), "Expected a type")),
+        }
+    }
+
+    fn ident(&mut self, message: &str) -> Result<Ident, ParseError> {
+        match &self.peek().kind {
+            TokenKind::Ident(name) => {
+                let ident = Ident::new(Box::leak(name.clone().into_boxed_str()), self.peek().span);
+                self.advance();
+                Ok(ident)
+            }
+            _ => Err(self.error(self.peek(), message)),
+        }
+    }
+
     fn r#match(&mut self, token_kinds: Vec<TokenKind>) -> bool {
         for kind in token_kinds {
             if self.check(kind) {
@@ -112,7 +1156,31 @@ impl Parser {
     }
 
     pub fn expression(&mut self) -> Result<Expr, ParseError> {
-        self.equality()
+        self.or()
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
+
+        while self.r#match(vec![TokenKind::Or, TokenKind::Xor]) {
+            let operator = self.token_to_bin_op(self.previous());
+            let right = self.and()?;
+            expr = self.binary(expr, operator, right);
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.equality()?;
+
+        while self.r#match(vec![TokenKind::And]) {
+            let operator = self.token_to_bin_op(self.previous());
+            let right = self.equality()?;
+            expr = self.binary(expr, operator, right);
+        }
+
+        Ok(expr)
     }
 
     fn equality(&mut self) -> Result<Expr, ParseError> {
@@ -122,11 +1190,7 @@ impl Parser {
             let operator = self.token_to_bin_op(self.previous());
 
             let right = self.comparison()?;
-            expr = Expr::new(
-                ExprKind::Binary(Box::new(expr), operator, Box::new(right)),
-                Span::new(0, 0),
-                Type::Unit,
-            );
+            expr = self.binary(expr, operator, right);
         }
 
         Ok(expr)
@@ -143,11 +1207,7 @@ impl Parser {
         ]) {
             let operator = self.token_to_bin_op(self.previous());
             let right = self.term()?;
-            expr = Expr::new(
-                ExprKind::Binary(Box::new(expr), operator, Box::new(right)),
-                Span::new(0, 0),
-                Type::Unit,
-            )
+            expr = self.binary(expr, operator, right);
         }
 
         Ok(expr)
@@ -159,11 +1219,7 @@ impl Parser {
         while self.r#match(vec![TokenKind::Sub, TokenKind::Add]) {
             let operator = self.token_to_bin_op(self.previous());
             let right = self.factor()?;
-            expr = Expr::new(
-                ExprKind::Binary(Box::new(expr), operator, Box::new(right)),
-                Span::new(0, 0),
-                Type::Unit,
-            );
+            expr = self.binary(expr, operator, right);
         }
 
         Ok(expr)
@@ -175,11 +1231,7 @@ impl Parser {
         while self.r#match(vec![TokenKind::Mul, TokenKind::Div, TokenKind::Mod]) {
             let operator = self.token_to_bin_op(self.previous());
             let right = self.unary()?;
-            expr = Expr::new(
-                ExprKind::Binary(Box::new(expr), operator, Box::new(right)),
-                Span::new(0, 0),
-                Type::Unit,
-            );
+            expr = self.binary(expr, operator, right);
         }
 
         Ok(expr)
@@ -187,51 +1239,340 @@ impl Parser {
 
     fn unary(&mut self) -> Result<Expr, ParseError> {
         if self.r#match(vec![TokenKind::Not, TokenKind::Sub]) {
+            let start = self.previous().span.start;
             let operator = self.token_to_un_op(self.previous());
             let right = self.unary()?;
+            let span = Span::new(start, right.span.end);
+            let type_ = operator.result_type(right.type_);
+
             return Ok(Expr::new(
                 ExprKind::Unary(operator, Box::new(right)),
-                Span::new(0, 0),
-                Type::Unit,
+                span,
+                type_,
             ));
         }
 
-        self.primary()
+        self.call()
+    }
+
+    /// Fields, method calls, indices and calls after a value, like `t.round_to(1s).as_seconds()`
+    fn call(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.r#match(vec![TokenKind::OpenBracket]) {
+                let (args, end) = self.arguments()?;
+                let type_ = match expr.type_ {
+                    Type::Function(function) => function.return_type,
+                    _ => Type::Unit,
+                };
+
+                let start = expr.span.start;
+
+                expr = Expr::new(
+                    ExprKind::Call(Box::new(expr), args.into_boxed_slice()),
+                    Span::new(start, end),
+                    type_,
+                );
+                continue;
+            }
+
+            if self.r#match(vec![TokenKind::OpenSquareBracket]) {
+                let index = self.expression()?;
+                let end = self
+                    .consume(
+                        TokenKind::CloseSquareBracket,
+                        "Expected `]` after the index",
+                    )?
+                    .span
+                    .end;
+                let type_ = match expr.type_ {
+                    Type::List(elem) => *elem,
+                    _ => Type::Unit,
+                };
+
+                let start = expr.span.start;
+
+                expr = Expr::new(
+                    ExprKind::Index(Box::new(expr), Box::new(index)),
+                    Span::new(start, end),
+                    type_,
+                );
+                continue;
+            }
+
+            if !self.r#match(vec![TokenKind::Dot]) {
+                break;
+            }
+
+            let ident = self.ident("Expected a field or method name after `.`")?;
+            let start = expr.span.start;
+
+            expr = if self.r#match(vec![TokenKind::OpenBracket]) {
+                let (args, end) = self.arguments()?;
+
+                Expr::new(
+                    ExprKind::MethodCall {
+                        receiver: Box::new(expr),
+                        ident,
+                        args: args.into_iter().map(Box::new).collect(),
+                    },
+                    Span::new(start, end),
+                    Type::Unit,
+                )
+            } else {
+                Expr::new(
+                    ExprKind::FieldAcc(Box::new(expr), ident),
+                    Span::new(start, ident.span.end),
+                    Type::Unit,
+                )
+            };
+        }
+
+        Ok(expr)
+    }
+
+    /// The arguments of a call after the `(`, and where the `)` ends
+    fn arguments(&mut self) -> Result<(Vec<Expr>, usize), ParseError> {
+        let mut args = Vec::new();
+        if !self.check(TokenKind::CloseBracket) {
+            loop {
+                args.push(self.expression()?);
+
+                if !self.r#match(vec![TokenKind::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let end = self
+            .consume(TokenKind::CloseBracket, "Expected `)` after the arguments")?
+            .span
+            .end;
+
+        Ok((args, end))
     }
 
     fn primary(&mut self) -> Result<Expr, ParseError> {
+        let span = self.peek().span;
+
         let expr = match self.peek().kind {
             TokenKind::Bool(v) => Some(Expr::new(
                 ExprKind::Lit(Lit::new(LitKind::Bool(v))),
-                Span::new(0, 0),
-                Type::Unit,
+                span,
+                Type::Bool,
             )),
             TokenKind::Num(v) => Some(Expr::new(
                 ExprKind::Lit(Lit::new(LitKind::Num(v))),
-                Span::new(0, 0),
-                Type::Unit,
+                span,
+                Type::Number,
+            )),
+            TokenKind::Time(v, time_kind) => Some(Expr::new(
+                ExprKind::Lit(Lit::new(LitKind::Time(v, time_kind))),
+                span,
+                Type::Time,
+            )),
+            TokenKind::Str(ref str) => Some(Expr::new(
+                ExprKind::Lit(Lit::new(LitKind::Str(Box::leak(
+                    str.clone().into_boxed_str(),
+                )))),
+                span,
+                Type::Str,
             )),
+            TokenKind::Stamp(ref stamp) => match Stamp::parse(stamp) {
+                Some(stamp) => Some(Expr::new(ExprKind::Timestamp(stamp), span, Type::Timestamp)),
+                None => {
+                    let message = format!(
+                        "`@{stamp}` isn't a timestamp like `@2026-10-18T17:00` or `@17:00`"
+                    );
+                    return Err(self.error(self.peek(), &message));
+                }
+            },
             _ => None,
         };
 
         if let Some(expr) = expr {
             self.advance();
+
+            if let ExprKind::Lit(Lit(LitKind::Num(num))) = expr.expr_kind
+                && self.is_percent()
+            {
+                self.advance();
+                return Ok(Expr::new(
+                    ExprKind::Lit(Lit::new(LitKind::Num(num / 100.))),
+                    Span::new(span.start, self.previous().span.end),
+                    Type::Number,
+                ));
+            }
+
             return Ok(expr);
         }
 
+        if let TokenKind::Ident(_) = self.peek().kind {
+            let ident = self.ident("Expected an identifier")?;
+
+            if let Some(def) = self.struct_named(ident.name)
+                && self.check(TokenKind::OpenCurlBracket)
+            {
+                let id = def.id;
+                return self.struct_lit(id, span);
+            }
+
+            if self.r#match(vec![TokenKind::ColCol]) {
+                let lit = self.variant(ident)?;
+
+                return Ok(Expr::new(
+                    ExprKind::Lit(lit),
+                    Span::new(span.start, self.previous().span.end),
+                    lit.0.type_(),
+                ));
+            }
+
+            if !self.r#match(vec![TokenKind::OpenBracket]) {
+                return Ok(Expr::new(ExprKind::Ident(ident), span, Type::Unit));
+            }
+
+            let (args, end) = self.arguments()?;
+
+            return Ok(Expr::new(
+                ExprKind::FnCall(ident, args.into_boxed_slice()),
+                Span::new(span.start, end),
+                Type::Unit,
+            ));
+        }
+
+        if self.check(TokenKind::OpenCurlBracket) {
+            let block = self.value_block()?;
+            let (span, type_) = (block.span, block.type_);
+
+            return Ok(Expr::new(ExprKind::Block(Box::new(block)), span, type_));
+        }
+
+        if self.r#match(vec![TokenKind::If]) {
+            return self.if_expr();
+        }
+
+        if self.r#match(vec![TokenKind::Pipe, TokenKind::Or]) {
+            return self.closure();
+        }
+
+        if self.r#match(vec![TokenKind::Spawn]) {
+            let body = self.block()?;
+            let task = Closure {
+                args: Box::new([]),
+                body: Box::new(body),
+                return_type: Type::Unit,
+                captures: Vec::new(),
+            };
+
+            return Ok(Expr::new(
+                ExprKind::Spawn(Box::new(task)),
+                Span::new(span.start, self.previous().span.end),
+                Type::Task,
+            ));
+        }
+
+        if self.r#match(vec![TokenKind::Match]) {
+            let start = self.previous().span.start;
+            let (value, arms) = self.match_arms(Self::expression)?;
+            let type_ = arms
+                .iter()
+                .try_fold(Type::Unit, |type_, arm| type_.unify(arm.body.type_))
+                .unwrap_or(Type::Unit);
+
+            return Ok(Expr::new(
+                ExprKind::Match(Box::new(value), arms),
+                Span::new(start, self.previous().span.end),
+                type_,
+            ));
+        }
+
+        if self.r#match(vec![TokenKind::OpenSquareBracket]) {
+            let mut elems: Vec<Expr> = Vec::new();
+            while !self.check(TokenKind::CloseSquareBracket) {
+                elems.push(self.expression()?);
+
+                if !self.r#match(vec![TokenKind::Comma]) {
+                    break;
+                }
+            }
+
+            let end = self
+                .consume(
+                    TokenKind::CloseSquareBracket,
+                    "Expected `]` after the elements",
+                )?
+                .span
+                .end;
+            let elem = elems.first().map_or(Type::Unit, |first| first.type_);
+
+            return Ok(Expr::new(
+                ExprKind::List(elems),
+                Span::new(span.start, end),
+                elem.list_of(),
+            ));
+        }
+
         if self.r#match(vec![TokenKind::OpenBracket]) {
             let expr = self.expression()?;
-            self.consume(TokenKind::CloseBracket, "Expected a closing Bracket")?;
+            let end = self
+                .consume(TokenKind::CloseBracket, "Expected a closing Bracket")?
+                .span
+                .end;
+            let type_ = expr.type_;
+
             return Ok(Expr::new(
                 ExprKind::Grouping(Box::new(expr)),
-                Span::new(0, 0),
-                Type::Unit,
+                Span::new(span.start, end),
+                type_,
             ));
         }
 
         return Err(self.error(self.peek(), "Expected expression"));
     }
 
+    /// The fields of a struct literal after its name, which starts at `span`
+    fn struct_lit(&mut self, id: StructId, span: Span) -> Result<Expr, ParseError> {
+        self.consume(
+            TokenKind::OpenCurlBracket,
+            "Expected `{` after the struct name",
+        )?;
+
+        let mut fields = Vec::new();
+        while !self.check(TokenKind::CloseCurlBracket) {
+            let field = self.ident("Expected a field name")?;
+            self.consume(TokenKind::Col, "Expected `:` after the field name")?;
+            fields.push((field, self.expression()?));
+
+            if !self.r#match(vec![TokenKind::Comma]) {
+                break;
+            }
+        }
+
+        let end = self
+            .consume(TokenKind::CloseCurlBracket, "Expected `}` after the fields")?
+            .span
+            .end;
+
+        Ok(Expr::new(
+            ExprKind::StructLit(id, fields),
+            Span::new(span.start, end),
+            Type::Struct(id),
+        ))
+    }
+
+    /// Builds a binary expression, inferring its type where the operands allow it
+    fn binary(&self, left: Expr, operator: BinOp, right: Expr) -> Expr {
+        let type_ = operator.result_type(left.type_, right.type_);
+        let span = Span::new(left.span.start, right.span.end);
+
+        Expr::new(
+            ExprKind::Binary(Box::new(left), operator, Box::new(right)),
+            span,
+            type_,
+        )
+    }
+
     fn consume(&mut self, token_kind: TokenKind, message: &str) -> Result<&Token, ParseError> {
         if self.check(token_kind) {
             return Ok(self.advance());
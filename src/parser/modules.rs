@@ -0,0 +1,315 @@
+//! Loads a script together with the files it imports into a single [`Ast`].
+//!
+//! Every file is parsed once, however often it's imported, and its code runs
+//! where it's first imported. The declarations at the top level of an
+//! imported file are named `module::name` everywhere else, so files can use
+//! the same names without clashing.
+
+use super::{
+    ast::{
+        stmt::{Module, Stmt, StmtKind},
+        Ast, EnumDef, Namespace, Span, StructDef,
+    },
+    lexer::lexer,
+    Parser,
+};
+use crate::error_handling::{add_source, error_at, error_message};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Reads and parses a script and everything it imports, reporting what goes wrong
+pub fn load(path: &str) -> Option<Ast> {
+    let path = Path::new(path);
+    let mut loader = Loader::default();
+
+    if let Ok(canonical) = path.canonicalize() {
+        loader.loading.push((canonical, path.display().to_string()));
+    }
+    let (program, namespace) = loader.parse_file(path, None, None).ok()?;
+
+    let mut ast = Ast::new();
+    ast.program = loader.modules;
+    ast.program.extend(program);
+    ast.structs = loader.structs;
+    ast.enums = loader.enums;
+    ast.namespace = namespace;
+
+    Some(ast)
+}
+
+#[derive(Default)]
+struct Loader {
+    /// The files imported so far
+    loaded: Vec<Loaded>,
+    /// The files being loaded, each imported by the one before it, with the
+    /// path they were found under
+    loading: Vec<(PathBuf, String)>,
+    /// The code of the imported files, in the order it runs
+    modules: Vec<Stmt>,
+    /// The structs of all files parsed so far
+    structs: Vec<StructDef>,
+    /// The enums of all files parsed so far
+    enums: Vec<EnumDef>,
+}
+
+struct Loaded {
+    path: PathBuf,
+    /// The path the file was first imported under
+    shown: &'static str,
+    prefix: &'static str,
+    /// The names declared at the top level of the file
+    names: Vec<&'static str>,
+}
+
+impl Loader {
+    /// Parses a file after loading what it imports, returning its code and
+    /// the names it can use
+    fn parse_file(
+        &mut self,
+        path: &Path,
+        prefix: Option<&'static str>,
+        imported_at: Option<Span>,
+    ) -> Result<(Vec<Stmt>, Namespace), ()> {
+        let code = match fs::read_to_string(path) {
+            Ok(code) => code,
+            Err(err) => {
+                let message = format!("Couldn't read `{}`: {err}", path.display());
+                match imported_at {
+                    Some(span) => error_at(span, &message),
+                    None => error_message(&message),
+                }
+                return Err(());
+            }
+        };
+
+        let code: &'static str = Box::leak(code.into_boxed_str());
+        let start = add_source(Box::leak(path.display().to_string().into_boxed_str()), code);
+
+        let mut tokens = lexer(code).map_err(|err| {
+            let span = Span::new(err.span.start + start, err.span.end + start);
+            error_at(span, &err.message);
+        })?;
+        for token in tokens.iter_mut() {
+            token.span.start += start;
+            token.span.end += start;
+        }
+
+        let mut parser = Parser::new(tokens);
+        let imports = parser.imports().map_err(|_| ())?;
+
+        let mut namespace = Namespace {
+            prefix,
+            imported: Vec::new(),
+        };
+        // The modules imported as a whole, by the name they go by in this file
+        let mut modules: Vec<(&'static str, usize)> = Vec::new();
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        for import in imports {
+            // `import backoff::{...}` uses a module this file already imported as `backoff`
+            let known = modules.iter().find(|(name, _)| {
+                !import.is_file && !import.path.contains('/') && *name == import.name
+            });
+            let index = match known {
+                Some((_, index)) => *index,
+                None => self.load_module(&dir.join(&import.path), import.span)?,
+            };
+            let module = &self.loaded[index];
+
+            match import.names {
+                Some(names) => {
+                    for name in names {
+                        if !module.names.contains(&name.name) {
+                            let message = format!("`{}` has no `{}`", module.shown, name.name);
+                            error_at(name.span, &message);
+                            return Err(());
+                        }
+
+                        let full_name =
+                            Box::leak(format!("{}::{}", module.prefix, name.name).into_boxed_str());
+                        namespace.imported.push((name.name, full_name));
+                    }
+                }
+                None if modules.iter().any(|(name, _)| *name == import.name) => {
+                    let message = format!(
+                        "There already is a module named `{}` in this file",
+                        import.name
+                    );
+                    error_at(import.span, &message);
+                    return Err(());
+                }
+                None => modules.push((import.name, index)),
+            }
+        }
+
+        let modules: Vec<_> = modules
+            .into_iter()
+            .map(|(name, index)| (name, self.loaded[index].prefix))
+            .collect();
+        parser.qualify_names(&modules);
+        parser.namespace = namespace.clone();
+        parser.structs = std::mem::take(&mut self.structs);
+        parser.enums = std::mem::take(&mut self.enums);
+
+        let ast = parser.parse().map_err(|_| ())?;
+        self.structs = ast.structs;
+        self.enums = ast.enums;
+
+        Ok((ast.program, namespace))
+    }
+
+    /// Loads an imported file unless it already was, returning where it is in `loaded`
+    fn load_module(&mut self, path: &Path, imported_at: Span) -> Result<usize, ()> {
+        let canonical = match path.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(err) => {
+                error_at(
+                    imported_at,
+                    &format!("Couldn't read `{}`: {err}", path.display()),
+                );
+                return Err(());
+            }
+        };
+
+        if let Some(index) = self
+            .loaded
+            .iter()
+            .position(|loaded| loaded.path == canonical)
+        {
+            return Ok(index);
+        }
+
+        if let Some(first) = self
+            .loading
+            .iter()
+            .position(|(loading, _)| *loading == canonical)
+        {
+            let cycle: Vec<_> = self.loading[first..]
+                .iter()
+                .map(|(_, shown)| shown.as_str())
+                .chain([self.loading[first].1.as_str()])
+                .collect();
+            error_at(
+                imported_at,
+                &format!(
+                    "Files can't import each other in a cycle: {}",
+                    cycle.join(" -> ")
+                ),
+            );
+            return Err(());
+        }
+
+        let prefix = self.prefix_for(path);
+
+        self.loading
+            .push((canonical.clone(), path.display().to_string()));
+        let (program, namespace) = self.parse_file(path, Some(prefix), Some(imported_at))?;
+        self.loading.pop();
+
+        let qualified = format!("{prefix}::");
+        let mut names: Vec<&'static str> = self
+            .structs
+            .iter()
+            .map(|def| def.id.name)
+            .chain(self.enums.iter().map(|def| def.id.name))
+            .filter_map(|name| name.strip_prefix(&qualified))
+            .collect();
+        for stmt in program.iter() {
+            match &stmt.stmt_kind {
+                StmtKind::VarBind {
+                    identifier: ident, ..
+                }
+                | StmtKind::FnDef { ident, .. } => names.push(ident.name),
+                _ => (),
+            }
+        }
+
+        let shown = Box::leak(path.display().to_string().into_boxed_str());
+        self.modules.push(Stmt::new(
+            StmtKind::Module(Box::new(Module {
+                path: shown,
+                namespace,
+                program,
+            })),
+            imported_at,
+        ));
+        self.loaded.push(Loaded {
+            path: canonical,
+            shown,
+            prefix,
+            names,
+        });
+
+        Ok(self.loaded.len() - 1)
+    }
+
+    /// The prefix of the declarations of a file, its name unless another file
+    /// of the same name was imported before
+    fn prefix_for(&self, path: &Path) -> &'static str {
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default();
+
+        let mut prefix = stem.to_string();
+        let mut count = 1;
+        while self.loaded.iter().any(|loaded| loaded.prefix == prefix) {
+            count += 1;
+            prefix = format!("{stem}{count}");
+        }
+
+        Box::leak(prefix.into_boxed_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::ast::expr::ExprKind, resolver::Resolver};
+
+    #[test]
+    fn test_imported_names_are_qualified() {
+        let dir = std::env::temp_dir().join(format!("wait-modules-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("lib/backoff.wait"),
+            "enum Kind { Fixed, Growing }\nfunc exponential(n: num) -> time { 1s * n }\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("main.wait"),
+            "import \"lib/backoff.wait\";\nimport backoff::{exponential};\n\
+             func twice() -> time { exponential(2) }\n\
+             backoff::Kind k = backoff::Kind::Fixed;\nwait(backoff::exponential(1));\n",
+        )
+        .unwrap();
+
+        let mut ast = load(dir.join("main.wait").to_str().unwrap()).unwrap();
+        Resolver::new().resolve(&mut ast).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The module is only loaded once, and runs before the script
+        let StmtKind::Module(module) = &ast.program[0].stmt_kind else {
+            unreachable!()
+        };
+        assert_eq!(ast.program.len(), 4);
+        assert_eq!(module.namespace.prefix, Some("backoff"));
+        assert_eq!(ast.enums[0].id.name, "backoff::Kind");
+
+        let StmtKind::FnDef { ident, .. } = &module.program[0].stmt_kind else {
+            unreachable!()
+        };
+        assert_eq!(ident.name, "backoff::exponential");
+
+        let StmtKind::FnDef { body, .. } = &ast.program[1].stmt_kind else {
+            unreachable!()
+        };
+        let Some(ExprKind::FnCall(called, _)) = body.value.as_ref().map(|value| &value.expr_kind)
+        else {
+            unreachable!()
+        };
+        assert_eq!(called.name, "backoff::exponential");
+    }
+}
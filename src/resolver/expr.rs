@@ -0,0 +1,506 @@
+use super::{Around, Kind, Resolver};
+use crate::{
+    error_handling::error_at,
+    parser::ast::{
+        expr::{Closure, Expr, ExprKind, Ident},
+        lit::LitKind,
+        pattern::{Arm, Pattern, PatternKind},
+        FnType, Span, StructId, Type,
+    },
+    runtime::{builtins::Builtin, methods::Method},
+};
+
+impl Resolver {
+    pub fn resolve_expr(&mut self, expr: &mut Expr) {
+        match &mut expr.expr_kind {
+            ExprKind::Binary(left, bin_op_kind, right) => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+
+                // Now that the variables are known the type might be too
+                expr.type_ = bin_op_kind.result_type(left.type_, right.type_);
+            }
+            ExprKind::Unary(un_op_kind, target_expr) => {
+                self.resolve_expr(target_expr);
+                expr.type_ = un_op_kind.result_type(target_expr.type_);
+            }
+            ExprKind::FnCall(ident, args) => {
+                // A variable holding a function is called like any other function value
+                if self.lookup(Kind::Function, ident.name).is_none()
+                    && Builtin::from_name(ident.name).is_none()
+                    && self.lookup(Kind::Variable, ident.name).is_some()
+                {
+                    let callee = Expr::new(ExprKind::Ident(*ident), ident.span, Type::Unit);
+                    expr.expr_kind = ExprKind::Call(Box::new(callee), std::mem::take(args));
+                    return self.resolve_expr(expr);
+                }
+
+                for arg in args.iter_mut() {
+                    self.resolve_expr(arg);
+                }
+
+                if let Some(function) = self.bind(Kind::Function, ident)
+                    && let Type::Function(function) = function.type_
+                {
+                    expr.type_ = function.return_type;
+                } else if ident.binding.is_none()
+                    && let Some(builtin) = Builtin::from_name(ident.name)
+                {
+                    expr.type_ = builtin.return_type();
+                }
+            }
+            ExprKind::Call(callee, args) => {
+                self.resolve_expr(callee);
+
+                for arg in args.iter_mut() {
+                    self.resolve_expr(arg);
+                }
+
+                match callee.type_ {
+                    Type::Function(function) => {
+                        self.resolve_args(function, args, expr.span);
+                        expr.type_ = function.return_type;
+                    }
+                    Type::Unit => {}
+                    type_ => {
+                        error_at(
+                            callee.span,
+                            &format!("Only functions can be called, found a {type_}"),
+                        );
+                        self.had_error = true;
+                    }
+                }
+            }
+            ExprKind::Closure(closure) => {
+                self.resolve_closure(closure);
+                expr.type_ = closure.type_();
+            }
+            ExprKind::Spawn(task) => self.resolve_closure(task),
+            ExprKind::FnRef(ident) => {
+                if let Some(function) = self.bind(Kind::Function, ident) {
+                    expr.type_ = function.type_;
+                }
+            }
+            ExprKind::MethodCall {
+                receiver,
+                ident,
+                args,
+            } => {
+                self.resolve_expr(receiver);
+
+                for arg in args.iter_mut() {
+                    self.resolve_expr(arg);
+                }
+
+                // Values of unknown type are checked once they are there
+                if receiver.type_ == Type::Unit {
+                    return;
+                }
+
+                match Method::lookup(receiver.type_, ident.name) {
+                    Some(method) if method.arity() != args.len() => {
+                        error_at(
+                            expr.span,
+                            &format!(
+                                "`{}` takes {} arguments but {} were given",
+                                ident.name,
+                                method.arity(),
+                                args.len()
+                            ),
+                        );
+                        self.had_error = true;
+                    }
+                    Some(method) => expr.type_ = method.result_type(receiver.type_),
+                    None => {
+                        error_at(
+                            ident.span,
+                            &format!(
+                                "`{}` values have no method `{}`",
+                                receiver.type_, ident.name
+                            ),
+                        );
+                        self.had_error = true;
+                    }
+                }
+            }
+            ExprKind::FieldAcc(receiver, ident) => {
+                self.resolve_expr(receiver);
+
+                if let Some(type_) = self.field_type(receiver, ident) {
+                    expr.type_ = type_;
+                }
+            }
+            ExprKind::List(elems) => {
+                let mut elem = Type::Unit;
+                for value in elems.iter_mut() {
+                    self.resolve_expr(value);
+
+                    match elem.unify(value.type_) {
+                        Some(type_) => elem = type_,
+                        None => {
+                            error_at(
+                                value.span,
+                                &format!("Expected a {elem} in this list, found a {}", value.type_),
+                            );
+                            self.had_error = true;
+                        }
+                    }
+                }
+
+                // Types are leaked, so a new one is only made when it changes
+                if !matches!(expr.type_, Type::List(known) if *known == elem) {
+                    expr.type_ = elem.list_of();
+                }
+            }
+            ExprKind::Index(list, index) => {
+                self.resolve_expr(list);
+                self.resolve_expr(index);
+
+                match list.type_ {
+                    Type::List(elem) => expr.type_ = *elem,
+                    Type::Unit => {}
+                    type_ => {
+                        error_at(
+                            list.span,
+                            &format!("Only lists can be indexed, found a {type_}"),
+                        );
+                        self.had_error = true;
+                    }
+                }
+
+                if !matches!(index.type_, Type::Number | Type::Unit) {
+                    error_at(
+                        index.span,
+                        &format!("Expected a num as the index, found a {}", index.type_),
+                    );
+                    self.had_error = true;
+                }
+            }
+            ExprKind::StructLit(id, fields) => {
+                for (_, value) in fields.iter_mut() {
+                    self.resolve_expr(value);
+                }
+
+                self.resolve_struct_lit(*id, fields, expr.span);
+            }
+            ExprKind::Lit(_) | ExprKind::Timestamp(_) => {}
+            ExprKind::Ident(ident) => {
+                // A function used as a value, unless a variable hides it
+                if self.lookup(Kind::Variable, ident.name).is_none()
+                    && self.lookup(Kind::Function, ident.name).is_some()
+                {
+                    expr.expr_kind = ExprKind::FnRef(*ident);
+                    return self.resolve_expr(expr);
+                }
+
+                if let Some(variable) = self.bind(Kind::Variable, ident) {
+                    expr.type_ = variable.type_;
+                }
+            }
+            ExprKind::Grouping(group) => {
+                self.resolve_expr(group);
+                expr.type_ = group.type_;
+            }
+            ExprKind::Block(block) => {
+                self.around.push(Around::Value);
+                self.resolve_block(block);
+                self.around.pop();
+
+                expr.type_ = block.type_;
+            }
+            ExprKind::If(condition, true_block, else_expr) => {
+                self.resolve_expr(condition);
+
+                self.around.push(Around::Value);
+                self.resolve_block(true_block);
+                self.resolve_expr(else_expr);
+                self.around.pop();
+
+                match true_block.type_.unify(else_expr.type_) {
+                    Some(type_) => expr.type_ = type_,
+                    None => {
+                        error_at(
+                            expr.span,
+                            &format!(
+                                "The branches of this `if` have different types, {} and {}",
+                                true_block.type_, else_expr.type_
+                            ),
+                        );
+                        self.had_error = true;
+                    }
+                }
+            }
+            ExprKind::Match(value, arms) => {
+                self.around.push(Around::Value);
+                self.resolve_match(value, arms, expr.span, |resolver, body| {
+                    resolver.resolve_expr(body)
+                });
+                self.around.pop();
+
+                let mut type_ = Type::Unit;
+                for arm in arms.iter() {
+                    match type_.unify(arm.body.type_) {
+                        Some(unified) => type_ = unified,
+                        None => {
+                            error_at(
+                                arm.body.span,
+                                &format!(
+                                    "The arms of this `match` have different types, {} and {}",
+                                    type_, arm.body.type_
+                                ),
+                            );
+                            self.had_error = true;
+                        }
+                    }
+                }
+                expr.type_ = type_;
+            }
+        }
+    }
+
+    /// Resolves the body of a closure in a scope of its own, where the
+    /// variables around it it uses are captured
+    pub fn resolve_closure(&mut self, closure: &mut Closure) {
+        let around = self.begin_function(true, closure.return_type);
+        for (arg, type_) in closure.args.iter() {
+            self.declare(Kind::Variable, arg, *type_, false);
+        }
+        self.resolve_block(&mut closure.body);
+        closure.captures = self.end_function(around);
+
+        let value = closure.body.type_;
+        match closure.return_type {
+            Type::Unit => closure.return_type = value,
+            type_ if value != Type::Unit && value != type_ => {
+                let span = closure
+                    .body
+                    .value
+                    .as_ref()
+                    .map_or(closure.body.span, |value| value.span);
+                error_at(
+                    span,
+                    &format!("Expected this closure to give a {type_}, found a {value}"),
+                );
+                self.had_error = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Reports arguments that are known to not fit the function they are passed to
+    fn resolve_args(&mut self, function: &FnType, args: &[Expr], span: Span) {
+        if function.args.len() != args.len() {
+            error_at(
+                span,
+                &format!(
+                    "This function takes {} arguments but {} were given",
+                    function.args.len(),
+                    args.len()
+                ),
+            );
+            self.had_error = true;
+            return;
+        }
+
+        for (arg, type_) in args.iter().zip(function.args.iter()) {
+            if arg.type_.unify(*type_).is_none() {
+                error_at(
+                    arg.span,
+                    &format!("Expected a {type_} as this argument, found a {}", arg.type_),
+                );
+                self.had_error = true;
+            }
+        }
+    }
+
+    /// Checks the patterns and guards of a `match` and that some arm matches
+    /// every value, `resolve_body` resolves what an arm runs
+    pub fn resolve_match<B>(
+        &mut self,
+        value: &mut Expr,
+        arms: &mut [Arm<B>],
+        span: Span,
+        resolve_body: impl Fn(&mut Self, &mut B),
+    ) {
+        self.resolve_expr(value);
+
+        // A value of unknown type gets the type of the patterns
+        let mut type_ = value.type_;
+        for arm in arms.iter_mut() {
+            self.resolve_pattern(&arm.pattern, &mut type_);
+
+            if let Some(guard) = &mut arm.guard {
+                self.resolve_expr(guard);
+
+                if !matches!(guard.type_, Type::Bool | Type::Unit) {
+                    error_at(
+                        guard.span,
+                        &format!("Expected a bool as the guard, found a {}", guard.type_),
+                    );
+                    self.had_error = true;
+                }
+            }
+
+            resolve_body(self, &mut arm.body);
+        }
+
+        let covered = |lit: &LitKind| {
+            arms.iter().any(|arm| {
+                arm.guard.is_none()
+                    && matches!(&arm.pattern.pattern_kind, PatternKind::Lit(other) if other.0 == *lit)
+            })
+        };
+        let wildcard = arms
+            .iter()
+            .any(|arm| arm.guard.is_none() && arm.pattern.pattern_kind == PatternKind::Wildcard);
+
+        let missing: Vec<String> = match type_ {
+            _ if wildcard => Vec::new(),
+            Type::Bool => [true, false]
+                .into_iter()
+                .filter(|bool| !covered(&LitKind::Bool(*bool)))
+                .map(|bool| format!("`{bool}`"))
+                .collect(),
+            Type::Enum(id) => self.enums[id.index]
+                .variants
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !covered(&LitKind::Enum(id, *index)))
+                .map(|(_, variant)| format!("`{}::{}`", id.name, variant.name))
+                .collect(),
+            _ => {
+                let values = match type_ {
+                    Type::Unit => String::from("value"),
+                    type_ => type_.to_string(),
+                };
+
+                error_at(
+                    span,
+                    &format!("This `match` doesn't cover every {values}, add a `_` arm"),
+                );
+                self.had_error = true;
+                return;
+            }
+        };
+
+        if !missing.is_empty() {
+            error_at(
+                span,
+                &format!("This `match` doesn't cover {}", missing.join(", ")),
+            );
+            self.had_error = true;
+        }
+    }
+
+    /// Reports a pattern that can't match a value of the type
+    fn resolve_pattern(&mut self, pattern: &Pattern, type_: &mut Type) {
+        if let PatternKind::Range { start, end, .. } = &pattern.pattern_kind {
+            let message = match (start.0.type_(), end.0.type_()) {
+                (Type::Number, Type::Number) | (Type::Time, Type::Time) => None,
+                (Type::Number | Type::Time, end) => Some(format!(
+                    "Expected a {} as the end of this range, found a {end}",
+                    start.0.type_()
+                )),
+                _ => Some(String::from("Ranges can only go over numbers and times")),
+            };
+
+            if let Some(message) = message {
+                error_at(pattern.span, &message);
+                self.had_error = true;
+                return;
+            }
+        }
+
+        let Some(pattern_type) = pattern.pattern_kind.type_() else {
+            return;
+        };
+
+        if *type_ == Type::Unit {
+            *type_ = pattern_type;
+        } else if *type_ != pattern_type {
+            error_at(
+                pattern.span,
+                &format!("Expected a {type_} in this pattern, found a {pattern_type}"),
+            );
+            self.had_error = true;
+        }
+    }
+
+    /// The type of a field, reporting fields the receiver is known to not have
+    pub fn field_type(&mut self, receiver: &Expr, field: &Ident) -> Option<Type> {
+        let message = match receiver.type_ {
+            Type::Struct(id) => match self.structs[id.index].field(field.name) {
+                Some((_, type_)) => return Some(type_),
+                None => format!("`{}` has no field `{}`", id.name, field.name),
+            },
+            Type::Time
+            | Type::Number
+            | Type::Bool
+            | Type::Str
+            | Type::Task
+            | Type::Timestamp
+            | Type::List(_)
+            | Type::Enum(_)
+            | Type::Function(_) => {
+                format!("`{}` values have no field `{}`", receiver.type_, field.name)
+            }
+            // The fields of a user aren't known before running
+            Type::User | Type::Unit => return None,
+        };
+
+        self.error(field, &message);
+
+        None
+    }
+
+    /// Checks that every field is given once with the declared type and puts
+    /// them in the order of the declaration
+    fn resolve_struct_lit(&mut self, id: StructId, fields: &mut [(Ident, Expr)], span: Span) {
+        let def = self.structs[id.index].clone();
+        let had_error = self.had_error;
+
+        for index in 0..fields.len() {
+            let (given, rest) = fields.split_at_mut(index);
+            let (field, value) = &mut rest[0];
+
+            match def.field(field.name) {
+                Some(_) if given.iter().any(|(other, _)| other.name == field.name) => {
+                    self.error(field, &format!("The field `{}` is given twice", field.name));
+                }
+                Some((_, type_)) => self.check_type(value, type_, field),
+                None => self.error(
+                    field,
+                    &format!("`{}` has no field `{}`", id.name, field.name),
+                ),
+            }
+        }
+
+        let missing: Vec<_> = def
+            .fields
+            .iter()
+            .filter(|(field, _)| fields.iter().all(|(given, _)| given.name != field.name))
+            .map(|(field, _)| format!("`{}`", field.name))
+            .collect();
+
+        if !missing.is_empty() {
+            error_at(
+                span,
+                &format!(
+                    "`{}` is missing the {} {}",
+                    id.name,
+                    if missing.len() == 1 {
+                        "field"
+                    } else {
+                        "fields"
+                    },
+                    missing.join(", ")
+                ),
+            );
+            self.had_error = true;
+        }
+
+        if self.had_error == had_error {
+            fields.sort_by_key(|(field, _)| def.field(field.name).map(|(index, _)| index));
+        }
+    }
+}
@@ -0,0 +1,483 @@
+//! Binds every use of a variable or function to its declaration before a
+//! script runs.
+//!
+//! Scoping is lexical: a function sees its own variables and those declared at
+//! the top level of the script, but not the locals of the code around it.
+//! Closures capture the locals around them they use instead.
+
+use crate::{
+    error_handling::{error_at, warn_at},
+    parser::ast::{
+        expr::{Binding, Ident},
+        Ast, EnumDef, Namespace, StructDef, Type,
+    },
+    runtime::builtins::Builtin,
+};
+
+mod expr;
+mod stmt;
+
+pub struct Resolver {
+    globals: Scope,
+    /// Scopes of the function being resolved, or of the blocks of the script
+    /// itself at the top level, innermost scope last
+    locals: Vec<Scope>,
+    /// Scopes of the code around the function being resolved, which it can't see
+    enclosing: Vec<Vec<Scope>>,
+    /// What each function being resolved captures, `None` for functions
+    /// declared with `func`, which can't, innermost last
+    captures: Vec<Option<Vec<Ident>>>,
+    /// What each function being resolved returns, innermost last, with
+    /// [`Type::Unit`] for a closure that doesn't say
+    returns: Vec<Type>,
+    /// The loops and value blocks around the current statement, innermost last
+    around: Vec<Around>,
+    /// The structs declared in the script
+    structs: Vec<StructDef>,
+    /// The enums declared in the script
+    enums: Vec<EnumDef>,
+    /// The names of the file being resolved
+    namespace: Namespace,
+    had_error: bool,
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    variables: Vec<Declaration>,
+    functions: Vec<Declaration>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Declaration {
+    name: &'static str,
+    type_: Type,
+    /// Constants can't be assigned to after their declaration
+    is_const: bool,
+    /// A copy of a variable around a closure, which can't be assigned to either
+    is_captured: bool,
+}
+
+/// Code that `break`, `continue` and `return` could leave
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Around {
+    /// A loop with its label
+    Loop(Option<&'static str>),
+    /// A block used as a value, which has to run to its end
+    Value,
+    /// The block of a `retry`, which runs to its end or fails
+    Retry,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Variable,
+    Function,
+}
+
+impl Kind {
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::Variable => "variable",
+            Kind::Function => "function",
+        }
+    }
+}
+
+impl Scope {
+    fn names(&self, kind: Kind) -> &Vec<Declaration> {
+        match kind {
+            Kind::Variable => &self.variables,
+            Kind::Function => &self.functions,
+        }
+    }
+
+    fn names_mut(&mut self, kind: Kind) -> &mut Vec<Declaration> {
+        match kind {
+            Kind::Variable => &mut self.variables,
+            Kind::Function => &mut self.functions,
+        }
+    }
+
+    /// The slot and declaration of the latest declaration with this name
+    fn find(&self, kind: Kind, name: &str) -> Option<(usize, Declaration)> {
+        let names = self.names(kind);
+
+        names
+            .iter()
+            .rposition(|declaration| declaration.name == name)
+            .map(|slot| (slot, names[slot]))
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            globals: Scope::default(),
+            locals: Vec::new(),
+            enclosing: Vec::new(),
+            captures: Vec::new(),
+            returns: Vec::new(),
+            around: Vec::new(),
+            structs: Vec::new(),
+            enums: Vec::new(),
+            namespace: Namespace::default(),
+            had_error: false,
+        }
+    }
+
+    /// Fills in the bindings of `ast`, reporting every name that can't be resolved
+    pub fn resolve(mut self, ast: &mut Ast) -> Result<(), ()> {
+        self.structs = ast.structs.clone();
+        self.enums = ast.enums.clone();
+        self.namespace = ast.namespace.clone();
+
+        for stmt in ast.program.iter_mut() {
+            self.resolve_stmt(stmt);
+        }
+
+        match self.had_error {
+            true => Err(()),
+            false => Ok(()),
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.locals.push(Scope::default());
+    }
+
+    fn end_scope(&mut self) {
+        self.locals.pop();
+    }
+
+    /// Starts resolving a function body, hiding the scopes and loops around it
+    ///
+    /// Returns the loops around it, which [`Resolver::end_function`] restores.
+    fn begin_function(&mut self, is_closure: bool, return_type: Type) -> Vec<Around> {
+        let locals = std::mem::take(&mut self.locals);
+        self.enclosing.push(locals);
+        self.captures.push(is_closure.then(Vec::new));
+        self.returns.push(return_type);
+        self.begin_scope();
+
+        std::mem::take(&mut self.around)
+    }
+
+    /// Returns the variables the function captured
+    fn end_function(&mut self, around: Vec<Around>) -> Vec<Ident> {
+        self.locals = self.enclosing.pop().unwrap_or_default();
+        self.around = around;
+        self.returns.pop();
+
+        self.captures.pop().flatten().unwrap_or_default()
+    }
+
+    /// The scopes of the function at this depth of nesting, 0 being the script itself
+    fn scopes_at(&mut self, level: usize) -> &mut Vec<Scope> {
+        match self.enclosing.get_mut(level) {
+            Some(scopes) => scopes,
+            None => &mut self.locals,
+        }
+    }
+
+    /// Captures a variable of the code around the closure at `level`, and of
+    /// the closures between them, and returns where the closure finds its copy
+    ///
+    /// Copies go into the outermost scope of the closure, after its arguments.
+    fn capture(&mut self, level: usize, ident: &Ident) -> Option<(Binding, Declaration)> {
+        // Functions declared with `func` can't capture, so neither can closures in them
+        self.captures.get(level.checked_sub(1)?)?.as_ref()?;
+
+        let found =
+            self.scopes_at(level - 1)
+                .iter()
+                .rev()
+                .enumerate()
+                .find_map(|(depth, scope)| {
+                    scope
+                        .find(Kind::Variable, ident.name)
+                        .map(|(slot, declaration)| (Binding::Local { depth, slot }, declaration))
+                });
+        let (binding, declaration) = match found {
+            Some(found) => found,
+            None => self.capture(level - 1, ident)?,
+        };
+
+        let captured = Ident {
+            binding: Some(binding),
+            ..*ident
+        };
+        self.captures[level - 1].as_mut()?.push(captured);
+
+        let declaration = Declaration {
+            is_const: true,
+            is_captured: true,
+            ..declaration
+        };
+        let scopes = self.scopes_at(level);
+        let depth = scopes.len() - 1;
+        scopes[0].variables.push(declaration);
+        let slot = scopes[0].variables.len() - 1;
+
+        Some((Binding::Local { depth, slot }, declaration))
+    }
+
+    fn declare(&mut self, kind: Kind, ident: &Ident, type_: Type, is_const: bool) {
+        if self.lookup(kind, ident.name).is_some() {
+            warn_at(
+                ident.span,
+                &format!(
+                    "The {} `{}` shadows an earlier one",
+                    kind.name(),
+                    ident.name
+                ),
+            );
+        }
+
+        let scope = match self.locals.last_mut() {
+            Some(scope) => scope,
+            None => &mut self.globals,
+        };
+
+        scope.names_mut(kind).push(Declaration {
+            name: ident.name,
+            type_,
+            is_const,
+            is_captured: false,
+        });
+    }
+
+    /// The closest declaration with this name, `None` for the binding if it's
+    /// in the code around the current function
+    fn lookup(&self, kind: Kind, name: &str) -> Option<(Option<Binding>, Declaration)> {
+        for (depth, scope) in self.locals.iter().rev().enumerate() {
+            if let Some((slot, declaration)) = scope.find(kind, name) {
+                return Some((Some(Binding::Local { depth, slot }), declaration));
+            }
+        }
+
+        for scope in self
+            .enclosing
+            .iter()
+            .rev()
+            .flat_map(|scopes| scopes.iter().rev())
+        {
+            if let Some((_, declaration)) = scope.find(kind, name) {
+                return Some((None, declaration));
+            }
+        }
+
+        self.namespace.candidates(name).iter().find_map(|name| {
+            self.globals
+                .find(kind, name)
+                .map(|(slot, declaration)| (Some(Binding::Global(slot)), declaration))
+        })
+    }
+
+    /// Gives a declaration at the top level of an imported file its full name
+    fn qualify(&self, ident: &mut Ident) {
+        if self.locals.is_empty() && self.enclosing.is_empty() {
+            ident.name = self.namespace.qualify(ident.name);
+        }
+    }
+
+    /// Binds a use of a name and returns its declaration, reporting an error
+    /// if there is no declaration it could refer to
+    ///
+    /// Builtin functions stay unbound. Variables of the code around a closure
+    /// are captured by it.
+    fn bind(&mut self, kind: Kind, ident: &mut Ident) -> Option<Declaration> {
+        let mut found = self.lookup(kind, ident.name);
+        if let Some((None, _)) = found
+            && matches!(kind, Kind::Variable)
+            && let Some((binding, declaration)) = self.capture(self.enclosing.len(), ident)
+        {
+            found = Some((Some(binding), declaration));
+        }
+
+        let message = match found {
+            Some((Some(binding), declaration)) => {
+                // Imported names are used by their full name from here on
+                ident.name = declaration.name;
+                ident.binding = Some(binding);
+                return Some(declaration);
+            }
+            Some((None, _)) => format!(
+                "The {} `{}` belongs to the code around this function, functions can only use their own and global ones",
+                kind.name(),
+                ident.name
+            ),
+            None if matches!(kind, Kind::Function) && Builtin::from_name(ident.name).is_some() => {
+                return None;
+            }
+            None => format!("Unknown {} `{}`", kind.name(), ident.name),
+        };
+
+        self.error(ident, &message);
+
+        None
+    }
+
+    fn error(&mut self, ident: &Ident, message: &str) {
+        error_at(ident.span, message);
+        self.had_error = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ast::expr::ExprKind, ast::stmt::StmtKind, parse};
+
+    fn resolve(code: &str) -> Ast {
+        let mut ast = parse(code).unwrap();
+        Resolver::new().resolve(&mut ast).unwrap();
+        ast
+    }
+
+    /// The binding of the expression of the `index`th expression statement of a block
+    fn binding_of(ast: &Ast, stmt: usize) -> Option<Binding> {
+        match &ast.program[stmt].stmt_kind {
+            StmtKind::Expr(expr) => match &expr.expr_kind {
+                ExprKind::Ident(ident) | ExprKind::FnCall(ident, _) => ident.binding,
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_globals_are_bound_to_slots() {
+        let ast = resolve("num a = 1; num b = 2; b; a;");
+
+        assert_eq!(binding_of(&ast, 2), Some(Binding::Global(1)));
+        assert_eq!(binding_of(&ast, 3), Some(Binding::Global(0)));
+    }
+
+    #[test]
+    fn test_locals_are_bound_to_depth_and_slot() {
+        let ast = resolve(
+            "
+            func f(a: num, b: num) -> num {
+                num c = a;
+                if (true) {
+                    return b + c;
+                }
+                return c;
+            }
+            f(1, 2);
+            ",
+        );
+
+        let StmtKind::FnDef { body, .. } = &ast.program[0].stmt_kind else {
+            unreachable!()
+        };
+        let StmtKind::If(_, block, _) = &body.stmts[1].stmt_kind else {
+            unreachable!()
+        };
+        let StmtKind::Return(expr) = &block.stmts[0].stmt_kind else {
+            unreachable!()
+        };
+        let ExprKind::Binary(left, _, right) = &expr.expr_kind else {
+            unreachable!()
+        };
+
+        // The arguments and the body are separate scopes
+        let binding = |expr: &crate::parser::ast::expr::Expr| match &expr.expr_kind {
+            ExprKind::Ident(ident) => ident.binding,
+            _ => None,
+        };
+        assert_eq!(binding(left), Some(Binding::Local { depth: 2, slot: 1 }));
+        assert_eq!(binding(right), Some(Binding::Local { depth: 1, slot: 0 }));
+        assert_eq!(binding_of(&ast, 1), Some(Binding::Global(0)));
+    }
+
+    #[test]
+    fn test_assignments_are_bound() {
+        let ast = resolve("num a = 1; const time t = 1s; a += 2; a;");
+
+        let StmtKind::Assign { target, .. } = &ast.program[2].stmt_kind else {
+            unreachable!()
+        };
+        assert_eq!(target.binding, Some(Binding::Global(0)));
+        assert_eq!(binding_of(&ast, 3), Some(Binding::Global(0)));
+    }
+
+    #[test]
+    fn test_exhaustive_matches_resolve() {
+        let ast = resolve(
+            "
+            enum State { Pending, Done }
+            State s = State::Pending;
+            match s { State::Pending => {} State::Done => {} }
+            match s == State::Done { true => {}, false => {} }
+            time t = match s { State::Done => 1s, _ => 2s };
+            ",
+        );
+
+        let StmtKind::VarBind { value, .. } = &ast.program[3].stmt_kind else {
+            unreachable!()
+        };
+        assert_eq!(value.type_, Type::Time);
+    }
+
+    #[test]
+    fn test_closures_capture_locals() {
+        let ast = resolve(
+            "
+            if (true) {
+                num a = 1;
+                || -> num f = || -> num { a };
+            }
+            ",
+        );
+
+        let StmtKind::If(_, block, _) = &ast.program[0].stmt_kind else {
+            unreachable!()
+        };
+        let StmtKind::VarBind { value, .. } = &block.stmts[1].stmt_kind else {
+            unreachable!()
+        };
+        let ExprKind::Closure(closure) = &value.expr_kind else {
+            unreachable!()
+        };
+        let Some(ExprKind::Ident(use_)) = closure.body.value.as_ref().map(|value| &value.expr_kind)
+        else {
+            unreachable!()
+        };
+
+        // Bound where the closure is created, and to the copy after the arguments inside it
+        assert_eq!(
+            closure.captures[0].binding,
+            Some(Binding::Local { depth: 0, slot: 0 })
+        );
+        assert_eq!(use_.binding, Some(Binding::Local { depth: 1, slot: 0 }));
+    }
+
+    #[test]
+    fn test_builtins_stay_unbound() {
+        let ast = resolve("wait(1s);");
+
+        assert_eq!(binding_of(&ast, 0), None);
+    }
+
+    #[test]
+    fn test_returned_values_match_the_function() {
+        let mut ast =
+            parse("func f() -> time { return 1s; } func g() -> [num] { return []; }").unwrap();
+        assert!(Resolver::new().resolve(&mut ast).is_ok());
+
+        let mut ast = parse("func f() -> time { return true; }").unwrap();
+        assert!(Resolver::new().resolve(&mut ast).is_err());
+
+        let mut ast = parse("|num| -> num f = |n: num| -> num { return \"1s\"; };").unwrap();
+        assert!(Resolver::new().resolve(&mut ast).is_err());
+    }
+
+    #[test]
+    fn test_return_needs_a_function() {
+        let mut ast = parse("if (true) { return 1s; }").unwrap();
+        assert!(Resolver::new().resolve(&mut ast).is_err());
+
+        resolve("func f() -> time { if (true) { return 1s; } return 2s; } task t = spawn { return 1s; };");
+    }
+}
@@ -0,0 +1,410 @@
+use super::{Around, Declaration, Kind, Resolver};
+use crate::{
+    error_handling::error_at,
+    parser::ast::{
+        block::Block,
+        expr::{Expr, ExprKind, Ident},
+        lit::{Lit, LitKind},
+        stmt::{Else, Range, RetryOptions, Stmt, StmtKind},
+        Span, Type,
+    },
+    runtime::schedule::Cron,
+};
+
+impl Resolver {
+    pub fn resolve_stmt(&mut self, stmt: &mut Stmt) {
+        match &mut stmt.stmt_kind {
+            StmtKind::VarBind {
+                type_,
+                identifier,
+                value,
+                is_const,
+            } => {
+                // Resolved first, so `num a = a;` refers to an earlier `a`
+                self.resolve_expr(value);
+                self.check_type(value, *type_, identifier);
+                self.qualify(identifier);
+                self.declare(Kind::Variable, identifier, *type_, *is_const);
+            }
+            StmtKind::Assign { target, op, value } => {
+                self.resolve_expr(value);
+
+                let Some(variable) = self.bind(Kind::Variable, target) else {
+                    return;
+                };
+
+                if variable.is_captured {
+                    self.error(
+                        target,
+                        &format!(
+                            "`{}` is captured by this closure and can't be assigned to, closures get a copy of it",
+                            target.name
+                        ),
+                    );
+                } else if variable.is_const {
+                    self.error(
+                        target,
+                        &format!("`{}` is a constant and can't be assigned to", target.name),
+                    );
+                } else if op.is_none() {
+                    // Compound assignments are checked when they run, `time * num` stays a time
+                    self.check_type(value, variable.type_, target);
+                }
+            }
+            StmtKind::FieldAssign {
+                receiver,
+                field,
+                op,
+                value,
+            } => {
+                self.resolve_expr(receiver);
+                self.resolve_expr(value);
+
+                if let Some(type_) = self.field_type(receiver, field)
+                    && op.is_none()
+                {
+                    self.check_type(value, type_, field);
+                }
+            }
+            StmtKind::FnDef {
+                ident,
+                args,
+                body,
+                return_type,
+            } => {
+                // Declared before the body, so it can call itself
+                let type_ =
+                    Type::function(args.iter().map(|(_, type_)| *type_).collect(), *return_type);
+                self.qualify(ident);
+                self.declare(Kind::Function, ident, type_, false);
+
+                let loops = self.begin_function(false, *return_type);
+                for (arg, type_) in args.iter() {
+                    self.declare(Kind::Variable, arg, *type_, false);
+                }
+                self.resolve_block(body);
+                self.end_function(loops);
+            }
+            StmtKind::Expr(expr) => self.resolve_expr(expr),
+            StmtKind::If(condition, true_block, else_) => {
+                self.resolve_expr(condition);
+                self.resolve_block(true_block);
+
+                match else_ {
+                    Some(Else::Block(block)) => self.resolve_block(block),
+                    Some(Else::If(stmt)) => self.resolve_stmt(stmt),
+                    None => {}
+                }
+            }
+            StmtKind::Match(value, arms) => {
+                self.resolve_match(value, arms, stmt.span, |resolver, block| {
+                    resolver.resolve_block(block)
+                });
+            }
+            StmtKind::While(condition, block, label) => {
+                self.resolve_expr(condition);
+
+                self.around
+                    .push(Around::Loop(label.as_ref().map(|label| label.name)));
+                self.resolve_block(block);
+                self.around.pop();
+            }
+            StmtKind::OnInterrupt(handler) => self.resolve_closure(handler),
+            StmtKind::Within(time, block, fallback) => {
+                self.resolve_expr(time);
+
+                if !matches!(time.type_, Type::Time | Type::Number | Type::Unit) {
+                    error_at(
+                        time.span,
+                        &format!("Expected a time for the deadline, found a {}", time.type_),
+                    );
+                    self.had_error = true;
+                }
+
+                self.resolve_block(block);
+                if let Some(fallback) = fallback {
+                    self.resolve_block(fallback);
+                }
+            }
+            StmtKind::For {
+                var,
+                range,
+                block,
+                label,
+            } => {
+                self.resolve_expr(&mut range.start);
+                self.resolve_expr(&mut range.end);
+                if let Some(step) = &mut range.step {
+                    self.resolve_expr(step);
+                }
+                self.resolve_range(range);
+
+                // The loop variable gets its own scope around the block
+                self.begin_scope();
+                self.declare(Kind::Variable, var, range.type_, true);
+
+                self.around
+                    .push(Around::Loop(label.as_ref().map(|label| label.name)));
+                self.resolve_block(block);
+                self.around.pop();
+
+                self.end_scope();
+            }
+            StmtKind::Every {
+                schedule,
+                times,
+                duration,
+                block,
+                label,
+            } => {
+                self.resolve_expr(schedule);
+                match (&schedule.expr_kind, schedule.type_) {
+                    (ExprKind::Lit(Lit(LitKind::Str(text))), _) => {
+                        if let Err(message) = Cron::parse(text) {
+                            error_at(schedule.span, &message);
+                            self.had_error = true;
+                        }
+                    }
+                    (_, Type::Time | Type::Number | Type::Str | Type::Unit) => {}
+                    (_, type_) => {
+                        error_at(
+                            schedule.span,
+                            &format!("Expected a time or a cron expression after `every`, found a {type_}"),
+                        );
+                        self.had_error = true;
+                    }
+                }
+
+                if let Some(times) = times {
+                    self.resolve_option(times, Type::Number, "after `times`");
+                }
+                if let Some(duration) = duration {
+                    self.resolve_option(duration, Type::Time, "after `for`");
+                }
+
+                self.around
+                    .push(Around::Loop(label.as_ref().map(|label| label.name)));
+                self.resolve_block(block);
+                self.around.pop();
+            }
+            StmtKind::ForEach {
+                var,
+                list,
+                block,
+                label,
+            } => {
+                self.resolve_expr(list);
+
+                let elem = match list.type_ {
+                    Type::List(elem) => *elem,
+                    Type::Unit => Type::Unit,
+                    type_ => {
+                        error_at(
+                            list.span,
+                            &format!("`for` can only go over ranges and lists, found a {type_}"),
+                        );
+                        self.had_error = true;
+                        Type::Unit
+                    }
+                };
+
+                self.begin_scope();
+                self.declare(Kind::Variable, var, elem, true);
+
+                self.around
+                    .push(Around::Loop(label.as_ref().map(|label| label.name)));
+                self.resolve_block(block);
+                self.around.pop();
+
+                self.end_scope();
+            }
+            StmtKind::Break(label) => self.resolve_jump("break", label, stmt.span),
+            StmtKind::Continue(label) => self.resolve_jump("continue", label, stmt.span),
+            StmtKind::Return(expr) => {
+                self.resolve_expr(expr);
+
+                // Only function bodies push what they capture
+                if self.captures.is_empty() {
+                    error_at(stmt.span, "`return` outside of a function");
+                    self.had_error = true;
+                } else if self.around.contains(&Around::Retry) {
+                    error_at(stmt.span, "`return` can't leave a `retry` block");
+                    self.had_error = true;
+                } else if let Some(&type_) = self.returns.last()
+                    && expr.type_.unify(type_).is_none()
+                {
+                    error_at(
+                        expr.span,
+                        &format!(
+                            "Expected this function to return a {type_}, found a {}",
+                            expr.type_
+                        ),
+                    );
+                    self.had_error = true;
+                }
+            }
+            StmtKind::Retry(options, block) => {
+                let RetryOptions {
+                    attempts,
+                    backoff,
+                    jitter,
+                } = &mut **options;
+
+                if let Some(attempts) = attempts {
+                    self.resolve_option(attempts, Type::Number, "for the attempts");
+                }
+                if let Some(backoff) = backoff {
+                    self.resolve_option(&mut backoff.delay, Type::Time, "for the backoff");
+                    if let Some(max) = &mut backoff.max {
+                        self.resolve_option(max, Type::Time, "for the longest pause");
+                    }
+                }
+                if let Some(jitter) = jitter {
+                    self.resolve_option(jitter, Type::Number, "for the jitter");
+                }
+
+                // The number of the attempt is a variable of the block, which
+                // isn't worth a warning when a nested `retry` shadows it
+                self.begin_scope();
+                let scope = self.locals.last_mut().expect("begun above");
+                scope.variables.push(Declaration {
+                    name: "attempt",
+                    type_: Type::Number,
+                    is_const: true,
+                    is_captured: false,
+                });
+
+                self.around.push(Around::Retry);
+                self.resolve_block(block);
+                self.around.pop();
+
+                self.end_scope();
+            }
+            StmtKind::Module(module) => {
+                let namespace = std::mem::replace(&mut self.namespace, module.namespace.clone());
+                for stmt in module.program.iter_mut() {
+                    self.resolve_stmt(stmt);
+                }
+                self.namespace = namespace;
+            }
+        }
+    }
+
+    /// Types the range by its start and reports values known to not fit in it
+    fn resolve_range(&mut self, range: &mut Range) {
+        range.type_ = range.start.type_;
+
+        let message = match range.type_ {
+            Type::Number | Type::Time => [Some(&range.end), range.step.as_ref()]
+                .into_iter()
+                .flatten()
+                .find(|value| value.type_ != Type::Unit && value.type_ != range.type_)
+                .map(|value| {
+                    (
+                        value.span,
+                        format!(
+                            "Expected a {} in this range, found a {}",
+                            range.type_, value.type_
+                        ),
+                    )
+                }),
+            Type::Unit => None,
+            _ => Some((
+                range.start.span,
+                String::from("Ranges can only go over numbers and times"),
+            )),
+        };
+
+        if let Some((span, message)) = message {
+            error_at(span, &message);
+            self.had_error = true;
+        }
+    }
+
+    /// Resolves a setting of a statement like the number of times an `every`
+    /// loop runs, where a number also stands for seconds
+    fn resolve_option(&mut self, expr: &mut Expr, expected: Type, what: &str) {
+        self.resolve_expr(expr);
+
+        if !matches!(expr.type_, Type::Number | Type::Unit) && expr.type_ != expected {
+            error_at(
+                expr.span,
+                &format!("Expected a {expected} {what}, found a {}", expr.type_),
+            );
+            self.had_error = true;
+        }
+    }
+
+    /// Reports a `break` or `continue` that has no loop to leave
+    fn resolve_jump(&mut self, keyword: &str, label: &Option<Ident>, span: Span) {
+        let label = label.as_ref().map(|label| label.name);
+
+        for around in self.around.iter().rev() {
+            match around {
+                Around::Loop(loop_label) if label.is_none() || label == *loop_label => return,
+                Around::Loop(_) => {}
+                Around::Value => {
+                    let message =
+                        format!("`{keyword}` can't leave a block that is used as a value");
+                    error_at(span, &message);
+                    self.had_error = true;
+                    return;
+                }
+                Around::Retry => {
+                    error_at(span, &format!("`{keyword}` can't leave a `retry` block"));
+                    self.had_error = true;
+                    return;
+                }
+            }
+        }
+
+        let message = match label {
+            None => format!("`{keyword}` outside of a loop"),
+            Some(label) => format!("There is no loop labeled `{label}` around this `{keyword}`"),
+        };
+
+        error_at(span, &message);
+        self.had_error = true;
+    }
+
+    /// Reports a value that is known to not match the type of the variable it's stored in
+    ///
+    /// An empty list takes the type of its elements from the variable.
+    pub fn check_type(&mut self, value: &mut Expr, type_: Type, ident: &Ident) {
+        if let ExprKind::List(elems) = &value.expr_kind
+            && elems.is_empty()
+            && matches!(type_, Type::List(_))
+        {
+            value.type_ = type_;
+        }
+
+        // Values of unknown type are checked when they run
+        if value.type_ == Type::Unit || value.type_ == type_ {
+            return;
+        }
+
+        error_at(
+            value.span,
+            &format!(
+                "Expected a {} for `{}`, found a {}",
+                type_, ident.name, value.type_
+            ),
+        );
+        self.had_error = true;
+    }
+
+    pub fn resolve_block(&mut self, block: &mut Block) {
+        self.begin_scope();
+
+        for stmt in block.stmts.iter_mut() {
+            self.resolve_stmt(stmt);
+        }
+
+        if let Some(value) = &mut block.value {
+            self.resolve_expr(value);
+            block.type_ = value.type_;
+        }
+
+        self.end_scope();
+    }
+}
@@ -0,0 +1,297 @@
+use super::{external, ops::as_ms, prelude, Runtime};
+use crate::parser::ast::{
+    lit::{Lit, LitKind, TimeKind},
+    Type,
+};
+use std::time::Duration;
+
+/// Functions every script can call without defining them
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Builtin {
+    /// Waits for a time, plain numbers are seconds
+    ///
+    /// ## Example
+    /// ```rust
+    /// wait(30s);
+    /// ```
+    Wait,
+    /// The time since the script started, which only ever grows
+    ///
+    /// ## Example
+    /// ```rust
+    /// time start = now();
+    /// ```
+    Now,
+    /// The time since an earlier `now()`
+    Elapsed,
+    /// The shorter of two times
+    MinTime,
+    /// The longer of two times
+    MaxTime,
+    /// A time or number kept between a lowest and a highest one
+    ///
+    /// ## Example
+    /// ```rust
+    /// wait(clamp(delay, 1s, 1min));
+    /// ```
+    Clamp,
+    /// A time as a number of seconds
+    ToSeconds,
+    /// A number of seconds as a time
+    FromSeconds,
+    /// A time written out like `1min 30s`
+    FormatDuration,
+    /// A time written like in a script
+    ///
+    /// ## Example
+    /// ```rust
+    /// time t = parse_duration("1min 30s");
+    /// ```
+    ParseDuration,
+    /// A number between a lowest and a highest one
+    Random,
+    /// A time between a shortest and a longest one
+    ///
+    /// ## Example
+    /// ```rust
+    /// wait(random_time(1s, 5s));
+    /// ```
+    RandomTime,
+    /// A time moved up or down by at most a share of it
+    ///
+    /// ## Example
+    /// ```rust
+    /// wait(jitter(30s, 10%));
+    /// ```
+    Jitter,
+    /// Waits until a task finished
+    ///
+    /// ## Example
+    /// ```rust
+    /// task h = spawn { wait(5s); };
+    /// join(h);
+    /// ```
+    Join,
+    /// Waits until the first of two tasks finished and gives that one, the
+    /// other keeps running
+    ///
+    /// ## Example
+    /// ```rust
+    /// task first = race(fetch, timeout);
+    /// ```
+    Race,
+    /// Waits until the clock shows a timestamp, right away if it already passed
+    ///
+    /// ## Example
+    /// ```rust
+    /// wait_until(@2026-10-18T17:00 + 1d);
+    /// ```
+    WaitUntil,
+    /// Waits until a file exists, and gives whether it did before the timeout
+    ///
+    /// ## Example
+    /// ```rust
+    /// bool ready = wait_for_file("/tmp/build.done", 10min);
+    /// ```
+    WaitForFile,
+    /// Waits until the process with an id exited, and gives whether it did
+    /// before the timeout
+    ///
+    /// ## Example
+    /// ```rust
+    /// wait_for_process_exit(4242, 30s);
+    /// ```
+    WaitForProcessExit,
+    /// Waits until something accepts connections on a port, and gives whether
+    /// it did before the timeout
+    ///
+    /// ## Example
+    /// ```rust
+    /// wait_for_port("127.0.0.1", 8080, 1min);
+    /// ```
+    WaitForPort,
+    /// Waits until a line comes in on stdin, and gives whether one did before
+    /// the timeout
+    ///
+    /// ## Example
+    /// ```rust
+    /// if (!wait_for_line_on_stdin(5min)) { wait(1h); }
+    /// ```
+    WaitForLineOnStdin,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wait" => Some(Builtin::Wait),
+            "now" => Some(Builtin::Now),
+            "elapsed" => Some(Builtin::Elapsed),
+            "min_time" => Some(Builtin::MinTime),
+            "max_time" => Some(Builtin::MaxTime),
+            "clamp" => Some(Builtin::Clamp),
+            "to_seconds" => Some(Builtin::ToSeconds),
+            "from_seconds" => Some(Builtin::FromSeconds),
+            "format_duration" => Some(Builtin::FormatDuration),
+            "parse_duration" => Some(Builtin::ParseDuration),
+            "random" => Some(Builtin::Random),
+            "random_time" => Some(Builtin::RandomTime),
+            "jitter" => Some(Builtin::Jitter),
+            "join" => Some(Builtin::Join),
+            "race" => Some(Builtin::Race),
+            "wait_until" => Some(Builtin::WaitUntil),
+            "wait_for_file" => Some(Builtin::WaitForFile),
+            "wait_for_process_exit" => Some(Builtin::WaitForProcessExit),
+            "wait_for_port" => Some(Builtin::WaitForPort),
+            "wait_for_line_on_stdin" => Some(Builtin::WaitForLineOnStdin),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Wait => "wait",
+            Builtin::Now => "now",
+            Builtin::Elapsed => "elapsed",
+            Builtin::MinTime => "min_time",
+            Builtin::MaxTime => "max_time",
+            Builtin::Clamp => "clamp",
+            Builtin::ToSeconds => "to_seconds",
+            Builtin::FromSeconds => "from_seconds",
+            Builtin::FormatDuration => "format_duration",
+            Builtin::ParseDuration => "parse_duration",
+            Builtin::Random => "random",
+            Builtin::RandomTime => "random_time",
+            Builtin::Jitter => "jitter",
+            Builtin::Join => "join",
+            Builtin::Race => "race",
+            Builtin::WaitUntil => "wait_until",
+            Builtin::WaitForFile => "wait_for_file",
+            Builtin::WaitForProcessExit => "wait_for_process_exit",
+            Builtin::WaitForPort => "wait_for_port",
+            Builtin::WaitForLineOnStdin => "wait_for_line_on_stdin",
+        }
+    }
+
+    /// Whether calling it can wait
+    pub fn waits(&self) -> bool {
+        matches!(
+            self,
+            Builtin::Wait
+                | Builtin::Join
+                | Builtin::Race
+                | Builtin::WaitUntil
+                | Builtin::WaitForFile
+                | Builtin::WaitForProcessExit
+                | Builtin::WaitForPort
+                | Builtin::WaitForLineOnStdin
+        )
+    }
+
+    /// What calling it evaluates to, [`Type::Unit`] if that depends on the arguments
+    pub fn return_type(&self) -> Type {
+        match self {
+            Builtin::Wait
+            | Builtin::Now
+            | Builtin::Elapsed
+            | Builtin::MinTime
+            | Builtin::MaxTime
+            | Builtin::FromSeconds
+            | Builtin::ParseDuration
+            | Builtin::RandomTime
+            | Builtin::Jitter
+            | Builtin::Join
+            | Builtin::WaitUntil => Type::Time,
+            Builtin::ToSeconds | Builtin::Random => Type::Number,
+            Builtin::FormatDuration => Type::Str,
+            Builtin::Race => Type::Task,
+            Builtin::WaitForFile
+            | Builtin::WaitForProcessExit
+            | Builtin::WaitForPort
+            | Builtin::WaitForLineOnStdin => Type::Bool,
+            Builtin::Clamp => Type::Unit,
+        }
+    }
+
+    /// How many arguments it takes
+    fn arity(&self) -> usize {
+        match self {
+            Builtin::Now => 0,
+            Builtin::Wait
+            | Builtin::Elapsed
+            | Builtin::ToSeconds
+            | Builtin::FromSeconds
+            | Builtin::FormatDuration
+            | Builtin::ParseDuration
+            | Builtin::Join
+            | Builtin::WaitUntil
+            | Builtin::WaitForLineOnStdin => 1,
+            Builtin::MinTime
+            | Builtin::MaxTime
+            | Builtin::Random
+            | Builtin::RandomTime
+            | Builtin::Jitter
+            | Builtin::Race
+            | Builtin::WaitForFile
+            | Builtin::WaitForProcessExit => 2,
+            Builtin::Clamp | Builtin::WaitForPort => 3,
+        }
+    }
+
+    pub fn call(&self, args: &[Lit], runtime: &Runtime) -> Result<Lit, String> {
+        if args.len() != self.arity() {
+            return Err(self.arity_error(self.arity(), args.len()));
+        }
+
+        match self {
+            Builtin::Wait => {
+                let time = &args[0];
+
+                let Some(ms) = as_ms(time) else {
+                    return Err(String::from("`wait` expects a time"));
+                };
+
+                // Waiting for a negative time is done immediately
+                let ms = ms.max(0.);
+                let Ok(duration) = Duration::try_from_secs_f64(ms as f64 / 1000.) else {
+                    return Err(String::from(
+                        "The time given to `wait` is too long to wait for",
+                    ));
+                };
+                runtime.wait(duration)?;
+
+                Ok(match time.0 {
+                    LitKind::Num(_) => Lit::from((ms / 1000., TimeKind::Sec)),
+                    _ => *time,
+                })
+            }
+            Builtin::Now => Ok(prelude::now(runtime)),
+            Builtin::Elapsed => prelude::elapsed(&args[0], runtime),
+            Builtin::MinTime => prelude::pick_time(&args[0], &args[1], false),
+            Builtin::MaxTime => prelude::pick_time(&args[0], &args[1], true),
+            Builtin::Clamp => prelude::clamp(&args[0], &args[1], &args[2]),
+            Builtin::ToSeconds => prelude::to_seconds(&args[0]),
+            Builtin::FromSeconds => prelude::from_seconds(&args[0]),
+            Builtin::FormatDuration => prelude::format_duration(&args[0]),
+            Builtin::ParseDuration => prelude::parse_duration(&args[0]),
+            Builtin::Random => prelude::random(&args[0], &args[1], runtime),
+            Builtin::RandomTime => prelude::random_time(&args[0], &args[1], runtime),
+            Builtin::Jitter => prelude::jitter(&args[0], &args[1], runtime),
+            Builtin::Join => prelude::join(&args[0], runtime),
+            Builtin::Race => prelude::race(&args[0], &args[1], runtime),
+            Builtin::WaitUntil => prelude::wait_until(&args[0], runtime),
+            Builtin::WaitForFile => external::wait_for_file(&args[0], &args[1], runtime),
+            Builtin::WaitForProcessExit => {
+                external::wait_for_process_exit(&args[0], &args[1], runtime)
+            }
+            Builtin::WaitForPort => external::wait_for_port(&args[0], &args[1], &args[2], runtime),
+            Builtin::WaitForLineOnStdin => external::wait_for_line_on_stdin(&args[0], runtime),
+        }
+    }
+
+    fn arity_error(&self, expected: usize, given: usize) -> String {
+        format!(
+            "`{}` takes {expected} arguments but {given} were given",
+            self.name()
+        )
+    }
+}
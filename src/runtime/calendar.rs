@@ -0,0 +1,131 @@
+//! Dates and times of day for timestamps like `@2026-10-18T17:00`, which are
+//! kept as milliseconds since the Unix epoch in UTC
+
+use super::clock::Clock;
+use crate::parser::ast::lit::Stamp;
+
+pub const DAY_MS: i64 = 86_400_000;
+
+/// Days from 1970-01-01 to a date of the Gregorian calendar
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // Counts from March, so the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The year, month and day of a number of days since 1970-01-01
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    } as u32;
+
+    (era * 400 + year_of_era + (month <= 2) as i64, month, day)
+}
+
+/// The day of the week of a number of days since 1970-01-01, 0 is Sunday
+pub fn weekday(days: i64) -> u32 {
+    // 1970-01-01 was a Thursday
+    (days + 4).rem_euclid(7) as u32
+}
+
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The time since the Unix epoch the clock shows
+pub fn wall_ms(clock: &dyn Clock) -> i64 {
+    (clock.epoch() + clock.now()).as_millis() as i64
+}
+
+/// The milliseconds since the Unix epoch a timestamp written in the script
+/// stands for, a time of day without a date is the next time the clock shows it
+pub fn resolve(stamp: &Stamp, clock: &dyn Clock) -> i64 {
+    let time_ms = stamp.time as i64 * 1000;
+
+    let local = |ms: i64| match stamp.utc {
+        true => ms,
+        false => to_local(ms, clock),
+    };
+
+    let local_ms = match stamp.date {
+        Some((year, month, day)) => days_from_civil(year, month, day) * DAY_MS + time_ms,
+        None => {
+            let local_now = local(wall_ms(clock));
+
+            let today = local_now.div_euclid(DAY_MS) * DAY_MS + time_ms;
+            match today < local_now {
+                true => today + DAY_MS,
+                false => today,
+            }
+        }
+    };
+
+    match stamp.utc {
+        true => local_ms,
+        false => from_local(local_ms, clock),
+    }
+}
+
+/// The local time the clock's time zone shows at a time since the Unix epoch
+pub fn to_local(utc_ms: i64, clock: &dyn Clock) -> i64 {
+    utc_ms + offset_ms(utc_ms, clock)
+}
+
+/// The time since the Unix epoch at which the clock's time zone shows a local time
+pub fn from_local(local_ms: i64, clock: &dyn Clock) -> i64 {
+    // The offset at the time itself, which differs from the one now around daylight saving changes
+    let guess = local_ms - offset_ms(local_ms, clock);
+    local_ms - offset_ms(guess, clock)
+}
+
+fn offset_ms(at_ms: i64, clock: &dyn Clock) -> i64 {
+    clock.utc_offset(at_ms.div_euclid(1000)) * 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::clock::VirtualClock;
+    use std::time::Duration;
+
+    #[test]
+    fn test_timestamps_resolve_against_the_clock() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(2100, 2), 28);
+        assert_eq!(civil_from_days(11_017), (2000, 3, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(weekday(days_from_civil(2026, 10, 18)), 0);
+
+        // 2026-10-18T16:00Z, two hours ahead of UTC
+        let clock = VirtualClock::starting_at(Duration::from_secs(1_792_339_200), 2 * 3600);
+        let stamp = |text: &str| resolve(&Stamp::parse(text).unwrap(), &clock);
+
+        assert_eq!(stamp("2026-10-18T16:00Z"), 1_792_339_200_000);
+        assert_eq!(stamp("2026-10-18T18:00"), 1_792_339_200_000);
+        // 17:00 local already passed, 19:00 local is still ahead today
+        assert_eq!(stamp("17:00"), 1_792_339_200_000 + DAY_MS - 3_600_000);
+        assert_eq!(stamp("19:00:30"), 1_792_339_200_000 + 3_630_000);
+    }
+}
@@ -0,0 +1,457 @@
+//! Checkpoints that let a script run with `--checkpoint` continue after a
+//! restart with `wait resume`.
+//!
+//! Before every wait the VM writes down its state, the values of the script
+//! and where the wait ends on the clock of the script. Resuming starts the
+//! clock where it would be now had the script kept running, so the time that
+//! passed in between counts towards the wait and any `within` block.
+
+use super::clock::Clock;
+use crate::parser::ast::{
+    lit::{Lit, LitKind, TimeKind},
+    EnumId, FnType, StructId, Type,
+};
+use std::{
+    cell::{Cell, RefCell},
+    fs,
+    path::PathBuf,
+    time::Duration,
+};
+
+/// The first line of every checkpoint
+const HEADER: &str = "wait checkpoint";
+
+/// Where a script writes its checkpoints
+pub struct Checkpoint {
+    path: PathBuf,
+    /// The script it resumes, with an absolute path
+    script: String,
+    /// Fingerprint of the compiled script, so a changed one isn't resumed
+    pub code: Cell<u64>,
+    /// The state of the VM before the instruction that runs now, written by
+    /// the first wait the instruction does
+    pending: RefCell<Option<String>>,
+    /// When the wait the script was resumed at ends, on the clock of the script
+    resume_until: Cell<Option<Duration>>,
+    /// Whether the script was told it can't be checkpointed at some wait
+    pub warned: Cell<bool>,
+}
+
+impl Checkpoint {
+    pub fn new(path: PathBuf, script: String) -> Self {
+        Self {
+            path,
+            script,
+            code: Cell::new(0),
+            pending: RefCell::new(None),
+            resume_until: Cell::new(None),
+            warned: Cell::new(false),
+        }
+    }
+
+    /// Continues writing to the checkpoint a script was resumed from
+    pub fn resumed(path: PathBuf, saved: &Saved) -> Self {
+        let checkpoint = Self::new(path, saved.script.clone());
+        checkpoint.code.set(saved.code);
+        checkpoint.resume_until.set(Some(saved.wait_until));
+
+        checkpoint
+    }
+
+    /// Keeps the state of the VM for the next wait, `None` if it can't be saved
+    pub fn set_pending(&self, state: Option<String>) {
+        *self.pending.borrow_mut() = state;
+    }
+
+    /// Writes the state kept for it before a wait of the given length, and
+    /// gives how long the wait really is, which is shorter for the one the
+    /// script was resumed at
+    pub fn before_wait(&self, duration: Duration, clock: &dyn Clock) -> Result<Duration, String> {
+        let now = clock.now();
+        let duration = match self.resume_until.take() {
+            Some(until) => until.saturating_sub(now),
+            None => duration,
+        };
+
+        // Only the first wait of an instruction, later ones like the checks
+        // of `wait_for_file` resume at the same place
+        let Some(state) = self.pending.borrow_mut().take() else {
+            return Ok(duration);
+        };
+
+        let text = format!(
+            "{HEADER}\nscript {}\ncode {:x}\nepoch {}\nwait {}\n{state}",
+            self.script,
+            self.code.get(),
+            clock.epoch().as_millis(),
+            (now + duration).as_millis()
+        );
+
+        // Written next to it first, so a crash never leaves half a checkpoint
+        let temp = self.path.with_extension("tmp");
+        fs::write(&temp, text)
+            .and_then(|()| fs::rename(&temp, &self.path))
+            .map_err(|err| {
+                format!(
+                    "Couldn't write the checkpoint `{}`: {err}",
+                    self.path.display()
+                )
+            })?;
+
+        Ok(duration)
+    }
+
+    /// Removes the checkpoint once the script finished
+    pub fn remove(&self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A checkpoint read back from its file
+#[derive(Debug)]
+pub struct Saved {
+    pub script: String,
+    pub code: u64,
+    /// When the clock of the script started, since the Unix epoch
+    pub epoch: Duration,
+    pub wait_until: Duration,
+    /// The lines with the state of the VM
+    pub lines: Vec<String>,
+}
+
+impl Saved {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Couldn't read the checkpoint `{path}`: {err}"))?;
+        let broken = || format!("`{path}` isn't a checkpoint written by `wait run --checkpoint`");
+
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(broken());
+        }
+
+        let mut script = None;
+        let mut code = None;
+        let mut epoch = None;
+        let mut wait_until = None;
+        let mut rest = Vec::new();
+
+        for line in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let ms = || value.parse().ok().map(Duration::from_millis);
+
+            match key {
+                "script" => script = Some(value.to_string()),
+                "code" => code = u64::from_str_radix(value, 16).ok(),
+                "epoch" => epoch = ms(),
+                "wait" => wait_until = ms(),
+                _ => rest.push(line.to_string()),
+            }
+        }
+
+        match (script, code, epoch, wait_until) {
+            (Some(script), Some(code), Some(epoch), Some(wait_until)) => Ok(Self {
+                script,
+                code,
+                epoch,
+                wait_until,
+                lines: rest,
+            }),
+            _ => Err(broken()),
+        }
+    }
+
+    /// The values on each line starting with the key
+    pub fn get<'a>(&'a self, key: &'a str) -> impl Iterator<Item = Vec<&'a str>> + 'a {
+        self.lines.iter().filter_map(move |line| {
+            let mut words = line.split(' ');
+            (words.next() == Some(key)).then(|| words.filter(|word| !word.is_empty()).collect())
+        })
+    }
+}
+
+/// A value as a single word of a checkpoint, tasks can't be saved
+pub fn encode(lit: &Lit) -> Result<String, String> {
+    Ok(match lit.0 {
+        LitKind::Num(num) => format!("n{num}"),
+        LitKind::Time(num, kind) => format!("t{}{num}", unit(kind)),
+        LitKind::Bool(bool) => format!("b{}", bool as u8),
+        LitKind::Struct(id, index) => format!("S{index}.{}.{}", id.index, id.name),
+        LitKind::List(elem, index) => format!("L{index}.{}", encode_type(elem)),
+        LitKind::Enum(id, variant) => format!("e{variant}.{}.{}", id.index, id.name),
+        LitKind::Function(type_, index) => {
+            format!("f{index}.{}", encode_type(&Type::Function(type_)))
+        }
+        LitKind::Str(text) => format!("s{}", escape(text)),
+        LitKind::Task(_) => return Err(String::from("Tasks can't be saved in a checkpoint")),
+        LitKind::Timestamp(ms) => format!("p{ms}"),
+    })
+}
+
+/// The value written as a word by [`encode`]
+pub fn decode(word: &str) -> Option<Lit> {
+    let mut chars = word.chars();
+    let kind = chars.next()?;
+    let rest = chars.as_str();
+
+    let kind = match kind {
+        'n' => LitKind::Num(rest.parse().ok()?),
+        't' => {
+            let mut chars = rest.chars();
+            let kind = unit_kind(chars.next()?)?;
+            LitKind::Time(chars.as_str().parse().ok()?, kind)
+        }
+        'b' => LitKind::Bool(rest == "1"),
+        'S' => {
+            let [index, id, name] = split(rest)?;
+            LitKind::Struct(
+                StructId {
+                    index: id.parse().ok()?,
+                    name: leak(name),
+                },
+                index.parse().ok()?,
+            )
+        }
+        'L' => {
+            let (index, elem) = rest.split_once('.')?;
+            LitKind::List(Box::leak(Box::new(decode_type(elem)?)), index.parse().ok()?)
+        }
+        'e' => {
+            let [variant, id, name] = split(rest)?;
+            LitKind::Enum(
+                EnumId {
+                    index: id.parse().ok()?,
+                    name: leak(name),
+                },
+                variant.parse().ok()?,
+            )
+        }
+        'f' => {
+            let (index, type_) = rest.split_once('.')?;
+            let Type::Function(type_) = decode_type(type_)? else {
+                return None;
+            };
+            LitKind::Function(type_, index.parse().ok()?)
+        }
+        's' => LitKind::Str(leak(&unescape(rest)?)),
+        'p' => LitKind::Timestamp(rest.parse().ok()?),
+        _ => return None,
+    };
+
+    Some(Lit::new(kind))
+}
+
+/// Values as the words of a line of a checkpoint
+pub fn encode_all(values: &[Lit]) -> Result<String, String> {
+    let words: Vec<_> = values.iter().map(encode).collect::<Result<_, _>>()?;
+    Ok(words.join(" "))
+}
+
+/// The values written as words by [`encode`]
+pub fn decode_all(words: &[&str]) -> Option<Vec<Lit>> {
+    words.iter().map(|word| decode(word)).collect()
+}
+
+pub fn encode_type(type_: &Type) -> String {
+    match type_ {
+        Type::Time => String::from("t"),
+        Type::Number => String::from("n"),
+        Type::Bool => String::from("b"),
+        Type::User => String::from("u"),
+        Type::Unit => String::from("_"),
+        Type::Str => String::from("s"),
+        Type::Task => String::from("k"),
+        Type::Timestamp => String::from("p"),
+        Type::Struct(id) => format!("S{}.{};", id.index, id.name),
+        Type::Enum(id) => format!("E{}.{};", id.index, id.name),
+        Type::List(elem) => format!("L{}", encode_type(elem)),
+        Type::Function(function) => {
+            let args: String = function.args.iter().map(encode_type).collect();
+            format!(
+                "F{}.{args}{}",
+                function.args.len(),
+                encode_type(&function.return_type)
+            )
+        }
+    }
+}
+
+fn decode_type(text: &str) -> Option<Type> {
+    match TypeReader(text).read()? {
+        (type_, "") => Some(type_),
+        _ => None,
+    }
+}
+
+/// Reads types written by [`encode_type`] one after the other
+struct TypeReader<'a>(&'a str);
+
+impl<'a> TypeReader<'a> {
+    fn read(mut self) -> Option<(Type, &'a str)> {
+        let type_ = self.next()?;
+        Some((type_, self.0))
+    }
+
+    fn next(&mut self) -> Option<Type> {
+        let mut chars = self.0.chars();
+        let kind = chars.next()?;
+        self.0 = chars.as_str();
+
+        Some(match kind {
+            't' => Type::Time,
+            'n' => Type::Number,
+            'b' => Type::Bool,
+            'u' => Type::User,
+            '_' => Type::Unit,
+            's' => Type::Str,
+            'k' => Type::Task,
+            'p' => Type::Timestamp,
+            'S' => {
+                let (index, name) = self.id()?;
+                Type::Struct(StructId { index, name })
+            }
+            'E' => {
+                let (index, name) = self.id()?;
+                Type::Enum(EnumId { index, name })
+            }
+            'L' => Type::List(Box::leak(Box::new(self.next()?))),
+            'F' => {
+                let (count, rest) = self.0.split_once('.')?;
+                self.0 = rest;
+
+                let args = (0..count.parse::<usize>().ok()?)
+                    .map(|_| self.next())
+                    .collect::<Option<_>>()?;
+                let return_type = self.next()?;
+                Type::Function(Box::leak(Box::new(FnType { args, return_type })))
+            }
+            _ => return None,
+        })
+    }
+
+    /// The index and name of a struct or enum, up to the `;` after it
+    fn id(&mut self) -> Option<(usize, &'static str)> {
+        let (id, rest) = self.0.split_once(';')?;
+        self.0 = rest;
+
+        let (index, name) = id.split_once('.')?;
+        Some((index.parse().ok()?, leak(name)))
+    }
+}
+
+fn unit(kind: TimeKind) -> char {
+    match kind {
+        TimeKind::Ms => 'm',
+        TimeKind::Sec => 's',
+        TimeKind::Min => 'M',
+        TimeKind::Hour => 'h',
+        TimeKind::Day => 'd',
+        TimeKind::Week => 'w',
+        TimeKind::Year => 'y',
+    }
+}
+
+fn unit_kind(unit: char) -> Option<TimeKind> {
+    Some(match unit {
+        'm' => TimeKind::Ms,
+        's' => TimeKind::Sec,
+        'M' => TimeKind::Min,
+        'h' => TimeKind::Hour,
+        'd' => TimeKind::Day,
+        'w' => TimeKind::Week,
+        'y' => TimeKind::Year,
+        _ => return None,
+    })
+}
+
+/// Keeps a string on a single word
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char in text.chars() {
+        match char {
+            '\\' => escaped.push_str("\\\\"),
+            ' ' => escaped.push_str("\\s"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            _ => escaped.push(char),
+        }
+    }
+
+    escaped
+}
+
+fn unescape(text: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(char) = chars.next() {
+        unescaped.push(match char {
+            '\\' => match chars.next()? {
+                '\\' => '\\',
+                's' => ' ',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                _ => return None,
+            },
+            _ => char,
+        });
+    }
+
+    Some(unescaped)
+}
+
+/// Three parts separated by `.`, the last one can't have any
+fn split(text: &str) -> Option<[&str; 3]> {
+    let mut parts = text.splitn(3, '.');
+    Some([parts.next()?, parts.next()?, parts.next()?])
+}
+
+/// Names and strings live as long as the script, like the ones it was parsed from
+fn leak(text: &str) -> &'static str {
+    Box::leak(text.to_string().into_boxed_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_survive_a_checkpoint() {
+        let job = StructId {
+            index: 1,
+            name: "jobs::Job",
+        };
+        let state = EnumId {
+            index: 0,
+            name: "State",
+        };
+        let callback: &'static FnType = Box::leak(Box::new(FnType {
+            args: vec![Type::Struct(job), Type::List(&Type::Time)],
+            return_type: Type::Enum(state),
+        }));
+
+        let values = [
+            Lit::from(-2.5),
+            Lit::from(f32::INFINITY),
+            Lit::from((90., TimeKind::Min)),
+            Lit::from(true),
+            Lit::new(LitKind::Struct(job, 3)),
+            Lit::new(LitKind::List(&Type::Unit, 0)),
+            Lit::new(LitKind::Enum(state, 2)),
+            Lit::new(LitKind::Function(callback, 7)),
+            Lit::new(LitKind::Str("a \\ b\n\tc")),
+            Lit::new(LitKind::Str("")),
+            Lit::new(LitKind::Timestamp(-1_792_339_200_000)),
+        ];
+
+        for value in values {
+            let word = encode(&value).unwrap();
+            assert!(!word.contains([' ', '\n']), "{word}");
+            assert_eq!(decode(&word), Some(value));
+        }
+
+        assert!(encode(&Lit::new(LitKind::Task(1))).is_err());
+        assert_eq!(decode("x1"), None);
+    }
+}
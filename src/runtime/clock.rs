@@ -0,0 +1,143 @@
+use super::interrupt;
+#[cfg(test)]
+use std::cell::Cell;
+use std::{
+    sync::atomic::Ordering,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Where the time comes from while a script runs
+pub trait Clock {
+    /// Time passed since the clock was created
+    fn now(&self) -> Duration;
+
+    /// Blocks until `duration` has passed
+    fn sleep(&self, duration: Duration);
+
+    /// Time since the Unix epoch when the clock was created
+    fn epoch(&self) -> Duration;
+
+    /// How many seconds local time is ahead of UTC at a time given in
+    /// seconds since the Unix epoch
+    fn utc_offset(&self, at: i64) -> i64;
+}
+
+/// The real time, waiting actually blocks the thread
+pub struct SystemClock {
+    start: Instant,
+    epoch: Duration,
+    /// How long the clock already ran before it was created
+    offset: Duration,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            epoch: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default(),
+            offset: Duration::ZERO,
+        }
+    }
+
+    /// The clock of a script that started at a time since the Unix epoch,
+    /// showing the time since then
+    pub fn since(epoch: Duration) -> Self {
+        let clock = Self::new();
+
+        Self {
+            offset: clock.epoch.saturating_sub(epoch),
+            epoch,
+            ..clock
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.offset + self.start.elapsed()
+    }
+
+    /// Sleeps a bit at a time, so Ctrl-C cuts it short
+    fn sleep(&self, duration: Duration) {
+        let end = Instant::now() + duration;
+
+        while !interrupt::PENDING.load(Ordering::SeqCst) {
+            let left = end.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+
+            thread::sleep(left.min(Duration::from_millis(50)));
+        }
+    }
+
+    fn epoch(&self) -> Duration {
+        self.epoch
+    }
+
+    #[cfg(unix)]
+    fn utc_offset(&self, at: i64) -> i64 {
+        let time = at as libc::time_t;
+        // SAFETY: all zeros is a valid `tm`, and both pointers outlive the call
+        let mut local: libc::tm = unsafe { std::mem::zeroed() };
+        if unsafe { libc::localtime_r(&time, &mut local) }.is_null() {
+            return 0;
+        }
+
+        local.tm_gmtoff as i64
+    }
+
+    /// Local time is taken to be UTC where it can't be looked up
+    #[cfg(not(unix))]
+    fn utc_offset(&self, _at: i64) -> i64 {
+        0
+    }
+}
+
+/// A clock that only moves forward when something waits, so waiting takes no real time
+#[cfg(test)]
+pub struct VirtualClock {
+    now: Cell<Duration>,
+    epoch: Duration,
+    utc_offset: i64,
+}
+
+#[cfg(test)]
+impl VirtualClock {
+    /// Starts at the Unix epoch, in a place where local time is UTC
+    pub fn new() -> Self {
+        Self::starting_at(Duration::ZERO, 0)
+    }
+
+    /// Starts at a time since the Unix epoch, in a place where local time is
+    /// `utc_offset` seconds ahead of UTC
+    pub fn starting_at(epoch: Duration, utc_offset: i64) -> Self {
+        Self {
+            now: Cell::new(Duration::ZERO),
+            epoch,
+            utc_offset,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+
+    fn epoch(&self) -> Duration {
+        self.epoch
+    }
+
+    fn utc_offset(&self, _at: i64) -> i64 {
+        self.utc_offset
+    }
+}
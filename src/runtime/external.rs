@@ -0,0 +1,223 @@
+//! Builtins that wait for something outside the script, like a file showing
+//! up or a port opening. They check every [`POLL`] until it happened or they
+//! time out, waiting in between like `wait` so other tasks run meanwhile.
+
+use super::{ops::as_ms, Runtime};
+use crate::parser::ast::lit::{Lit, LitKind};
+use std::{
+    io::BufRead,
+    net::{TcpStream, ToSocketAddrs},
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Mutex, OnceLock,
+    },
+    thread,
+    time::Duration,
+};
+
+/// How long to wait between two checks
+const POLL: Duration = Duration::from_millis(100);
+
+/// The lines read from stdin by a thread that starts the first time a
+/// script waits for one, so a wait that times out doesn't lose the line
+static STDIN: OnceLock<Mutex<Receiver<()>>> = OnceLock::new();
+
+/// `wait_for_file(path, timeout)`, whether the file exists before the timeout
+pub fn wait_for_file(path: &Lit, timeout: &Lit, runtime: &Runtime) -> Result<Lit, String> {
+    let LitKind::Str(path) = path.0 else {
+        return Err(String::from("`wait_for_file` expects the path of the file"));
+    };
+
+    poll(timeout, runtime, "wait_for_file", || {
+        Ok(Path::new(path).exists().then_some(true))
+    })
+}
+
+/// `wait_for_process_exit(pid, timeout)`, whether the process with the id is
+/// gone before the timeout
+pub fn wait_for_process_exit(pid: &Lit, timeout: &Lit, runtime: &Runtime) -> Result<Lit, String> {
+    let LitKind::Num(pid) = pid.0 else {
+        return Err(String::from(
+            "`wait_for_process_exit` expects the id of a process",
+        ));
+    };
+    if pid < 1. || pid.fract() != 0. {
+        return Err(format!("{pid} isn't the id of a process"));
+    }
+
+    poll(timeout, runtime, "wait_for_process_exit", || {
+        Ok(has_exited(pid as u32)?.then_some(true))
+    })
+}
+
+/// `wait_for_port(host, port, timeout)`, whether something accepts
+/// connections on the port before the timeout
+pub fn wait_for_port(
+    host: &Lit,
+    port: &Lit,
+    timeout: &Lit,
+    runtime: &Runtime,
+) -> Result<Lit, String> {
+    let (LitKind::Str(host), LitKind::Num(port)) = (host.0, port.0) else {
+        return Err(String::from("`wait_for_port` expects a host and a port"));
+    };
+    if !(0. ..=65535.).contains(&port) || port.fract() != 0. {
+        return Err(format!("{port} isn't a port"));
+    }
+
+    let addrs: Vec<_> = (host, port as u16)
+        .to_socket_addrs()
+        .map_err(|err| format!("Couldn't look up `{host}`: {err}"))?
+        .collect();
+
+    poll(timeout, runtime, "wait_for_port", || {
+        let open = addrs
+            .iter()
+            .any(|addr| TcpStream::connect_timeout(addr, POLL).is_ok());
+
+        Ok(open.then_some(true))
+    })
+}
+
+/// `wait_for_line_on_stdin(timeout)`, whether a line comes in on stdin
+/// before the timeout, which is then used up
+pub fn wait_for_line_on_stdin(timeout: &Lit, runtime: &Runtime) -> Result<Lit, String> {
+    let lines = STDIN.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for _ in std::io::stdin().lock().lines() {
+                if sender.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Mutex::new(receiver)
+    });
+
+    // Only locked while looking, another task may look while this one waits
+    poll(timeout, runtime, "wait_for_line_on_stdin", || {
+        let lines = lines.lock().unwrap_or_else(|err| err.into_inner());
+        match lines.try_recv() {
+            Ok(()) => Ok(Some(true)),
+            Err(TryRecvError::Empty) => Ok(None),
+            // No line can come once stdin is closed
+            Err(TryRecvError::Disconnected) => Ok(Some(false)),
+        }
+    })
+}
+
+/// Checks until `check` gives an answer or the timeout is up, which is `false`
+fn poll(
+    timeout: &Lit,
+    runtime: &Runtime,
+    name: &str,
+    mut check: impl FnMut() -> Result<Option<bool>, String>,
+) -> Result<Lit, String> {
+    let Some(ms) = as_ms(timeout) else {
+        return Err(format!("`{name}` expects a time to give up after"));
+    };
+    // An infinite timeout never ends
+    let end = Duration::try_from_secs_f64(ms.max(0.) as f64 / 1000.)
+        .ok()
+        .map(|timeout| runtime.clock.now() + timeout);
+
+    loop {
+        if let Some(answer) = check()? {
+            return Ok(Lit::from(answer));
+        }
+
+        let left = end.map_or(POLL, |end| end.saturating_sub(runtime.clock.now()));
+        if left.is_zero() {
+            return Ok(Lit::from(false));
+        }
+
+        runtime.wait(left.min(POLL))?;
+        if runtime.expired.get().is_some() {
+            return Ok(Lit::from(false));
+        }
+    }
+}
+
+#[cfg(unix)]
+fn has_exited(pid: u32) -> Result<bool, String> {
+    // SAFETY: signal 0 only checks whether the process exists
+    if unsafe { libc::kill(pid as libc::pid_t, 0) } == 0 {
+        return Ok(false);
+    }
+
+    // Processes of other users exist too, they just can't be sent signals
+    Ok(std::io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH))
+}
+
+#[cfg(not(unix))]
+fn has_exited(_pid: u32) -> Result<bool, String> {
+    Err(String::from("`wait_for_process_exit` only works on Unix"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::ast::lit::TimeKind,
+        runtime::clock::{Clock, VirtualClock},
+    };
+    use std::{net::TcpListener, rc::Rc};
+
+    #[test]
+    fn test_external_waits_time_out() {
+        let clock = Rc::new(VirtualClock::new());
+        let runtime = Runtime::new(clock.clone());
+        let timeout = Lit::from((2., TimeKind::Sec));
+        let leak = |text: String| Lit::new(LitKind::Str(Box::leak(text.into_boxed_str())));
+
+        let dir = std::env::temp_dir().join(format!("wait-external-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("ready");
+        let path = leak(file.display().to_string());
+
+        assert_eq!(
+            wait_for_file(&path, &timeout, &runtime),
+            Ok(Lit::from(false))
+        );
+        assert_eq!(clock.now(), Duration::from_secs(2));
+        std::fs::write(&file, "").unwrap();
+        assert_eq!(
+            wait_for_file(&path, &timeout, &runtime),
+            Ok(Lit::from(true))
+        );
+        assert_eq!(clock.now(), Duration::from_secs(2));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = Lit::from(listener.local_addr().unwrap().port() as f32);
+        let host = leak(String::from("127.0.0.1"));
+        assert_eq!(
+            wait_for_port(&host, &port, &timeout, &runtime),
+            Ok(Lit::from(true))
+        );
+        drop(listener);
+        assert_eq!(
+            wait_for_port(&host, &port, &timeout, &runtime),
+            Ok(Lit::from(false))
+        );
+
+        #[cfg(unix)]
+        {
+            let mut child = std::process::Command::new("true").spawn().unwrap();
+            let pid = Lit::from(child.id() as f32);
+            child.wait().unwrap();
+            assert_eq!(
+                wait_for_process_exit(&pid, &timeout, &runtime),
+                Ok(Lit::from(true))
+            );
+
+            let this = Lit::from(std::process::id() as f32);
+            assert_eq!(
+                wait_for_process_exit(&this, &timeout, &runtime),
+                Ok(Lit::from(false))
+            );
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use super::checkpoint::{self, Saved};
+use crate::parser::ast::{
+    lit::{Lit, LitKind},
+    FnType, StructId, Type,
+};
+
+/// Where the values that don't fit in a [`Lit`] live, a [`LitKind::Struct`],
+/// [`LitKind::List`] or [`LitKind::Function`] refers to one of them
+///
+/// Values are only freed by [`Heap::collect`], which both backends call
+/// before a wait. Nothing is freed while a task started with `spawn` is
+/// running, as its values live on the stack of its coroutine where they
+/// can't be found.
+#[derive(Debug, Default)]
+pub struct Heap {
+    structs: Vec<Vec<(&'static str, Lit)>>,
+    lists: Vec<Vec<Lit>>,
+    functions: Vec<Function>,
+    /// How many values there are when the next collection is worth it
+    collect_at: usize,
+}
+
+/// The fewest values [`Heap::is_due`] waits for before another collection
+const COLLECT_MIN: usize = 1024;
+
+/// A function or closure used as a value
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    /// Which of the functions of the running backend it calls
+    pub code: usize,
+    /// The values of the variables a closure captured, empty for a function
+    /// declared with `func`
+    pub captures: Vec<Lit>,
+}
+
+impl Heap {
+    /// Stores a struct with its fields in the order of the declaration
+    pub fn alloc_struct(&mut self, id: StructId, fields: Vec<(&'static str, Lit)>) -> Lit {
+        self.structs.push(fields);
+
+        Lit::new(LitKind::Struct(id, self.structs.len() - 1))
+    }
+
+    pub fn alloc_list(&mut self, elem: &'static Type, elems: Vec<Lit>) -> Lit {
+        self.lists.push(elems);
+
+        Lit::new(LitKind::List(elem, self.lists.len() - 1))
+    }
+
+    /// Stores a function with the values it captured
+    pub fn alloc_function(&mut self, type_: &'static FnType, function: Function) -> Lit {
+        self.functions.push(function);
+
+        Lit::new(LitKind::Function(type_, self.functions.len() - 1))
+    }
+
+    /// The function a value calls, which has to fit the arguments
+    pub fn function(&self, callee: &Lit, args: &[Lit]) -> Result<&Function, String> {
+        let LitKind::Function(type_, index) = callee.0 else {
+            return Err(format!(
+                "Only functions can be called, found a {}",
+                callee.0.type_name()
+            ));
+        };
+
+        if type_.args.len() != args.len() {
+            return Err(format!(
+                "This function takes {} arguments but {} were given",
+                type_.args.len(),
+                args.len()
+            ));
+        }
+
+        for (arg, type_) in args.iter().zip(type_.args.iter()) {
+            if arg.0.type_() != *type_ {
+                return Err(format!(
+                    "Expected a {type_} as an argument, found a {}",
+                    arg.0.type_name()
+                ));
+            }
+        }
+
+        Ok(&self.functions[index])
+    }
+
+    /// The elements of a list, `None` if the value isn't one
+    pub fn list(&self, list: &Lit) -> Option<&Vec<Lit>> {
+        match list.0 {
+            LitKind::List(_, index) => Some(&self.lists[index]),
+            _ => None,
+        }
+    }
+
+    /// Adds an element to the end of a list, which has to have the type of its elements
+    pub fn push(&mut self, list: &Lit, value: Lit) -> Result<(), String> {
+        let LitKind::List(elem, index) = list.0 else {
+            return Err(format!("Expected a list, found a {}", list.0.type_name()));
+        };
+
+        if *elem == Type::Unit {
+            return Err(String::from(
+                "The type of the elements of this list isn't known, declare it with one like `[time] list = [];`",
+            ));
+        }
+
+        if value.0.type_() != *elem {
+            return Err(format!(
+                "Expected a {elem} to add to a {}, found a {}",
+                list.0.type_(),
+                value.0.type_name()
+            ));
+        }
+
+        self.lists[index].push(value);
+
+        Ok(())
+    }
+
+    /// The element at a position, which has to be a whole number within the list
+    pub fn index(&self, list: &Lit, index: &Lit) -> Result<Lit, String> {
+        let Some(elems) = self.list(list) else {
+            return Err(format!(
+                "Only lists can be indexed, found a {}",
+                list.0.type_name()
+            ));
+        };
+
+        let LitKind::Num(index) = index.0 else {
+            return Err(format!(
+                "Expected a num as the index, found a {}",
+                index.0.type_name()
+            ));
+        };
+
+        if index.fract() != 0. || index < 0. || index as usize >= elems.len() {
+            return Err(format!(
+                "The index {index} is out of bounds for a list of length {}",
+                elems.len()
+            ));
+        }
+
+        Ok(elems[index as usize])
+    }
+
+    pub fn get_field(&self, receiver: &Lit, name: &str) -> Result<Lit, String> {
+        let (_, (index, slot)) = self.field(receiver, name)?;
+
+        Ok(self.structs[index][slot].1)
+    }
+
+    /// Changes a field, which keeps the type it was declared with
+    pub fn set_field(&mut self, receiver: &Lit, name: &str, value: Lit) -> Result<(), String> {
+        let (type_name, (index, slot)) = self.field(receiver, name)?;
+        let field = &mut self.structs[index][slot].1;
+
+        if field.0.type_() != value.0.type_() {
+            return Err(format!(
+                "Expected a {} for `{type_name}.{name}`, found a {}",
+                field.0.type_name(),
+                value.0.type_name()
+            ));
+        }
+
+        *field = value;
+
+        Ok(())
+    }
+
+    /// Whether enough values were stored since the last collection for
+    /// another one to be worth it
+    pub fn is_due(&self) -> bool {
+        self.len() >= self.collect_at
+    }
+
+    /// Frees the values none of the roots refers to, directly or through
+    /// other values, and moves the rest together. The roots are changed to
+    /// refer to where their values are now.
+    pub fn collect(&mut self, roots: &mut [&mut [Lit]]) {
+        let mut structs = vec![false; self.structs.len()];
+        let mut lists = vec![false; self.lists.len()];
+        let mut functions = vec![false; self.functions.len()];
+
+        let mut pending: Vec<Lit> = roots.iter().flat_map(|root| root.iter().copied()).collect();
+        while let Some(value) = pending.pop() {
+            match value.0 {
+                LitKind::Struct(_, index) if !structs[index] => {
+                    structs[index] = true;
+                    pending.extend(self.structs[index].iter().map(|(_, field)| *field));
+                }
+                LitKind::List(_, index) if !lists[index] => {
+                    lists[index] = true;
+                    pending.extend(self.lists[index].iter().copied());
+                }
+                LitKind::Function(_, index) if !functions[index] => {
+                    functions[index] = true;
+                    pending.extend(self.functions[index].captures.iter().copied());
+                }
+                _ => {}
+            }
+        }
+
+        retain(&mut self.structs, &structs);
+        retain(&mut self.lists, &lists);
+        retain(&mut self.functions, &functions);
+
+        let (structs, lists, functions) = (moved(&structs), moved(&lists), moved(&functions));
+        let relocate = |value: &mut Lit| match &mut value.0 {
+            LitKind::Struct(_, index) => *index = structs[*index],
+            LitKind::List(_, index) => *index = lists[*index],
+            LitKind::Function(_, index) => *index = functions[*index],
+            _ => {}
+        };
+
+        for root in roots.iter_mut() {
+            root.iter_mut().for_each(&relocate);
+        }
+        for fields in self.structs.iter_mut() {
+            fields.iter_mut().for_each(|(_, field)| relocate(field));
+        }
+        for elems in self.lists.iter_mut() {
+            elems.iter_mut().for_each(&relocate);
+        }
+        for function in self.functions.iter_mut() {
+            function.captures.iter_mut().for_each(&relocate);
+        }
+
+        self.collect_at = (self.len() * 2).max(COLLECT_MIN);
+    }
+
+    fn len(&self) -> usize {
+        self.structs.len() + self.lists.len() + self.functions.len()
+    }
+
+    /// The values on the heap as lines of a checkpoint, in the order they
+    /// were stored so the values referring to them stay the same
+    pub fn save(&self) -> Result<Vec<String>, String> {
+        let mut lines = Vec::new();
+        for fields in self.structs.iter() {
+            let fields: Vec<_> = fields
+                .iter()
+                .map(|(name, value)| Ok(format!("{name}={}", checkpoint::encode(value)?)))
+                .collect::<Result<_, String>>()?;
+            lines.push(format!("struct {}", fields.join(" ")));
+        }
+        for elems in self.lists.iter() {
+            lines.push(format!("list {}", checkpoint::encode_all(elems)?));
+        }
+        for function in self.functions.iter() {
+            lines.push(format!(
+                "function {} {}",
+                function.code,
+                checkpoint::encode_all(&function.captures)?
+            ));
+        }
+
+        Ok(lines)
+    }
+
+    /// The heap saved in a checkpoint by [`Heap::save`]
+    pub fn restore(saved: &Saved) -> Option<Self> {
+        let structs = saved
+            .get("struct")
+            .map(|fields| {
+                fields
+                    .iter()
+                    .map(|field| {
+                        let (name, value) = field.split_once('=')?;
+                        Some((
+                            &*Box::leak(name.to_string().into_boxed_str()),
+                            checkpoint::decode(value)?,
+                        ))
+                    })
+                    .collect()
+            })
+            .collect::<Option<_>>()?;
+        let lists = saved
+            .get("list")
+            .map(|elems| checkpoint::decode_all(&elems))
+            .collect::<Option<_>>()?;
+        let functions = saved
+            .get("function")
+            .map(|words| {
+                let (code, captures) = words.split_first()?;
+                Some(Function {
+                    code: code.parse().ok()?,
+                    captures: checkpoint::decode_all(captures)?,
+                })
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Self {
+            structs,
+            lists,
+            functions,
+            collect_at: 0,
+        })
+    }
+
+    /// The struct's name and where the field is stored
+    fn field(&self, receiver: &Lit, name: &str) -> Result<(&'static str, (usize, usize)), String> {
+        let LitKind::Struct(id, index) = receiver.0 else {
+            return Err(format!(
+                "`{}` values have no field `{name}`",
+                receiver.0.type_name()
+            ));
+        };
+
+        match self.structs[index]
+            .iter()
+            .position(|(field, _)| *field == name)
+        {
+            Some(slot) => Ok((id.name, (index, slot))),
+            None => Err(format!("`{}` has no field `{name}`", id.name)),
+        }
+    }
+}
+
+/// Keeps the values that are marked as still in use
+fn retain<T>(values: &mut Vec<T>, marks: &[bool]) {
+    let mut marks = marks.iter();
+    values.retain(|_| *marks.next().unwrap_or(&false));
+}
+
+/// Where each value kept by [`retain`] moved to, by where it was before
+fn moved(marks: &[bool]) -> Vec<usize> {
+    marks
+        .iter()
+        .scan(0, |next, marked| {
+            let index = *next;
+            *next += usize::from(*marked);
+            Some(index)
+        })
+        .collect()
+}
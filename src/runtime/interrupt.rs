@@ -0,0 +1,41 @@
+//! Ctrl-C while a script runs, which cuts the wait that is running short and
+//! runs the block the script registered with `on_interrupt`
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// What a script that was interrupted exits with, 128 and the number of
+/// SIGINT like in shells
+pub const EXIT_CODE: i32 = 130;
+
+/// Set by Ctrl-C until the `on_interrupt` block starts, the runtime of a
+/// script run from the command line looks at it
+pub static PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Whether Ctrl-C was pressed at all, which decides the exit code
+static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+/// Makes Ctrl-C interrupt the script instead of ending the process
+#[cfg(unix)]
+pub fn install() {
+    // SAFETY: the handler only stores to atomics, which is safe in a signal handler
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+/// Ctrl-C ends the process right away where signals can't be handled
+#[cfg(not(unix))]
+pub fn install() {}
+
+pub fn received() -> bool {
+    RECEIVED.load(Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(_signal: libc::c_int) {
+    PENDING.store(true, Ordering::SeqCst);
+    RECEIVED.store(true, Ordering::SeqCst);
+}
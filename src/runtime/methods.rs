@@ -0,0 +1,320 @@
+use super::{
+    heap::Heap,
+    ops::{self, as_ms},
+};
+use crate::parser::ast::{
+    expr::BinOp,
+    lit::{Lit, LitKind, TimeKind},
+    Type,
+};
+
+/// Methods the built-in value types have
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Method {
+    /// How many seconds a time is
+    ///
+    /// ## Example
+    /// ```rust
+    /// 1min.as_seconds()
+    /// ```
+    AsSeconds,
+    /// How many minutes a time is
+    ///
+    /// ## Example
+    /// ```rust
+    /// 90s.as_minutes()
+    /// ```
+    AsMinutes,
+    /// The value without its sign
+    ///
+    /// ## Example
+    /// ```rust
+    /// n.abs()
+    /// ```
+    Abs,
+    /// The closest multiple of a step, plain numbers are seconds for times
+    ///
+    /// ## Example
+    /// ```rust
+    /// t.round_to(1s)
+    /// ```
+    RoundTo,
+    /// How many elements a list has
+    ///
+    /// ## Example
+    /// ```rust
+    /// delays.len()
+    /// ```
+    Len,
+    /// Adds an element to the end of a list and gives its new length
+    ///
+    /// ## Example
+    /// ```rust
+    /// delays.push(8s)
+    /// ```
+    Push,
+    /// All elements of a list of numbers or times added up
+    ///
+    /// ## Example
+    /// ```rust
+    /// delays.sum()
+    /// ```
+    Sum,
+    /// The smallest element of a list of numbers or times
+    ///
+    /// ## Example
+    /// ```rust
+    /// delays.min()
+    /// ```
+    Min,
+    /// The largest element of a list of numbers or times
+    ///
+    /// ## Example
+    /// ```rust
+    /// delays.max()
+    /// ```
+    Max,
+}
+
+const TIME_METHODS: &[Method] = &[
+    Method::AsSeconds,
+    Method::AsMinutes,
+    Method::Abs,
+    Method::RoundTo,
+];
+const NUMBER_METHODS: &[Method] = &[Method::Abs, Method::RoundTo];
+const LIST_METHODS: &[Method] = &[Method::Len, Method::Push];
+const NUMERIC_LIST_METHODS: &[Method] = &[
+    Method::Len,
+    Method::Push,
+    Method::Sum,
+    Method::Min,
+    Method::Max,
+];
+
+impl Method {
+    /// The methods values of a type have
+    pub fn table(type_: Type) -> &'static [Method] {
+        match type_ {
+            Type::Time => TIME_METHODS,
+            Type::Number => NUMBER_METHODS,
+            Type::List(Type::Time | Type::Number) => NUMERIC_LIST_METHODS,
+            Type::List(_) => LIST_METHODS,
+            _ => &[],
+        }
+    }
+
+    pub fn lookup(type_: Type, name: &str) -> Option<Self> {
+        Self::table(type_)
+            .iter()
+            .find(|method| method.name() == name)
+            .copied()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Method::AsSeconds => "as_seconds",
+            Method::AsMinutes => "as_minutes",
+            Method::Abs => "abs",
+            Method::RoundTo => "round_to",
+            Method::Len => "len",
+            Method::Push => "push",
+            Method::Sum => "sum",
+            Method::Min => "min",
+            Method::Max => "max",
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Method::RoundTo | Method::Push => 1,
+            _ => 0,
+        }
+    }
+
+    pub fn result_type(&self, receiver: Type) -> Type {
+        match self {
+            Method::AsSeconds | Method::AsMinutes | Method::Len | Method::Push => Type::Number,
+            Method::Abs | Method::RoundTo => receiver,
+            Method::Sum | Method::Min | Method::Max => match receiver {
+                Type::List(elem) => *elem,
+                _ => Type::Unit,
+            },
+        }
+    }
+
+    pub fn call(&self, receiver: &Lit, args: &[Lit], heap: &mut Heap) -> Result<Lit, String> {
+        if args.len() != self.arity() {
+            return Err(format!(
+                "`{}` takes {} arguments but {} were given",
+                self.name(),
+                self.arity(),
+                args.len()
+            ));
+        }
+
+        Ok(match (self, receiver.0) {
+            (Method::AsSeconds, LitKind::Time(time, time_kind)) => {
+                Lit::from(time * time_kind.as_ms() / TimeKind::Sec.as_ms())
+            }
+            (Method::AsMinutes, LitKind::Time(time, time_kind)) => {
+                Lit::from(time * time_kind.as_ms() / TimeKind::Min.as_ms())
+            }
+            (Method::Abs, LitKind::Time(time, time_kind)) => Lit::from((time.abs(), time_kind)),
+            (Method::Abs, LitKind::Num(num)) => Lit::from(num.abs()),
+            (Method::RoundTo, LitKind::Time(time, time_kind)) => {
+                let Some(step) = as_ms(&args[0]).filter(|step| *step > 0.) else {
+                    return Err(String::from("`round_to` expects a step greater than zero"));
+                };
+
+                let ms = (time * time_kind.as_ms() / step).round() * step;
+                Lit::from((ms / time_kind.as_ms(), time_kind))
+            }
+            (Method::RoundTo, LitKind::Num(num)) => {
+                let LitKind::Num(step) = args[0].0 else {
+                    return Err(format!(
+                        "`round_to` on a num expects a num, not a {}",
+                        args[0].0.type_name()
+                    ));
+                };
+                if step <= 0. {
+                    return Err(String::from("`round_to` expects a step greater than zero"));
+                }
+
+                Lit::from((num / step).round() * step)
+            }
+            (Method::Len, LitKind::List(..)) => {
+                Lit::from(heap.list(receiver).map_or(0, Vec::len) as f32)
+            }
+            (Method::Push, LitKind::List(..)) => {
+                heap.push(receiver, args[0])?;
+                Lit::from(heap.list(receiver).map_or(0, Vec::len) as f32)
+            }
+            (Method::Sum, LitKind::List(elem, _)) => {
+                let zero = match elem {
+                    Type::Time => Lit::from((0, TimeKind::Sec)),
+                    _ => Lit::from(0),
+                };
+
+                heap.list(receiver)
+                    .into_iter()
+                    .flatten()
+                    .try_fold(zero, |sum, value| ops::binary(&sum, &BinOp::Add, value))?
+            }
+            (Method::Min | Method::Max, LitKind::List(..)) => {
+                let keeps = match self {
+                    Method::Min => BinOp::Le,
+                    _ => BinOp::Ge,
+                };
+
+                let mut elems = heap.list(receiver).into_iter().flatten();
+                let Some(first) = elems.next() else {
+                    return Err(format!("An empty list has no `{}`", self.name()));
+                };
+
+                elems.try_fold(*first, |best, value| {
+                    let is_kept = ops::binary(&best, &keeps, value)?;
+                    Ok::<_, String>(if is_kept.is_true() == Some(true) {
+                        best
+                    } else {
+                        *value
+                    })
+                })?
+            }
+            _ => {
+                return Err(format!(
+                    "`{}` values have no method `{}`",
+                    receiver.0.type_name(),
+                    self.name()
+                ))
+            }
+        })
+    }
+}
+
+/// Calls a method by name, looking it up in the table of the receiver's type
+pub fn call_method(
+    receiver: &Lit,
+    name: &str,
+    args: &[Lit],
+    heap: &mut Heap,
+) -> Result<Lit, String> {
+    match Method::lookup(receiver.0.type_(), name) {
+        Some(method) => method.call(receiver, args, heap),
+        None => Err(format!(
+            "`{}` values have no method `{name}`",
+            receiver.0.type_name()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_methods_work() {
+        let heap = &mut Heap::default();
+        let minute = Lit::from((1, TimeKind::Min));
+
+        assert_eq!(
+            Method::AsSeconds.call(&minute, &[], heap),
+            Ok(Lit::from(60))
+        );
+        assert_eq!(
+            Method::AsMinutes.call(&Lit::from((90, TimeKind::Sec)), &[], heap),
+            Ok(Lit::from(1.5))
+        );
+        assert_eq!(
+            Method::Abs.call(&Lit::from(-3), &[], heap),
+            Ok(Lit::from(3))
+        );
+        assert_eq!(
+            Method::RoundTo.call(
+                &Lit::from((1.4, TimeKind::Sec)),
+                &[Lit::from((500, TimeKind::Ms))],
+                heap
+            ),
+            Ok(Lit::from((1.5, TimeKind::Sec)))
+        );
+        assert!(Method::RoundTo
+            .call(&Lit::from(2), &[Lit::from(0)], heap)
+            .is_err());
+        assert_eq!(Method::lookup(Type::Bool, "abs"), None);
+    }
+
+    #[test]
+    fn test_list_methods_work() {
+        let heap = &mut Heap::default();
+        let delays = heap.alloc_list(
+            &Type::Time,
+            vec![
+                Lit::from((1, TimeKind::Sec)),
+                Lit::from((500, TimeKind::Ms)),
+            ],
+        );
+
+        assert_eq!(
+            Method::Push.call(&delays, &[Lit::from((2, TimeKind::Sec))], heap),
+            Ok(Lit::from(3))
+        );
+        assert!(Method::Push.call(&delays, &[Lit::from(2)], heap).is_err());
+        assert_eq!(
+            Method::Sum.call(&delays, &[], heap),
+            Ok(Lit::from((3.5, TimeKind::Sec)))
+        );
+        assert_eq!(
+            Method::Min.call(&delays, &[], heap),
+            Ok(Lit::from((500, TimeKind::Ms)))
+        );
+        assert_eq!(
+            Method::Max.call(&delays, &[], heap),
+            Ok(Lit::from((2, TimeKind::Sec)))
+        );
+
+        let empty = heap.alloc_list(&Type::Number, Vec::new());
+        assert_eq!(Method::Sum.call(&empty, &[], heap), Ok(Lit::from(0)));
+        assert!(Method::Min.call(&empty, &[], heap).is_err());
+        assert_eq!(Method::lookup(Type::Bool.list_of(), "sum"), None);
+    }
+}
@@ -0,0 +1,197 @@
+//! What the tree walker and the bytecode VM share while running a script
+
+use self::{
+    checkpoint::Checkpoint,
+    clock::{Clock, SystemClock},
+    heap::Heap,
+    progress::Progress,
+    tasks::Scheduler,
+};
+use crate::parser::ast::Span;
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+pub mod builtins;
+pub mod calendar;
+pub mod checkpoint;
+pub mod clock;
+pub mod external;
+pub mod heap;
+pub mod interrupt;
+pub mod methods;
+pub mod ops;
+pub mod prelude;
+pub mod progress;
+pub mod retry;
+pub mod schedule;
+pub mod tasks;
+
+/// State the builtins and values need while a script runs
+#[derive(Clone)]
+pub struct Runtime {
+    pub clock: Rc<dyn Clock>,
+    pub heap: Rc<RefCell<Heap>>,
+    /// Where `random` and the like get their numbers from
+    pub rng: Rc<RefCell<StdRng>>,
+    pub tasks: Rc<Scheduler>,
+    /// The task running the code that uses this runtime, 0 for the script itself
+    pub task: usize,
+    /// When the `within` blocks the task is in run out of time, outermost first
+    pub deadlines: Rc<RefCell<Vec<Duration>>>,
+    /// The `within` block whose deadline cut the last wait short, by its
+    /// position in `deadlines`
+    pub expired: Rc<Cell<Option<usize>>>,
+    /// Set when the script is interrupted, which fails every wait until the
+    /// `on_interrupt` block starts
+    pub interrupted: &'static AtomicBool,
+    /// Shows the waits while they run, if `--progress` asks for it
+    pub progress: Option<Rc<Progress>>,
+    /// The code the task waits at next, for the progress display
+    pub waiting_at: Rc<Cell<Span>>,
+    /// Where the state of the script is written before every wait, if
+    /// `--checkpoint` asks for it
+    pub checkpoint: Option<Rc<Checkpoint>>,
+}
+
+impl Runtime {
+    pub fn new(clock: Rc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            heap: Rc::new(RefCell::new(Heap::default())),
+            rng: Rc::new(RefCell::new(StdRng::from_entropy())),
+            tasks: Rc::new(Scheduler::new()),
+            task: 0,
+            deadlines: Rc::new(RefCell::new(Vec::new())),
+            expired: Rc::new(Cell::new(None)),
+            interrupted: Box::leak(Box::new(AtomicBool::new(false))),
+            progress: None,
+            waiting_at: Rc::new(Cell::new(Span::from(0))),
+            checkpoint: None,
+        }
+    }
+
+    /// The runtime for the code of a task started with `spawn`
+    pub fn for_task(&self, task: usize) -> Self {
+        Self {
+            task,
+            deadlines: Rc::new(RefCell::new(Vec::new())),
+            expired: Rc::new(Cell::new(None)),
+            waiting_at: Rc::new(Cell::new(self.waiting_at.get())),
+            ..self.clone()
+        }
+    }
+
+    /// Waits in the task of this runtime, but only until the earliest
+    /// deadline, which is marked as expired if it cuts the wait short
+    pub fn wait(&self, duration: Duration) -> Result<(), String> {
+        let duration = match &self.checkpoint {
+            Some(checkpoint) if self.task == 0 => checkpoint.before_wait(duration, &*self.clock)?,
+            _ => duration,
+        };
+        let requested = duration;
+        let now = self.clock.now();
+        let earliest = self
+            .deadlines
+            .borrow()
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, deadline)| **deadline)
+            .map(|(index, deadline)| (index, *deadline));
+
+        let (duration, expired) = match earliest {
+            Some((index, deadline)) if now.saturating_add(duration) > deadline => {
+                (deadline.saturating_sub(now), Some(index))
+            }
+            _ => (duration, None),
+        };
+
+        let _shown = self
+            .progress
+            .as_ref()
+            .map(|progress| progress.show(self.waiting_at.get(), duration));
+        self.tasks.wait(self.task, duration, &*self.clock)?;
+
+        if self.is_interrupted() {
+            let left = now
+                .saturating_add(requested)
+                .saturating_sub(self.clock.now());
+            return Err(format!(
+                "Interrupted with {} of the wait left",
+                prelude::format_ms(left.as_secs_f32() * 1000.)
+            ));
+        }
+        self.expired.set(expired);
+
+        Ok(())
+    }
+
+    /// Waits in the task of this runtime until one of the tasks finished,
+    /// and gives the one that finished first. Like [`Runtime::wait`] it only
+    /// waits until the earliest deadline, and gives `None` if that came first.
+    pub fn join(&self, targets: &[usize]) -> Result<Option<usize>, String> {
+        let earliest = self
+            .deadlines
+            .borrow()
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, deadline)| **deadline)
+            .map(|(index, deadline)| (index, *deadline));
+
+        let finished = self.tasks.join(
+            self.task,
+            targets,
+            earliest.map(|(_, deadline)| deadline),
+            &*self.clock,
+        )?;
+        self.expired.set(match finished {
+            Some(_) => None,
+            None => earliest.map(|(index, _)| index),
+        });
+
+        Ok(finished)
+    }
+
+    /// Starts a `within` block that runs out of time after `time`, and gives
+    /// its position in `deadlines`
+    pub fn push_deadline(&self, time: Duration) -> usize {
+        let mut deadlines = self.deadlines.borrow_mut();
+        deadlines.push(self.clock.now().saturating_add(time));
+
+        deadlines.len() - 1
+    }
+
+    /// Ends the `within` block at this position and the ones inside it
+    pub fn pop_deadline(&self, index: usize) {
+        self.deadlines.borrow_mut().truncate(index);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    /// Whether the script was interrupted and its `on_interrupt` block has
+    /// to run now, which lets it wait again
+    pub fn take_interrupt(&self) -> bool {
+        self.interrupted.swap(false, Ordering::SeqCst)
+    }
+
+    /// Makes the random numbers the same on every run with this seed
+    pub fn seed(&self, seed: u64) {
+        *self.rng.borrow_mut() = StdRng::seed_from_u64(seed);
+    }
+}
+
+/// The runtime of a script run from the command line, which Ctrl-C interrupts
+impl Default for Runtime {
+    fn default() -> Self {
+        Self {
+            interrupted: &interrupt::PENDING,
+            ..Self::new(Rc::new(SystemClock::new()))
+        }
+    }
+}
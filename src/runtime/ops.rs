@@ -0,0 +1,301 @@
+use crate::parser::ast::{
+    expr::{BinOp, UnOp},
+    lit::{Lit, LitKind, TimeKind},
+    pattern::PatternKind,
+};
+
+pub fn binary(left: &Lit, bin_op_kind: &BinOp, right: &Lit) -> Result<Lit, String> {
+    // Booleans, enum variants, strings and tasks can only be compared for equality
+    if let BinOp::EqEq | BinOp::Ne = bin_op_kind
+        && let (LitKind::Bool(_), LitKind::Bool(_))
+        | (LitKind::Enum(..), LitKind::Enum(..))
+        | (LitKind::Str(_), LitKind::Str(_))
+        | (LitKind::Task(_), LitKind::Task(_)) = (left.0, right.0)
+    {
+        let eq = left == right;
+        return Ok(Lit::new(LitKind::Bool((*bin_op_kind == BinOp::EqEq) == eq)));
+    }
+
+    if matches!(left.0, LitKind::Timestamp(_)) || matches!(right.0, LitKind::Timestamp(_)) {
+        return Ok(Lit::new(bin_op_timestamp(&left.0, bin_op_kind, &right.0)?));
+    }
+
+    match bin_op_kind {
+        BinOp::Add
+        | BinOp::Sub
+        | BinOp::Mul
+        | BinOp::Div
+        | BinOp::Mod
+        | BinOp::EqEq
+        | BinOp::Ne
+        | BinOp::Lt
+        | BinOp::Le
+        | BinOp::Gt
+        | BinOp::Ge => Ok(Lit::new(bin_op_num(&left.0, bin_op_kind, &right.0)?)),
+        BinOp::And | BinOp::Or | BinOp::Xor => {
+            Ok(Lit::new(bin_op_bool(&left.0, bin_op_kind, &right.0)?))
+        }
+    }
+}
+
+pub fn unary(un_op_kind: &UnOp, target: &Lit) -> Result<Lit, String> {
+    let lit_kind = match (un_op_kind, target.0) {
+        (UnOp::Neg, LitKind::Num(num)) => LitKind::Num(-num),
+        (UnOp::Neg, LitKind::Time(time, time_kind)) => LitKind::Time(-time, time_kind),
+        (UnOp::Not, LitKind::Bool(bool)) => LitKind::Bool(!bool),
+        (UnOp::Neg, _) => return Err(String::from("Only numbers and times can be negated")),
+        (UnOp::Not, _) => return Err(String::from("Only booleans can be inverted")),
+    };
+
+    Ok(Lit::new(lit_kind))
+}
+
+fn bin_op_num(left: &LitKind, bin_op_kind: &BinOp, right: &LitKind) -> Result<LitKind, String> {
+    let mut ret_time_kind = None;
+
+    let left_ms = match left {
+        LitKind::Time(num, time_kind) => {
+            ret_time_kind = Some(*time_kind);
+            time_kind.as_ms() * num
+        }
+        LitKind::Num(num) => *num,
+        LitKind::Bool(_)
+        | LitKind::Struct(..)
+        | LitKind::List(..)
+        | LitKind::Enum(..)
+        | LitKind::Function(..)
+        | LitKind::Str(_)
+        | LitKind::Task(_)
+        | LitKind::Timestamp(_) => return Err(mismatched(left, bin_op_kind, right)),
+    };
+
+    let right_ms = match right {
+        LitKind::Time(num, time_kind) => {
+            ret_time_kind = ret_time_kind.or(Some(*time_kind));
+            time_kind.as_ms() * num
+        }
+        LitKind::Num(num) => *num,
+        LitKind::Bool(_)
+        | LitKind::Struct(..)
+        | LitKind::List(..)
+        | LitKind::Enum(..)
+        | LitKind::Function(..)
+        | LitKind::Str(_)
+        | LitKind::Task(_)
+        | LitKind::Timestamp(_) => return Err(mismatched(left, bin_op_kind, right)),
+    };
+
+    let both_times = matches!((left, right), (LitKind::Time(..), LitKind::Time(..)));
+
+    let num_result = match bin_op_kind {
+        BinOp::Add => left_ms + right_ms,
+        BinOp::Sub => left_ms - right_ms,
+        BinOp::Mul if both_times => return Err(mismatched(left, bin_op_kind, right)),
+        BinOp::Mul => left_ms * right_ms,
+        BinOp::Div | BinOp::Mod if right_ms == 0. => return Err(String::from("Division by zero")),
+        // How many times one time fits into the other
+        BinOp::Div if both_times => return Ok(LitKind::Num(left_ms / right_ms)),
+        BinOp::Div => left_ms / right_ms,
+        BinOp::Mod => left_ms % right_ms,
+        BinOp::EqEq => return Ok(LitKind::Bool(left_ms == right_ms)),
+        BinOp::Ne => return Ok(LitKind::Bool(left_ms != right_ms)),
+        BinOp::Lt => return Ok(LitKind::Bool(left_ms < right_ms)),
+        BinOp::Le => return Ok(LitKind::Bool(left_ms <= right_ms)),
+        BinOp::Gt => return Ok(LitKind::Bool(left_ms > right_ms)),
+        BinOp::Ge => return Ok(LitKind::Bool(left_ms >= right_ms)),
+        BinOp::And | BinOp::Or | BinOp::Xor => unreachable!(),
+    };
+
+    // Keep the unit of the time the calculation started with
+    Ok(match ret_time_kind {
+        Some(time_kind) => LitKind::Time(num_result / time_kind.as_ms(), time_kind),
+        None => LitKind::Num(num_result),
+    })
+}
+
+/// Timestamps move by times and are a time apart from each other
+fn bin_op_timestamp(
+    left: &LitKind,
+    bin_op_kind: &BinOp,
+    right: &LitKind,
+) -> Result<LitKind, String> {
+    let lit_kind = match (left, bin_op_kind, right) {
+        (LitKind::Timestamp(at), BinOp::Add, LitKind::Time(time, time_kind))
+        | (LitKind::Time(time, time_kind), BinOp::Add, LitKind::Timestamp(at)) => {
+            move_timestamp(*at, time * time_kind.as_ms())?
+        }
+        (LitKind::Timestamp(at), BinOp::Sub, LitKind::Time(time, time_kind)) => {
+            move_timestamp(*at, -(time * time_kind.as_ms()))?
+        }
+        (LitKind::Timestamp(left_at), BinOp::Sub, LitKind::Timestamp(right_at)) => {
+            let Some(ms) = left_at.checked_sub(*right_at) else {
+                return Err(String::from(
+                    "The timestamps are too far apart to keep track of the time between them",
+                ));
+            };
+            LitKind::Time(ms as f32, TimeKind::Ms)
+        }
+        (LitKind::Timestamp(left_at), _, LitKind::Timestamp(right_at)) => {
+            LitKind::Bool(match bin_op_kind {
+                BinOp::EqEq => left_at == right_at,
+                BinOp::Ne => left_at != right_at,
+                BinOp::Lt => left_at < right_at,
+                BinOp::Le => left_at <= right_at,
+                BinOp::Gt => left_at > right_at,
+                BinOp::Ge => left_at >= right_at,
+                _ => return Err(mismatched(left, bin_op_kind, right)),
+            })
+        }
+        _ => return Err(mismatched(left, bin_op_kind, right)),
+    };
+
+    Ok(lit_kind)
+}
+
+/// The timestamp the given milliseconds after one
+fn move_timestamp(at: i64, ms: f32) -> Result<LitKind, String> {
+    let too_far = || String::from("The timestamp is too far away to keep track of");
+    if ms.is_nan() || ms.abs() >= i64::MAX as f32 {
+        return Err(too_far());
+    }
+
+    at.checked_add(ms as i64)
+        .map(LitKind::Timestamp)
+        .ok_or_else(too_far)
+}
+
+fn bin_op_bool(left: &LitKind, bin_op_kind: &BinOp, right: &LitKind) -> Result<LitKind, String> {
+    let (LitKind::Bool(left_bool), LitKind::Bool(right_bool)) = (left, right) else {
+        return Err(mismatched(left, bin_op_kind, right));
+    };
+
+    let result = match bin_op_kind {
+        BinOp::And => *left_bool && *right_bool,
+        BinOp::Or => *left_bool || *right_bool,
+        BinOp::Xor => left_bool ^ right_bool,
+        _ => unreachable!(),
+    };
+
+    Ok(LitKind::Bool(result))
+}
+
+fn mismatched(left: &LitKind, bin_op_kind: &BinOp, right: &LitKind) -> String {
+    format!(
+        "`{}` can't be used with {} and {}",
+        bin_op_kind,
+        left.type_name(),
+        right.type_name()
+    )
+}
+
+/// Whether a value matches a pattern of a `match`, compared like with `==` and `<`
+pub fn matches(value: &Lit, pattern: &PatternKind) -> Result<bool, String> {
+    let is =
+        |op, other: &Lit| binary(value, &op, other).map(|result| result.is_true() == Some(true));
+
+    match pattern {
+        PatternKind::Wildcard => Ok(true),
+        PatternKind::Lit(lit) => is(BinOp::EqEq, lit),
+        PatternKind::Range {
+            start,
+            end,
+            inclusive,
+        } => {
+            let below_end = match inclusive {
+                true => is(BinOp::Le, end)?,
+                false => is(BinOp::Lt, end)?,
+            };
+
+            Ok(is(BinOp::Ge, start)? && below_end)
+        }
+    }
+}
+
+/// The step of a range that doesn't give one, `1` or `1s` depending on its start
+pub fn default_step(start: &Lit) -> Lit {
+    match start.0 {
+        LitKind::Time(..) => Lit::new(LitKind::Time(1., TimeKind::Sec)),
+        _ => Lit::from(1),
+    }
+}
+
+/// Checks that a range goes over numbers or times in steps that move forward
+pub fn check_range(start: &Lit, end: &Lit, step: &Lit) -> Result<(), String> {
+    let step_ms = match start.0 {
+        LitKind::Num(_) | LitKind::Time(..) => as_ms(step),
+        LitKind::Bool(_)
+        | LitKind::Struct(..)
+        | LitKind::List(..)
+        | LitKind::Enum(..)
+        | LitKind::Function(..)
+        | LitKind::Str(_)
+        | LitKind::Task(_)
+        | LitKind::Timestamp(_) => {
+            return Err(String::from("Ranges can only go over numbers and times"))
+        }
+    };
+
+    for (value, name) in [(end, "end"), (step, "step")] {
+        if value.0.type_() != start.0.type_() {
+            return Err(format!(
+                "The {name} of a range of {}s has to be a {} too, found a {}",
+                start.0.type_name(),
+                start.0.type_name(),
+                value.0.type_name()
+            ));
+        }
+    }
+
+    match step_ms {
+        Some(step_ms) if step_ms > 0. => Ok(()),
+        _ => Err(String::from(
+            "The step of a range has to be greater than zero",
+        )),
+    }
+}
+
+/// Milliseconds of a time or seconds of a number, used by builtins taking a duration
+pub fn as_ms(lit: &Lit) -> Option<f32> {
+    match lit.0 {
+        LitKind::Time(time, time_kind) => Some(time * time_kind.as_ms()),
+        LitKind::Num(secs) => Some(secs * TimeKind::Sec.as_ms()),
+        LitKind::Bool(_)
+        | LitKind::Struct(..)
+        | LitKind::List(..)
+        | LitKind::Enum(..)
+        | LitKind::Function(..)
+        | LitKind::Str(_)
+        | LitKind::Task(_)
+        | LitKind::Timestamp(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamps_too_far_away_are_errors() {
+        let at = Lit::new(LitKind::Timestamp(1_792_281_600_000));
+        let day = Lit::from((1, TimeKind::Day));
+        let far = Lit::from((1e11, TimeKind::Year));
+
+        assert_eq!(
+            binary(&at, &BinOp::Add, &day),
+            Ok(Lit::new(LitKind::Timestamp(1_792_368_000_000)))
+        );
+        assert!(binary(&at, &BinOp::Add, &far).is_err());
+        assert!(binary(&far, &BinOp::Add, &at).is_err());
+        assert!(binary(&at, &BinOp::Sub, &far).is_err());
+
+        let max = Lit::new(LitKind::Timestamp(i64::MAX));
+        let min = Lit::new(LitKind::Timestamp(i64::MIN));
+        assert!(binary(&max, &BinOp::Add, &Lit::from((1, TimeKind::Sec))).is_err());
+        assert!(binary(&min, &BinOp::Sub, &Lit::from((1, TimeKind::Sec))).is_err());
+        assert!(binary(&max, &BinOp::Sub, &min).is_err());
+        assert_eq!(
+            binary(&at, &BinOp::Sub, &at),
+            Ok(Lit::new(LitKind::Time(0., TimeKind::Ms)))
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! The time, randomness and task utilities every script can use besides
+//! `wait`, called like the other [`Builtin`](super::builtins::Builtin)s
+
+use super::{calendar, ops::as_ms, Runtime};
+use crate::parser::{
+    ast::lit::{Lit, LitKind, TimeKind},
+    lexer::{lexer, token::TokenKind},
+};
+use rand::Rng;
+use std::time::Duration;
+
+/// `now()`, the time since the script started, which only ever grows
+pub fn now(runtime: &Runtime) -> Lit {
+    Lit::from((runtime.clock.now().as_secs_f32() * 1000., TimeKind::Ms))
+}
+
+/// `elapsed(since)`, the time since an earlier `now()`
+pub fn elapsed(since: &Lit, runtime: &Runtime) -> Result<Lit, String> {
+    let since = as_ms(since).ok_or("`elapsed` expects a time")?;
+
+    Ok(Lit::from((
+        runtime.clock.now().as_secs_f32() * 1000. - since,
+        TimeKind::Ms,
+    )))
+}
+
+/// `min_time(a, b)` and `max_time(a, b)`, the shorter or longer time
+pub fn pick_time(a: &Lit, b: &Lit, longer: bool) -> Result<Lit, String> {
+    let (Some(a_ms), Some(b_ms)) = (as_ms(a), as_ms(b)) else {
+        return Err(format!(
+            "`{}` expects times",
+            if longer { "max_time" } else { "min_time" }
+        ));
+    };
+
+    Ok(if (b_ms > a_ms) == longer { *b } else { *a })
+}
+
+/// `clamp(value, min, max)`, a time or number kept between two others of the same kind
+pub fn clamp(value: &Lit, min: &Lit, max: &Lit) -> Result<Lit, String> {
+    let (value_ms, min_ms, max_ms) = match (value.0, min.0, max.0) {
+        (LitKind::Num(value), LitKind::Num(min), LitKind::Num(max)) => (value, min, max),
+        (LitKind::Time(..), LitKind::Time(..), LitKind::Time(..)) => (
+            as_ms(value).unwrap(),
+            as_ms(min).unwrap(),
+            as_ms(max).unwrap(),
+        ),
+        _ => return Err(String::from("`clamp` expects three times or three numbers")),
+    };
+
+    if min_ms > max_ms {
+        return Err(String::from(
+            "`clamp` expects its lowest value to be below the highest one",
+        ));
+    }
+
+    Ok(if value_ms < min_ms {
+        *min
+    } else if value_ms > max_ms {
+        *max
+    } else {
+        *value
+    })
+}
+
+/// `to_seconds(t)`, a time as a number of seconds
+pub fn to_seconds(time: &Lit) -> Result<Lit, String> {
+    match time.0 {
+        LitKind::Time(..) => Ok(Lit::from(as_ms(time).unwrap() / TimeKind::Sec.as_ms())),
+        _ => Err(String::from("`to_seconds` expects a time")),
+    }
+}
+
+/// `from_seconds(n)`, a number of seconds as a time
+pub fn from_seconds(secs: &Lit) -> Result<Lit, String> {
+    match secs.0 {
+        LitKind::Num(secs) => Ok(Lit::from((secs, TimeKind::Sec))),
+        _ => Err(String::from("`from_seconds` expects a number")),
+    }
+}
+
+/// `format_duration(t)`, a time written out like `1min 30s`
+pub fn format_duration(time: &Lit) -> Result<Lit, String> {
+    let ms = match time.0 {
+        LitKind::Time(..) => as_ms(time).unwrap(),
+        _ => return Err(String::from("`format_duration` expects a time")),
+    };
+
+    // Never freed, like the values on the heap
+    let text = Box::leak(format_ms(ms).into_boxed_str());
+
+    Ok(Lit::new(LitKind::Str(text)))
+}
+
+/// `parse_duration(text)`, a time written like in a script, `"1min 30s"`
+pub fn parse_duration(text: &Lit) -> Result<Lit, String> {
+    let LitKind::Str(text) = text.0 else {
+        return Err(String::from("`parse_duration` expects a string"));
+    };
+
+    match parse_ms(text) {
+        Some(ms) => Ok(Lit::from((ms, TimeKind::Ms))),
+        None => Err(format!("`{text}` isn't a time like `1min 30s`")),
+    }
+}
+
+/// `random(min, max)`, a number anywhere between the two
+pub fn random(min: &Lit, max: &Lit, runtime: &Runtime) -> Result<Lit, String> {
+    let (LitKind::Num(min), LitKind::Num(max)) = (min.0, max.0) else {
+        return Err(String::from("`random` expects two numbers"));
+    };
+
+    Ok(Lit::from(between(min, max, runtime)?))
+}
+
+/// `random_time(min, max)`, a time anywhere between the two
+pub fn random_time(min: &Lit, max: &Lit, runtime: &Runtime) -> Result<Lit, String> {
+    let (LitKind::Time(..), LitKind::Time(..)) = (min.0, max.0) else {
+        return Err(String::from("`random_time` expects two times"));
+    };
+
+    let ms = between(as_ms(min).unwrap(), as_ms(max).unwrap(), runtime)?;
+
+    Ok(Lit::from((ms, TimeKind::Ms)))
+}
+
+/// `jitter(t, share)`, a time moved up or down by at most `share` of it
+pub fn jitter(time: &Lit, share: &Lit, runtime: &Runtime) -> Result<Lit, String> {
+    let (LitKind::Time(..), LitKind::Num(share)) = (time.0, share.0) else {
+        return Err(String::from(
+            "`jitter` expects a time and a share of it like `10%`",
+        ));
+    };
+
+    let ms = as_ms(time).unwrap();
+    let offset = ms.abs() * share.abs();
+
+    Ok(Lit::from((
+        between(ms - offset, ms + offset, runtime)?,
+        TimeKind::Ms,
+    )))
+}
+
+/// `join(task)`, waits until the task finished and gives how long that took
+pub fn join(task: &Lit, runtime: &Runtime) -> Result<Lit, String> {
+    let LitKind::Task(task) = task.0 else {
+        return Err(String::from("`join` expects a task"));
+    };
+
+    let start = runtime.clock.now();
+    runtime.join(&[task])?;
+
+    Ok(Lit::from((
+        (runtime.clock.now() - start).as_secs_f32() * 1000.,
+        TimeKind::Ms,
+    )))
+}
+
+/// `race(a, b)`, waits until one of the tasks finished and gives that one
+pub fn race(a: &Lit, b: &Lit, runtime: &Runtime) -> Result<Lit, String> {
+    let (LitKind::Task(a), LitKind::Task(b)) = (a.0, b.0) else {
+        return Err(String::from("`race` expects two tasks"));
+    };
+
+    // Cut short by a deadline, the `within` block is left without the value
+    let first = runtime.join(&[a, b])?.unwrap_or(a);
+
+    Ok(Lit::new(LitKind::Task(first)))
+}
+
+/// `wait_until(timestamp)`, waits until the clock shows the timestamp and
+/// gives how long that took
+pub fn wait_until(timestamp: &Lit, runtime: &Runtime) -> Result<Lit, String> {
+    let LitKind::Timestamp(at) = timestamp.0 else {
+        return Err(String::from(
+            "`wait_until` expects a timestamp like `@17:00`",
+        ));
+    };
+
+    let ms = at.saturating_sub(calendar::wall_ms(&*runtime.clock)).max(0) as u64;
+    runtime.wait(Duration::from_millis(ms))?;
+
+    Ok(Lit::from((ms as f32, TimeKind::Ms)))
+}
+
+fn between(min: f32, max: f32, runtime: &Runtime) -> Result<f32, String> {
+    if min > max || !min.is_finite() || !max.is_finite() {
+        return Err(format!("There is no number between {min} and {max}"));
+    }
+
+    Ok(runtime.rng.borrow_mut().gen_range(min..=max))
+}
+
+/// Formats milliseconds like `1h 30min 5s`
+pub fn format_ms(ms: f32) -> String {
+    if ms.is_infinite() {
+        return String::from("unbounded");
+    }
+
+    if ms < 0. {
+        return format!("-{}", format_ms(-ms));
+    }
+
+    let mut remaining = ms.round() as u64;
+    let mut parts = Vec::new();
+
+    for (unit, unit_ms) in [
+        ("d", 86_400_000),
+        ("h", 3_600_000),
+        ("min", 60_000),
+        ("s", 1000),
+        ("ms", 1),
+    ] {
+        if remaining >= unit_ms {
+            parts.push(format!("{}{unit}", remaining / unit_ms));
+            remaining %= unit_ms;
+        }
+    }
+
+    if parts.is_empty() {
+        return String::from("0s");
+    }
+
+    parts.join(" ")
+}
+
+/// Parses times written like in a script and adds them up, `1min 30s` is
+/// 90000 milliseconds
+pub fn parse_ms(text: &str) -> Option<f32> {
+    let tokens = lexer(text).ok()?;
+    let (negative, times) = match tokens.split_first()? {
+        (first, rest) if first.kind == TokenKind::Sub => (true, rest),
+        _ => (false, tokens.as_slice()),
+    };
+
+    // Everything up to the end has to be a time, and there has to be one
+    let (eof, times) = times.split_last()?;
+    if eof.kind != TokenKind::Eof || times.is_empty() {
+        return None;
+    }
+
+    let mut ms = 0.;
+    for token in times {
+        let TokenKind::Time(time, time_kind) = token.kind else {
+            return None;
+        };
+
+        ms += time * time_kind.as_ms();
+    }
+
+    Some(if negative { -ms } else { ms })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::clock::VirtualClock;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_durations_round_trip() {
+        assert_eq!(parse_ms("1min 30s"), Some(90_000.));
+        assert_eq!(parse_ms("-250ms"), Some(-250.));
+        assert_eq!(parse_ms("90"), None);
+        assert_eq!(parse_ms(""), None);
+        assert_eq!(format_ms(-1500.), "-1s 500ms");
+        assert_eq!(parse_ms(&format_ms(3_723_004.)), Some(3_723_004.));
+    }
+
+    #[test]
+    fn test_timestamps_at_the_ends_of_the_clock_are_waited_for() {
+        let runtime = Runtime::new(Rc::new(VirtualClock::new()));
+
+        let past = Lit::new(LitKind::Timestamp(i64::MIN));
+        assert_eq!(
+            wait_until(&past, &runtime),
+            Ok(Lit::from((0, TimeKind::Ms)))
+        );
+        assert_eq!(runtime.clock.now(), Duration::ZERO);
+
+        let future = Lit::new(LitKind::Timestamp(i64::MAX));
+        assert!(wait_until(&future, &runtime).is_ok());
+        assert_eq!(runtime.clock.now(), Duration::from_millis(i64::MAX as u64));
+    }
+}
@@ -0,0 +1,201 @@
+//! The waits that are running, shown on stderr while a script runs from the
+//! command line with `--progress`. A terminal gets a bar for every wait that
+//! is redrawn in place, anything else gets a line for each of them now and then.
+
+use super::prelude::format_ms;
+use crate::{error_handling::place, parser::ast::Span};
+use colored::Colorize;
+use std::{
+    io::{stderr, IsTerminal},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often the bars are redrawn on a terminal
+const REDRAW: Duration = Duration::from_millis(200);
+
+/// How often the waits are logged when stderr isn't a terminal
+const LOG: Duration = Duration::from_secs(10);
+
+const BAR_WIDTH: usize = 20;
+
+/// When to show progress, set with `--progress`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Mode {
+    /// Only on a terminal, nothing is logged when stderr isn't one
+    Auto,
+    /// On a terminal and as lines in logs
+    Always,
+    Never,
+}
+
+impl Mode {
+    /// The mode for the name used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Mode::Auto),
+            "always" => Some(Mode::Always),
+            "never" => Some(Mode::Never),
+            _ => None,
+        }
+    }
+}
+
+pub struct Progress {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    waits: Vec<Wait>,
+    counter: u64,
+    /// How many lines the last redraw left on the terminal
+    drawn: usize,
+}
+
+struct Wait {
+    id: u64,
+    /// The file, line and code of the wait
+    place: String,
+    start: Instant,
+    length: Duration,
+}
+
+/// Takes a wait off the display once it's dropped
+pub struct Shown {
+    state: Arc<Mutex<State>>,
+    id: u64,
+}
+
+impl Progress {
+    /// Starts showing the waits if the mode asks for it where stderr goes
+    pub fn start(mode: Mode) -> Option<Self> {
+        let terminal = stderr().is_terminal();
+        let every = match (mode, terminal) {
+            (Mode::Never, _) | (Mode::Auto, false) => return None,
+            (_, true) => REDRAW,
+            (Mode::Always, false) => LOG,
+        };
+
+        let state = Arc::new(Mutex::new(State::default()));
+        let shared = Arc::clone(&state);
+        thread::spawn(move || loop {
+            thread::sleep(every);
+
+            let mut state = lock(&shared);
+            match terminal {
+                true => state.redraw(),
+                false => state.log(),
+            }
+        });
+
+        Some(Self { state })
+    }
+
+    /// Shows a wait of the code at the span until the result is dropped
+    pub fn show(&self, span: Span, length: Duration) -> Shown {
+        let mut state = lock(&self.state);
+        state.counter += 1;
+        let id = state.counter;
+
+        state.waits.push(Wait {
+            id,
+            place: place(span).unwrap_or_default(),
+            start: Instant::now(),
+            length,
+        });
+
+        Shown {
+            state: Arc::clone(&self.state),
+            id,
+        }
+    }
+}
+
+impl Drop for Shown {
+    fn drop(&mut self) {
+        let mut state = lock(&self.state);
+        state.waits.retain(|wait| wait.id != self.id);
+        // Nothing else is written to stderr while the bars are up
+        eprint!("{}", state.erase());
+    }
+}
+
+impl State {
+    fn redraw(&mut self) {
+        let mut out = self.erase();
+        for wait in self.waits.iter() {
+            out.push_str(&wait.bar());
+            out.push('\n');
+        }
+        self.drawn = self.waits.len();
+
+        eprint!("{out}");
+    }
+
+    /// Moves the cursor up over the lines drawn last, clearing them
+    fn erase(&mut self) -> String {
+        "\x1b[1A\x1b[2K".repeat(std::mem::take(&mut self.drawn))
+    }
+
+    fn log(&self) {
+        for wait in self.waits.iter() {
+            let (done, left) = wait.status();
+            eprintln!("{}: {:.0}% done, {left} left", wait.place, done * 100.);
+        }
+    }
+}
+
+impl Wait {
+    /// The share of the wait that is over and the time left
+    fn status(&self) -> (f32, String) {
+        let over = self.start.elapsed().min(self.length);
+        let done = match self.length.is_zero() {
+            true => 1.,
+            false => over.as_secs_f32() / self.length.as_secs_f32(),
+        };
+        let left = (self.length - over).as_secs_f32().ceil() * 1000.;
+
+        (done, format_ms(left))
+    }
+
+    fn bar(&self) -> String {
+        let (done, left) = self.status();
+        let filled = ((done * BAR_WIDTH as f32) as usize).min(BAR_WIDTH);
+
+        format!(
+            "{} [{}{}] {:>3.0}% {left} left",
+            self.place.bold().blue(),
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            done * 100.
+        )
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_waits_show_how_much_is_left() {
+        let wait = Wait {
+            id: 0,
+            place: String::from("script.wait:1 wait(2h);"),
+            start: Instant::now() - Duration::from_secs(1800),
+            length: Duration::from_secs(7200),
+        };
+
+        let (done, left) = wait.status();
+        assert_eq!((done * 100.).round(), 25.);
+        assert_eq!(left, "1h 30min");
+        assert!(wait
+            .bar()
+            .contains("[#####---------------]  25% 1h 30min left"));
+    }
+}
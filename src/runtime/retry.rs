@@ -0,0 +1,128 @@
+//! The attempts of `retry` blocks and the pauses between them
+
+use super::{
+    ops::as_ms,
+    prelude::{self, format_ms},
+    Runtime,
+};
+use crate::parser::ast::{
+    lit::{Lit, LitKind, TimeKind},
+    stmt::BackoffKind,
+};
+use std::time::Duration;
+
+/// How many values the state of a `retry` block takes, see [`start`]
+pub const SLOTS: usize = 5;
+
+/// The state of a `retry` block before its first attempt, kept in values so
+/// the VM can keep it in locals: the number of the attempt, which is what
+/// `attempt` reads, how many attempts there are, the first and the longest
+/// pause in milliseconds and the share of jitter
+pub fn start(attempts: Lit, delay: Lit, max: Lit, jitter: Lit) -> Result<[Lit; SLOTS], String> {
+    let attempts = match attempts.0 {
+        LitKind::Num(attempts) if attempts >= 1. => attempts.floor(),
+        LitKind::Num(_) => return Err(String::from("`retry` needs at least one attempt")),
+        _ => {
+            return Err(format!(
+                "Expected a number of attempts, found a {}",
+                attempts.0.type_name()
+            ))
+        }
+    };
+
+    let mut pauses = [0.; 2];
+    for (pause, value) in pauses.iter_mut().zip([delay, max]) {
+        *pause = match as_ms(&value) {
+            Some(ms) => ms.max(0.),
+            None => {
+                return Err(format!(
+                    "Expected a time for the backoff, found a {}",
+                    value.0.type_name()
+                ))
+            }
+        };
+    }
+
+    let jitter = match jitter.0 {
+        LitKind::Num(share) => share.abs(),
+        _ => {
+            return Err(format!(
+                "Expected a share like `20%` for the jitter, found a {}",
+                jitter.0.type_name()
+            ))
+        }
+    };
+
+    Ok([
+        Lit::from(0),
+        Lit::from(attempts),
+        Lit::from((pauses[0], TimeKind::Ms)),
+        Lit::from((pauses[1], TimeKind::Ms)),
+        Lit::from(jitter),
+    ])
+}
+
+/// Starts the next attempt, pausing first if one failed already, and fails
+/// once there are no attempts left
+///
+/// `report` gets what happened to the attempt before for the trace of the run.
+pub fn next(
+    state: &mut [Lit],
+    backoff: BackoffKind,
+    runtime: &Runtime,
+    report: impl Fn(&str),
+) -> Result<(), String> {
+    let [Lit(LitKind::Num(attempt)), Lit(LitKind::Num(attempts)), delay, max, Lit(LitKind::Num(jitter))] =
+        *state
+    else {
+        unreachable!("started by `start`")
+    };
+
+    if attempt >= attempts {
+        return Err(format!("All {attempts} attempts of `retry` failed"));
+    }
+
+    if attempt >= 1. {
+        let delay = as_ms(&delay).expect("checked by `start`");
+        let pause = match backoff {
+            BackoffKind::Constant => delay,
+            BackoffKind::Linear => delay * attempt,
+            BackoffKind::Exponential => delay * 2f32.powf(attempt - 1.),
+        }
+        .min(as_ms(&max).expect("checked by `start`"));
+
+        let pause = match jitter {
+            0. => pause,
+            _ => {
+                let pause = Lit::from((pause, TimeKind::Ms));
+                as_ms(&prelude::jitter(&pause, &Lit::from(jitter), runtime)?).unwrap_or(0.)
+            }
+        };
+
+        let Ok(duration) = Duration::try_from_secs_f64(pause.max(0.) as f64 / 1000.) else {
+            return Err(format!(
+                "The pause before attempt {} of `retry` is too long to wait for",
+                attempt + 1.
+            ));
+        };
+
+        report(&format!(
+            "Attempt {attempt} of {attempts} failed, trying again in {}",
+            format_ms(pause)
+        ));
+        runtime.wait(duration)?;
+    }
+
+    state[0] = Lit::from(attempt + 1.);
+
+    Ok(())
+}
+
+/// Reports an attempt that succeeded after others failed for the trace of the run
+pub fn succeeded(state: &[Lit], report: impl Fn(&str)) {
+    if let [Lit(LitKind::Num(attempt)), Lit(LitKind::Num(attempts)), ..] = *state
+        && attempt > 1.
+    {
+        report(&format!("Attempt {attempt} of {attempts} succeeded"));
+    }
+}
@@ -0,0 +1,315 @@
+//! The schedules of `every` loops, whose iterations are due at times counted
+//! from when the loop started rather than from when the last one ended, so
+//! the time the body takes doesn't add up over the iterations
+
+use super::{
+    calendar::{civil_from_days, from_local, to_local, wall_ms, weekday, DAY_MS},
+    clock::Clock,
+    ops::as_ms,
+    Runtime,
+};
+use crate::parser::ast::lit::{Lit, LitKind};
+use std::time::Duration;
+
+/// How many values the state of a schedule takes, see [`start`]
+pub const SLOTS: usize = 4;
+
+/// How far ahead a cron expression is looked up, enough for every weekday
+/// to fall on every day of every month, leap days included
+const CRON_DAYS: i64 = 366 * 28;
+
+/// The state of a schedule that starts now, kept in values so the VM can keep
+/// it in locals: what it runs every, how many runs are left, when it ends and
+/// when the next run is due
+pub fn start(
+    every: Lit,
+    times: Option<Lit>,
+    duration: Option<Lit>,
+    runtime: &Runtime,
+) -> Result<[Lit; SLOTS], String> {
+    let now = wall_ms(&*runtime.clock);
+
+    match every.0 {
+        LitKind::Str(text) => {
+            Cron::parse(text)?;
+        }
+        _ => match as_ms(&every) {
+            // Casts saturate, so the interval has to be below the largest one first
+            Some(ms)
+                if ms >= 1. && ms < i64::MAX as f32 && now.checked_add(ms as i64).is_some() => {}
+            // Runs are counted in whole milliseconds
+            Some(ms) if ms > 0. && ms < 1. => {
+                return Err(String::from(
+                    "The interval of `every` has to be at least 1ms",
+                ));
+            }
+            Some(ms) if ms > 0. => {
+                return Err(String::from(
+                    "The interval of `every` is too long to wait for",
+                ));
+            }
+            Some(_) => {
+                return Err(String::from(
+                    "The interval of `every` has to be greater than zero",
+                ));
+            }
+            None => {
+                return Err(format!(
+                    "Expected a time or a cron expression after `every`, found a {}",
+                    every.0.type_name()
+                ));
+            }
+        },
+    }
+
+    let runs = match times.map(|times| times.0) {
+        Some(LitKind::Num(times)) => times.floor(),
+        Some(times) => {
+            return Err(format!(
+                "Expected a number after `times`, found a {}",
+                times.type_name()
+            ));
+        }
+        None => f32::INFINITY,
+    };
+
+    let end = match duration {
+        Some(duration) => match as_ms(&duration) {
+            Some(ms) => now.saturating_add(ms as i64),
+            None => {
+                return Err(format!(
+                    "Expected a time after `for`, found a {}",
+                    duration.0.type_name()
+                ));
+            }
+        },
+        None => i64::MAX,
+    };
+
+    Ok([
+        every,
+        Lit::from(runs),
+        Lit::new(LitKind::Timestamp(end)),
+        Lit::new(LitKind::Timestamp(now)),
+    ])
+}
+
+/// Waits until the next run is due, or gives `false` once the schedule is over
+///
+/// A run that is late because the one before took too long starts right away,
+/// the ones it missed entirely are skipped.
+pub fn next(state: &mut [Lit], runtime: &Runtime) -> Result<bool, String> {
+    let [every, Lit(LitKind::Num(runs)), Lit(LitKind::Timestamp(end)), Lit(LitKind::Timestamp(due))] =
+        *state
+    else {
+        unreachable!("started by `start`")
+    };
+
+    if runs < 1. {
+        return Ok(false);
+    }
+
+    let clock = &*runtime.clock;
+    let now = wall_ms(clock);
+
+    let (at, after) = match every.0 {
+        LitKind::Str(text) => {
+            let at = Cron::parse(text)?.next(now.max(due), clock)?;
+            (at, at + 60_000)
+        }
+        _ => {
+            let interval = as_ms(&every).expect("checked by `start`") as i64;
+            let at = due + (now - due).max(0) / interval * interval;
+            (at, at.saturating_add(interval))
+        }
+    };
+
+    if at >= end {
+        return Ok(false);
+    }
+
+    runtime.wait(Duration::from_millis((at - now).max(0) as u64))?;
+
+    state[1] = Lit::from(runs - 1.);
+    state[3] = Lit::new(LitKind::Timestamp(after));
+
+    Ok(true)
+}
+
+/// A calendar schedule like `*/15 9-17 * * 1-5`, with the minutes, hours,
+/// days of the month, months and days of the week it runs at in local time
+#[derive(Debug, PartialEq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    /// 0 and 7 are both Sunday
+    weekdays: u64,
+    /// Whether the days of the month or of the week are left open with `*`,
+    /// if neither is a day has to match either of them
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "`{text}` isn't a cron expression, it needs minutes, hours, days, months and weekdays"
+            ));
+        };
+
+        Ok(Self {
+            minutes: field(minutes, 0, 59, "minute")?,
+            hours: field(hours, 0, 23, "hour")?,
+            days: field(days, 1, 31, "day")?,
+            months: field(months, 1, 12, "month")?,
+            // Sunday is also 0
+            weekdays: match field(weekdays, 0, 7, "weekday")? {
+                bits if bits & 1 << 7 != 0 => bits | 1,
+                bits => bits,
+            },
+            any_day: days.starts_with('*'),
+            any_weekday: weekdays.starts_with('*'),
+        })
+    }
+
+    /// The first minute it runs at that isn't before `from`, both since the Unix epoch
+    pub fn next(&self, from: i64, clock: &dyn Clock) -> Result<i64, String> {
+        // Rounded up to the next whole minute
+        let start = (to_local(from, clock) + 59_999).div_euclid(60_000) * 60_000;
+        let first_day = start.div_euclid(DAY_MS);
+
+        for day in first_day..first_day + CRON_DAYS {
+            if !self.runs_on(day) {
+                continue;
+            }
+
+            for hour in 0..24 {
+                for minute in 0..60 {
+                    let local = day * DAY_MS + (hour * 60 + minute) * 60_000;
+
+                    if self.hours & 1 << hour != 0
+                        && self.minutes & 1 << minute != 0
+                        && local >= start
+                    {
+                        return Ok(from_local(local, clock));
+                    }
+                }
+            }
+        }
+
+        Err(String::from("The cron expression never matches a date"))
+    }
+
+    fn runs_on(&self, day: i64) -> bool {
+        let (_, month, day_of_month) = civil_from_days(day);
+        let in_month = self.days & 1 << day_of_month != 0;
+        let in_week = self.weekdays & 1 << weekday(day) != 0;
+
+        let matches = match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => in_week,
+            (false, true) => in_month,
+            (false, false) => in_month || in_week,
+        };
+
+        self.months & 1 << month != 0 && matches
+    }
+}
+
+/// The values a field of a cron expression allows, as bits
+fn field(text: &str, min: u32, max: u32, name: &str) -> Result<u64, String> {
+    let mut bits = 0;
+
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (part, None),
+        };
+
+        let number = |text: &str| match text.parse::<u32>() {
+            Ok(number) if (min..=max).contains(&number) => Ok(number),
+            _ => Err(format!("`{text}` isn't a {name} from {min} to {max}")),
+        };
+
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            // A single value with a step goes up to the highest one
+            None if step.is_some() => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+
+        let step = match step.map(str::parse::<u32>) {
+            Some(Ok(step)) if step > 0 => step,
+            Some(_) => {
+                return Err(format!(
+                    "`{part}` needs a step greater than zero after the `/`"
+                ));
+            }
+            None => 1,
+        };
+
+        if start > end {
+            return Err(format!("`{part}` goes from a higher {name} to a lower one"));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::ast::lit::TimeKind,
+        runtime::{calendar::days_from_civil, clock::VirtualClock},
+    };
+
+    #[test]
+    fn test_intervals_are_whole_milliseconds() {
+        let runtime = Runtime::new(std::rc::Rc::new(VirtualClock::new()));
+        let start = |ms: f32| start(Lit::from((ms, TimeKind::Ms)), None, None, &runtime);
+
+        assert!(start(0.5).is_err());
+        assert!(start(0.).is_err());
+
+        let mut state = start(1.).unwrap();
+        assert_eq!(next(&mut state, &runtime), Ok(true));
+        assert_eq!(next(&mut state, &runtime), Ok(true));
+        assert_eq!(runtime.clock.now(), Duration::from_millis(1));
+    }
+
+    #[test]
+    fn test_cron_finds_the_next_run() {
+        // Sunday 2026-10-18T16:00Z, two hours ahead of UTC
+        let clock = VirtualClock::starting_at(Duration::from_secs(1_792_339_200), 2 * 3600);
+        let now = wall_ms(&clock);
+        let at = |day: u32, hour: i64, minute: i64| {
+            (days_from_civil(2026, 10, day) * DAY_MS + (hour * 60 + minute) * 60_000)
+                - 2 * 3_600_000
+        };
+
+        let next = |text: &str| Cron::parse(text).unwrap().next(now, &clock).unwrap();
+        assert_eq!(next("* * * * *"), now);
+        assert_eq!(next("10/25 * * * *"), at(18, 18, 10));
+        assert_eq!(next("0 9-17 * * 1-5"), at(19, 9, 0));
+        assert_eq!(next("30 8 20,25 * 6"), at(20, 8, 30));
+        assert_eq!(next("0 0 * * 7"), at(25, 0, 0));
+
+        assert!(Cron::parse("* * * *").is_err());
+        assert!(Cron::parse("60 * * * *").is_err());
+        assert!(Cron::parse("*/0 * * * *").is_err());
+        assert!(Cron::parse("0 0 31 2 *")
+            .unwrap()
+            .next(now, &clock)
+            .is_err());
+    }
+}
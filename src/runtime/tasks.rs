@@ -0,0 +1,261 @@
+//! Tasks started with `spawn`, which take turns running over the one clock
+//! of the script.
+//!
+//! Every task is a coroutine with a stack of its own on the thread of the
+//! script, and only the script itself resumes them. When a task waits, it
+//! gives the turn back to the script, which resumes the task that is due the
+//! soonest. The clock only moves forward once no task is due before then, so
+//! two tasks waiting 5s at the same time take 5s together.
+
+use super::clock::Clock;
+use corosensei::{stack::DefaultStack, Coroutine, CoroutineResult, Yielder};
+use std::{
+    cell::{Cell, RefCell},
+    ptr,
+    rc::Rc,
+    time::Duration,
+};
+
+/// The stack of a task, as large as the one of the script itself since the
+/// tree walker needs it for nested calls. Only the part in use takes memory.
+const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Takes turns between the tasks of a script, the script itself is task 0
+pub struct Scheduler {
+    state: RefCell<State>,
+    /// The coroutines of the tasks by their number, taken out while they run
+    /// and dropped once they finished
+    bodies: RefCell<Vec<Option<Body>>>,
+    /// Gives the turn of the task that is running back to the script, null
+    /// while the script itself runs
+    yielder: Cell<*const Yielder<(), ()>>,
+    /// Set once a task failed with an error, which fails the script too
+    failed: Cell<bool>,
+}
+
+type Body = Coroutine<(), (), Result<(), ()>, DefaultStack>;
+
+struct State {
+    tasks: Vec<Task>,
+    /// Orders the tasks due at the same time and the tasks that finished
+    counter: u64,
+}
+
+enum Task {
+    /// Runs once the clock reaches the time, tasks due at the same time run
+    /// in the order of the number
+    Due(Duration, u64),
+    Running,
+    /// Waits for one of the tasks to finish, or until the time if it's
+    /// given, ordered like a task that is due
+    Joining(Vec<usize>, Option<(Duration, u64)>),
+    /// Finished, the number orders the tasks that did
+    Done(u64),
+}
+
+impl State {
+    fn count(&mut self) -> u64 {
+        self.counter += 1;
+        self.counter
+    }
+
+    /// The task that is due the soonest
+    fn next(&self) -> Option<(usize, Duration)> {
+        self.tasks
+            .iter()
+            .enumerate()
+            .filter_map(|(index, task)| match task {
+                Task::Due(at, order) | Task::Joining(_, Some((at, order))) => {
+                    Some((index, *at, *order))
+                }
+                _ => None,
+            })
+            .min_by_key(|(_, at, order)| (*at, *order))
+            .map(|(index, at, _)| (index, at))
+    }
+
+    /// The task of the ones given that finished first
+    fn finished(&self, tasks: &[usize]) -> Option<usize> {
+        tasks
+            .iter()
+            .filter_map(|task| match self.tasks.get(*task) {
+                Some(Task::Done(order)) => Some((*task, *order)),
+                _ => None,
+            })
+            .min_by_key(|(_, order)| *order)
+            .map(|(task, _)| task)
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            state: RefCell::new(State {
+                tasks: vec![Task::Running],
+                counter: 0,
+            }),
+            bodies: RefCell::new(vec![None]),
+            yielder: Cell::new(ptr::null()),
+            failed: Cell::new(false),
+        }
+    }
+
+    /// Adds a task that runs as soon as the running one waits
+    pub fn spawn(&self, clock: &dyn Clock) -> usize {
+        let mut state = self.state.borrow_mut();
+        let order = state.count();
+        state.tasks.push(Task::Due(clock.now(), order));
+        self.bodies.borrow_mut().push(None);
+
+        state.tasks.len() - 1
+    }
+
+    /// Sets the code a spawned task runs once it's its turn
+    pub fn start(self: &Rc<Self>, task: usize, body: impl FnOnce() -> Result<(), ()> + 'static) {
+        let stack = DefaultStack::new(STACK_SIZE).expect("a stack for the task");
+        let scheduler = Rc::clone(self);
+
+        let body = Coroutine::with_stack(stack, move |yielder: &Yielder<(), ()>, ()| {
+            scheduler.yielder.set(yielder);
+            body()
+        });
+        self.bodies.borrow_mut()[task] = Some(body);
+    }
+
+    /// Lets the other tasks run while a task waits
+    pub fn wait(&self, task: usize, duration: Duration, clock: &dyn Clock) -> Result<(), String> {
+        let mut state = self.state.borrow_mut();
+        let order = state.count();
+        state.tasks[task] = Task::Due(clock.now().saturating_add(duration), order);
+        drop(state);
+
+        self.switch(task, clock)
+    }
+
+    /// Lets the other tasks run until one of the given ones finished, and
+    /// gives the one that finished first, or `None` if the clock reached
+    /// `until` before
+    pub fn join(
+        &self,
+        task: usize,
+        targets: &[usize],
+        until: Option<Duration>,
+        clock: &dyn Clock,
+    ) -> Result<Option<usize>, String> {
+        if let Some(finished) = self.state.borrow().finished(targets) {
+            return Ok(Some(finished));
+        }
+
+        if targets.contains(&task) {
+            return Err(String::from("A task can't wait for itself to finish"));
+        }
+
+        let mut state = self.state.borrow_mut();
+        let until = until.map(|until| (until, state.count()));
+        state.tasks[task] = Task::Joining(targets.to_vec(), until);
+        drop(state);
+        self.switch(task, clock)?;
+
+        if let Some(finished) = self.state.borrow().finished(targets) {
+            return Ok(Some(finished));
+        }
+
+        // Otherwise the turn only comes back early when every task waits for another one
+        match until {
+            Some((until, _)) if clock.now() >= until => Ok(None),
+            _ => Err(String::from(
+                "Every task is waiting for another one to finish",
+            )),
+        }
+    }
+
+    /// Whether one of the tasks failed, the script stops then without
+    /// retrying or running any more of them
+    pub fn failed(&self) -> bool {
+        self.failed.get()
+    }
+
+    /// Whether every task but the script itself finished
+    pub fn alone(&self) -> bool {
+        self.state.borrow().tasks[1..]
+            .iter()
+            .all(|task| matches!(task, Task::Done(_)))
+    }
+
+    /// Gives the turn away until the task is due again
+    ///
+    /// A spawned task hands it back to the script, which resumes the tasks
+    /// that are due in turn until it's due itself.
+    fn switch(&self, task: usize, clock: &dyn Clock) -> Result<(), String> {
+        if task != 0 {
+            let yielder = self.yielder.get();
+            // SAFETY: a spawned task only runs inside its coroutine, which set
+            // the yielder before it started or resumed after the last suspend
+            // below, and lives at least as long as the code running in it
+            unsafe { &*yielder }.suspend(());
+            self.yielder.set(yielder);
+
+            return Ok(());
+        }
+
+        loop {
+            if self.failed.get() {
+                self.state.borrow_mut().tasks[task] = Task::Running;
+                return Err(String::from("A task started with `spawn` failed"));
+            }
+
+            let next = self.state.borrow().next();
+            let Some((next, at)) = next else {
+                self.state.borrow_mut().tasks[task] = Task::Running;
+                return Err(String::from(
+                    "Every task is waiting for another one to finish",
+                ));
+            };
+
+            // The clock only moves once no task is due before `at`
+            if let Some(ahead) = at.checked_sub(clock.now()) {
+                clock.sleep(ahead);
+            }
+
+            self.state.borrow_mut().tasks[next] = Task::Running;
+            if next == task {
+                return Ok(());
+            }
+
+            self.resume(next, clock);
+        }
+    }
+
+    /// Runs a task until it waits or finishes
+    fn resume(&self, task: usize, clock: &dyn Clock) {
+        let mut body = self.bodies.borrow_mut()[task]
+            .take()
+            .expect("started with `start`");
+        let result = body.resume(());
+        self.yielder.set(ptr::null());
+
+        match result {
+            CoroutineResult::Yield(()) => self.bodies.borrow_mut()[task] = Some(body),
+            CoroutineResult::Return(result) => {
+                self.failed.set(self.failed.get() || result.is_err());
+                self.finish(task, clock);
+            }
+        }
+    }
+
+    /// Marks a task as finished, which makes the ones joining it due
+    fn finish(&self, task: usize, clock: &dyn Clock) {
+        let mut state = self.state.borrow_mut();
+        let order = state.count();
+        state.tasks[task] = Task::Done(order);
+
+        for index in 0..state.tasks.len() {
+            if let Task::Joining(targets, _) = &state.tasks[index]
+                && targets.contains(&task)
+            {
+                let order = state.count();
+                state.tasks[index] = Task::Due(clock.now(), order);
+            }
+        }
+    }
+}
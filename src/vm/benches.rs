@@ -0,0 +1,104 @@
+//! Compares the bytecode VM with the tree walker, run with `cargo bench`
+
+extern crate test;
+
+use super::{compiler::Compiler, Vm};
+use crate::{
+    interpreter::Interpreter,
+    parser::{ast::Ast, parse},
+    resolver::Resolver,
+    runtime::{clock::VirtualClock, Runtime},
+};
+use std::rc::Rc;
+use test::Bencher;
+
+/// Identifiers can't contain digits, so number variables with letters
+fn name(mut index: usize) -> String {
+    let mut name = String::from("v_");
+
+    loop {
+        name.push((b'a' + (index % 26) as u8) as char);
+        index /= 26;
+
+        if index == 0 {
+            return name;
+        }
+    }
+}
+
+/// Many variables, each one calculated from the first and the one before
+fn variables_script() -> Ast {
+    let mut code = String::from("num v_a = 1;\n");
+
+    for i in 1..300 {
+        code.push_str(&format!("num {} = {} * 2 - v_a;\n", name(i), name(i - 1)));
+    }
+
+    parse(&code).unwrap()
+}
+
+/// Many calls of a function with arguments
+fn calls_script() -> Ast {
+    let mut code =
+        String::from("func scale(t: time, factor: num) -> time { return t * factor + 1ms; }\n");
+
+    for _ in 0..300 {
+        code.push_str("wait(scale(scale(1ms, 2), 3));\n");
+    }
+
+    parse(&code).unwrap()
+}
+
+/// A loop counting up a variable
+fn loop_script() -> Ast {
+    parse("num i = 0; while (i < 1000) { i += 1; }").unwrap()
+}
+
+fn runtime() -> Runtime {
+    Runtime::new(Rc::new(VirtualClock::new()))
+}
+
+/// Resolving happens once, like parsing does
+fn bench_tree_walker(b: &mut Bencher, mut ast: Ast) {
+    Resolver::new().resolve(&mut ast).unwrap();
+    let interpreter = || Interpreter::new(runtime());
+
+    b.iter(|| interpreter().run(ast.clone()).unwrap());
+}
+
+/// Compiling happens once, like parsing does for the tree walker
+fn bench_bytecode(b: &mut Bencher, ast: Ast) {
+    let chunk = Rc::new(Compiler::new().compile(&ast).unwrap());
+
+    b.iter(|| Vm::new(runtime()).run_chunk(&chunk).unwrap());
+}
+
+#[bench]
+fn bench_variables_tree_walker(b: &mut Bencher) {
+    bench_tree_walker(b, variables_script());
+}
+
+#[bench]
+fn bench_variables_bytecode(b: &mut Bencher) {
+    bench_bytecode(b, variables_script());
+}
+
+#[bench]
+fn bench_calls_tree_walker(b: &mut Bencher) {
+    bench_tree_walker(b, calls_script());
+}
+
+#[bench]
+fn bench_calls_bytecode(b: &mut Bencher) {
+    bench_bytecode(b, calls_script());
+}
+
+#[bench]
+fn bench_loop_tree_walker(b: &mut Bencher) {
+    bench_tree_walker(b, loop_script());
+}
+
+#[bench]
+fn bench_loop_bytecode(b: &mut Bencher) {
+    bench_bytecode(b, loop_script());
+}
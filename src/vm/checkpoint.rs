@@ -0,0 +1,303 @@
+//! The state of the VM in the checkpoints written with `--checkpoint`, see
+//! [`crate::runtime::checkpoint`]
+
+use super::{
+    chunk::{Chunk, Op},
+    compiler::Compiler,
+    Frame, Retry, Vm, Within,
+};
+use crate::{
+    error_handling::{error_message, warn_at},
+    parser::ast::{pattern::PatternKind, Ast, Span},
+    runtime::{
+        checkpoint::{decode_all, encode, encode_all, encode_type, Saved},
+        heap::Heap,
+    },
+};
+use std::{rc::Rc, time::Duration};
+
+/// Tells compiled scripts apart, a checkpoint only fits the one that wrote it
+///
+/// The hash is the 64-bit FNV-1a of [`encode_chunk`], so it stays the same
+/// across runs and builds of `wait`.
+pub fn fingerprint(chunk: &Chunk) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    encode_chunk(chunk).bytes().fold(OFFSET, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// The instructions and tables of a compiled script, one per line. Spans are
+/// left out, moving code around without changing it keeps the checkpoint.
+fn encode_chunk(chunk: &Chunk) -> String {
+    let mut lines: Vec<String> = chunk.code.iter().map(encode_op).collect();
+
+    for constant in chunk.constants.iter() {
+        // Tasks are only ever made while running
+        lines.push(format!("const {}", encode(constant).unwrap_or_default()));
+    }
+    for name in chunk.names.iter() {
+        lines.push(format!("name {name}"));
+    }
+    for type_ in chunk.types.iter() {
+        lines.push(format!("type {}", encode_type(type_)));
+    }
+    for pattern in chunk.patterns.iter() {
+        lines.push(match pattern {
+            PatternKind::Wildcard => String::from("pattern _"),
+            PatternKind::Lit(lit) => format!("pattern {}", encode(lit).unwrap_or_default()),
+            PatternKind::Range {
+                start,
+                end,
+                inclusive,
+            } => format!(
+                "pattern {} {} {}",
+                encode(start).unwrap_or_default(),
+                encode(end).unwrap_or_default(),
+                *inclusive as u8
+            ),
+        });
+    }
+    for stamp in chunk.stamps.iter() {
+        let date = match stamp.date {
+            Some((year, month, day)) => format!("{year}-{month}-{day}"),
+            None => String::from("-"),
+        };
+        lines.push(format!("stamp {date} {} {}", stamp.time, stamp.utc as u8));
+    }
+    for function in chunk.functions.iter() {
+        let args: Vec<_> = function.args.iter().map(encode_type).collect();
+        lines.push(format!(
+            "function {} {} {} {} {}",
+            function.name,
+            function.entry,
+            function.captures,
+            encode_type(&function.type_),
+            args.join(" ")
+        ));
+    }
+    for struct_ in chunk.structs.iter() {
+        let fields: Vec<_> = struct_
+            .fields
+            .iter()
+            .map(|(ident, type_)| format!("{}={}", ident.name, encode_type(type_)))
+            .collect();
+        lines.push(format!("struct {} {}", struct_.id.name, fields.join(" ")));
+    }
+    lines.push(format!("globals {}", chunk.globals));
+
+    lines.join("\n")
+}
+
+/// An instruction as a line of [`encode_chunk`]
+fn encode_op(op: &Op) -> String {
+    match *op {
+        Op::Const(index) => format!("const {index}"),
+        Op::Timestamp(index) => format!("timestamp {index}"),
+        Op::GetLocal(slot) => format!("get_local {slot}"),
+        Op::GetGlobal(slot) => format!("get_global {slot}"),
+        Op::SetLocal(slot) => format!("set_local {slot}"),
+        Op::SetGlobal(slot) => format!("set_global {slot}"),
+        Op::CheckType(index) => format!("check_type {index}"),
+        Op::DefaultStep => String::from("default_step"),
+        Op::CheckRange => String::from("check_range"),
+        Op::Pop => String::from("pop"),
+        Op::PopN(count) => format!("pop_n {count}"),
+        Op::PopUnder(count) => format!("pop_under {count}"),
+        Op::Binary(op) => format!("binary {}", op as u8),
+        Op::Unary(op) => format!("unary {}", op as u8),
+        Op::Jump(target) => format!("jump {target}"),
+        Op::JumpIfFalse(target) => format!("jump_if_false {target}"),
+        Op::Call(index) => format!("call {index}"),
+        Op::CallValue(args) => format!("call_value {args}"),
+        Op::MakeFunction(index) => format!("make_function {index}"),
+        Op::Spawn(index) => format!("spawn {index}"),
+        Op::CallBuiltin(builtin, args) => format!("call_builtin {} {args}", builtin as u8),
+        Op::CallMethod(name, args) => format!("call_method {name} {args}"),
+        Op::GetField(name) => format!("get_field {name}"),
+        Op::SetField(name) => format!("set_field {name}"),
+        Op::MakeStruct(index) => format!("make_struct {index}"),
+        Op::MakeList(type_, count) => format!("make_list {type_} {count}"),
+        Op::Index => String::from("index"),
+        Op::Match(index) => format!("match {index}"),
+        Op::Unmatched => String::from("unmatched"),
+        Op::Every(times, duration) => format!("every {} {}", times as u8, duration as u8),
+        Op::Tick(slot, end) => format!("tick {slot} {end}"),
+        Op::StartRetry => String::from("start_retry"),
+        Op::Attempt(slot, backoff) => format!("attempt {slot} {}", backoff as u8),
+        Op::EndAttempt(slot, restart) => format!("end_attempt {slot} {restart}"),
+        Op::OnInterrupt(index) => format!("on_interrupt {index}"),
+        Op::Within(fallback) => format!("within {fallback}"),
+        Op::EndWithin => String::from("end_within"),
+        Op::Dup => String::from("dup"),
+        Op::Return => String::from("return"),
+        Op::MissingReturn => String::from("missing_return"),
+    }
+}
+
+impl Vm {
+    /// Keeps the state before the instruction at `ip` for the checkpoint its
+    /// wait writes, if the script writes any
+    pub(super) fn checkpoint(&self, ip: usize, base: usize, span: Span) {
+        let Some(checkpoint) = &self.runtime.checkpoint else {
+            return;
+        };
+        // The state of tasks is on the stacks of their coroutines
+        if self.runtime.task != 0 {
+            return;
+        }
+
+        let state = match self.runtime.tasks.alone() {
+            true => self.save(ip, base),
+            false => Err(String::from("Tasks started with `spawn` are running")),
+        };
+
+        match state {
+            Ok(state) => checkpoint.set_pending(Some(state)),
+            Err(reason) => {
+                checkpoint.set_pending(None);
+                if !checkpoint.warned.replace(true) {
+                    warn_at(
+                        span,
+                        &format!(
+                            "{reason}, so the checkpoint stays at the last wait that could be saved"
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    fn save(&self, ip: usize, base: usize) -> Result<String, String> {
+        let mut lines = vec![
+            format!("ip {ip} {base}"),
+            format!("stack {}", encode_all(&self.stack)?),
+            format!("globals {}", encode_all(&self.globals.borrow())?),
+        ];
+
+        for frame in self.frames.iter() {
+            lines.push(format!("frame {} {}", frame.return_ip, frame.base));
+        }
+        for (within, deadline) in self
+            .withins
+            .iter()
+            .zip(self.runtime.deadlines.borrow().iter())
+        {
+            lines.push(format!(
+                "within {} {} {} {} {} {}",
+                within.stack,
+                within.frames,
+                within.base,
+                within.retries,
+                within.fallback,
+                deadline.as_millis()
+            ));
+        }
+        for retry in self.retries.iter() {
+            lines.push(format!(
+                "retry {} {} {} {} {}",
+                retry.stack, retry.frames, retry.base, retry.withins, retry.restart
+            ));
+        }
+        if let Some((entry, captures)) = &*self.on_interrupt.borrow() {
+            lines.push(format!("interrupt {entry} {}", encode_all(captures)?));
+        }
+
+        lines.extend(self.runtime.heap.borrow().save()?);
+
+        Ok(lines.join("\n"))
+    }
+
+    /// Continues the script at the wait its checkpoint was written at
+    pub fn resume(&mut self, ast: &Ast, saved: &Saved) -> Result<(), ()> {
+        let chunk = Rc::new(Compiler::new().compile(ast)?);
+        if fingerprint(&chunk) != saved.code {
+            error_message(
+                "The script changed since the checkpoint was written, so it can't be resumed",
+            );
+            return Err(());
+        }
+
+        let Some((ip, base)) = self.restore(saved) else {
+            error_message(
+                "The checkpoint is broken, some of the state of the script can't be read",
+            );
+            return Err(());
+        };
+
+        self.run_from(&chunk, ip, base)
+    }
+
+    /// Takes the state saved by [`Vm::save`], and gives where to continue
+    fn restore(&mut self, saved: &Saved) -> Option<(usize, usize)> {
+        let number = |word: &str| word.parse::<usize>().ok();
+        let line = |key| saved.get(key).next();
+
+        let [ip, base] = line("ip")?[..] else {
+            return None;
+        };
+        self.stack = decode_all(&line("stack")?)?;
+        *self.globals.borrow_mut() = decode_all(&line("globals")?)?;
+
+        self.frames = saved
+            .get("frame")
+            .map(|words| match words[..] {
+                [return_ip, base] => Some(Frame {
+                    return_ip: number(return_ip)?,
+                    base: number(base)?,
+                }),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+
+        let mut deadlines = Vec::new();
+        self.withins = saved
+            .get("within")
+            .map(|words| {
+                let [stack, frames, base, retries, fallback, deadline] = words[..] else {
+                    return None;
+                };
+                deadlines.push(Duration::from_millis(deadline.parse().ok()?));
+
+                Some(Within {
+                    stack: number(stack)?,
+                    frames: number(frames)?,
+                    base: number(base)?,
+                    retries: number(retries)?,
+                    fallback: number(fallback)?,
+                })
+            })
+            .collect::<Option<_>>()?;
+        *self.runtime.deadlines.borrow_mut() = deadlines;
+
+        self.retries = saved
+            .get("retry")
+            .map(|words| {
+                let [stack, frames, base, withins, restart] = words[..] else {
+                    return None;
+                };
+
+                Some(Retry {
+                    stack: number(stack)?,
+                    frames: number(frames)?,
+                    base: number(base)?,
+                    withins: number(withins)?,
+                    restart: number(restart)?,
+                })
+            })
+            .collect::<Option<_>>()?;
+
+        *self.on_interrupt.borrow_mut() = match line("interrupt") {
+            Some(words) => {
+                let (entry, captures) = words.split_first()?;
+                Some((number(entry)?, decode_all(captures)?))
+            }
+            None => None,
+        };
+        *self.runtime.heap.borrow_mut() = Heap::restore(saved)?;
+
+        Some((number(ip)?, number(base)?))
+    }
+}
@@ -0,0 +1,205 @@
+use crate::{
+    parser::ast::{
+        expr::{BinOp, UnOp},
+        lit::{Lit, Stamp},
+        pattern::PatternKind,
+        stmt::BackoffKind,
+        Span, StructDef, Type,
+    },
+    runtime::builtins::Builtin,
+};
+
+/// A single instruction of the VM
+///
+/// Operands are indices into the tables of the [`Chunk`] or slots of the
+/// current call, so an instruction is never larger than 8 bytes.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Op {
+    /// Pushes a constant
+    Const(u32),
+    /// Pushes the timestamp at this index of the stamps table as the clock
+    /// shows it now
+    Timestamp(u32),
+    /// Pushes a local of the current call
+    GetLocal(u16),
+    /// Pushes a variable declared at the top level of the script
+    GetGlobal(u16),
+    /// Pops the top value into a local of the current call
+    SetLocal(u16),
+    /// Pops the top value into a variable declared at the top level of the script
+    SetGlobal(u16),
+    /// Fails unless the top value has the type at this index of the types
+    /// table, used before storing it
+    CheckType(u32),
+    /// Pushes the step of a range that doesn't give one, its start and end are on the stack
+    DefaultStep,
+    /// Fails unless the start, end and step on the stack make a valid range
+    CheckRange,
+    /// Pops the top value
+    Pop,
+    /// Pops the given number of values, used for locals going out of scope
+    PopN(u16),
+    /// Pops the given number of values under the top one, used for the
+    /// locals of a block used as a value
+    PopUnder(u16),
+    /// Pops two values and pushes the result
+    Binary(BinOp),
+    /// Pops a value and pushes the result
+    Unary(UnOp),
+    /// Continues at the given instruction
+    Jump(u32),
+    /// Pops a boolean and continues at the given instruction if it's false
+    JumpIfFalse(u32),
+    /// Calls a function defined in the script, its arguments are on the stack
+    Call(u32),
+    /// Calls the function value under the given number of arguments
+    CallValue(u8),
+    /// Pops the values the function at this index captures and pushes the
+    /// function as a value
+    MakeFunction(u32),
+    /// Pops the values the task body at this index captures, starts running
+    /// it as a task and pushes the task
+    Spawn(u32),
+    /// Calls a builtin with the given number of arguments on the stack
+    CallBuiltin(Builtin, u8),
+    /// Calls the method named at this index of the names table on the value
+    /// under the given number of arguments
+    CallMethod(u32, u8),
+    /// Replaces the top value with its field named at this index of the names table
+    GetField(u32),
+    /// Pops a value into the field named at this index of the names table of
+    /// the struct under it, which is popped too
+    SetField(u32),
+    /// Pops the fields of the struct declared at this index and pushes the struct
+    MakeStruct(u32),
+    /// Pops the given number of elements into a list of the type at this
+    /// index of the types table
+    MakeList(u16, u32),
+    /// Pops an index and the list under it and pushes the element there
+    Index,
+    /// Replaces the top value with whether it matches the pattern at this
+    /// index of the patterns table
+    Match(u32),
+    /// Reached the end of a `match` without an arm matching the top value
+    Unmatched,
+    /// Pops the interval or cron expression of an `every` loop, and the
+    /// number of times and the time it runs for if they're given, and pushes
+    /// the state of its schedule
+    Every(bool, bool),
+    /// Waits for the next run of the schedule in the locals starting at the
+    /// given one, or continues at the given instruction once it's over
+    Tick(u16, u32),
+    /// Pops the attempts, the first and the longest pause and the jitter of
+    /// a `retry` block and pushes the state of its attempts
+    StartRetry,
+    /// Starts the next attempt of the `retry` block whose state is in the
+    /// locals starting at the given one, pausing first if one failed already
+    Attempt(u16, BackoffKind),
+    /// Pops the value of an attempt, which failed if it's `false`, and
+    /// continues at the given instruction to try again then
+    EndAttempt(u16, u32),
+    /// Pops the captured values of an `on_interrupt` block and registers it
+    /// with them
+    OnInterrupt(u32),
+    /// Pops a time and starts a `within` block that runs out of time after
+    /// it, whose fallback starts at the given instruction
+    Within(u32),
+    /// Ends the innermost `within` block
+    EndWithin,
+    /// Pushes a copy of the top value
+    Dup,
+    /// Leaves the current call with the top value
+    Return,
+    /// Reached the end of a function without a `return`
+    MissingReturn,
+}
+
+/// A function or closure defined in the script
+#[derive(Debug, PartialEq, Clone)]
+pub struct FunctionProto {
+    /// Empty for a closure
+    pub name: &'static str,
+    pub args: Box<[Type]>,
+    /// Index of the first instruction of the body
+    pub entry: u32,
+    /// How many values a closure captures, they follow the arguments
+    pub captures: u16,
+    /// The [`Type::Function`] of the function used as a value, [`Type::Task`]
+    /// for the body of a task
+    pub type_: Type,
+}
+
+/// A compiled script
+#[derive(Debug, PartialEq, Clone)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    /// Where in the source each instruction comes from, used for errors
+    pub spans: Vec<Span>,
+    pub constants: Vec<Lit>,
+    /// Names of fields and methods
+    pub names: Vec<&'static str>,
+    /// Types values are checked against
+    pub types: Vec<Type>,
+    /// Patterns of the arms of `match`es
+    pub patterns: Vec<PatternKind>,
+    /// Timestamps written in the script, which depend on the clock
+    pub stamps: Vec<Stamp>,
+    pub functions: Vec<FunctionProto>,
+    /// The structs declared in the script
+    pub structs: Vec<StructDef>,
+    /// Number of variables declared at the top level of the script
+    pub globals: u16,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self {
+            code: Vec::new(),
+            spans: Vec::new(),
+            constants: Vec::new(),
+            names: Vec::new(),
+            types: Vec::new(),
+            patterns: Vec::new(),
+            stamps: Vec::new(),
+            functions: Vec::new(),
+            structs: Vec::new(),
+            globals: 0,
+        }
+    }
+
+    /// Appends an instruction and returns its index
+    pub fn push(&mut self, op: Op, span: Span) -> u32 {
+        self.code.push(op);
+        self.spans.push(span);
+
+        (self.code.len() - 1) as u32
+    }
+
+    /// The index of a name in the names table, adding it if it's not there yet
+    pub fn name(&mut self, name: &'static str) -> u32 {
+        let index = self
+            .names
+            .iter()
+            .position(|other| *other == name)
+            .unwrap_or_else(|| {
+                self.names.push(name);
+                self.names.len() - 1
+            });
+
+        index as u32
+    }
+
+    /// The index of a type in the types table, adding it if it's not there yet
+    pub fn type_(&mut self, type_: Type) -> u32 {
+        let index = self
+            .types
+            .iter()
+            .position(|other| *other == type_)
+            .unwrap_or_else(|| {
+                self.types.push(type_);
+                self.types.len() - 1
+            });
+
+        index as u32
+    }
+}
@@ -0,0 +1,998 @@
+use super::chunk::{Chunk, FunctionProto, Op};
+use crate::{
+    error_handling::error_at,
+    parser::ast::{
+        block::Block,
+        expr::{BinOp, Expr, ExprKind, Ident},
+        lit::Lit,
+        pattern::Arm,
+        stmt::{BackoffKind, Else, Stmt, StmtKind},
+        Ast, Span, Type,
+    },
+    runtime::{builtins::Builtin, retry, schedule},
+};
+
+/// Compiles the syntax tree into bytecode, resolving every variable to a slot
+pub struct Compiler {
+    chunk: Chunk,
+    /// Slots of the variables declared at the top level of the script
+    globals: Vec<(&'static str, Type)>,
+    /// Slots of the locals of the call being compiled, innermost scope last
+    locals: Vec<Local>,
+    /// Functions that can be called from the current scope
+    functions: Vec<(&'static str, usize, u32)>,
+    /// Loops around the code being compiled, innermost last
+    loops: Vec<Loop>,
+    /// Values of the expression being compiled that are on the stack above the locals
+    temps: usize,
+    scope_depth: usize,
+    in_function: bool,
+    /// How many `within` blocks of the current call are around the code being compiled
+    withins: usize,
+}
+
+struct Loop {
+    label: Option<&'static str>,
+    /// Jumps of the `continue`s, patched once the next iteration's start is known
+    continues: Vec<u32>,
+    /// How many locals there were before the loop, the rest is popped when leaving it early
+    locals: usize,
+    /// How many `within` blocks there were around the loop, the rest ends when leaving it early
+    withins: usize,
+    /// Jumps of the `break`s, patched once the end of the loop is known
+    breaks: Vec<u32>,
+}
+
+struct Local {
+    name: &'static str,
+    depth: usize,
+    type_: Type,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self {
+            chunk: Chunk::new(),
+            globals: Vec::new(),
+            locals: Vec::new(),
+            functions: Vec::new(),
+            loops: Vec::new(),
+            temps: 0,
+            scope_depth: 0,
+            in_function: false,
+            withins: 0,
+        }
+    }
+
+    pub fn compile(mut self, ast: &Ast) -> Result<Chunk, ()> {
+        self.chunk.structs = ast.structs.clone();
+
+        for stmt in ast.program.iter() {
+            self.stmt(stmt)?;
+        }
+
+        Ok(self.chunk)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), ()> {
+        match &stmt.stmt_kind {
+            StmtKind::VarBind {
+                type_,
+                identifier,
+                value,
+                ..
+            } => {
+                self.expr(value)?;
+                let checked = self.chunk.type_(*type_);
+                self.chunk.push(Op::CheckType(checked), value.span);
+
+                if self.in_function || self.scope_depth > 0 {
+                    // The value stays on the stack and becomes the local
+                    self.locals.push(Local {
+                        name: identifier.name,
+                        depth: self.scope_depth,
+                        type_: *type_,
+                    });
+                } else {
+                    self.globals.push((identifier.name, *type_));
+                    self.chunk.globals =
+                        operand(self.globals.len(), "global variables", stmt.span)?;
+                    self.chunk
+                        .push(Op::SetGlobal(self.chunk.globals - 1), stmt.span);
+                }
+            }
+            StmtKind::Assign { target, op, value } => {
+                let (get, set, type_) = self.variable(target)?;
+
+                if let Some(op) = op {
+                    self.chunk.push(get, target.span);
+                    self.expr_over(value, 1)?;
+                    self.chunk.push(Op::Binary(*op), stmt.span);
+                } else {
+                    self.expr(value)?;
+                }
+
+                let checked = self.chunk.type_(type_);
+                self.chunk.push(Op::CheckType(checked), value.span);
+                self.chunk.push(set, stmt.span);
+            }
+            StmtKind::FieldAssign {
+                receiver,
+                field,
+                op,
+                value,
+            } => {
+                self.expr(receiver)?;
+                let name = self.chunk.name(field.name);
+
+                if let Some(op) = op {
+                    self.chunk.push(Op::Dup, receiver.span);
+                    self.chunk.push(Op::GetField(name), field.span);
+                    self.expr_over(value, 2)?;
+                    self.chunk.push(Op::Binary(*op), stmt.span);
+                } else {
+                    self.expr_over(value, 1)?;
+                }
+
+                self.chunk.push(Op::SetField(name), stmt.span);
+            }
+            StmtKind::FnDef {
+                ident,
+                args,
+                body,
+                return_type,
+            } => {
+                let type_ =
+                    Type::function(args.iter().map(|(_, type_)| *type_).collect(), *return_type);
+                self.function(Some(ident.name), args, &[], body, type_)?;
+            }
+            StmtKind::Expr(expr) => {
+                self.expr(expr)?;
+                self.chunk.push(Op::Pop, stmt.span);
+            }
+            StmtKind::If(condition, true_block, else_) => {
+                self.expr(condition)?;
+                let to_else = self.chunk.push(Op::JumpIfFalse(0), condition.span);
+
+                self.block(true_block)?;
+
+                let Some(else_) = else_ else {
+                    self.patch_jump(to_else);
+                    return Ok(());
+                };
+
+                let to_end = self.chunk.push(Op::Jump(0), stmt.span);
+                self.patch_jump(to_else);
+
+                match else_ {
+                    Else::Block(block) => self.block(block)?,
+                    Else::If(stmt) => self.stmt(stmt)?,
+                }
+                self.patch_jump(to_end);
+            }
+            StmtKind::Match(value, arms) => {
+                // The value lives in a local while the arms are checked
+                self.expr(value)?;
+
+                self.scope_depth += 1;
+                let slot = self.first_slot(1, stmt.span)?;
+                self.locals.push(Local {
+                    name: "",
+                    depth: self.scope_depth,
+                    type_: value.type_,
+                });
+
+                let result = self.arms(arms, Some(slot), stmt.span, |compiler, block| {
+                    compiler.block(block)
+                });
+                self.scope_depth -= 1;
+                self.locals.truncate(slot as usize);
+                result?;
+
+                self.chunk.push(Op::Pop, stmt.span);
+            }
+            StmtKind::While(condition, block, label) => {
+                let start = self.chunk.code.len() as u32;
+
+                self.expr(condition)?;
+                let to_end = self.chunk.push(Op::JumpIfFalse(0), condition.span);
+
+                let loop_ = self.loop_body(label, block)?;
+                for jump in loop_.continues {
+                    self.patch_jump_to(jump, start);
+                }
+
+                self.chunk.push(Op::Jump(start), stmt.span);
+                self.patch_jump(to_end);
+
+                for jump in loop_.breaks {
+                    self.patch_jump(jump);
+                }
+            }
+            StmtKind::Retry(options, block) => {
+                // The state of the attempts lives in locals, the first of them is `attempt`
+                let defaults = [
+                    Lit::from(3),
+                    Lit::from(0),
+                    Lit::from(f32::INFINITY),
+                    Lit::from(0),
+                ];
+                let backoff = options.backoff.as_ref();
+                let given = [
+                    options.attempts.as_ref(),
+                    backoff.map(|backoff| &backoff.delay),
+                    backoff.and_then(|backoff| backoff.max.as_ref()),
+                    options.jitter.as_ref(),
+                ];
+
+                for (count, (expr, default)) in given.into_iter().zip(defaults).enumerate() {
+                    match expr {
+                        Some(expr) => self.expr_over(expr, count)?,
+                        None => {
+                            let index = self.constant(default);
+                            self.chunk.push(Op::Const(index), stmt.span);
+                        }
+                    }
+                }
+                self.chunk.push(Op::StartRetry, stmt.span);
+
+                self.scope_depth += 1;
+                let slot = self.first_slot(retry::SLOTS, stmt.span)?;
+                for index in 0..retry::SLOTS {
+                    self.locals.push(Local {
+                        name: if index == 0 { "attempt" } else { "" },
+                        depth: self.scope_depth,
+                        type_: Type::Number,
+                    });
+                }
+
+                let kind = backoff.map_or(BackoffKind::Constant, |backoff| backoff.kind);
+                let start = self.chunk.push(Op::Attempt(slot, kind), stmt.span);
+
+                let result = match block.value {
+                    Some(_) => self.block_value(block),
+                    None => self.block(block).map(|_| {
+                        let index = self.constant(Lit::from(true));
+                        self.chunk.push(Op::Const(index), block.span);
+                    }),
+                };
+                self.scope_depth -= 1;
+                result?;
+
+                self.chunk.push(Op::EndAttempt(slot, start), stmt.span);
+
+                self.locals.truncate(slot as usize);
+                self.chunk.push(Op::PopN(retry::SLOTS as u16), stmt.span);
+            }
+            StmtKind::OnInterrupt(handler) => {
+                for ident in handler.captures.iter() {
+                    let (get, ..) = self.variable(ident)?;
+                    self.chunk.push(get, ident.span);
+                }
+
+                // Compiled like the body of a task, which doesn't end with a value
+                let index =
+                    self.function(None, &[], &handler.captures, &handler.body, Type::Task)?;
+                self.chunk.push(Op::OnInterrupt(index), stmt.span);
+            }
+            StmtKind::Within(time, block, fallback) => {
+                self.expr(time)?;
+                let within = self.chunk.push(Op::Within(0), stmt.span);
+
+                self.withins += 1;
+                let result = self.block(block);
+                self.withins -= 1;
+                result?;
+
+                self.chunk.push(Op::EndWithin, block.span);
+                let to_end = self.chunk.push(Op::Jump(0), stmt.span);
+
+                // A wait cut short continues here, with the block already ended
+                self.patch_jump(within);
+                if let Some(fallback) = fallback {
+                    self.block(fallback)?;
+                }
+                self.patch_jump(to_end);
+            }
+            StmtKind::For {
+                var,
+                range,
+                block,
+                label,
+            } => {
+                // The loop variable, the end and the step live in three locals
+                self.expr(&range.start)?;
+                self.expr_over(&range.end, 1)?;
+                match &range.step {
+                    Some(step) => self.expr_over(step, 2)?,
+                    None => {
+                        self.chunk.push(Op::DefaultStep, range.end.span);
+                    }
+                }
+                self.chunk.push(Op::CheckRange, stmt.span);
+
+                self.scope_depth += 1;
+                let slot = self.first_slot(3, stmt.span)?;
+                for name in [var.name, "", ""] {
+                    self.locals.push(Local {
+                        name,
+                        depth: self.scope_depth,
+                        type_: range.type_,
+                    });
+                }
+
+                let comparison = match range.inclusive {
+                    true => BinOp::Le,
+                    false => BinOp::Lt,
+                };
+
+                let start = self.chunk.push(Op::GetLocal(slot), stmt.span);
+                self.chunk.push(Op::GetLocal(slot + 1), range.end.span);
+                self.chunk.push(Op::Binary(comparison), stmt.span);
+                let to_end = self.chunk.push(Op::JumpIfFalse(0), stmt.span);
+
+                let loop_ = self.loop_body(label, block);
+                self.scope_depth -= 1;
+                let loop_ = loop_?;
+
+                for jump in loop_.continues {
+                    self.patch_jump(jump);
+                }
+
+                self.chunk.push(Op::GetLocal(slot), stmt.span);
+                self.chunk.push(Op::GetLocal(slot + 2), stmt.span);
+                self.chunk.push(Op::Binary(BinOp::Add), stmt.span);
+                self.chunk.push(Op::SetLocal(slot), stmt.span);
+                self.chunk.push(Op::Jump(start), stmt.span);
+                self.patch_jump(to_end);
+
+                for jump in loop_.breaks {
+                    self.patch_jump(jump);
+                }
+
+                self.locals.truncate(slot as usize);
+                self.chunk.push(Op::PopN(3), stmt.span);
+            }
+            StmtKind::Every {
+                schedule,
+                times,
+                duration,
+                block,
+                label,
+            } => {
+                // The state of the schedule lives in hidden locals
+                self.expr(schedule)?;
+                for (given, expr) in [times, duration].into_iter().flatten().enumerate() {
+                    self.expr_over(expr, given + 1)?;
+                }
+                self.chunk
+                    .push(Op::Every(times.is_some(), duration.is_some()), stmt.span);
+
+                self.scope_depth += 1;
+                let slot = self.first_slot(schedule::SLOTS, stmt.span)?;
+                for _ in 0..schedule::SLOTS {
+                    self.locals.push(Local {
+                        name: "",
+                        depth: self.scope_depth,
+                        type_: Type::Unit,
+                    });
+                }
+
+                let start = self.chunk.push(Op::Tick(slot, 0), stmt.span);
+
+                let loop_ = self.loop_body(label, block);
+                self.scope_depth -= 1;
+                let loop_ = loop_?;
+
+                for jump in loop_.continues {
+                    self.patch_jump_to(jump, start);
+                }
+
+                self.chunk.push(Op::Jump(start), stmt.span);
+                self.patch_jump(start);
+
+                for jump in loop_.breaks {
+                    self.patch_jump(jump);
+                }
+
+                self.locals.truncate(slot as usize);
+                self.chunk.push(Op::PopN(schedule::SLOTS as u16), stmt.span);
+            }
+            StmtKind::ForEach {
+                var,
+                list,
+                block,
+                label,
+            } => {
+                // The list, the index of the next element and the loop variable live in three locals
+                self.expr(list)?;
+                let zero = self.constant(Lit::from(0));
+                self.chunk.push(Op::Const(zero), stmt.span);
+                self.chunk.push(Op::Const(zero), stmt.span);
+
+                let elem = match list.type_ {
+                    Type::List(elem) => *elem,
+                    _ => Type::Unit,
+                };
+
+                self.scope_depth += 1;
+                let slot = self.first_slot(3, stmt.span)?;
+                for (name, type_) in [("", list.type_), ("", Type::Number), (var.name, elem)] {
+                    self.locals.push(Local {
+                        name,
+                        depth: self.scope_depth,
+                        type_,
+                    });
+                }
+
+                // The length is read every time, elements added while looping are visited too
+                let len = self.chunk.name("len");
+                let start = self.chunk.push(Op::GetLocal(slot + 1), stmt.span);
+                self.chunk.push(Op::GetLocal(slot), list.span);
+                self.chunk.push(Op::CallMethod(len, 0), list.span);
+                self.chunk.push(Op::Binary(BinOp::Lt), stmt.span);
+                let to_end = self.chunk.push(Op::JumpIfFalse(0), stmt.span);
+
+                self.chunk.push(Op::GetLocal(slot), stmt.span);
+                self.chunk.push(Op::GetLocal(slot + 1), stmt.span);
+                self.chunk.push(Op::Index, stmt.span);
+                self.chunk.push(Op::SetLocal(slot + 2), stmt.span);
+
+                let loop_ = self.loop_body(label, block);
+                self.scope_depth -= 1;
+                let loop_ = loop_?;
+
+                for jump in loop_.continues {
+                    self.patch_jump(jump);
+                }
+
+                let one = self.constant(Lit::from(1));
+                self.chunk.push(Op::GetLocal(slot + 1), stmt.span);
+                self.chunk.push(Op::Const(one), stmt.span);
+                self.chunk.push(Op::Binary(BinOp::Add), stmt.span);
+                self.chunk.push(Op::SetLocal(slot + 1), stmt.span);
+                self.chunk.push(Op::Jump(start), stmt.span);
+                self.patch_jump(to_end);
+
+                for jump in loop_.breaks {
+                    self.patch_jump(jump);
+                }
+
+                self.locals.truncate(slot as usize);
+                self.chunk.push(Op::PopN(3), stmt.span);
+            }
+            StmtKind::Break(label) => {
+                let index = self.loop_index(label, stmt.span)?;
+                self.pop_loop_locals(index, stmt.span)?;
+
+                let jump = self.chunk.push(Op::Jump(0), stmt.span);
+                self.loops[index].breaks.push(jump);
+            }
+            StmtKind::Continue(label) => {
+                let index = self.loop_index(label, stmt.span)?;
+                self.pop_loop_locals(index, stmt.span)?;
+
+                let jump = self.chunk.push(Op::Jump(0), stmt.span);
+                self.loops[index].continues.push(jump);
+            }
+            StmtKind::Return(expr) => {
+                if !self.in_function {
+                    error_at(stmt.span, "`return` outside of a function");
+                    return Err(());
+                }
+
+                self.expr(expr)?;
+                for _ in 0..self.withins {
+                    self.chunk.push(Op::EndWithin, stmt.span);
+                }
+                self.chunk.push(Op::Return, stmt.span);
+            }
+            StmtKind::Module(module) => {
+                for stmt in module.program.iter() {
+                    self.stmt(stmt)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn block(&mut self, block: &Block) -> Result<(), ()> {
+        self.scope_depth += 1;
+
+        for stmt in block.stmts.iter() {
+            self.stmt(stmt)?;
+        }
+
+        // The value of a block that isn't used as one is thrown away
+        if let Some(value) = &block.value {
+            self.expr(value)?;
+            self.chunk.push(Op::Pop, value.span);
+        }
+
+        self.scope_depth -= 1;
+
+        let locals = self.locals.len();
+        self.locals.retain(|local| local.depth <= self.scope_depth);
+        self.functions
+            .retain(|(_, depth, _)| *depth <= self.scope_depth);
+
+        let out_of_scope = locals - self.locals.len();
+        if out_of_scope > 0 {
+            let count = operand(out_of_scope, "local variables", block.span)?;
+            self.chunk.push(Op::PopN(count), block.span);
+        }
+
+        Ok(())
+    }
+
+    /// Compiles a block used as a value, which leaves just the value on the stack
+    fn block_value(&mut self, block: &Block) -> Result<(), ()> {
+        // Values of the expression around the block sit between the locals
+        // before it and its own, so they take up slots too
+        let temps = std::mem::take(&mut self.temps);
+        let first = self.locals.len();
+        for _ in 0..temps {
+            self.locals.push(Local {
+                name: "",
+                depth: self.scope_depth,
+                type_: Type::Unit,
+            });
+        }
+
+        self.scope_depth += 1;
+        let result = self.block_value_body(block);
+        self.scope_depth -= 1;
+
+        let own = self.locals.len() - first - temps;
+        self.locals.truncate(first);
+        self.functions
+            .retain(|(_, depth, _)| *depth <= self.scope_depth);
+        self.temps = temps;
+        result?;
+
+        if own > 0 {
+            let count = operand(own, "local variables", block.span)?;
+            self.chunk.push(Op::PopUnder(count), block.span);
+        }
+
+        Ok(())
+    }
+
+    fn block_value_body(&mut self, block: &Block) -> Result<(), ()> {
+        for stmt in block.stmts.iter() {
+            self.stmt(stmt)?;
+        }
+
+        match &block.value {
+            Some(value) => self.expr(value),
+            None => {
+                error_at(block.span, "Expected a value at the end of the block");
+                Err(())
+            }
+        }
+    }
+
+    /// Compiles a function or closure where it's defined, behind a jump over
+    /// it, and gives its index
+    ///
+    /// A function with a name is registered before its body is compiled, so
+    /// it can call itself.
+    fn function(
+        &mut self,
+        name: Option<&'static str>,
+        args: &[(Ident, Type)],
+        captures: &[Ident],
+        body: &Block,
+        type_: Type,
+    ) -> Result<u32, ()> {
+        let captured = operand(captures.len(), "captured variables", body.span)?;
+        let skip = self.chunk.push(Op::Jump(0), body.span);
+
+        let index = self.chunk.functions.len() as u32;
+        self.chunk.functions.push(FunctionProto {
+            name: name.unwrap_or_default(),
+            args: args.iter().map(|(_, type_)| *type_).collect(),
+            entry: skip + 1,
+            captures: captured,
+            type_,
+        });
+        if let Some(name) = name {
+            self.functions.push((name, self.scope_depth, index));
+        }
+
+        // The arguments and captured values are the first locals of the call
+        let args = args.iter().map(|(arg, type_)| Local {
+            name: arg.name,
+            depth: 1,
+            type_: *type_,
+        });
+        let captures = captures.iter().map(|ident| Local {
+            name: ident.name,
+            depth: 1,
+            type_: Type::Unit,
+        });
+
+        let locals = std::mem::replace(&mut self.locals, args.chain(captures).collect());
+        let loops = std::mem::take(&mut self.loops);
+        let temps = std::mem::take(&mut self.temps);
+        let functions = self.functions.len();
+        let scope_depth = std::mem::replace(&mut self.scope_depth, 2);
+        let in_function = std::mem::replace(&mut self.in_function, true);
+        let withins = std::mem::take(&mut self.withins);
+
+        let result = self.function_body(body, type_ == Type::Task);
+
+        self.locals = locals;
+        self.loops = loops;
+        self.temps = temps;
+        self.functions.truncate(functions);
+        self.scope_depth = scope_depth;
+        self.in_function = in_function;
+        self.withins = withins;
+        result?;
+
+        self.patch_jump(skip);
+
+        Ok(index)
+    }
+
+    /// Returns the value the body ends with, if it doesn't return before
+    ///
+    /// The body of a task doesn't need to end with a value.
+    fn function_body(&mut self, body: &Block, is_task: bool) -> Result<(), ()> {
+        for stmt in body.stmts.iter() {
+            self.stmt(stmt)?;
+        }
+
+        match &body.value {
+            Some(value) => {
+                self.expr(value)?;
+                self.chunk.push(Op::Return, value.span);
+            }
+            None if is_task => {
+                let unit = self.constant(Lit::from(0));
+                self.chunk.push(Op::Const(unit), body.span);
+                self.chunk.push(Op::Return, body.span);
+            }
+            None => {
+                self.chunk.push(Op::MissingReturn, body.span);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compiles an expression while `count` more values are on top of the stack
+    /// Adds a value to the constants table and gives its index
+    fn constant(&mut self, lit: Lit) -> u32 {
+        self.chunk.constants.push(lit);
+
+        (self.chunk.constants.len() - 1) as u32
+    }
+
+    /// Checks the arms of a `match` in order and runs the first one that
+    /// matches, the value is in the local at `slot` or otherwise on top of the stack
+    fn arms<B>(
+        &mut self,
+        arms: &[Arm<B>],
+        slot: Option<u16>,
+        span: Span,
+        body: impl Fn(&mut Self, &B) -> Result<(), ()>,
+    ) -> Result<(), ()> {
+        let (get, temps) = match slot {
+            Some(slot) => (Op::GetLocal(slot), 0),
+            None => (Op::Dup, 1),
+        };
+        let mut to_end = Vec::with_capacity(arms.len());
+
+        for arm in arms {
+            self.chunk.patterns.push(arm.pattern.pattern_kind.clone());
+            let pattern = (self.chunk.patterns.len() - 1) as u32;
+
+            self.chunk.push(get, arm.pattern.span);
+            self.chunk.push(Op::Match(pattern), arm.pattern.span);
+            let mut to_next = vec![self.chunk.push(Op::JumpIfFalse(0), arm.pattern.span)];
+
+            if let Some(guard) = &arm.guard {
+                self.expr_over(guard, temps)?;
+                to_next.push(self.chunk.push(Op::JumpIfFalse(0), guard.span));
+            }
+
+            body(self, &arm.body)?;
+            to_end.push(self.chunk.push(Op::Jump(0), span));
+
+            for jump in to_next {
+                self.patch_jump(jump);
+            }
+        }
+
+        self.chunk.push(Op::Unmatched, span);
+
+        for jump in to_end {
+            self.patch_jump(jump);
+        }
+
+        Ok(())
+    }
+
+    fn expr_over(&mut self, expr: &Expr, count: usize) -> Result<(), ()> {
+        self.temps += count;
+        let result = self.expr(expr);
+        self.temps -= count;
+
+        result
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<(), ()> {
+        match &expr.expr_kind {
+            ExprKind::Binary(left, bin_op_kind, right) => {
+                self.expr(left)?;
+                self.expr_over(right, 1)?;
+                self.chunk.push(Op::Binary(*bin_op_kind), expr.span);
+            }
+            ExprKind::Unary(un_op_kind, target_expr) => {
+                self.expr(target_expr)?;
+                self.chunk.push(Op::Unary(*un_op_kind), expr.span);
+            }
+            ExprKind::FnCall(ident, args) => self.fn_call(ident, args, expr.span)?,
+            ExprKind::Call(callee, args) => {
+                self.expr(callee)?;
+                for (index, arg) in args.iter().enumerate() {
+                    self.expr_over(arg, index + 1)?;
+                }
+
+                let count = operand(args.len(), "arguments", expr.span)?;
+                self.chunk.push(Op::CallValue(count), expr.span);
+            }
+            ExprKind::Closure(closure) => {
+                // The captured values are copied where the closure is created
+                for ident in closure.captures.iter() {
+                    let (get, ..) = self.variable(ident)?;
+                    self.chunk.push(get, ident.span);
+                }
+
+                let index = self.function(
+                    None,
+                    &closure.args,
+                    &closure.captures,
+                    &closure.body,
+                    expr.type_,
+                )?;
+                self.chunk.push(Op::MakeFunction(index), expr.span);
+            }
+            ExprKind::Spawn(task) => {
+                for ident in task.captures.iter() {
+                    let (get, ..) = self.variable(ident)?;
+                    self.chunk.push(get, ident.span);
+                }
+
+                let index = self.function(None, &[], &task.captures, &task.body, Type::Task)?;
+                self.chunk.push(Op::Spawn(index), expr.span);
+            }
+            ExprKind::FnRef(ident) => {
+                let function = self
+                    .functions
+                    .iter()
+                    .rev()
+                    .find(|(name, _, _)| *name == ident.name);
+
+                let Some((_, _, index)) = function else {
+                    error_at(ident.span, &format!("Unknown function `{}`", ident.name));
+                    return Err(());
+                };
+
+                self.chunk.push(Op::MakeFunction(*index), expr.span);
+            }
+            ExprKind::MethodCall {
+                receiver,
+                ident,
+                args,
+            } => {
+                self.expr(receiver)?;
+                for (index, arg) in args.iter().enumerate() {
+                    self.expr_over(arg, index + 1)?;
+                }
+
+                let name = self.chunk.name(ident.name);
+                let count = operand(args.len(), "arguments", expr.span)?;
+                self.chunk.push(Op::CallMethod(name, count), expr.span);
+            }
+            ExprKind::FieldAcc(receiver, ident) => {
+                self.expr(receiver)?;
+                let name = self.chunk.name(ident.name);
+                self.chunk.push(Op::GetField(name), expr.span);
+            }
+            ExprKind::StructLit(id, fields) => {
+                for (index, (_, value)) in fields.iter().enumerate() {
+                    self.expr_over(value, index)?;
+                }
+
+                self.chunk.push(Op::MakeStruct(id.index as u32), expr.span);
+            }
+            ExprKind::List(elems) => {
+                for (index, elem) in elems.iter().enumerate() {
+                    self.expr_over(elem, index)?;
+                }
+
+                let type_ = self.chunk.type_(expr.type_) as usize;
+                let type_ = operand(type_, "types", expr.span)?;
+                let count = operand(elems.len(), "elements in a list", expr.span)?;
+                self.chunk.push(Op::MakeList(type_, count), expr.span);
+            }
+            ExprKind::Index(list, index) => {
+                self.expr(list)?;
+                self.expr_over(index, 1)?;
+                self.chunk.push(Op::Index, expr.span);
+            }
+            ExprKind::Match(value, arms) => {
+                // The value stays on the stack under the arms until one of them is done
+                self.expr(value)?;
+                self.arms(arms, None, expr.span, |compiler, body| {
+                    compiler.expr_over(body, 1)
+                })?;
+                self.chunk.push(Op::PopUnder(1), expr.span);
+            }
+            ExprKind::Lit(lit) => {
+                let index = self.constant(*lit);
+                self.chunk.push(Op::Const(index), expr.span);
+            }
+            ExprKind::Timestamp(stamp) => {
+                self.chunk.stamps.push(*stamp);
+                let index = (self.chunk.stamps.len() - 1) as u32;
+                self.chunk.push(Op::Timestamp(index), expr.span);
+            }
+            ExprKind::Ident(ident) => {
+                let (get, ..) = self.variable(ident)?;
+                self.chunk.push(get, expr.span);
+            }
+            ExprKind::Grouping(group) => self.expr(group)?,
+            ExprKind::Block(block) => self.block_value(block)?,
+            ExprKind::If(condition, true_block, else_expr) => {
+                self.expr(condition)?;
+                let to_else = self.chunk.push(Op::JumpIfFalse(0), condition.span);
+
+                self.block_value(true_block)?;
+                let to_end = self.chunk.push(Op::Jump(0), expr.span);
+                self.patch_jump(to_else);
+
+                self.expr(else_expr)?;
+                self.patch_jump(to_end);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The instructions reading and writing a variable and its declared type
+    fn variable(&self, ident: &Ident) -> Result<(Op, Op, Type), ()> {
+        if let Some(slot) = self.locals.iter().rposition(|l| l.name == ident.name) {
+            let slot_op = operand(slot, "local variables", ident.span)?;
+            return Ok((
+                Op::GetLocal(slot_op),
+                Op::SetLocal(slot_op),
+                self.locals[slot].type_,
+            ));
+        }
+
+        if let Some(slot) = self.globals.iter().rposition(|(g, _)| *g == ident.name) {
+            let slot_op = operand(slot, "global variables", ident.span)?;
+            return Ok((
+                Op::GetGlobal(slot_op),
+                Op::SetGlobal(slot_op),
+                self.globals[slot].1,
+            ));
+        }
+
+        error_at(ident.span, &format!("Unknown variable `{}`", ident.name));
+        Err(())
+    }
+
+    fn fn_call(&mut self, ident: &Ident, args: &[Expr], span: Span) -> Result<(), ()> {
+        for (index, arg) in args.iter().enumerate() {
+            self.expr_over(arg, index)?;
+        }
+
+        let function = self
+            .functions
+            .iter()
+            .rev()
+            .find(|(name, _, _)| *name == ident.name);
+
+        if let Some((_, _, index)) = function {
+            let expected = self.chunk.functions[*index as usize].args.len();
+
+            if expected != args.len() {
+                error_at(
+                    span,
+                    &format!(
+                        "`{}` takes {expected} arguments but {} were given",
+                        ident.name,
+                        args.len()
+                    ),
+                );
+                return Err(());
+            }
+
+            self.chunk.push(Op::Call(*index), span);
+        } else if let Some(builtin) = Builtin::from_name(ident.name) {
+            let count = operand(args.len(), "arguments", span)?;
+            self.chunk.push(Op::CallBuiltin(builtin, count), span);
+        } else {
+            error_at(ident.span, &format!("Unknown function `{}`", ident.name));
+            return Err(());
+        }
+
+        Ok(())
+    }
+
+    /// Compiles the body of a loop and returns the jumps leaving it
+    fn loop_body(&mut self, label: &Option<Ident>, block: &Block) -> Result<Loop, ()> {
+        self.loops.push(Loop {
+            label: label.as_ref().map(|label| label.name),
+            continues: Vec::new(),
+            locals: self.locals.len(),
+            withins: self.withins,
+            breaks: Vec::new(),
+        });
+
+        let result = self.block(block);
+        let loop_ = self.loops.pop().expect("pushed above");
+        result?;
+
+        Ok(loop_)
+    }
+
+    /// The loop a `break` or `continue` refers to
+    fn loop_index(&self, label: &Option<Ident>, span: Span) -> Result<usize, ()> {
+        let index = self.loops.iter().rposition(|loop_| match label {
+            Some(label) => loop_.label == Some(label.name),
+            None => true,
+        });
+
+        index.ok_or_else(|| error_at(span, "No loop to leave here"))
+    }
+
+    /// Pops the locals declared inside a loop and ends the `within` blocks
+    /// inside it before jumping out of its body
+    fn pop_loop_locals(&mut self, index: usize, span: Span) -> Result<(), ()> {
+        let count = self.locals.len() - self.loops[index].locals;
+
+        if count > 0 {
+            let count = operand(count, "local variables", span)?;
+            self.chunk.push(Op::PopN(count), span);
+        }
+
+        for _ in self.loops[index].withins..self.withins {
+            self.chunk.push(Op::EndWithin, span);
+        }
+
+        Ok(())
+    }
+
+    /// The slot of the first of `count` locals about to be declared, checking
+    /// the ones after it, which are read at offsets from it, fit as well
+    fn first_slot(&self, count: usize, span: Span) -> Result<u16, ()> {
+        operand::<u16>(self.locals.len() + count - 1, "local variables", span)?;
+
+        operand(self.locals.len(), "local variables", span)
+    }
+
+    /// Points a jump to the next instruction
+    fn patch_jump(&mut self, jump: u32) {
+        self.patch_jump_to(jump, self.chunk.code.len() as u32);
+    }
+
+    fn patch_jump_to(&mut self, jump: u32, target: u32) {
+        match &mut self.chunk.code[jump as usize] {
+            Op::Jump(to) | Op::JumpIfFalse(to) | Op::Within(to) | Op::Tick(_, to) => *to = target,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Fits a count or index into the operand of an instruction, reporting the
+/// script has more of them than the bytecode can hold otherwise
+fn operand<T: TryFrom<usize>>(value: usize, what: &str, span: Span) -> Result<T, ()> {
+    T::try_from(value).map_err(|_| error_at(span, &format!("Too many {what} for the bytecode")))
+}
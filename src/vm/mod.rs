@@ -0,0 +1,1412 @@
+//! An alternative backend that compiles the syntax tree to bytecode first.
+//!
+//! Variables are resolved to slots while compiling, so running a script
+//! neither clones the tree nor searches for variables by name.
+
+use self::{
+    chunk::{Chunk, Op},
+    compiler::Compiler,
+};
+use crate::{
+    error_handling::{error_at, warn_at},
+    parser::ast::{
+        lit::{Lit, LitKind},
+        Ast, Type,
+    },
+    runtime::{calendar, heap, methods, ops, retry, schedule, Runtime},
+};
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+#[cfg(test)]
+mod benches;
+mod checkpoint;
+pub mod chunk;
+pub mod compiler;
+
+pub struct Vm {
+    stack: Vec<Lit>,
+    frames: Vec<Frame>,
+    /// The `within` blocks running, outermost first
+    withins: Vec<Within>,
+    /// The attempts of `retry` blocks running, outermost first
+    retries: Vec<Retry>,
+    /// Shared with the tasks the script starts
+    globals: Rc<RefCell<Vec<Lit>>>,
+    /// The `on_interrupt` block registered last, shared with the tasks too
+    on_interrupt: Rc<RefCell<Option<Handler>>>,
+    runtime: Runtime,
+}
+
+/// Where an `on_interrupt` block starts, with the values it captured
+type Handler = (usize, Vec<Lit>);
+
+/// A call of a function defined in the script
+struct Frame {
+    return_ip: usize,
+    /// Index of the first argument on the stack
+    base: usize,
+}
+
+/// A `within` block running, with what to go back to when its deadline
+/// cuts a wait short
+struct Within {
+    stack: usize,
+    frames: usize,
+    base: usize,
+    retries: usize,
+    fallback: usize,
+}
+
+/// An attempt of a `retry` block running, with what to go back to when it
+/// fails with an error
+struct Retry {
+    stack: usize,
+    frames: usize,
+    base: usize,
+    withins: usize,
+    /// Where the next attempt starts
+    restart: usize,
+}
+
+impl Vm {
+    pub fn new(runtime: Runtime) -> Self {
+        Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            withins: Vec::new(),
+            retries: Vec::new(),
+            globals: Rc::new(RefCell::new(Vec::new())),
+            on_interrupt: Rc::new(RefCell::new(None)),
+            runtime,
+        }
+    }
+
+    pub fn run(&mut self, ast: &Ast) -> Result<(), ()> {
+        let chunk = Compiler::new().compile(ast)?;
+        self.run_chunk(&Rc::new(chunk))
+    }
+
+    pub fn run_chunk(&mut self, chunk: &Rc<Chunk>) -> Result<(), ()> {
+        *self.globals.borrow_mut() = vec![Lit::from(0); chunk.globals as usize];
+        if let Some(checkpoint) = &self.runtime.checkpoint {
+            checkpoint.code.set(checkpoint::fingerprint(chunk));
+        }
+
+        self.run_from(chunk, 0, 0)
+    }
+
+    /// Runs the script from an instruction, and its `on_interrupt` block once
+    /// it's interrupted
+    fn run_from(&mut self, chunk: &Rc<Chunk>, ip: usize, base: usize) -> Result<(), ()> {
+        let result = self.execute(chunk, ip, base);
+        match self.runtime.take_interrupt() {
+            true => self.run_on_interrupt(chunk),
+            false => result,
+        }
+    }
+
+    /// Runs the `on_interrupt` block the script registered, if it did
+    fn run_on_interrupt(&mut self, chunk: &Rc<Chunk>) -> Result<(), ()> {
+        let Some((entry, captures)) = self.on_interrupt.borrow_mut().take() else {
+            return Ok(());
+        };
+
+        // Whatever ran when the script was interrupted is left behind
+        for index in (0..self.withins.len()).rev() {
+            self.runtime.pop_deadline(index);
+        }
+        self.withins.clear();
+        self.retries.clear();
+        self.frames.clear();
+        self.stack = captures;
+
+        self.execute(chunk, entry, 0)
+    }
+
+    /// Runs the code from the given instruction until its end, or until the
+    /// outermost call returns
+    fn execute(&mut self, chunk: &Rc<Chunk>, mut ip: usize, mut base: usize) -> Result<(), ()> {
+        loop {
+            match self.run_ops(chunk, &mut ip, &mut base) {
+                // An error in a `retry` block fails the attempt instead of the script
+                Err(())
+                    if !self.runtime.is_interrupted()
+                        && !self.runtime.tasks.failed()
+                        && let Some(retry) = self.retries.pop() =>
+                {
+                    for index in (retry.withins..self.withins.len()).rev() {
+                        self.runtime.pop_deadline(index);
+                    }
+                    self.withins.truncate(retry.withins);
+
+                    self.stack.truncate(retry.stack);
+                    self.frames.truncate(retry.frames);
+                    base = retry.base;
+                    ip = retry.restart;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn run_ops(&mut self, chunk: &Rc<Chunk>, ip: &mut usize, base: &mut usize) -> Result<(), ()> {
+        while let Some(op) = chunk.code.get(*ip) {
+            *ip += 1;
+
+            // Reports an error at the instruction that is running
+            let span = chunk.spans[*ip - 1];
+            let fail = |message: &str| error_at(span, message);
+
+            match *op {
+                Op::Const(index) => self.stack.push(chunk.constants[index as usize]),
+                Op::Timestamp(index) => {
+                    let at = calendar::resolve(&chunk.stamps[index as usize], &*self.runtime.clock);
+                    self.stack.push(Lit::new(LitKind::Timestamp(at)));
+                }
+                Op::GetLocal(slot) => self.stack.push(self.stack[*base + slot as usize]),
+                Op::GetGlobal(slot) => self.stack.push(self.globals.borrow()[slot as usize]),
+                Op::SetLocal(slot) => self.stack[*base + slot as usize] = self.pop(),
+                Op::SetGlobal(slot) => {
+                    let value = self.pop();
+                    self.globals.borrow_mut()[slot as usize] = value;
+                }
+                Op::CheckType(type_) => {
+                    let type_ = chunk.types[type_ as usize];
+                    let value = self.stack[self.stack.len() - 1];
+
+                    if value.0.type_() != type_ {
+                        fail(&format!(
+                            "Expected a {type_}, found a {}",
+                            value.0.type_name()
+                        ));
+                        return Err(());
+                    }
+                }
+                Op::DefaultStep => {
+                    let start = self.stack[self.stack.len() - 2];
+                    self.stack.push(ops::default_step(&start));
+                }
+                Op::CheckRange => {
+                    let [start, end, step] = self.stack[self.stack.len() - 3..] else {
+                        unreachable!()
+                    };
+
+                    ops::check_range(&start, &end, &step).map_err(|m| fail(&m))?;
+                }
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::PopN(count) => self.stack.truncate(self.stack.len() - count as usize),
+                Op::PopUnder(count) => {
+                    let value = self.pop();
+                    self.stack.truncate(self.stack.len() - count as usize);
+                    self.stack.push(value);
+                }
+                Op::Binary(bin_op_kind) => {
+                    let right = self.pop();
+                    let left = self.pop();
+
+                    let result = ops::binary(&left, &bin_op_kind, &right).map_err(|m| fail(&m))?;
+                    self.stack.push(result);
+                }
+                Op::Unary(un_op_kind) => {
+                    let target = self.pop();
+
+                    let result = ops::unary(&un_op_kind, &target).map_err(|m| fail(&m))?;
+                    self.stack.push(result);
+                }
+                Op::Jump(target) => *ip = target as usize,
+                Op::JumpIfFalse(target) => match self.pop().0 {
+                    LitKind::Bool(true) => {}
+                    LitKind::Bool(false) => *ip = target as usize,
+                    _ => {
+                        fail("Expected a bool");
+                        return Err(());
+                    }
+                },
+                Op::Call(index) => {
+                    let function = &chunk.functions[index as usize];
+                    let args_start = self.stack.len() - function.args.len();
+
+                    for (arg, type_) in self.stack[args_start..].iter().zip(function.args.iter()) {
+                        if arg.0.type_() != *type_ {
+                            fail(&format!("`{}` expects a {type_} here", function.name));
+                            return Err(());
+                        }
+                    }
+
+                    self.frames.push(Frame {
+                        return_ip: *ip,
+                        base: *base,
+                    });
+                    *base = args_start;
+                    *ip = function.entry as usize;
+                }
+                Op::CallValue(arg_count) => {
+                    let args_start = self.stack.len() - arg_count as usize;
+                    let callee = self.stack[args_start - 1];
+
+                    let (code, captures) = {
+                        let heap = self.runtime.heap.borrow();
+                        let function = heap
+                            .function(&callee, &self.stack[args_start..])
+                            .map_err(|m| fail(&m))?;
+
+                        (function.code, function.captures.clone())
+                    };
+
+                    // The arguments take the place of the callee, the captured values follow them
+                    self.stack.remove(args_start - 1);
+                    self.stack.extend(captures);
+
+                    self.frames.push(Frame {
+                        return_ip: *ip,
+                        base: *base,
+                    });
+                    *base = args_start - 1;
+                    *ip = chunk.functions[code].entry as usize;
+                }
+                Op::MakeFunction(index) => {
+                    let function = &chunk.functions[index as usize];
+                    let Type::Function(type_) = function.type_ else {
+                        unreachable!("the resolver gives functions their type")
+                    };
+                    let captures = self
+                        .stack
+                        .split_off(self.stack.len() - function.captures as usize);
+
+                    let function = heap::Function {
+                        code: index as usize,
+                        captures,
+                    };
+                    let value = self
+                        .runtime
+                        .heap
+                        .borrow_mut()
+                        .alloc_function(type_, function);
+                    self.stack.push(value);
+                }
+                Op::Spawn(index) => {
+                    let function = &chunk.functions[index as usize];
+                    let captures = self
+                        .stack
+                        .split_off(self.stack.len() - function.captures as usize);
+
+                    let task = self.runtime.tasks.spawn(&*self.runtime.clock);
+                    let vm = self.for_task(task, captures);
+                    let (chunk, entry) = (Rc::clone(chunk), function.entry as usize);
+
+                    self.runtime
+                        .tasks
+                        .start(task, move || vm.run_task(&chunk, entry));
+
+                    self.stack.push(Lit::new(LitKind::Task(task)));
+                }
+                Op::CallBuiltin(builtin, arg_count) => {
+                    if builtin.waits() {
+                        self.collect();
+                        self.checkpoint(*ip - 1, *base, span);
+                    }
+                    let args = self.stack.split_off(self.stack.len() - arg_count as usize);
+
+                    self.runtime.waiting_at.set(span);
+                    let result = builtin.call(&args, &self.runtime).map_err(|m| fail(&m))?;
+                    self.stack.push(result);
+                    self.cut_short(base, ip);
+                }
+                Op::Every(has_times, has_duration) => {
+                    let duration = has_duration.then(|| self.pop());
+                    let times = has_times.then(|| self.pop());
+                    let every = self.pop();
+
+                    let state = schedule::start(every, times, duration, &self.runtime)
+                        .map_err(|m| fail(&m))?;
+                    self.stack.extend(state);
+                }
+                Op::Tick(slot, end) => {
+                    self.collect();
+                    self.checkpoint(*ip - 1, *base, span);
+                    let state = &mut self.stack[*base + slot as usize..][..schedule::SLOTS];
+                    self.runtime.waiting_at.set(span);
+                    let due = schedule::next(state, &self.runtime).map_err(|m| fail(&m))?;
+
+                    if !due {
+                        *ip = end as usize;
+                    }
+                    self.cut_short(base, ip);
+                }
+                Op::StartRetry => {
+                    let jitter = self.pop();
+                    let max = self.pop();
+                    let delay = self.pop();
+                    let attempts = self.pop();
+
+                    let state = retry::start(attempts, delay, max, jitter).map_err(|m| fail(&m))?;
+                    self.stack.extend(state);
+                }
+                Op::Attempt(slot, backoff) => {
+                    self.collect();
+                    self.checkpoint(*ip - 1, *base, span);
+                    let state = &mut self.stack[*base + slot as usize..][..retry::SLOTS];
+                    self.runtime.waiting_at.set(span);
+                    retry::next(state, backoff, &self.runtime, |m| warn_at(span, m))
+                        .map_err(|m| fail(&m))?;
+
+                    if self.runtime.expired.get().is_some() {
+                        self.cut_short(base, ip);
+                    } else {
+                        self.retries.push(Retry {
+                            stack: self.stack.len(),
+                            frames: self.frames.len(),
+                            base: *base,
+                            withins: self.withins.len(),
+                            restart: *ip - 1,
+                        });
+                    }
+                }
+                Op::EndAttempt(slot, restart) => {
+                    self.retries.pop();
+
+                    match self.pop().0 {
+                        LitKind::Bool(false) => *ip = restart as usize,
+                        _ => retry::succeeded(&self.stack[*base + slot as usize..], |m| {
+                            warn_at(span, m)
+                        }),
+                    }
+                }
+                Op::OnInterrupt(index) => {
+                    let function = &chunk.functions[index as usize];
+                    let captures = self
+                        .stack
+                        .split_off(self.stack.len() - function.captures as usize);
+
+                    *self.on_interrupt.borrow_mut() = Some((function.entry as usize, captures));
+                }
+                Op::Within(fallback) => {
+                    let time = self.pop();
+                    let Some(ms) = ops::as_ms(&time) else {
+                        fail("Expected a time for the deadline");
+                        return Err(());
+                    };
+
+                    let Ok(deadline) = Duration::try_from_secs_f64(ms.max(0.) as f64 / 1000.)
+                    else {
+                        fail("The deadline is too far away to keep track of");
+                        return Err(());
+                    };
+
+                    self.runtime.push_deadline(deadline);
+                    self.withins.push(Within {
+                        stack: self.stack.len(),
+                        frames: self.frames.len(),
+                        base: *base,
+                        retries: self.retries.len(),
+                        fallback: fallback as usize,
+                    });
+                }
+                Op::EndWithin => {
+                    self.withins.pop();
+                    self.runtime.pop_deadline(self.withins.len());
+                }
+                Op::CallMethod(name, arg_count) => {
+                    let args = self.stack.split_off(self.stack.len() - arg_count as usize);
+                    let receiver = self.pop();
+
+                    let name = chunk.names[name as usize];
+                    let heap = &mut self.runtime.heap.borrow_mut();
+                    let result =
+                        methods::call_method(&receiver, name, &args, heap).map_err(|m| fail(&m))?;
+                    self.stack.push(result);
+                }
+                Op::GetField(name) => {
+                    let receiver = self.pop();
+                    let field = self
+                        .runtime
+                        .heap
+                        .borrow()
+                        .get_field(&receiver, chunk.names[name as usize])
+                        .map_err(|m| fail(&m))?;
+                    self.stack.push(field);
+                }
+                Op::SetField(name) => {
+                    let value = self.pop();
+                    let receiver = self.pop();
+
+                    self.runtime
+                        .heap
+                        .borrow_mut()
+                        .set_field(&receiver, chunk.names[name as usize], value)
+                        .map_err(|m| fail(&m))?;
+                }
+                Op::MakeStruct(index) => {
+                    let def = &chunk.structs[index as usize];
+                    let values = self.stack.split_off(self.stack.len() - def.fields.len());
+                    let fields = def
+                        .fields
+                        .iter()
+                        .map(|(field, _)| field.name)
+                        .zip(values)
+                        .collect();
+
+                    let value = self.runtime.heap.borrow_mut().alloc_struct(def.id, fields);
+                    self.stack.push(value);
+                }
+                Op::MakeList(type_, count) => {
+                    let Type::List(elem) = chunk.types[type_ as usize] else {
+                        unreachable!()
+                    };
+                    let values = self.stack.split_off(self.stack.len() - count as usize);
+
+                    if let Some(value) = values.iter().find(|value| value.0.type_() != *elem) {
+                        fail(&format!(
+                            "Expected a {elem} in this list, found a {}",
+                            value.0.type_name()
+                        ));
+                        return Err(());
+                    }
+
+                    let list = self.runtime.heap.borrow_mut().alloc_list(elem, values);
+                    self.stack.push(list);
+                }
+                Op::Index => {
+                    let index = self.pop();
+                    let list = self.pop();
+
+                    let value = self
+                        .runtime
+                        .heap
+                        .borrow()
+                        .index(&list, &index)
+                        .map_err(|m| fail(&m))?;
+                    self.stack.push(value);
+                }
+                Op::Dup => self.stack.push(self.stack[self.stack.len() - 1]),
+                Op::Return => {
+                    let value = self.pop();
+                    // The outermost call only returns in a task, which ends with it
+                    let Some(frame) = self.frames.pop() else {
+                        return Ok(());
+                    };
+
+                    self.stack.truncate(*base);
+                    self.stack.push(value);
+                    *base = frame.base;
+                    *ip = frame.return_ip;
+                }
+                Op::Match(pattern) => {
+                    let value = self.pop();
+                    let matches = ops::matches(&value, &chunk.patterns[pattern as usize])
+                        .map_err(|m| fail(&m))?;
+                    self.stack.push(Lit::new(LitKind::Bool(matches)));
+                }
+                Op::Unmatched => {
+                    let value = self.stack[self.stack.len() - 1];
+                    fail(&format!(
+                        "No arm of this `match` matches the {}",
+                        value.0.type_name()
+                    ));
+                    return Err(());
+                }
+                Op::MissingReturn => {
+                    fail("The function ended without returning a value");
+                    return Err(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A VM for a task started with `spawn`, which shares the globals of
+    /// this one and starts with the values the task captured
+    fn for_task(&self, task: usize, captures: Vec<Lit>) -> Self {
+        Self {
+            stack: captures,
+            frames: Vec::new(),
+            withins: Vec::new(),
+            retries: Vec::new(),
+            globals: Rc::clone(&self.globals),
+            on_interrupt: Rc::clone(&self.on_interrupt),
+            runtime: self.runtime.for_task(task),
+        }
+    }
+
+    /// Runs the body of a task once the scheduler gives it its turn
+    fn run_task(mut self, chunk: &Rc<Chunk>, entry: usize) -> Result<(), ()> {
+        // An interrupted task ends quietly, the script runs its `on_interrupt` block
+        match self.execute(chunk, entry, 0) {
+            Err(()) if self.runtime.is_interrupted() => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Frees the values on the heap the script can't reach anymore, which
+    /// only the script itself knows while no other task is running. Before
+    /// a checkpoint it always does, so only those values are saved.
+    fn collect(&mut self) {
+        let mut heap = self.runtime.heap.borrow_mut();
+        let due = heap.is_due() || self.runtime.checkpoint.is_some();
+        if self.runtime.task != 0 || !due || !self.runtime.tasks.alone() {
+            return;
+        }
+
+        let mut globals = self.globals.borrow_mut();
+        let mut on_interrupt = self.on_interrupt.borrow_mut();
+        let captures = match &mut *on_interrupt {
+            Some((_, captures)) => &mut captures[..],
+            None => &mut [],
+        };
+        heap.collect(&mut [&mut self.stack, &mut globals, captures]);
+    }
+
+    /// Leaves everything up to the `within` block whose deadline cut a wait short
+    fn cut_short(&mut self, base: &mut usize, ip: &mut usize) {
+        let Some(index) = self.runtime.expired.take() else {
+            return;
+        };
+
+        let within = self
+            .withins
+            .drain(index..)
+            .next()
+            .expect("the deadline is running");
+        self.runtime.pop_deadline(index);
+
+        self.retries.truncate(within.retries);
+        self.stack.truncate(within.stack);
+        self.frames.truncate(within.frames);
+        *base = within.base;
+        *ip = within.fallback;
+    }
+
+    fn pop(&mut self) -> Lit {
+        self.stack
+            .pop()
+            .expect("the compiler only pops what it pushed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        engine::{Backend, Engine},
+        parser::{
+            ast::{expr::ExprKind, stmt::StmtKind},
+            parse,
+        },
+        runtime::{
+            checkpoint::{Checkpoint, Saved},
+            clock::{Clock, VirtualClock},
+        },
+    };
+    use std::{rc::Rc, sync::atomic::Ordering, time::Duration};
+
+    /// Runs the script on both backends and returns how long each waited
+    fn run_both(code: &str) -> (Duration, Duration) {
+        run_both_at(code, Duration::ZERO, 0)
+    }
+
+    /// Runs with a clock that shows the given time since the Unix epoch and
+    /// is the given seconds ahead of UTC
+    fn run_both_at(code: &str, epoch: Duration, utc_offset: i64) -> (Duration, Duration) {
+        let ast = parse(code).unwrap();
+
+        let run = |backend| {
+            let clock = Rc::new(VirtualClock::starting_at(epoch, utc_offset));
+            Engine::new(backend, Runtime::new(clock.clone()))
+                .run(ast.clone())
+                .unwrap();
+            clock.now()
+        };
+
+        (run(Backend::TreeWalker), run(Backend::Bytecode))
+    }
+
+    #[test]
+    fn test_backends_agree() {
+        let code = "
+            time base = 2s;
+
+            func scale(t: time, factor: num) -> time {
+                time scaled = t * factor;
+                return scaled + base;
+            }
+
+            wait(scale(1s, 3));
+
+            if (base > 1s && !false) {
+                time local = 500ms;
+                wait(local);
+            }
+
+            while (base < 1s) {
+                wait(1h);
+            }
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_millis(5500));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_assignments_agree() {
+        let code = "
+            time total = 0s;
+            const num count = 3;
+
+            func twice(t: time) -> time {
+                t *= 2;
+                return t;
+            }
+
+            num i = 0;
+            while (i < count) {
+                time step = 1s;
+                step += 500ms;
+                total = total + step;
+                i += 1;
+            }
+
+            wait(twice(total));
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_millis(9000));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_loops_can_be_left_early() {
+        let code = "
+            num tries = 0;
+            outer: while (true) {
+                tries += 1;
+                time step = 1s;
+
+                while (true) {
+                    num inner = 1;
+                    wait(step);
+                    if (tries < 3) { continue outer; }
+                    if (tries == 3) { break; }
+                    break outer;
+                }
+
+                wait(1min);
+            }
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_millis(64_000));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_ranges_agree() {
+        let code = "
+            time total = 0s;
+
+            for t in 0s..1min step 15s {
+                total += t;
+            }
+
+            outer: for i in 1..=10 {
+                num doubled = i * 2;
+                if (doubled == 4) { continue; }
+                for j in 0..i {
+                    if (j == 3) { break outer; }
+                    wait(1s);
+                }
+            }
+
+            wait(total);
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_millis(97_000));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_else_branches_agree() {
+        let code = "
+            func pick(n: num) -> time {
+                if (n < 0) {
+                    return 1s;
+                } else if (n == 0) {
+                    time zero = 2s;
+                    return zero;
+                } else if (n < 10) {
+                    while (true) {
+                        return 3s;
+                    }
+                }
+
+                return 4s;
+            }
+
+            wait(pick(-1));
+            wait(pick(0));
+            wait(pick(5));
+            wait(pick(50));
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_millis(10_000));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_block_values_agree() {
+        let code = "
+            func scale(t: time, fast: bool) -> time {
+                time base = 1s;
+                return base + {
+                    time extra = if (fast) { t } else { t * 2 };
+                    extra + base
+                };
+            }
+
+            wait(scale(1s, true));
+            wait(scale(1s, false));
+
+            time t = if (false) { 1s } else if (true) { time x = 2s; x } else { 5s };
+            wait(t);
+
+            num n = 0;
+            for i in 0..3 { n += { num d = i; d * 2 }; }
+            wait(n);
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_millis(15_000));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_methods_agree() {
+        let code = "
+            func half(t: time) -> time { return t / 2; }
+
+            time t = 1.4s;
+            wait(t.round_to(500ms));
+            wait(half(3s).abs().as_seconds());
+            wait((-2).abs() * 1s);
+            wait({ time x = 1min; 1s * x.as_minutes() });
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_millis(6_000));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_long_list_literals_agree() {
+        let mut ast = parse("[num] l = [1]; wait(l.len() * 1ms);").unwrap();
+        let StmtKind::VarBind { value, .. } = &mut ast.program[0].stmt_kind else {
+            unreachable!()
+        };
+        let ExprKind::List(elems) = &mut value.expr_kind else {
+            unreachable!()
+        };
+        *elems = vec![elems[0].clone(); u16::MAX as usize + 2];
+
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            let clock = Rc::new(VirtualClock::new());
+            Engine::new(backend, Runtime::new(clock.clone()))
+                .run(ast.clone())
+                .unwrap();
+            assert_eq!(clock.now(), Duration::from_millis(65_537));
+        }
+    }
+
+    #[test]
+    fn test_structs_agree() {
+        let code = "
+            struct Step { delay: time, times: num }
+            struct Job { first: Step, timeout: time }
+
+            func total(job: Job) -> time {
+                return job.first.delay * job.first.times + job.timeout;
+            }
+
+            Job job = Job { timeout: 1s, first: Step { times: 2, delay: 500ms } };
+            Job same = job;
+            same.timeout += 1s;
+            job.first.times = 3;
+
+            wait(total(job));
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_millis(3_500));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_lists_agree() {
+        let code = "
+            [time] delays = [1s, 2s];
+            [time] same = delays;
+            same.push(4s);
+
+            num count = 0;
+            for delay in delays {
+                if (count == 0) {
+                    delays.push(500ms);
+                }
+                count += 1;
+                wait(delay);
+            }
+
+            [num] empty = [];
+            wait(delays[count - 1] * { num len = delays.len(); len } + delays.min());
+            wait(delays.sum() - delays.max());
+            wait(empty.sum());
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_millis(13_500));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_unreachable_values_are_freed() {
+        let code = "
+            struct Step { delay: time, next: [time] }
+
+            [time] kept = [1ms];
+            Step last = Step { delay: 0s, next: kept };
+            |time| -> time grow = |t: time| -> time { t };
+
+            for i in 0..3000 {
+                [time] delays = [1ms, 2ms];
+                Step step = Step { delay: delays[1], next: delays };
+                time offset = step.next[0];
+                grow = |t: time| -> time { t + offset };
+                if (i % 1000 == 0) {
+                    kept.push(step.delay);
+                    last = step;
+                }
+                wait(grow(0s));
+            }
+            wait(kept.sum() + last.delay + last.next[0]);
+        ";
+        let ast = parse(code).unwrap();
+
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            let runtime = Runtime::new(Rc::new(VirtualClock::new()));
+            Engine::new(backend, runtime.clone())
+                .run(ast.clone())
+                .unwrap();
+
+            assert_eq!(runtime.clock.now(), Duration::from_millis(3000 + 7 + 3));
+            assert!(runtime.heap.borrow().save().unwrap().len() < 3000);
+        }
+    }
+
+    #[test]
+    fn test_values_in_use_while_waiting_are_kept() {
+        let code = "
+            struct Step { delay: time, next: [time] }
+
+            func pause(t: time) -> time {
+                wait(t);
+                return t;
+            }
+
+            for i in 0..2000 {
+                [time] garbage = [1ms];
+            }
+            Step held = Step { next: [2ms], delay: pause(1ms) };
+
+            for i in 0..2000 {
+                [time] garbage = [1ms];
+            }
+            for delay in [held.next[0], 3ms] {
+                wait(delay);
+            }
+        ";
+
+        assert_eq!(
+            run_both(code),
+            (Duration::from_millis(6), Duration::from_millis(6))
+        );
+    }
+
+    #[test]
+    fn test_matches_agree() {
+        let code = "
+            enum State { Pending, Running, Done }
+
+            func delay(state: State, elapsed: time) -> time {
+                return match state {
+                    State::Pending => 1s,
+                    State::Running if elapsed >= 1min => { time long = 3s; long },
+                    State::Running => match elapsed { 0s..=10s => 2s, _ => 500ms },
+                    State::Done => 0s,
+                };
+            }
+
+            State state = State::Pending;
+            for elapsed in [5s, 20s, 2min, 0s] {
+                wait(delay(state, elapsed));
+
+                match state {
+                    State::Pending => state = State::Running,
+                    State::Running if elapsed > 1min => {
+                        num steps = 0;
+                        state = State::Done;
+                    }
+                    _ if state == State::Done => { break; }
+                    _ => {}
+                }
+            }
+
+            match -2 { -3..0 => wait(1s), _ => wait(1min) }
+            wait(match state != State::Done { true => 1min, false => 100ms });
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_millis(5_600));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_closures_agree() {
+        let code = "
+            func double(t: time) -> time {
+                return t * 2;
+            }
+
+            func apply(f: |time| -> time, t: time) -> time {
+                return f(t);
+            }
+
+            func scaler(factor: num) -> |time| -> time {
+                return |t: time| -> time { t * factor };
+            }
+
+            wait(apply(double, 1s));
+            wait(apply(|t: time| { t + 1s }, 1s));
+            wait(scaler(2)(500ms));
+
+            if (true) {
+                time base = 1s;
+                num count = 2;
+                |num| -> time later = |n: num| -> time {
+                    |time| -> time inner = |t: time| -> time { t + base * count };
+                    return inner(base * n);
+                };
+
+                // Closures keep the value they captured
+                count = 10;
+                wait(later(1));
+            }
+
+            for f in [double, scaler(3)] {
+                wait(f(100ms));
+            }
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_millis(8_500));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_time_prelude_agrees() {
+        let code = "
+            time start = now();
+            wait(1s);
+            time passed = elapsed(start);
+            wait(max_time(passed, 500ms));
+            wait(min_time(2s, from_seconds(to_seconds(3s))));
+            wait(clamp(10min, 1s, 1min));
+            wait(parse_duration(format_duration(90s)));
+
+            string text = format_duration(1500ms);
+            if (text == \"1s 500ms\" && to_seconds(elapsed(start)) == 154) {
+                wait(6s);
+            }
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_secs(160));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_seeded_randomness_agrees() {
+        let code = "
+            for i in 0..20 {
+                wait(random_time(1s, 5s));
+                wait(jitter(10s, 10%) + random(0, 1) * 1s);
+            }
+        ";
+        let ast = parse(code).unwrap();
+
+        let run = |backend| {
+            let clock = Rc::new(VirtualClock::new());
+            Engine::new(backend, Runtime::new(clock.clone()))
+                .with_seed(7)
+                .run(ast.clone())
+                .unwrap();
+            clock.now()
+        };
+
+        let waited = run(Backend::TreeWalker);
+        assert_eq!(waited, run(Backend::TreeWalker));
+        assert_eq!(waited, run(Backend::Bytecode));
+        assert!(waited >= Duration::from_secs(200) && waited <= Duration::from_secs(340));
+    }
+
+    #[test]
+    fn test_tasks_wait_side_by_side() {
+        let code = "
+            func both(t: time) -> time {
+                task other = spawn { wait(t); };
+                wait(t);
+                return join(other);
+            }
+
+            task slow = spawn { wait(5s); };
+            task fast = spawn { wait(3s); };
+            if (race(slow, fast) == fast) { wait(1s); }
+            join(slow);
+
+            num done = 0;
+            join(spawn { done += 1; });
+            wait(both(2s) + done * 1s);
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_secs(8));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_deadlines_cut_waits_short() {
+        let code = "
+            num cut = 0;
+
+            func slow() -> time {
+                within 1s { wait(10s); } else { cut += 1; }
+                wait(1h);
+                return 0s;
+            }
+
+            within 3s {
+                for i in 0..5 {
+                    within 2s { wait(500ms); wait(5s); }
+                    cut += 10;
+                }
+            } else {
+                cut += 100;
+            }
+
+            within 1min { slow(); } else { cut += 1000; }
+            within 1s { wait(1s); } else { cut += 1000; }
+            wait(cut * 1s);
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_secs(1175));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_failing_tasks_fail_the_script() {
+        let code = "
+            task bad = spawn {
+                wait(1s);
+                [time] empty = [];
+                wait(empty[0]);
+            };
+            retry (attempts: 3) { wait(5s); }
+            wait(1h);
+        ";
+        let ast = parse(code).unwrap();
+
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            let clock = Rc::new(VirtualClock::new());
+            let result = Engine::new(backend, Runtime::new(clock.clone())).run(ast.clone());
+
+            assert_eq!(result, Err(()));
+            assert_eq!(clock.now(), Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn test_deadlines_cut_joins_short() {
+        let code = "
+            num cut = 0;
+            within 200ms {
+                task a = spawn { wait(1h); };
+                join(a);
+                cut += 100;
+            } else {
+                cut += 1;
+            }
+
+            task b = spawn { wait(2s); };
+            task c = spawn { wait(3s); };
+            within 1s { race(b, c); cut += 100; } else { cut += 2; }
+            within 10s { race(b, c); } else { cut += 100; }
+            wait(cut * 1s);
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_millis(2_200 + 3_000));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_times_too_long_for_the_clock_are_errors() {
+        let too_long = "1y * 10000000000000000000000000000000000000";
+
+        for code in [
+            format!("wait({too_long});"),
+            format!("within {too_long} {{ wait(1s); }}"),
+            format!("retry (attempts: 2, backoff: {too_long}) {{ false }}"),
+            format!("every {too_long} {{ wait(1s); }}"),
+        ] {
+            let ast = parse(&code).unwrap();
+
+            for backend in [Backend::TreeWalker, Backend::Bytecode] {
+                let runtime = Runtime::new(Rc::new(VirtualClock::new()));
+                assert!(Engine::new(backend, runtime).run(ast.clone()).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_waits_until_timestamps() {
+        let code = "
+            timestamp start = @2026-10-18T16:00Z;
+            wait_until(start - 1h);
+            wait_until(start + 90min);
+
+            // Local midnight is 22:00 in UTC, 19:00 already passed today
+            time gap = @2026-10-19T00:00 - start;
+            if (@19:00 > start + 1d && gap == 6h) { wait(gap); }
+            wait_until(@19:00);
+        ";
+
+        // 2026-10-18T16:00Z, two hours ahead of UTC
+        let (tree_walker, bytecode) =
+            run_both_at(code, Duration::from_secs(1_792_339_200), 2 * 3600);
+        assert_eq!(tree_walker, Duration::from_secs(25 * 3600));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_schedules_keep_to_the_start() {
+        let code = "
+            num runs = 0;
+
+            // 1970-01-01 starts on the hour
+            every \"15,45 * * * *\" times 3 { }
+
+            every 10min times 6 { runs += 1; wait(1s); }
+            every 1min for 5min { runs += 1; wait(90s); }
+            every 2s {
+                runs += 1;
+                if (runs == 13) { break; }
+            }
+            every 1s times 3 { continue; }
+            within 5s {
+                every 2s { runs += 100; }
+            }
+
+            wait(runs * 1s);
+        ";
+
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(
+            tree_walker,
+            Duration::from_secs(4500 + 3001 + 360 + 4 + 2 + 5 + 313)
+        );
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_retries_pause_between_attempts() {
+        let code = "
+            num tries = 0;
+            [num] pauses = [1, 2];
+
+            retry (attempts: 4, backoff: exponential(1s, max: 3s)) {
+                tries += 1;
+                attempt == 4
+            }
+            // Failing with an error, which leaves the loop and the function it's in
+            func pause(index: num) -> time {
+                for i in 0..3 { return pauses[index] * 1s; }
+                return 0s;
+            }
+            retry (backoff: linear(2s)) {
+                tries += 1;
+                wait(pause(3 - attempt));
+            }
+            within 5s {
+                retry (attempts: 10, backoff: linear(2s)) { tries += 1; false }
+            }
+            retry (attempts: 2) {
+                num outer = attempt;
+                retry (attempts: 2) { tries += 1; outer == 2 }
+            }
+
+            wait(tries * 1s);
+        ";
+
+        // 6s and 2s of pauses, the 2s of the attempt that got through, the
+        // deadline and 11 tries
+        let (tree_walker, bytecode) = run_both(code);
+        assert_eq!(tree_walker, Duration::from_secs(6 + 2 + 2 + 5 + 11));
+        assert_eq!(tree_walker, bytecode);
+    }
+
+    #[test]
+    fn test_interrupts_run_the_handler() {
+        let code = "
+            time grace = 5s;
+            on_interrupt { wait(grace); }
+
+            retry {
+                within 1h { wait(2h); } else { wait(1s); }
+            }
+            wait(1d);
+        ";
+        let ast = parse(code).unwrap();
+
+        // Interrupted during the first wait, which neither `within` nor `retry` catches
+        for backend in [Backend::TreeWalker, Backend::Bytecode] {
+            let clock = Rc::new(VirtualClock::new());
+            let runtime = Runtime::new(clock.clone());
+            runtime.interrupted.store(true, Ordering::SeqCst);
+
+            assert!(Engine::new(backend, runtime).run(ast.clone()).is_ok());
+            assert_eq!(clock.now(), Duration::from_secs(3605));
+        }
+    }
+
+    #[test]
+    fn test_checkpoints_resume_where_they_left_off() {
+        let code = "
+            [time] steps = [10s, 1h];
+
+            func twice(t: time) -> time {
+                wait(t);
+                return t * 2;
+            }
+
+            time total = 0s;
+            for i in 0..2 {
+                total += twice(steps[i]);
+            }
+            wait(total);
+        ";
+        let ast = parse(code).unwrap();
+        let file = std::env::temp_dir().join(format!("wait-checkpoint-{}", std::process::id()));
+
+        // Stops at the first wait, after writing the checkpoint for it
+        let runtime = Runtime::new(Rc::new(VirtualClock::new()));
+        runtime.interrupted.store(true, Ordering::SeqCst);
+        let checkpoint = Checkpoint::new(file.clone(), String::from("script.wait"));
+        let runtime = Runtime {
+            checkpoint: Some(Rc::new(checkpoint)),
+            ..runtime
+        };
+        Engine::new(Backend::Bytecode, runtime)
+            .run(ast.clone())
+            .unwrap();
+
+        // Resumed 4s into the first wait
+        let saved = Saved::load(file.to_str().unwrap()).unwrap();
+        let clock = Rc::new(VirtualClock::new());
+        clock.sleep(Duration::from_secs(4));
+        let runtime = Runtime {
+            checkpoint: Some(Rc::new(Checkpoint::resumed(file.clone(), &saved))),
+            ..Runtime::new(clock.clone())
+        };
+        Engine::new(Backend::Bytecode, runtime)
+            .resume(ast, &saved)
+            .unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(clock.now(), Duration::from_secs(10 + 3600 + 20 + 7200));
+    }
+
+    #[test]
+    fn test_checkpoints_only_keep_reachable_values() {
+        let code = "
+            [time] kept = [];
+            for i in 0..200 {
+                [time] delays = [1ms, 2ms];
+                kept.push(delays[0]);
+                wait(delays.sum());
+            }
+        ";
+        let ast = parse(code).unwrap();
+        let file = std::env::temp_dir().join(format!("wait-reachable-{}", std::process::id()));
+
+        let checkpoint = Checkpoint::new(file.clone(), String::from("script.wait"));
+        let runtime = Runtime {
+            checkpoint: Some(Rc::new(checkpoint)),
+            ..Runtime::new(Rc::new(VirtualClock::new()))
+        };
+        Engine::new(Backend::Bytecode, runtime.clone())
+            .run(ast)
+            .unwrap();
+        let _ = std::fs::remove_file(&file);
+
+        // The list of the last iteration and `kept`
+        assert_eq!(runtime.heap.borrow().save().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_fingerprints_only_change_with_the_code() {
+        let fingerprint = |code| {
+            checkpoint::fingerprint(&Compiler::new().compile(&parse(code).unwrap()).unwrap())
+        };
+        let code = "time t = 1s; wait(t * 2);";
+
+        assert_eq!(fingerprint(code), fingerprint(code));
+        assert_eq!(
+            fingerprint(code),
+            fingerprint("time t = 1s;\n\nwait(t  *  2);")
+        );
+        assert_ne!(fingerprint(code), fingerprint("time t = 1s; wait(t * 3);"));
+        assert_ne!(fingerprint(code), fingerprint("time t = 1s; wait(t / 2);"));
+    }
+
+    #[test]
+    fn test_ops_stay_small() {
+        assert!(std::mem::size_of::<Op>() <= 8);
+    }
+
+    #[test]
+    fn test_variables_are_resolved_to_slots() {
+        let ast =
+            parse("time a = 1s; func f(x: time) -> time { time y = x; return y + a; }").unwrap();
+        let chunk = Compiler::new().compile(&ast).unwrap();
+
+        assert!(chunk.code.contains(&Op::GetGlobal(0)));
+        assert!(chunk.code.contains(&Op::GetLocal(0)));
+        assert!(chunk.code.contains(&Op::GetLocal(1)));
+    }
+
+    #[test]
+    fn test_slots_that_dont_fit_dont_compile() {
+        let mut ast = parse("num g = 0;").unwrap();
+        let global = ast.program[0].clone();
+
+        ast.program = vec![global.clone(); u16::MAX as usize];
+        assert!(Compiler::new().compile(&ast).is_ok());
+
+        ast.program.push(global);
+        assert!(Compiler::new().compile(&ast).is_err());
+    }
+}